@@ -0,0 +1,100 @@
+use serde::{Deserialize, Serialize};
+use superconductor::bevy_app::{App, Plugin};
+
+use crate::env_setting;
+use crate::settings::{self, Setting};
+
+// Colours that mark something as placeable, blocked or picked out.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct HighlightPalette {
+    pub valid: [f32; 3],
+    pub invalid: [f32; 3],
+    pub accent: [f32; 3],
+}
+
+const DEFAULT_PALETTE: HighlightPalette = HighlightPalette {
+    valid: [0.0, 1.0, 0.0],
+    invalid: [1.0, 0.0, 0.0],
+    accent: [1.0, 0.8, 0.0],
+};
+
+// From the Okabe-Ito palette, with valid and invalid told apart by brightness as well as hue.
+const COLORBLIND_PALETTE: HighlightPalette = HighlightPalette {
+    valid: [0.35, 0.7, 0.9],
+    invalid: [0.8, 0.4, 0.0],
+    accent: [0.95, 0.9, 0.25],
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct AccessibilitySettings {
+    // Larger, bolder text on a solid backdrop for the web overlays, and starker ui panels.
+    pub high_contrast: bool,
+    // Stops the spinning props and makes framing the selection jump instead of flying. There's
+    // no head bob or camera shake yet; anything that adds some should check this too.
+    pub reduced_motion: bool,
+    pub colorblind_palette: bool,
+    // Shows captions for speech, sounds and cutscene dialogue.
+    #[serde(default)]
+    pub captions: bool,
+}
+
+impl Setting for AccessibilitySettings {
+    const KEY: &'static str = "accessibility";
+}
+
+impl AccessibilitySettings {
+    // HIGH_CONTRAST=1, REDUCED_MOTION=1, COLORBLIND_PALETTE=1 and CAPTIONS=1 on desktop, or
+    // ?high_contrast=1, ?reduced_motion=1, ?colorblind_palette=1 and ?captions=1 on the web. The
+    // web also follows the browser's prefers-contrast and prefers-reduced-motion preferences.
+    pub fn from_env() -> Self {
+        let flag = |desktop: &str, web: &str| env_setting(desktop, web).map(|value| value == "1");
+
+        Self {
+            high_contrast: flag("HIGH_CONTRAST", "high_contrast")
+                .unwrap_or_else(|| prefers("(prefers-contrast: more)")),
+            reduced_motion: flag("REDUCED_MOTION", "reduced_motion")
+                .unwrap_or_else(|| prefers("(prefers-reduced-motion: reduce)")),
+            colorblind_palette: flag("COLORBLIND_PALETTE", "colorblind_palette").unwrap_or(false),
+            captions: flag("CAPTIONS", "captions").unwrap_or(false),
+        }
+    }
+
+    pub fn palette(&self) -> HighlightPalette {
+        if self.colorblind_palette {
+            COLORBLIND_PALETTE
+        } else {
+            DEFAULT_PALETTE
+        }
+    }
+
+    // Added to the end of a text overlay's style, so that it takes precedence.
+    pub(crate) fn overlay_text_style(&self) -> &'static str {
+        if self.high_contrast {
+            "font-size: 150%; font-weight: bold; color: white; background: black; \
+             text-shadow: none;"
+        } else {
+            ""
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn prefers(_media_query: &str) -> bool {
+    false
+}
+
+#[cfg(feature = "wasm")]
+fn prefers(media_query: &str) -> bool {
+    web_sys::window()
+        .and_then(|window| window.match_media(media_query).ok().flatten())
+        .map_or(false, |list| list.matches())
+}
+
+pub struct AccessibilityPlugin;
+
+impl Plugin for AccessibilityPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(AccessibilitySettings::from_env());
+        settings::persist::<AccessibilitySettings>(app);
+    }
+}
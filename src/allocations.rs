@@ -0,0 +1,73 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Local, ResMut},
+};
+
+use crate::debug_stats::DebugStats;
+use crate::schedule;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+static ALLOCATED_BYTES: AtomicUsize = AtomicUsize::new(0);
+
+// Counts every allocation on top of the system allocator. It's only the global allocator in
+// builds with the `allocation_stats` feature, as the counting isn't free.
+pub struct CountingAllocator;
+
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(layout.size(), Ordering::Relaxed);
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        ALLOCATED_BYTES.fetch_add(new_size, Ordering::Relaxed);
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+// What was allocated over the last frame, rendering and all. Reallocations count as new
+// allocations of their new size.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct AllocationStats {
+    pub allocations: usize,
+    pub bytes: usize,
+}
+
+pub struct AllocationStatsPlugin;
+
+impl Plugin for AllocationStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugStats>();
+
+        app.add_system_set(schedule::input().with_system(update_allocation_stats));
+    }
+}
+
+fn update_allocation_stats(mut stats: ResMut<DebugStats>, mut previous: Local<(usize, usize)>) {
+    let current = (
+        ALLOCATIONS.load(Ordering::Relaxed),
+        ALLOCATED_BYTES.load(Ordering::Relaxed),
+    );
+
+    stats.allocations = Some(AllocationStats {
+        allocations: current.0.wrapping_sub(previous.0),
+        bytes: current.1.wrapping_sub(previous.1),
+    });
+
+    *previous = current;
+}
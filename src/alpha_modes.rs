@@ -0,0 +1,77 @@
+use serde_json::Value;
+use superconductor::{bevy_ecs::prelude::Component, url::Url};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AlphaMode {
+    Opaque,
+    // Fully transparent below the cutoff and opaque above it.
+    Mask(f32),
+    Blend,
+}
+
+// Replaces the alpha mode of every material in a model before the renderer gets it, e.g. to
+// show a ghosted preview of an avatar. Has to be on the model entity before its download
+// starts. The renderer sorts blended draws by model rather than by instance, so overlapping
+// instances of the same blended model can still draw in the wrong order.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct AlphaModeOverride {
+    pub mode: AlphaMode,
+    // Multiplied into each material's base colour alpha.
+    pub opacity: f32,
+}
+
+impl AlphaModeOverride {
+    pub fn new(mode: AlphaMode) -> Self {
+        Self { mode, opacity: 1.0 }
+    }
+
+    // Translucent, for previews that shouldn't hide what's behind them.
+    pub fn ghost(opacity: f32) -> Self {
+        Self {
+            mode: AlphaMode::Blend,
+            opacity,
+        }
+    }
+
+    // A distinct name to store the rewritten model under, so that different overrides of the
+    // same model don't overwrite each other.
+    pub(crate) fn derived_key(&self, url: &Url) -> Url {
+        let mut key = url.clone();
+        key.set_fragment(Some(&format!("alpha={:?},{}", self.mode, self.opacity)));
+        key
+    }
+
+    pub(crate) fn apply_to_json(&self, gltf: &mut Value) {
+        let materials = match gltf["materials"].as_array_mut() {
+            Some(materials) => materials,
+            None => return,
+        };
+
+        for material in materials {
+            match self.mode {
+                AlphaMode::Opaque => {
+                    material["alphaMode"] = "OPAQUE".into();
+                }
+                AlphaMode::Mask(cutoff) => {
+                    material["alphaMode"] = "MASK".into();
+                    material["alphaCutoff"] = cutoff.into();
+                }
+                AlphaMode::Blend => {
+                    material["alphaMode"] = "BLEND".into();
+                }
+            }
+
+            if self.opacity < 1.0 {
+                let pbr = &mut material["pbrMetallicRoughness"];
+
+                let alpha = pbr["baseColorFactor"][3].as_f64().unwrap_or(1.0);
+
+                if !pbr["baseColorFactor"].is_array() {
+                    pbr["baseColorFactor"] = serde_json::json!([1.0, 1.0, 1.0, 1.0]);
+                }
+
+                pbr["baseColorFactor"][3] = (alpha * self.opacity as f64).into();
+            }
+        }
+    }
+}
@@ -0,0 +1,744 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use superconductor::url::Url;
+
+pub const DEFAULT_MAX_CACHE_BYTES: u64 = 512 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum AssetError {
+    Network(String),
+    Status(u16),
+    Io(String),
+    Decode(String),
+    Integrity { expected: String, actual: String },
+}
+
+impl fmt::Display for AssetError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Network(error) => write!(f, "network error: {}", error),
+            Self::Status(status) => write!(f, "unexpected http status {}", status),
+            Self::Io(error) => write!(f, "cache io error: {}", error),
+            Self::Decode(error) => write!(f, "decode error: {}", error),
+            Self::Integrity { expected, actual } => write!(
+                f,
+                "content hash mismatch (expected sha256 {}, got {})",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl std::error::Error for AssetError {}
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CacheEntry {
+    etag: Option<String>,
+    last_modified: Option<String>,
+    size: u64,
+    last_used: u64,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct CacheIndex {
+    entries: HashMap<String, CacheEntry>,
+}
+
+impl CacheIndex {
+    // Least-recently-used eviction. Returns the urls that were dropped so the backend can
+    // delete the stored bodies.
+    fn evict(&mut self, max_bytes: u64) -> Vec<String> {
+        let mut total: u64 = self.entries.values().map(|entry| entry.size).sum();
+        let mut by_age: Vec<(String, u64, u64)> = self
+            .entries
+            .iter()
+            .map(|(url, entry)| (url.clone(), entry.last_used, entry.size))
+            .collect();
+
+        by_age.sort_by_key(|&(_, last_used, _)| last_used);
+
+        let mut evicted = Vec::new();
+
+        for (url, _, size) in by_age {
+            if total <= max_bytes {
+                break;
+            }
+
+            self.entries.remove(&url);
+            total -= size;
+            evicted.push(url);
+        }
+
+        evicted
+    }
+}
+
+// A small, stable hash for turning urls into file names.
+pub(crate) fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+// The name bytes derived from an asset are stored under. The extension tells apart multiple
+// files derived from the same asset.
+fn derived_name(url: &Url, extension: &str) -> String {
+    format!("{:016x}.{}", fnv1a(url.as_str().as_bytes()), extension)
+}
+
+#[cfg(not(feature = "wasm"))]
+pub use native::AssetCache;
+
+#[cfg(feature = "wasm")]
+pub use web::AssetCache;
+
+#[cfg(not(feature = "wasm"))]
+mod native {
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+    use std::time::{SystemTime, UNIX_EPOCH};
+
+    use super::{
+        derived_name, fnv1a, AssetError, CacheEntry, CacheIndex, Url, DEFAULT_MAX_CACHE_BYTES,
+    };
+
+    const INDEX_FILE: &str = "index.json";
+    const DERIVED_DIRECTORY: &str = "derived";
+
+    fn now() -> u64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_secs())
+            .unwrap_or_default()
+    }
+
+    fn load_index(path: &Path) -> Option<CacheIndex> {
+        let bytes = std::fs::read(path).ok()?;
+        serde_json::from_slice(&bytes).ok()
+    }
+
+    fn write_index(path: &Path, index: &CacheIndex) {
+        let result = serde_json::to_vec(index)
+            .map_err(|error| error.to_string())
+            .and_then(|bytes| std::fs::write(path, bytes).map_err(|error| error.to_string()));
+
+        if let Err(error) = result {
+            log::warn!("Failed to save the asset cache index {:?}: {}", path, error);
+        }
+    }
+
+    // Indexes derived files that were stored before they had an index, by when they were
+    // written.
+    fn scan_derived(directory: &Path) -> CacheIndex {
+        let entries = std::fs::read_dir(directory)
+            .into_iter()
+            .flatten()
+            .flatten()
+            .filter_map(|entry| {
+                let name = entry.file_name().into_string().ok()?;
+                let metadata = entry.metadata().ok()?;
+                let last_used = metadata
+                    .modified()
+                    .ok()?
+                    .duration_since(UNIX_EPOCH)
+                    .ok()?
+                    .as_secs();
+
+                let entry = CacheEntry {
+                    etag: None,
+                    last_modified: None,
+                    size: metadata.len(),
+                    last_used,
+                };
+
+                Some((name, entry)).filter(|(name, _)| name != INDEX_FILE)
+            })
+            .collect();
+
+        CacheIndex { entries }
+    }
+
+    pub struct AssetCache {
+        directory: PathBuf,
+        max_bytes: u64,
+        index: Mutex<CacheIndex>,
+        // Derived files get the same size limit as downloaded ones, but an index of their own,
+        // by file name.
+        derived: Mutex<CacheIndex>,
+        client: reqwest::Client,
+    }
+
+    impl AssetCache {
+        pub fn new(directory: impl Into<PathBuf>, max_bytes: u64) -> Self {
+            let directory = directory.into();
+
+            if let Err(error) = std::fs::create_dir_all(&directory) {
+                log::warn!("Failed to create asset cache {:?}: {}", directory, error);
+            }
+
+            let index = load_index(&directory.join(INDEX_FILE)).unwrap_or_default();
+
+            let derived_directory = directory.join(DERIVED_DIRECTORY);
+            let derived = load_index(&derived_directory.join(INDEX_FILE))
+                .unwrap_or_else(|| scan_derived(&derived_directory));
+
+            Self {
+                directory,
+                max_bytes,
+                index: Mutex::new(index),
+                derived: Mutex::new(derived),
+                client: reqwest::Client::new(),
+            }
+        }
+
+        fn body_path(&self, url: &str) -> PathBuf {
+            self.directory
+                .join(format!("{:016x}", fnv1a(url.as_bytes())))
+        }
+
+        fn save_index(&self, index: &CacheIndex) {
+            write_index(&self.directory.join(INDEX_FILE), index);
+        }
+
+        pub async fn fetch(&self, url: &Url) -> Result<Vec<u8>, AssetError> {
+            self.fetch_with_progress(url, &|_, _| {}).await
+        }
+
+        pub async fn fetch_with_progress(
+            &self,
+            url: &Url,
+            progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+        ) -> Result<Vec<u8>, AssetError> {
+            let key = url.as_str();
+            let cached = self.index.lock().unwrap().entries.get(key).cloned();
+
+            let mut request = self.client.get(url.clone());
+
+            if let Some(entry) = &cached {
+                if let Some(etag) = &entry.etag {
+                    request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+                }
+
+                if let Some(last_modified) = &entry.last_modified {
+                    request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+                }
+            }
+
+            let response = match request.send().await {
+                Ok(response) => response,
+                Err(error) => {
+                    // Serve a stale copy rather than nothing when offline.
+                    if cached.is_some() {
+                        if let Ok(bytes) = std::fs::read(self.body_path(key)) {
+                            log::warn!("Using stale cached copy of {}: {}", url, error);
+                            return Ok(bytes);
+                        }
+                    }
+
+                    return Err(AssetError::Network(error.to_string()));
+                }
+            };
+
+            if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+                if let Ok(bytes) = std::fs::read(self.body_path(key)) {
+                    progress(bytes.len() as u64, Some(bytes.len() as u64));
+
+                    let mut index = self.index.lock().unwrap();
+
+                    if let Some(entry) = index.entries.get_mut(key) {
+                        entry.last_used = now();
+                    }
+
+                    self.save_index(&index);
+
+                    return Ok(bytes);
+                }
+
+                // The body went missing from disk, so fetch it again from scratch.
+                self.index.lock().unwrap().entries.remove(key);
+                return self
+                    .client
+                    .get(url.clone())
+                    .send()
+                    .await
+                    .and_then(|response| response.error_for_status())
+                    .map_err(|error| AssetError::Network(error.to_string()))?
+                    .bytes()
+                    .await
+                    .map(|bytes| bytes.to_vec())
+                    .map_err(|error| AssetError::Network(error.to_string()));
+            }
+
+            if !response.status().is_success() {
+                return Err(AssetError::Status(response.status().as_u16()));
+            }
+
+            let header = |name: reqwest::header::HeaderName| {
+                response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .map(|value| value.to_string())
+            };
+
+            let etag = header(reqwest::header::ETAG);
+            let last_modified = header(reqwest::header::LAST_MODIFIED);
+
+            let total = response.content_length();
+            let mut response = response;
+            let mut bytes = Vec::with_capacity(total.unwrap_or_default() as usize);
+
+            while let Some(chunk) = response
+                .chunk()
+                .await
+                .map_err(|error| AssetError::Network(error.to_string()))?
+            {
+                bytes.extend_from_slice(&chunk);
+                progress(bytes.len() as u64, total);
+            }
+
+            if etag.is_none() && last_modified.is_none() {
+                return Ok(bytes);
+            }
+
+            std::fs::write(self.body_path(key), &bytes)
+                .map_err(|error| AssetError::Io(error.to_string()))?;
+
+            let mut index = self.index.lock().unwrap();
+
+            index.entries.insert(
+                key.to_string(),
+                CacheEntry {
+                    etag,
+                    last_modified,
+                    size: bytes.len() as u64,
+                    last_used: now(),
+                },
+            );
+
+            for evicted in index.evict(self.max_bytes) {
+                let _ = std::fs::remove_file(self.body_path(&evicted));
+            }
+
+            self.save_index(&index);
+
+            Ok(bytes)
+        }
+    }
+
+    impl AssetCache {
+        // Forgets the cached copy of a url, such as one that didn't match its expected hash,
+        // so that it isn't served again.
+        pub async fn evict(&self, url: &Url) {
+            let mut index = self.index.lock().unwrap();
+
+            if index.entries.remove(url.as_str()).is_some() {
+                let _ = std::fs::remove_file(self.body_path(url.as_str()));
+                self.save_index(&index);
+            }
+        }
+
+        // Stores bytes derived from a downloaded asset (such as a decompressed model) and
+        // returns a url the renderer can load them from. The extension tells apart multiple
+        // files derived from the same asset. The least recently stored files are deleted to
+        // make room, but never the one being stored.
+        pub fn store_derived(
+            &self,
+            url: &Url,
+            extension: &str,
+            bytes: &[u8],
+        ) -> Result<Url, AssetError> {
+            let directory = self.directory.join(DERIVED_DIRECTORY);
+
+            std::fs::create_dir_all(&directory)
+                .map_err(|error| AssetError::Io(error.to_string()))?;
+
+            let name = derived_name(url, extension);
+            let path = directory.join(&name);
+
+            std::fs::write(&path, bytes).map_err(|error| AssetError::Io(error.to_string()))?;
+
+            {
+                let mut derived = self.derived.lock().unwrap();
+                let size = bytes.len() as u64;
+
+                derived.entries.remove(&name);
+
+                for evicted in derived.evict(self.max_bytes.saturating_sub(size)) {
+                    let _ = std::fs::remove_file(directory.join(evicted));
+                }
+
+                derived.entries.insert(
+                    name,
+                    CacheEntry {
+                        etag: None,
+                        last_modified: None,
+                        size,
+                        last_used: now(),
+                    },
+                );
+
+                write_index(&directory.join(INDEX_FILE), &derived);
+            }
+
+            let path = path
+                .canonicalize()
+                .map_err(|error| AssetError::Io(error.to_string()))?;
+
+            Url::from_file_path(&path)
+                .map_err(|_| AssetError::Io(format!("{:?} is not a valid file url", path)))
+        }
+
+        // Deletes bytes stored with `store_derived` once nothing needs them any more.
+        pub fn remove_derived(&self, url: &Url, extension: &str) {
+            let directory = self.directory.join(DERIVED_DIRECTORY);
+            let name = derived_name(url, extension);
+            let mut derived = self.derived.lock().unwrap();
+
+            if derived.entries.remove(&name).is_some() {
+                write_index(&directory.join(INDEX_FILE), &derived);
+            }
+
+            let _ = std::fs::remove_file(directory.join(name));
+        }
+    }
+
+    impl Default for AssetCache {
+        fn default() -> Self {
+            let directory =
+                std::env::var("ASSET_CACHE_DIR").unwrap_or_else(|_| ".cache/assets".into());
+
+            Self::new(directory, DEFAULT_MAX_CACHE_BYTES)
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod web {
+    use std::collections::HashMap;
+    use std::sync::Mutex;
+
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    use super::{
+        derived_name, fnv1a, AssetError, CacheEntry, CacheIndex, Url, DEFAULT_MAX_CACHE_BYTES,
+    };
+
+    const CACHE_NAME: &str = "superconductor-assets";
+    const INDEX_KEY: &str = "superconductor-asset-index";
+
+    fn js_error(error: wasm_bindgen::JsValue) -> AssetError {
+        AssetError::Network(format!("{:?}", error))
+    }
+
+    fn now() -> u64 {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+
+    // Bodies live in the Cache API, while the etags, sizes and access times used for
+    // eviction live in local storage.
+    pub struct AssetCache {
+        max_bytes: u64,
+        index: Mutex<CacheIndex>,
+        // The blob url of each derived asset and a hash of its bytes, by the same name derived
+        // files get on desktop.
+        derived: Mutex<HashMap<String, (u64, String)>>,
+    }
+
+    impl AssetCache {
+        pub fn new(max_bytes: u64) -> Self {
+            let index = local_storage()
+                .and_then(|storage| storage.get_item(INDEX_KEY).ok().flatten())
+                .and_then(|json| serde_json::from_str(&json).ok())
+                .unwrap_or_default();
+
+            Self {
+                max_bytes,
+                index: Mutex::new(index),
+                derived: Default::default(),
+            }
+        }
+
+        fn save_index(&self) {
+            if let (Some(storage), Ok(json)) = (
+                local_storage(),
+                serde_json::to_string(&*self.index.lock().unwrap()),
+            ) {
+                let _ = storage.set_item(INDEX_KEY, &json);
+            }
+        }
+
+        pub async fn fetch(&self, url: &Url) -> Result<Vec<u8>, AssetError> {
+            self.fetch_with_progress(url, &|_, _| {}).await
+        }
+
+        // The fetch API doesn't expose download progress without streams, so progress is only
+        // reported once the body has arrived.
+        pub async fn fetch_with_progress(
+            &self,
+            url: &Url,
+            progress: &(dyn Fn(u64, Option<u64>) + Send + Sync),
+        ) -> Result<Vec<u8>, AssetError> {
+            let bytes = self.fetch_inner(url).await?;
+            progress(bytes.len() as u64, Some(bytes.len() as u64));
+            Ok(bytes)
+        }
+
+        async fn fetch_inner(&self, url: &Url) -> Result<Vec<u8>, AssetError> {
+            let window = web_sys::window().unwrap();
+            let key = url.as_str();
+
+            let cache: web_sys::Cache =
+                JsFuture::from(window.caches().map_err(js_error)?.open(CACHE_NAME))
+                    .await
+                    .map_err(js_error)?
+                    .unchecked_into();
+
+            let cached_response: Option<web_sys::Response> =
+                JsFuture::from(cache.match_with_str(key))
+                    .await
+                    .ok()
+                    .filter(|value| !value.is_undefined())
+                    .map(|value| value.unchecked_into());
+
+            let entry = self.index.lock().unwrap().entries.get(key).cloned();
+
+            let headers = web_sys::Headers::new().map_err(js_error)?;
+
+            if let (Some(entry), Some(_)) = (&entry, &cached_response) {
+                if let Some(etag) = &entry.etag {
+                    headers.set("If-None-Match", etag).map_err(js_error)?;
+                }
+
+                if let Some(last_modified) = &entry.last_modified {
+                    headers
+                        .set("If-Modified-Since", last_modified)
+                        .map_err(js_error)?;
+                }
+            }
+
+            let mut init = web_sys::RequestInit::new();
+            init.headers(&headers);
+
+            let response = match JsFuture::from(window.fetch_with_str_and_init(key, &init)).await {
+                Ok(response) => response.unchecked_into::<web_sys::Response>(),
+                Err(error) => match cached_response {
+                    Some(cached) => {
+                        log::warn!("Using stale cached copy of {}: {:?}", url, error);
+                        return read_body(&cached).await;
+                    }
+                    None => return Err(js_error(error)),
+                },
+            };
+
+            if response.status() == 304 {
+                if let Some(cached) = cached_response {
+                    if let Some(entry) = self.index.lock().unwrap().entries.get_mut(key) {
+                        entry.last_used = now();
+                    }
+
+                    self.save_index();
+
+                    return read_body(&cached).await;
+                }
+            }
+
+            if !response.ok() {
+                return Err(AssetError::Status(response.status()));
+            }
+
+            let etag = response.headers().get("ETag").ok().flatten();
+            let last_modified = response.headers().get("Last-Modified").ok().flatten();
+
+            if etag.is_some() || last_modified.is_some() {
+                let stored = response.clone().map_err(js_error)?;
+                JsFuture::from(cache.put_with_str(key, &stored))
+                    .await
+                    .map_err(js_error)?;
+            }
+
+            let bytes = read_body(&response).await?;
+
+            if etag.is_some() || last_modified.is_some() {
+                let evicted = {
+                    let mut index = self.index.lock().unwrap();
+
+                    index.entries.insert(
+                        key.to_string(),
+                        CacheEntry {
+                            etag,
+                            last_modified,
+                            size: bytes.len() as u64,
+                            last_used: now(),
+                        },
+                    );
+
+                    index.evict(self.max_bytes)
+                };
+
+                for url in evicted {
+                    let _ = JsFuture::from(cache.delete_with_str(&url)).await;
+                }
+
+                self.save_index();
+            }
+
+            Ok(bytes)
+        }
+    }
+
+    impl AssetCache {
+        // Forgets the cached copy of a url, such as one that didn't match its expected hash,
+        // so that it isn't served again.
+        pub async fn evict(&self, url: &Url) {
+            if self
+                .index
+                .lock()
+                .unwrap()
+                .entries
+                .remove(url.as_str())
+                .is_none()
+            {
+                return;
+            }
+
+            self.save_index();
+
+            if let Ok(caches) = web_sys::window().unwrap().caches() {
+                if let Ok(cache) = JsFuture::from(caches.open(CACHE_NAME)).await {
+                    let cache: web_sys::Cache = cache.unchecked_into();
+                    let _ = JsFuture::from(cache.delete_with_str(url.as_str())).await;
+                }
+            }
+        }
+
+        // Derived assets are kept in memory as blobs. Storing the same bytes again hands back
+        // the same blob url, while storing different ones (like a model that's been hot
+        // reloaded) revokes the old url, so that the old bytes can be freed.
+        pub fn store_derived(
+            &self,
+            url: &Url,
+            extension: &str,
+            bytes: &[u8],
+        ) -> Result<Url, AssetError> {
+            let name = derived_name(url, extension);
+            let hash = fnv1a(bytes);
+            let mut derived = self.derived.lock().unwrap();
+
+            let blob_url = match derived.get(&name) {
+                Some((stored, blob_url)) if *stored == hash => blob_url.clone(),
+                _ => {
+                    let array = js_sys::Array::of1(&js_sys::Uint8Array::from(bytes));
+                    let blob =
+                        web_sys::Blob::new_with_u8_array_sequence(&array).map_err(js_error)?;
+                    let blob_url =
+                        web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+                    if let Some((_, previous)) = derived.insert(name, (hash, blob_url.clone())) {
+                        let _ = web_sys::Url::revoke_object_url(&previous);
+                    }
+
+                    blob_url
+                }
+            };
+
+            Url::parse(&blob_url).map_err(|error| AssetError::Io(error.to_string()))
+        }
+
+        // Revokes a blob url handed out by `store_derived` once the renderer has loaded it, so
+        // that the bytes can be freed. Storing them again makes a new one.
+        pub fn release_derived(&self, blob_url: &Url) {
+            self.derived.lock().unwrap().retain(|_, (_, stored)| {
+                let keep = stored != blob_url.as_str();
+
+                if !keep {
+                    let _ = web_sys::Url::revoke_object_url(stored);
+                }
+
+                keep
+            });
+        }
+
+        // Revokes the blob url of bytes stored with `store_derived` once nothing needs them
+        // any more.
+        pub fn remove_derived(&self, url: &Url, extension: &str) {
+            let removed = self
+                .derived
+                .lock()
+                .unwrap()
+                .remove(&derived_name(url, extension));
+
+            if let Some((_, blob_url)) = removed {
+                let _ = web_sys::Url::revoke_object_url(&blob_url);
+            }
+        }
+    }
+
+    impl Default for AssetCache {
+        fn default() -> Self {
+            Self::new(DEFAULT_MAX_CACHE_BYTES)
+        }
+    }
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    async fn read_body(response: &web_sys::Response) -> Result<Vec<u8>, AssetError> {
+        let buffer = JsFuture::from(response.array_buffer().map_err(js_error)?)
+            .await
+            .map_err(js_error)?;
+
+        Ok(js_sys::Uint8Array::new(&buffer).to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn index(entries: &[(&str, u64, u64)]) -> CacheIndex {
+        CacheIndex {
+            entries: entries
+                .iter()
+                .map(|&(url, size, last_used)| {
+                    let entry = CacheEntry {
+                        etag: None,
+                        last_modified: None,
+                        size,
+                        last_used,
+                    };
+
+                    (url.to_string(), entry)
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn evicts_least_recently_used_first() {
+        let mut index = index(&[("a", 10, 3), ("b", 10, 1), ("c", 10, 2)]);
+
+        assert_eq!(index.evict(15), vec!["b".to_string(), "c".to_string()]);
+        assert_eq!(index.entries.keys().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn keeps_everything_within_budget() {
+        let mut index = index(&[("a", 10, 1), ("b", 20, 2)]);
+
+        assert!(index.evict(30).is_empty());
+        assert_eq!(index.entries.len(), 2);
+    }
+
+    #[test]
+    fn evicts_everything_over_an_empty_budget() {
+        let mut index = index(&[("a", 10, 1), ("b", 20, 2)]);
+
+        assert_eq!(index.evict(0).len(), 2);
+        assert!(index.entries.is_empty());
+    }
+}
@@ -0,0 +1,236 @@
+use std::any::TypeId;
+use std::collections::HashMap;
+use std::marker::PhantomData;
+use std::sync::{Arc, Weak};
+
+use superconductor::{
+    bevy_app::{App, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res, ResMut, With},
+    components,
+    url::Url,
+};
+
+use crate::downloads::{DownloadFailed, DownloadManager, DownloadPriority, QueuedModel};
+use crate::occlusion::Culled;
+
+// Something that can be loaded from a url into an entity that instances can point at.
+pub trait Asset: Send + Sync + 'static {
+    fn spawn(commands: &mut Commands, url: Url) -> Entity;
+}
+
+pub struct Model;
+
+pub struct AnimatedModel;
+
+// A large scene, downloaded after the avatar and props.
+pub struct Environment;
+
+fn spawn_model(
+    commands: &mut Commands,
+    url: Url,
+    animated: bool,
+    priority: DownloadPriority,
+) -> Entity {
+    commands
+        .spawn()
+        .insert(QueuedModel {
+            url,
+            animated,
+            priority,
+        })
+        .insert(components::Instances(Default::default()))
+        .insert(components::InstanceRange(Default::default()))
+        .id()
+}
+
+impl Asset for Model {
+    fn spawn(commands: &mut Commands, url: Url) -> Entity {
+        spawn_model(commands, url, false, DownloadPriority::Props)
+    }
+}
+
+impl Asset for AnimatedModel {
+    fn spawn(commands: &mut Commands, url: Url) -> Entity {
+        spawn_model(commands, url, true, DownloadPriority::Avatar)
+    }
+}
+
+impl Asset for Environment {
+    fn spawn(commands: &mut Commands, url: Url) -> Entity {
+        spawn_model(commands, url, false, DownloadPriority::Environment)
+    }
+}
+
+// A reference counted pointer to a loaded asset. The asset is unloaded once the last handle
+// is dropped and no instances point at it anymore.
+pub struct Handle<T> {
+    entity: Arc<Entity>,
+    _marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    // For use with `InstanceOf`.
+    pub fn entity(&self) -> Entity {
+        *self.entity
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self {
+            entity: self.entity.clone(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.entity() == other.entity()
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> std::fmt::Debug for Handle<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_tuple("Handle").field(&self.entity()).finish()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AssetState {
+    Queued,
+    Downloading,
+    // Downloaded and handed to the renderer.
+    Loading,
+    Ready,
+    Failed,
+}
+
+struct AssetEntry {
+    entity: Entity,
+    handles: Weak<Entity>,
+    state: AssetState,
+}
+
+// Sent when an asset finishes loading, with the entity of its handle.
+pub struct AssetReady(pub Entity);
+
+#[derive(Default)]
+pub struct AssetServer {
+    assets: HashMap<(TypeId, Url), AssetEntry>,
+}
+
+impl AssetServer {
+    // Loading the same url twice returns the same asset.
+    pub fn load<T: Asset>(&mut self, commands: &mut Commands, url: Url) -> Handle<T> {
+        let key = (TypeId::of::<T>(), url);
+
+        let entity = match self.assets.get_mut(&key) {
+            Some(entry) => match entry.handles.upgrade() {
+                Some(entity) => entity,
+                // Every handle was dropped, but the asset hadn't been unloaded yet.
+                None => {
+                    let entity = Arc::new(entry.entity);
+                    entry.handles = Arc::downgrade(&entity);
+                    entity
+                }
+            },
+            None => {
+                let entity = Arc::new(T::spawn(commands, key.1.clone()));
+
+                self.assets.insert(
+                    key,
+                    AssetEntry {
+                        entity: *entity,
+                        handles: Arc::downgrade(&entity),
+                        state: AssetState::Queued,
+                    },
+                );
+
+                entity
+            }
+        };
+
+        Handle {
+            entity,
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn get_state<T>(&self, handle: &Handle<T>) -> Option<AssetState> {
+        self.assets
+            .values()
+            .find(|entry| entry.entity == handle.entity())
+            .map(|entry| entry.state)
+    }
+}
+
+pub struct AssetPlugin;
+
+impl Plugin for AssetPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<AssetReady>();
+        app.insert_resource(AssetServer::default());
+
+        app.add_system(update_asset_states);
+        app.add_system(unload_unused_assets);
+    }
+}
+
+fn update_asset_states(
+    mut server: ResMut<AssetServer>,
+    manager: Res<DownloadManager>,
+    mut ready: EventWriter<AssetReady>,
+    failed_q: Query<(), With<DownloadFailed>>,
+    loaded_q: Query<(), With<components::Model>>,
+    animated_loaded_q: Query<(), With<components::AnimatedModel>>,
+    url_q: Query<(), With<components::ModelUrl>>,
+    animated_url_q: Query<(), With<components::AnimatedModelUrl>>,
+) {
+    for entry in server.assets.values_mut() {
+        let entity = entry.entity;
+
+        let state = if loaded_q.get(entity).is_ok() || animated_loaded_q.get(entity).is_ok() {
+            AssetState::Ready
+        } else if failed_q.get(entity).is_ok() {
+            AssetState::Failed
+        } else if url_q.get(entity).is_ok() || animated_url_q.get(entity).is_ok() {
+            AssetState::Loading
+        } else if manager.progress(entity).is_some() {
+            AssetState::Downloading
+        } else {
+            AssetState::Queued
+        };
+
+        if state == AssetState::Ready && entry.state != AssetState::Ready {
+            ready.send(AssetReady(entity));
+        }
+
+        entry.state = state;
+    }
+}
+
+fn unload_unused_assets(
+    mut commands: Commands,
+    mut server: ResMut<AssetServer>,
+    instance_of_q: Query<&components::InstanceOf>,
+    culled_q: Query<&Culled>,
+) {
+    server.assets.retain(|(_, url), entry| {
+        // Culled instances are still using their models.
+        if entry.handles.strong_count() > 0
+            || instance_of_q
+                .iter()
+                .any(|instance_of| instance_of.0 == entry.entity)
+            || culled_q.iter().any(|culled| culled.0 == entry.entity)
+        {
+            return true;
+        }
+
+        log::info!("Unloading {}", url);
+        commands.entity(entry.entity).despawn();
+        false
+    });
+}
@@ -0,0 +1,258 @@
+use std::collections::{HashMap, HashSet};
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Changed, Commands, Component, Entity, Query, ResMut, With},
+    bevy_ecs::system::CommandQueue,
+    components, renderer_core, url, Vec3,
+};
+
+use crate::downloads::{DownloadFailed, DownloadPriority, QueuedModel};
+use crate::protocol::AvatarProfile;
+use crate::schedule;
+use crate::{
+    AvatarModel, LocalAvatarProfile, LocalPlayer, PlayerState, PlayerStates, RemotePlayer,
+    PLAYER_STATES,
+};
+
+pub const DEFAULT_AVATAR_URL: &str = "http://localhost:8000/assets/models/avatar/squid6.glb";
+pub const MIN_AVATAR_SCALE: f32 = 0.25;
+pub const MAX_AVATAR_SCALE: f32 = 4.0;
+
+pub fn local_profile() -> AvatarProfile {
+    #[cfg(not(feature = "wasm"))]
+    let (model_url, scale) = (
+        std::env::var("AVATAR_URL").ok(),
+        std::env::var("AVATAR_SCALE").ok(),
+    );
+
+    #[cfg(feature = "wasm")]
+    let (model_url, scale) = (
+        crate::query_parameter("avatar"),
+        crate::query_parameter("scale"),
+    );
+
+    AvatarProfile {
+        model_url: model_url.unwrap_or_else(|| DEFAULT_AVATAR_URL.into()),
+        scale: scale.and_then(|scale| scale.parse().ok()).unwrap_or(1.0),
+    }
+}
+
+// Only http(s) glTF files are accepted, and the scale is clamped so that nobody can fill the
+// whole room or shrink out of sight.
+pub fn validate_profile(profile: &AvatarProfile) -> Option<(url::Url, f32)> {
+    let url = url::Url::parse(&profile.model_url).ok()?;
+
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return None;
+    }
+
+    let path = url.path().to_ascii_lowercase();
+
+    if !path.ends_with(".glb") && !path.ends_with(".gltf") {
+        return None;
+    }
+
+    if !profile.scale.is_finite() {
+        return None;
+    }
+
+    Some((url, profile.scale.clamp(MIN_AVATAR_SCALE, MAX_AVATAR_SCALE)))
+}
+
+pub fn spawn_avatar_model(commands: &mut Commands, url: url::Url) -> Entity {
+    commands
+        .spawn()
+        .insert(QueuedModel {
+            url,
+            animated: true,
+            priority: DownloadPriority::Avatar,
+        })
+        .insert(components::Instances(Default::default()))
+        .insert(components::InstanceRange(Default::default()))
+        .id()
+}
+
+pub struct AvatarModels {
+    pub fallback: Entity,
+    models: HashMap<url::Url, Entity>,
+    // Still downloading. Models are only given up on when their download fails, as large
+    // avatars can take a while on slow connections.
+    pending: HashSet<Entity>,
+    failed: HashSet<url::Url>,
+}
+
+impl AvatarModels {
+    pub fn new(fallback: Entity) -> Self {
+        Self {
+            fallback,
+            models: Default::default(),
+            pending: Default::default(),
+            failed: Default::default(),
+        }
+    }
+
+    fn get_or_spawn(&mut self, commands: &mut Commands, url: url::Url) -> Entity {
+        if self.failed.contains(&url) {
+            return self.fallback;
+        }
+
+        let pending = &mut self.pending;
+
+        *self.models.entry(url.clone()).or_insert_with(|| {
+            log::info!("Downloading avatar model {}", url);
+            let entity = spawn_avatar_model(commands, url);
+            pending.insert(entity);
+            entity
+        })
+    }
+}
+
+#[derive(Component)]
+pub struct PendingAvatarProfile(pub AvatarProfile);
+
+// The local player's avatar, plus swapping in the models of avatar profiles as they arrive.
+pub struct AvatarPlugin {
+    pub profile: AvatarProfile,
+}
+
+impl Default for AvatarPlugin {
+    fn default() -> Self {
+        Self {
+            profile: local_profile(),
+        }
+    }
+}
+
+impl Plugin for AvatarPlugin {
+    fn build(&self, app: &mut App) {
+        let fallback_url = url::Url::parse(DEFAULT_AVATAR_URL).unwrap();
+
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+
+        let fallback_avatar = spawn_avatar_model(&mut commands, fallback_url.clone());
+        let mut models = AvatarModels::new(fallback_avatar);
+
+        // The local avatar falls back the same way as everyone else's if it fails to load.
+        let (avatar, avatar_scale) = match validate_profile(&self.profile) {
+            Some((url, scale)) if url != fallback_url => {
+                (models.get_or_spawn(&mut commands, url), scale)
+            }
+            Some((_, scale)) => (fallback_avatar, scale),
+            None => {
+                log::warn!("Invalid local avatar profile {:?}", self.profile);
+                (fallback_avatar, 1.0)
+            }
+        };
+
+        commands
+            .spawn()
+            .insert(components::InstanceOf(avatar))
+            .insert(components::Instance(renderer_core::Instance::new(
+                Vec3::new(0.0, 1.0, -3.0),
+                avatar_scale,
+                Default::default(),
+            )))
+            .insert(components::AnimationState {
+                time: 0.5,
+                animation_index: 5,
+            })
+            .insert(PlayerState(PlayerStates::Idle))
+            .insert(LocalPlayer);
+
+        queue.apply(&mut app.world);
+
+        app.insert_resource(AvatarModel(fallback_avatar));
+        app.insert_resource(models);
+        app.insert_resource(LocalAvatarProfile(self.profile.clone()));
+
+        app.add_system(apply_avatar_profiles);
+        app.add_system(check_avatar_downloads);
+        app.add_system_set(schedule::animation_sync().with_system(sync_animation));
+    }
+}
+
+fn sync_animation(
+    mut anim_q: Query<(&PlayerState, &mut components::AnimationState), Changed<PlayerState>>,
+) {
+    for (p_state, mut anim_state) in anim_q.iter_mut() {
+        anim_state.animation_index = PLAYER_STATES.iter().position(|p| *p == p_state.0).unwrap();
+    }
+}
+
+fn apply_avatar_profiles(
+    mut commands: Commands,
+    mut models: ResMut<AvatarModels>,
+    mut avatar_q: Query<
+        (
+            Entity,
+            &PendingAvatarProfile,
+            &mut components::InstanceOf,
+            &mut components::Instance,
+        ),
+        With<RemotePlayer>,
+    >,
+) {
+    for (entity, profile, mut instance_of, mut instance) in avatar_q.iter_mut() {
+        match validate_profile(&profile.0) {
+            Some((url, scale)) => {
+                instance_of.0 = models.get_or_spawn(&mut commands, url);
+                instance.0.scale = scale;
+            }
+            None => {
+                log::warn!("Rejected avatar profile {:?}", profile.0);
+                instance_of.0 = models.fallback;
+                instance.0.scale = 1.0;
+            }
+        }
+
+        commands.entity(entity).remove::<PendingAvatarProfile>();
+    }
+}
+
+fn check_avatar_downloads(
+    mut commands: Commands,
+    mut models: ResMut<AvatarModels>,
+    loaded_q: Query<(), With<components::AnimatedModel>>,
+    failed_q: Query<(), With<DownloadFailed>>,
+    mut instance_of_q: Query<&mut components::InstanceOf>,
+) {
+    let models = &mut *models;
+    let mut failed = Vec::new();
+
+    models.pending.retain(|&entity| {
+        if loaded_q.get(entity).is_ok() {
+            return false;
+        }
+
+        if failed_q.get(entity).is_ok() {
+            failed.push(entity);
+            return false;
+        }
+
+        true
+    });
+
+    for entity in failed {
+        let url = models
+            .models
+            .iter()
+            .find(|(_, &model)| model == entity)
+            .map(|(url, _)| url.clone());
+
+        if let Some(url) = url {
+            log::warn!("Avatar model {} failed to load, using the fallback", url);
+            models.models.remove(&url);
+            models.failed.insert(url);
+        }
+
+        for mut instance_of in instance_of_q.iter_mut() {
+            if instance_of.0 == entity {
+                instance_of.0 = models.fallback;
+            }
+        }
+
+        commands.entity(entity).despawn();
+    }
+}
@@ -0,0 +1,802 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+
+use futures::{SinkExt, StreamExt};
+use times_square_superconductor::protocol::{
+    self, AvatarProfile, AvatarState, ClientMessage, ObjectId, ObjectTransform, PlayerId, RoomInfo,
+    ServerMessage,
+};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use tokio_tungstenite::tungstenite::Message;
+
+const DEFAULT_ROOM_CAPACITY: usize = 16;
+const MAX_PERSISTED_ROOMS: usize = 64;
+// Players and objects that are already in view stay there until they're this much further
+// than the interest radius, so that they don't flicker in and out at the edge.
+const INTEREST_EXIT_MARGIN: f32 = 1.1;
+
+struct Player {
+    sender: mpsc::UnboundedSender<ServerMessage>,
+    avatar_state: Option<AvatarState>,
+    // The other players that this one has been told about with `PlayerEntered`.
+    players_in_view: HashSet<PlayerId>,
+    objects_in_view: HashSet<ObjectId>,
+    // Set by the player with `SetPlayerValue`, like its name and ping.
+    values: BTreeMap<String, String>,
+}
+
+// Whether something at `position` is close enough to a player to be replicated to them. With
+// no interest radius, everything is. Players that haven't sent their position yet don't see
+// anything else.
+fn in_view(
+    interest_radius: Option<f32>,
+    already_in_view: bool,
+    player: Option<AvatarState>,
+    position: Option<[f32; 3]>,
+) -> bool {
+    let radius = match interest_radius {
+        Some(radius) => radius,
+        None => return true,
+    };
+
+    let (player, position) = match (player, position) {
+        (Some(player), Some(position)) => (player.position, position),
+        _ => return false,
+    };
+
+    let radius = if already_in_view {
+        radius * INTEREST_EXIT_MARGIN
+    } else {
+        radius
+    };
+
+    let distance_squared: f32 = (0..3).map(|i| (player[i] - position[i]).powi(2)).sum();
+
+    distance_squared <= radius * radius
+}
+
+#[derive(Default)]
+struct SharedObject {
+    owner: Option<PlayerId>,
+    transform: Option<ObjectTransform>,
+}
+
+#[derive(Default)]
+struct Room {
+    players: HashMap<PlayerId, Player>,
+    objects: HashMap<ObjectId, SharedObject>,
+    host: Option<PlayerId>,
+    values: BTreeMap<String, String>,
+}
+
+impl Room {
+    // The host is always the lowest player id in the room, so every client can predict who
+    // takes over when the current host disconnects.
+    fn elect_host(&mut self) {
+        let host = self.players.keys().min().copied();
+
+        if host == self.host {
+            return;
+        }
+
+        log::info!("Room host changed from {:?} to {:?}", self.host, host);
+
+        self.host = host;
+
+        broadcast(self, None, ServerMessage::HostChanged { host });
+
+        // Replay the last known state of the world to the new host so that it can carry on
+        // being authoritative for it.
+        if let Some(player) = host.and_then(|host| self.players.get(&host)) {
+            for (&object_id, object) in &self.objects {
+                if let Some(transform) = object.transform {
+                    let _ = player.sender.send(ServerMessage::ObjectTransform {
+                        object_id,
+                        transform,
+                    });
+                }
+            }
+        }
+    }
+}
+
+struct Server {
+    rooms: HashMap<String, Room>,
+    player_rooms: HashMap<PlayerId, String>,
+    avatar_profiles: HashMap<PlayerId, AvatarProfile>,
+    // Object state of rooms that everyone has left, restored when the room is next joined.
+    persisted_rooms: HashMap<String, HashMap<ObjectId, SharedObject>>,
+    room_capacity: usize,
+    // Only players and objects within this distance of a player are replicated to them.
+    interest_radius: Option<f32>,
+    next_player_id: PlayerId,
+}
+
+impl Server {
+    fn new(room_capacity: usize, interest_radius: Option<f32>) -> Self {
+        Self {
+            rooms: Default::default(),
+            player_rooms: Default::default(),
+            avatar_profiles: Default::default(),
+            persisted_rooms: Default::default(),
+            room_capacity,
+            interest_radius,
+            next_player_id: 0,
+        }
+    }
+
+    fn allocate_player_id(&mut self) -> PlayerId {
+        let id = self.next_player_id;
+        self.next_player_id += 1;
+        id
+    }
+
+    fn room_list(&self) -> Vec<RoomInfo> {
+        let mut list: Vec<RoomInfo> = self
+            .rooms
+            .iter()
+            .map(|(name, room)| RoomInfo {
+                name: name.clone(),
+                players: room.players.len(),
+                capacity: self.room_capacity,
+            })
+            .collect();
+
+        list.sort_by(|a, b| a.name.cmp(&b.name));
+        list
+    }
+
+    // Pick the fullest room that still has space so that players end up together,
+    // falling back to a fresh room when everything is full.
+    fn matchmake(&self) -> String {
+        self.rooms
+            .iter()
+            .filter(|(_, room)| room.players.len() < self.room_capacity)
+            .max_by_key(|(name, room)| (room.players.len(), std::cmp::Reverse((*name).clone())))
+            .map(|(name, _)| name.clone())
+            .unwrap_or_else(|| {
+                (0..)
+                    .map(|i| format!("room-{}", i))
+                    .find(|name| !self.rooms.contains_key(name))
+                    .unwrap()
+            })
+    }
+
+    fn handle_message(
+        &mut self,
+        player_id: PlayerId,
+        sender: &mpsc::UnboundedSender<ServerMessage>,
+        message: ClientMessage,
+    ) {
+        match message {
+            ClientMessage::ListRooms => {
+                let _ = sender.send(ServerMessage::RoomList(self.room_list()));
+            }
+            ClientMessage::JoinRoom { room } => self.join_room(player_id, sender, room),
+            ClientMessage::QuickJoin => {
+                let room = self.matchmake();
+                self.join_room(player_id, sender, room);
+            }
+            ClientMessage::LeaveRoom => self.leave_room(player_id),
+            ClientMessage::AvatarState(state) => {
+                if let Some(room) = self.room_of_mut(player_id) {
+                    if let Some(player) = room.players.get_mut(&player_id) {
+                        player.avatar_state = Some(state);
+                    }
+                }
+
+                self.update_interest(player_id);
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    send_to_viewers(
+                        room,
+                        player_id,
+                        ServerMessage::AvatarState { player_id, state },
+                    );
+                }
+            }
+            ClientMessage::AvatarProfile(profile) => {
+                if profile.model_url.len() > protocol::MAX_URL_LENGTH || !profile.scale.is_finite()
+                {
+                    log::warn!("Ignoring invalid avatar profile from player {}", player_id);
+                    return;
+                }
+
+                self.avatar_profiles.insert(player_id, profile.clone());
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    send_to_viewers(
+                        room,
+                        player_id,
+                        ServerMessage::AvatarProfile { player_id, profile },
+                    );
+                }
+            }
+            ClientMessage::Emote { index } => {
+                if let Some(room) = self.room_of_mut(player_id) {
+                    send_to_viewers(room, player_id, ServerMessage::Emote { player_id, index });
+                }
+            }
+            // Ownership is last-writer-wins: a claim always succeeds, stealing the object from
+            // whoever held it before.
+            ClientMessage::ClaimObject { object_id } => {
+                if let Some(room) = self.room_of_mut(player_id) {
+                    room.objects.entry(object_id).or_default().owner = Some(player_id);
+
+                    broadcast(
+                        room,
+                        None,
+                        ServerMessage::ObjectOwner {
+                            object_id,
+                            owner: Some(player_id),
+                        },
+                    );
+                }
+            }
+            ClientMessage::ReleaseObject { object_id } => {
+                if let Some(room) = self.room_of_mut(player_id) {
+                    if let Some(object) = room.objects.get_mut(&object_id) {
+                        if object.owner == Some(player_id) {
+                            object.owner = None;
+
+                            broadcast(
+                                room,
+                                None,
+                                ServerMessage::ObjectOwner {
+                                    object_id,
+                                    owner: None,
+                                },
+                            );
+                        }
+                    }
+                }
+            }
+            ClientMessage::ObjectTransform {
+                object_id,
+                transform,
+            } => {
+                let interest_radius = self.interest_radius;
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    let is_host = room.host == Some(player_id);
+                    let object = room.objects.entry(object_id).or_default();
+
+                    // Unowned objects are simulated by the host.
+                    if object.owner != Some(player_id) && !(object.owner.is_none() && is_host) {
+                        return;
+                    }
+
+                    object.transform = Some(transform);
+
+                    for (&id, player) in &mut room.players {
+                        let already_in_view = player.objects_in_view.contains(&object_id);
+                        let position = Some(transform.position);
+
+                        if !in_view(
+                            interest_radius,
+                            already_in_view,
+                            player.avatar_state,
+                            position,
+                        ) {
+                            player.objects_in_view.remove(&object_id);
+                            continue;
+                        }
+
+                        player.objects_in_view.insert(object_id);
+
+                        if id != player_id {
+                            let _ = player.sender.send(ServerMessage::ObjectTransform {
+                                object_id,
+                                transform,
+                            });
+                        }
+                    }
+                }
+            }
+            ClientMessage::Chat { text } => {
+                let text: String = text.chars().take(protocol::MAX_CHAT_LENGTH).collect();
+
+                if text.trim().is_empty() {
+                    return;
+                }
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    broadcast(room, None, ServerMessage::Chat { player_id, text });
+                }
+            }
+            ClientMessage::SetRoomValue { key, value } => {
+                if !protocol::is_valid_state_value(&key, value.as_deref()) {
+                    log::warn!("Ignoring invalid room value from player {}", player_id);
+                    return;
+                }
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    if !set_value(&mut room.values, &key, &value) {
+                        return;
+                    }
+
+                    broadcast(room, None, ServerMessage::RoomValue { key, value });
+                }
+            }
+            ClientMessage::SetPlayerValue { key, value } => {
+                if !protocol::is_valid_state_value(&key, value.as_deref()) {
+                    log::warn!("Ignoring invalid player value from player {}", player_id);
+                    return;
+                }
+
+                if let Some(room) = self.room_of_mut(player_id) {
+                    let changed = match room.players.get_mut(&player_id) {
+                        Some(player) => set_value(&mut player.values, &key, &value),
+                        None => false,
+                    };
+
+                    if changed {
+                        broadcast(
+                            room,
+                            None,
+                            ServerMessage::PlayerValue {
+                                player_id,
+                                key,
+                                value,
+                            },
+                        );
+                    }
+                }
+            }
+            ClientMessage::Ping { time } => {
+                let _ = sender.send(ServerMessage::Pong { time });
+            }
+        }
+    }
+
+    fn room_of_mut(&mut self, player_id: PlayerId) -> Option<&mut Room> {
+        let name = self.player_rooms.get(&player_id)?;
+        self.rooms.get_mut(name)
+    }
+
+    // Sends `PlayerEntered` and `PlayerExited` as players come into and go out of each other's
+    // interest radius, and the latest transforms of objects that come into view.
+    fn update_interest(&mut self, player_id: PlayerId) {
+        let interest_radius = self.interest_radius;
+        let profiles = &self.avatar_profiles;
+
+        let room = match self
+            .player_rooms
+            .get(&player_id)
+            .and_then(|name| self.rooms.get_mut(name))
+        {
+            Some(room) => room,
+            None => return,
+        };
+
+        let state = match room.players.get(&player_id) {
+            Some(player) => player.avatar_state,
+            None => return,
+        };
+
+        let others: Vec<(PlayerId, Option<AvatarState>)> = room
+            .players
+            .iter()
+            .filter(|(&id, _)| id != player_id)
+            .map(|(&id, player)| (id, player.avatar_state))
+            .collect();
+
+        for (other_id, other_state) in others {
+            let already_in_view = room.players[&player_id].players_in_view.contains(&other_id);
+
+            let position = other_state.map(|state| state.position);
+            let visible = in_view(interest_radius, already_in_view, state, position);
+
+            if visible == already_in_view {
+                continue;
+            }
+
+            for (viewer_id, viewed_id, viewed_state) in [
+                (player_id, other_id, other_state),
+                (other_id, player_id, state),
+            ] {
+                let viewer = room.players.get_mut(&viewer_id).unwrap();
+
+                if !visible {
+                    viewer.players_in_view.remove(&viewed_id);
+                    let _ = viewer.sender.send(ServerMessage::PlayerExited {
+                        player_id: viewed_id,
+                    });
+                    continue;
+                }
+
+                viewer.players_in_view.insert(viewed_id);
+                let _ = viewer.sender.send(ServerMessage::PlayerEntered {
+                    player_id: viewed_id,
+                });
+
+                if let Some(profile) = profiles.get(&viewed_id) {
+                    let _ = viewer.sender.send(ServerMessage::AvatarProfile {
+                        player_id: viewed_id,
+                        profile: profile.clone(),
+                    });
+                }
+
+                if let Some(state) = viewed_state {
+                    let _ = viewer.sender.send(ServerMessage::AvatarState {
+                        player_id: viewed_id,
+                        state,
+                    });
+                }
+            }
+        }
+
+        let player = room.players.get_mut(&player_id).unwrap();
+
+        for (&object_id, object) in &room.objects {
+            let transform = match object.transform {
+                Some(transform) => transform,
+                None => continue,
+            };
+
+            let already_in_view = player.objects_in_view.contains(&object_id);
+            let visible = in_view(
+                interest_radius,
+                already_in_view,
+                state,
+                Some(transform.position),
+            );
+
+            if !visible {
+                player.objects_in_view.remove(&object_id);
+            } else if !already_in_view {
+                player.objects_in_view.insert(object_id);
+                let _ = player.sender.send(ServerMessage::ObjectTransform {
+                    object_id,
+                    transform,
+                });
+            }
+        }
+    }
+
+    fn join_room(
+        &mut self,
+        player_id: PlayerId,
+        sender: &mpsc::UnboundedSender<ServerMessage>,
+        name: String,
+    ) {
+        if !protocol::is_valid_room_name(&name) {
+            let _ = sender.send(ServerMessage::JoinRejected {
+                room: name,
+                reason: "invalid room name".into(),
+            });
+            return;
+        }
+
+        if self.player_rooms.get(&player_id) == Some(&name) {
+            return;
+        }
+
+        let is_full = self
+            .rooms
+            .get(&name)
+            .map(|room| room.players.len() >= self.room_capacity)
+            .unwrap_or(false);
+
+        if is_full {
+            let _ = sender.send(ServerMessage::JoinRejected {
+                room: name,
+                reason: format!("room is full ({} players)", self.room_capacity),
+            });
+            return;
+        }
+
+        self.leave_room(player_id);
+
+        let persisted_rooms = &mut self.persisted_rooms;
+
+        let room = self.rooms.entry(name.clone()).or_insert_with(|| Room {
+            objects: persisted_rooms.remove(&name).unwrap_or_default(),
+            ..Default::default()
+        });
+
+        let _ = sender.send(ServerMessage::Joined {
+            room: name.clone(),
+            player_id,
+        });
+
+        // Bring the new player up to date with everyone already in the room. The avatars of
+        // the ones in view follow with `PlayerEntered`.
+        for (&other_id, other) in &room.players {
+            let _ = sender.send(ServerMessage::PlayerJoined {
+                player_id: other_id,
+            });
+
+            for (key, value) in &other.values {
+                let _ = sender.send(ServerMessage::PlayerValue {
+                    player_id: other_id,
+                    key: key.clone(),
+                    value: Some(value.clone()),
+                });
+            }
+        }
+
+        for (key, value) in &room.values {
+            let _ = sender.send(ServerMessage::RoomValue {
+                key: key.clone(),
+                value: Some(value.clone()),
+            });
+        }
+
+        for (&object_id, object) in &room.objects {
+            let _ = sender.send(ServerMessage::ObjectOwner {
+                object_id,
+                owner: object.owner,
+            });
+
+            if let Some(transform) = object.transform {
+                let _ = sender.send(ServerMessage::ObjectTransform {
+                    object_id,
+                    transform,
+                });
+            }
+        }
+
+        broadcast(room, None, ServerMessage::PlayerJoined { player_id });
+
+        room.players.insert(
+            player_id,
+            Player {
+                sender: sender.clone(),
+                avatar_state: None,
+                players_in_view: HashSet::new(),
+                objects_in_view: HashSet::new(),
+                values: BTreeMap::new(),
+            },
+        );
+
+        let _ = sender.send(ServerMessage::HostChanged { host: room.host });
+        room.elect_host();
+
+        log::info!("Player {} joined room {:?}", player_id, name);
+
+        self.player_rooms.insert(player_id, name);
+        self.update_interest(player_id);
+    }
+
+    fn leave_room(&mut self, player_id: PlayerId) {
+        let name = match self.player_rooms.remove(&player_id) {
+            Some(name) => name,
+            None => return,
+        };
+
+        if let Some(room) = self.rooms.get_mut(&name) {
+            room.players.remove(&player_id);
+
+            for player in room.players.values_mut() {
+                player.players_in_view.remove(&player_id);
+            }
+
+            broadcast(room, None, ServerMessage::PlayerLeft { player_id });
+
+            let released: Vec<ObjectId> = room
+                .objects
+                .iter_mut()
+                .filter(|(_, object)| object.owner == Some(player_id))
+                .map(|(&object_id, object)| {
+                    object.owner = None;
+                    object_id
+                })
+                .collect();
+
+            for object_id in released {
+                broadcast(
+                    room,
+                    None,
+                    ServerMessage::ObjectOwner {
+                        object_id,
+                        owner: None,
+                    },
+                );
+            }
+
+            room.elect_host();
+
+            if room.players.is_empty() {
+                let room = self.rooms.remove(&name).unwrap();
+
+                if !room.objects.is_empty() && self.persisted_rooms.len() < MAX_PERSISTED_ROOMS {
+                    self.persisted_rooms.insert(name.clone(), room.objects);
+                }
+            }
+        }
+
+        log::info!("Player {} left room {:?}", player_id, name);
+    }
+
+    fn disconnect(&mut self, player_id: PlayerId) {
+        self.leave_room(player_id);
+        self.avatar_profiles.remove(&player_id);
+    }
+}
+
+// Returns whether the value changed. New keys are ignored once there are MAX_STATE_KEYS.
+fn set_value(values: &mut BTreeMap<String, String>, key: &str, value: &Option<String>) -> bool {
+    match value {
+        Some(value) if values.get(key) == Some(value) => false,
+        Some(_) if !values.contains_key(key) && values.len() >= protocol::MAX_STATE_KEYS => false,
+        Some(value) => {
+            values.insert(key.to_string(), value.clone());
+            true
+        }
+        None => values.remove(key).is_some(),
+    }
+}
+
+fn broadcast(room: &Room, except: Option<PlayerId>, message: ServerMessage) {
+    for (&id, player) in &room.players {
+        if Some(id) != except {
+            let _ = player.sender.send(message.clone());
+        }
+    }
+}
+
+// Sends to the players that have `player_id` in view, which are the ones it has in view.
+fn send_to_viewers(room: &Room, player_id: PlayerId, message: ServerMessage) {
+    let viewers = match room.players.get(&player_id) {
+        Some(player) => &player.players_in_view,
+        None => return,
+    };
+
+    for id in viewers {
+        if let Some(viewer) = room.players.get(id) {
+            let _ = viewer.sender.send(message.clone());
+        }
+    }
+}
+
+async fn handle_connection(server: Arc<Mutex<Server>>, stream: TcpStream, address: SocketAddr) {
+    let socket = match tokio_tungstenite::accept_async(stream).await {
+        Ok(socket) => socket,
+        Err(error) => {
+            log::warn!("Websocket handshake with {} failed: {}", address, error);
+            return;
+        }
+    };
+
+    let (mut sink, mut stream) = socket.split();
+    let (sender, mut receiver) = mpsc::unbounded_channel::<ServerMessage>();
+
+    let player_id = server.lock().unwrap().allocate_player_id();
+
+    log::info!("{} connected as player {}", address, player_id);
+
+    let send_task = tokio::spawn(async move {
+        while let Some(message) = receiver.recv().await {
+            let text = serde_json::to_string(&message).unwrap();
+
+            if sink.send(Message::Text(text)).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while let Some(message) = stream.next().await {
+        let text = match message {
+            Ok(Message::Text(text)) => text,
+            Ok(Message::Close(_)) | Err(_) => break,
+            Ok(_) => continue,
+        };
+
+        match serde_json::from_str::<ClientMessage>(&text) {
+            Ok(message) => server
+                .lock()
+                .unwrap()
+                .handle_message(player_id, &sender, message),
+            Err(error) => log::warn!("Invalid message from player {}: {}", player_id, error),
+        }
+    }
+
+    server.lock().unwrap().disconnect(player_id);
+    send_task.abort();
+
+    log::info!("Player {} disconnected", player_id);
+}
+
+#[tokio::main]
+async fn main() {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    let mut args = std::env::args().skip(1);
+
+    let address = args
+        .next()
+        .unwrap_or_else(|| format!("0.0.0.0:{}", protocol::DEFAULT_PORT));
+
+    let room_capacity = args
+        .next()
+        .map(|capacity| capacity.parse().expect("Room capacity must be a number"))
+        .unwrap_or(DEFAULT_ROOM_CAPACITY);
+
+    // Unset (or 0) replicates everything in a room to everyone in it.
+    let interest_radius = std::env::var("INTEREST_RADIUS")
+        .ok()
+        .map(|radius| {
+            radius
+                .parse::<f32>()
+                .expect("INTEREST_RADIUS must be a number")
+        })
+        .filter(|radius| *radius > 0.0);
+
+    let listener = TcpListener::bind(&address)
+        .await
+        .unwrap_or_else(|error| panic!("Failed to bind to {}: {}", address, error));
+
+    log::info!(
+        "Listening on {} with a room capacity of {}",
+        address,
+        room_capacity
+    );
+
+    if let Some(radius) = interest_radius {
+        log::info!("Only replicating within {}m of each player", radius);
+    }
+
+    let server = Arc::new(Mutex::new(Server::new(room_capacity, interest_radius)));
+
+    while let Ok((stream, address)) = listener.accept().await {
+        tokio::spawn(handle_connection(server.clone(), stream, address));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn player() -> Player {
+        Player {
+            sender: mpsc::unbounded_channel().0,
+            avatar_state: None,
+            players_in_view: Default::default(),
+            objects_in_view: Default::default(),
+            values: Default::default(),
+        }
+    }
+
+    fn room(players: usize) -> Room {
+        Room {
+            players: (0..players).map(|id| (id as PlayerId, player())).collect(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn empty_server() {
+        assert_eq!(Server::new(4, None).matchmake(), "room-0");
+    }
+
+    #[test]
+    fn fullest_room_with_space() {
+        let mut server = Server::new(4, None);
+        server.rooms.insert("quiet".into(), room(1));
+        server.rooms.insert("busy".into(), room(3));
+        server.rooms.insert("full".into(), room(4));
+
+        assert_eq!(server.matchmake(), "busy");
+    }
+
+    #[test]
+    fn ties_go_to_the_first_name() {
+        let mut server = Server::new(4, None);
+        server.rooms.insert("b".into(), room(2));
+        server.rooms.insert("a".into(), room(2));
+
+        assert_eq!(server.matchmake(), "a");
+    }
+
+    #[test]
+    fn new_room_when_everything_is_full() {
+        let mut server = Server::new(2, None);
+        server.rooms.insert("room-0".into(), room(2));
+        server.rooms.insert("room-2".into(), room(2));
+
+        assert_eq!(server.matchmake(), "room-1");
+    }
+}
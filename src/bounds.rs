@@ -0,0 +1,449 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{
+        ChangeTrackers, Changed, Commands, Component, Entity, Query, ResMut, With, Without,
+    },
+    components, renderer_core,
+    renderer_core::glam::{Mat4, Quat},
+    Vec3,
+};
+
+use crate::downloads::ModelInspected;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive, ProceduralModel};
+use crate::schedule;
+
+const OUTLINE_THICKNESS: f32 = 0.01;
+// Outlines are regenerated when an instance's bounds change size by more than this.
+const OUTLINE_SIZE_TOLERANCE: f32 = 0.01;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Aabb {
+    pub min: Vec3,
+    pub max: Vec3,
+}
+
+impl Aabb {
+    pub fn from_points(points: impl IntoIterator<Item = Vec3>) -> Option<Self> {
+        points.into_iter().fold(None, |aabb: Option<Self>, point| {
+            Some(match aabb {
+                Some(aabb) => Self {
+                    min: aabb.min.min(point),
+                    max: aabb.max.max(point),
+                },
+                None => Self {
+                    min: point,
+                    max: point,
+                },
+            })
+        })
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+
+    pub fn center(&self) -> Vec3 {
+        (self.min + self.max) * 0.5
+    }
+
+    pub fn size(&self) -> Vec3 {
+        self.max - self.min
+    }
+
+    pub fn intersects(&self, other: &Self) -> bool {
+        self.min.cmple(other.max).all() && self.max.cmpge(other.min).all()
+    }
+
+    // The distance along the ray to where it enters the box, or zero if it starts inside.
+    pub fn ray_distance(&self, origin: Vec3, direction: Vec3) -> Option<f32> {
+        let inverse = direction.recip();
+        let a = (self.min - origin) * inverse;
+        let b = (self.max - origin) * inverse;
+
+        let near = a.min(b).max_element().max(0.0);
+        let far = a.max(b).min_element();
+
+        if near <= far {
+            Some(near)
+        } else {
+            None
+        }
+    }
+
+    fn corners(&self) -> [Vec3; 8] {
+        let (min, max) = (self.min, self.max);
+
+        [
+            Vec3::new(min.x, min.y, min.z),
+            Vec3::new(max.x, min.y, min.z),
+            Vec3::new(min.x, max.y, min.z),
+            Vec3::new(max.x, max.y, min.z),
+            Vec3::new(min.x, min.y, max.z),
+            Vec3::new(max.x, min.y, max.z),
+            Vec3::new(min.x, max.y, max.z),
+            Vec3::new(max.x, max.y, max.z),
+        ]
+    }
+
+    // The axis aligned box around the transformed corners, so rotations make it grow.
+    pub fn transformed(&self, transform: Mat4) -> Self {
+        Self::from_points(
+            self.corners()
+                .iter()
+                .map(|&corner| transform.transform_point3(corner)),
+        )
+        .unwrap()
+    }
+}
+
+pub(crate) fn node_transform(node: &Value) -> Mat4 {
+    let floats = |value: &Value| -> Vec<f32> {
+        value
+            .as_array()
+            .map(|array| {
+                array
+                    .iter()
+                    .filter_map(|value| value.as_f64())
+                    .map(|value| value as f32)
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+
+    let matrix = floats(&node["matrix"]);
+
+    if matrix.len() == 16 {
+        return Mat4::from_cols_slice(&matrix);
+    }
+
+    let translation = floats(&node["translation"]);
+    let rotation = floats(&node["rotation"]);
+    let scale = floats(&node["scale"]);
+
+    Mat4::from_scale_rotation_translation(
+        if scale.len() == 3 {
+            Vec3::from_slice(&scale)
+        } else {
+            Vec3::ONE
+        },
+        if rotation.len() == 4 {
+            Quat::from_slice(&rotation)
+        } else {
+            Quat::IDENTITY
+        },
+        if translation.len() == 3 {
+            Vec3::from_slice(&translation)
+        } else {
+            Vec3::ZERO
+        },
+    )
+}
+
+fn mesh_bounds(gltf: &Value, mesh: &Value) -> Option<Aabb> {
+    mesh["primitives"]
+        .as_array()?
+        .iter()
+        .filter_map(|primitive| {
+            let accessor =
+                &gltf["accessors"][primitive["attributes"]["POSITION"].as_u64()? as usize];
+
+            let vec3 = |value: &Value| -> Option<Vec3> {
+                let array = value.as_array()?;
+
+                Some(Vec3::new(
+                    array.get(0)?.as_f64()? as f32,
+                    array.get(1)?.as_f64()? as f32,
+                    array.get(2)?.as_f64()? as f32,
+                ))
+            };
+
+            Some(Aabb {
+                min: vec3(&accessor["min"])?,
+                max: vec3(&accessor["max"])?,
+            })
+        })
+        .reduce(|a, b| a.union(&b))
+}
+
+fn node_bounds(gltf: &Value, index: usize, parent: Mat4, depth: usize) -> Option<Aabb> {
+    // Guards against cycles in broken files.
+    if depth > 64 {
+        return None;
+    }
+
+    let node = &gltf["nodes"][index];
+    let transform = parent * node_transform(node);
+
+    let own = node["mesh"]
+        .as_u64()
+        .and_then(|mesh| mesh_bounds(gltf, &gltf["meshes"][mesh as usize]))
+        .map(|aabb| aabb.transformed(transform));
+
+    let children = node["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|child| node_bounds(gltf, child.as_u64()? as usize, transform, depth + 1));
+
+    own.into_iter().chain(children).reduce(|a, b| a.union(&b))
+}
+
+// Model space bounds, from the position accessors' min and max values and the node
+// transforms of the default scene. Skinning and morph targets aren't taken into account.
+pub fn gltf_bounds(gltf: &Value) -> Option<Aabb> {
+    let scene = gltf["scene"].as_u64().unwrap_or(0) as usize;
+
+    gltf["scenes"][scene]["nodes"]
+        .as_array()?
+        .iter()
+        .filter_map(|node| node_bounds(gltf, node.as_u64()? as usize, Mat4::IDENTITY, 0))
+        .reduce(|a, b| a.union(&b))
+}
+
+// The model space bounds of a model.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ModelBounds(pub Aabb);
+
+// The world space bounds of an instance. Kept up to date for every instance of a model with
+// known bounds.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Bounds(pub Aabb);
+
+pub fn instance_transform(instance: &renderer_core::Instance) -> Mat4 {
+    Mat4::from_scale_rotation_translation(
+        Vec3::splat(instance.scale),
+        instance.rotation,
+        instance.position,
+    )
+}
+
+pub struct BoundsToggled;
+
+// An outline entity for every instance, plus one for the combined bounds of every model.
+#[derive(Default)]
+pub struct BoundsOutlines {
+    pub enabled: bool,
+    outlines: HashMap<Entity, Outline>,
+}
+
+pub(crate) struct Outline {
+    pub model: Entity,
+    pub instance: Entity,
+    pub size: Vec3,
+}
+
+// Marks the outline models and instances, so that they don't get outlines of their own.
+#[derive(Component)]
+pub(crate) struct BoundsOutline;
+
+pub struct BoundsPlugin;
+
+impl Plugin for BoundsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<BoundsToggled>();
+        app.insert_resource(BoundsOutlines::default());
+
+        app.add_system(insert_model_bounds);
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(update_instance_bounds)
+                .with_system(update_bounds_outlines),
+        );
+    }
+}
+
+fn insert_model_bounds(
+    mut commands: Commands,
+    mut inspected: EventReader<ModelInspected>,
+    model_q: Query<(), With<components::Instances>>,
+    procedural_q: Query<(Entity, &ProceduralModel), Without<ModelBounds>>,
+) {
+    // Models that were despawned in the meantime are skipped.
+    for model in inspected.iter() {
+        if model_q.get(model.entity).is_err() {
+            continue;
+        }
+
+        if let Some(aabb) = gltf_bounds(&model.document.json) {
+            commands.entity(model.entity).insert(ModelBounds(aabb));
+        }
+    }
+
+    for (entity, model) in procedural_q.iter() {
+        if let Some(aabb) = Aabb::from_points(model.primitive.mesh().positions) {
+            commands.entity(entity).insert(ModelBounds(aabb));
+        }
+    }
+}
+
+// Only instances that moved or changed model are updated, unless a model's bounds changed.
+// Instances whose model didn't have bounds yet don't have any either, so they're tried again.
+fn update_instance_bounds(
+    mut commands: Commands,
+    model_q: Query<&ModelBounds>,
+    changed_model_q: Query<(), Changed<ModelBounds>>,
+    mut instance_q: Query<(
+        Entity,
+        &components::InstanceOf,
+        &components::Instance,
+        Option<&mut Bounds>,
+        ChangeTrackers<components::Instance>,
+        ChangeTrackers<components::InstanceOf>,
+    )>,
+) {
+    let _span = tracing::info_span!("update_instance_bounds").entered();
+
+    let models_changed = !changed_model_q.is_empty();
+
+    for (entity, instance_of, instance, bounds, instance_tracker, instance_of_tracker) in
+        instance_q.iter_mut()
+    {
+        let changed = models_changed
+            || bounds.is_none()
+            || instance_tracker.is_changed()
+            || instance_of_tracker.is_changed();
+
+        if !changed {
+            continue;
+        }
+
+        let model = match model_q.get(instance_of.0) {
+            Ok(model) => model,
+            Err(_) => continue,
+        };
+
+        let aabb = model.0.transformed(instance_transform(&instance.0));
+
+        match bounds {
+            Some(mut bounds) => bounds.0 = aabb,
+            None => {
+                commands.entity(entity).insert(Bounds(aabb));
+            }
+        }
+    }
+}
+
+pub(crate) fn spawn_outline(commands: &mut Commands, aabb: &Aabb, color: [f32; 3]) -> Outline {
+    let size = aabb.size();
+
+    let model = spawn_primitive(
+        commands,
+        Primitive::BoxOutline {
+            size,
+            thickness: OUTLINE_THICKNESS,
+        },
+        MaterialDescriptor::emissive(color),
+    );
+
+    commands.entity(model).insert(BoundsOutline);
+
+    let instance = commands
+        .spawn()
+        .insert(components::InstanceOf(model))
+        .insert(components::Instance(renderer_core::Instance::new(
+            aabb.center(),
+            1.0,
+            Default::default(),
+        )))
+        .insert(BoundsOutline)
+        .id();
+
+    Outline {
+        model,
+        instance,
+        size,
+    }
+}
+
+fn update_bounds_outlines(
+    mut commands: Commands,
+    mut toggles: EventReader<BoundsToggled>,
+    mut outlines: ResMut<BoundsOutlines>,
+    bounds_q: Query<(Entity, &Bounds, &components::InstanceOf), Without<BoundsOutline>>,
+    mut outline_q: Query<&mut components::Instance, With<BoundsOutline>>,
+) {
+    let _span = tracing::info_span!("update_bounds_outlines").entered();
+
+    for _ in toggles.iter() {
+        outlines.enabled = !outlines.enabled;
+    }
+
+    if !outlines.enabled {
+        for (_, outline) in outlines.outlines.drain() {
+            commands.entity(outline.model).despawn();
+            commands.entity(outline.instance).despawn();
+        }
+
+        return;
+    }
+
+    let mut model_bounds: HashMap<Entity, Aabb> = HashMap::new();
+    let mut wanted: Vec<(Entity, Aabb, [f32; 3])> = Vec::new();
+
+    for (entity, bounds, instance_of) in bounds_q.iter() {
+        wanted.push((entity, bounds.0, [0.0, 1.0, 0.0]));
+
+        model_bounds
+            .entry(instance_of.0)
+            .and_modify(|aabb| *aabb = aabb.union(&bounds.0))
+            .or_insert(bounds.0);
+    }
+
+    // Only worth drawing for models with more than one instance.
+    wanted.extend(
+        model_bounds
+            .into_iter()
+            .filter(|(model, _)| {
+                bounds_q
+                    .iter()
+                    .filter(|(_, _, instance_of)| instance_of.0 == *model)
+                    .count()
+                    > 1
+            })
+            .map(|(model, aabb)| (model, aabb, [1.0, 1.0, 0.0])),
+    );
+
+    let outlines = &mut *outlines;
+
+    outlines.outlines.retain(|entity, outline| {
+        let keep = wanted.iter().any(|(wanted, _, _)| wanted == entity);
+
+        if !keep {
+            commands.entity(outline.model).despawn();
+            commands.entity(outline.instance).despawn();
+        }
+
+        keep
+    });
+
+    for (entity, aabb, color) in wanted {
+        let resized = match outlines.outlines.get(&entity) {
+            Some(outline) => {
+                (outline.size - aabb.size()).abs().max_element() > OUTLINE_SIZE_TOLERANCE
+            }
+            None => true,
+        };
+
+        if resized {
+            if let Some(outline) = outlines.outlines.remove(&entity) {
+                commands.entity(outline.model).despawn();
+                commands.entity(outline.instance).despawn();
+            }
+
+            outlines
+                .outlines
+                .insert(entity, spawn_outline(&mut commands, &aabb, color));
+        } else if let Some(outline) = outlines.outlines.get(&entity) {
+            if let Ok(mut instance) = outline_q.get_mut(outline.instance) {
+                instance.0.position = aabb.center();
+            }
+        }
+    }
+}
@@ -0,0 +1,221 @@
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Local, Res, ResMut},
+    resources::Camera,
+    Mode, Vec3,
+};
+
+use crate::env_setting;
+use crate::input::KeyboardState;
+use crate::laser_pointers::Controllers;
+use crate::schedule::{self, SimulationSteps};
+use crate::settings::{self, Setting};
+use crate::wrist_menu::{WristMenuAction, WristMenuActivated};
+use crate::DELTA_TIME;
+
+const SPEED: f32 = 3.0;
+const LOOK_SENSITIVITY: f32 = 0.1;
+// How long a controller's menu button has to be held down to recenter.
+const RECENTER_HOLD_SECONDS: f32 = 1.0;
+
+// Where the camera starts, and goes back to when recentering.
+#[derive(Clone, Copy, Debug)]
+pub struct SpawnPose {
+    pub position: Vec3,
+    pub yaw_degrees: f32,
+    pub pitch_degrees: f32,
+}
+
+impl Default for SpawnPose {
+    fn default() -> Self {
+        Self {
+            position: Vec3::new(0.0, 1.75, 0.0),
+            yaw_degrees: 0.0,
+            pitch_degrees: 0.0,
+        }
+    }
+}
+
+// Darkens the edges of the view while moving or turning, which helps with motion sickness in
+// VR. Turning at a radian per second counts the same as moving at a meter per second.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct ComfortVignette {
+    // How much of the view is covered at full strength, from 0 to 1.
+    pub intensity: f32,
+    // The speed where the vignette starts to close in, in meters per second.
+    pub onset: f32,
+}
+
+impl Default for ComfortVignette {
+    fn default() -> Self {
+        Self {
+            intensity: 0.6,
+            onset: 0.5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ControlSettings {
+    pub comfort_vignette: Option<ComfortVignette>,
+}
+
+impl Setting for ControlSettings {
+    const KEY: &'static str = "controls";
+}
+
+impl ControlSettings {
+    // The vignette is on by default in VR. COMFORT_VIGNETTE (the intensity, 0 turns it off) and
+    // COMFORT_VIGNETTE_ONSET on desktop, or ?comfort_vignette= and ?comfort_vignette_onset= on
+    // the web.
+    pub fn from_env(mode: Mode) -> Self {
+        let defaults = ComfortVignette::default();
+
+        let parse = |desktop: &str, web: &str| -> Option<f32> {
+            env_setting(desktop, web).and_then(|value| match value.parse() {
+                Ok(value) => Some(value),
+                Err(_) => {
+                    log::warn!("Invalid {} {:?}", desktop, value);
+                    None
+                }
+            })
+        };
+
+        let intensity = parse("COMFORT_VIGNETTE", "comfort_vignette").or_else(|| {
+            if matches!(mode, Mode::Vr) {
+                Some(defaults.intensity)
+            } else {
+                None
+            }
+        });
+
+        let comfort_vignette = match intensity {
+            Some(intensity) if intensity > 0.0 => Some(ComfortVignette {
+                intensity: intensity.min(1.0),
+                onset: parse("COMFORT_VIGNETTE_ONSET", "comfort_vignette_onset")
+                    .unwrap_or(defaults.onset)
+                    .max(0.0),
+            }),
+            _ => None,
+        };
+
+        Self { comfort_vignette }
+    }
+}
+
+// Also sent by the R key, the wrist menu and holding down a controller's menu button.
+pub struct RecenterRequested;
+
+pub struct Recentered;
+
+// A free flying camera, driven by the keyboard state when the input plugin is added.
+// Recentering puts the camera rig back at the spawn pose. The renderer's XR plugin doesn't
+// expose its reference space, so the rig is what gets reset in XR as well.
+#[derive(Default)]
+pub struct CameraControlPlugin {
+    pub spawn: SpawnPose,
+    pub controls: ControlSettings,
+}
+
+impl Plugin for CameraControlPlugin {
+    fn build(&self, app: &mut App) {
+        let camera_rig: dolly::rig::CameraRig = dolly::rig::CameraRig::builder()
+            .with(dolly::drivers::Position::new(self.spawn.position))
+            .with(
+                dolly::drivers::YawPitch::new()
+                    .yaw_degrees(self.spawn.yaw_degrees)
+                    .pitch_degrees(self.spawn.pitch_degrees),
+            )
+            .build();
+
+        app.insert_resource(camera_rig);
+        app.insert_resource(self.spawn);
+        app.insert_resource(self.controls);
+        settings::persist::<ControlSettings>(app);
+        app.add_event::<RecenterRequested>();
+        app.add_event::<Recentered>();
+
+        app.add_system_set(schedule::input().with_system(recenter));
+        app.add_system_set(schedule::camera().with_system(update_camera));
+    }
+}
+
+fn recenter(
+    mut requests: EventReader<RecenterRequested>,
+    mut wrist_menu: EventReader<WristMenuActivated>,
+    mut recentered: EventWriter<Recentered>,
+    mut held: Local<Vec<f32>>,
+    controllers: Res<Controllers>,
+    spawn: Res<SpawnPose>,
+    mut camera_rig: ResMut<dolly::rig::CameraRig>,
+) {
+    let mut requested = requests.iter().count() > 0;
+
+    requested |= wrist_menu
+        .iter()
+        .any(|WristMenuActivated(action)| *action == WristMenuAction::Recenter);
+
+    held.resize(controllers.0.len(), 0.0);
+
+    for (held, pose) in held.iter_mut().zip(&controllers.0) {
+        match pose {
+            Some(pose) if pose.menu_button => {
+                let before = *held;
+                *held += DELTA_TIME;
+
+                // Only once per press, when the button has been held for long enough.
+                requested |= before < RECENTER_HOLD_SECONDS && *held >= RECENTER_HOLD_SECONDS;
+            }
+            _ => *held = 0.0,
+        }
+    }
+
+    if !requested {
+        return;
+    }
+
+    camera_rig.driver_mut::<dolly::drivers::Position>().position = spawn.position;
+
+    let yaw_pitch = camera_rig.driver_mut::<dolly::drivers::YawPitch>();
+    yaw_pitch.yaw_degrees = spawn.yaw_degrees;
+    yaw_pitch.pitch_degrees = spawn.pitch_degrees;
+
+    recentered.send(Recentered);
+}
+
+// Moves as far as the simulation stepped this frame, so that walking around keeps its speed
+// while the frame rate is capped.
+fn update_camera(
+    keyboard_state: Option<Res<KeyboardState>>,
+    steps: Option<Res<SimulationSteps>>,
+    mut camera: ResMut<Camera>,
+    mut camera_rig: ResMut<dolly::rig::CameraRig>,
+) {
+    let _span = tracing::info_span!("update_camera").entered();
+
+    let delta = DELTA_TIME * steps.map_or(1, |steps| steps.0) as f32;
+
+    if let Some(keyboard_state) = keyboard_state {
+        let look = -LOOK_SENSITIVITY * keyboard_state.look_delta;
+
+        camera_rig
+            .driver_mut::<dolly::drivers::YawPitch>()
+            .rotate_yaw_pitch(look.x, look.y);
+
+        let forwards = keyboard_state.forwards as i32 - keyboard_state.backwards as i32;
+        let right = keyboard_state.right as i32 - keyboard_state.left as i32;
+
+        let move_vec = camera_rig.final_transform.rotation
+            * Vec3::new(right as f32, 0.0, -forwards as f32).clamp_length_max(1.0);
+
+        camera_rig
+            .driver_mut::<dolly::drivers::Position>()
+            .translate(move_vec * delta * SPEED);
+    }
+
+    camera_rig.update(delta);
+
+    camera.position = camera_rig.final_transform.position;
+    camera.rotation = camera_rig.final_transform.rotation;
+}
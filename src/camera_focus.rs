@@ -0,0 +1,110 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Component, Query, Res, ResMut, With, Without},
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::bounds::{Aabb, Bounds};
+use crate::schedule;
+use crate::{LocalPlayer, DELTA_TIME};
+
+// Matches the renderer's default vertical field of view.
+const FIELD_OF_VIEW: f32 = 59.0 * std::f32::consts::PI / 180.0;
+const FRAME_SECONDS: f32 = 0.5;
+const MIN_FRAME_DISTANCE: f32 = 0.5;
+
+// Marks the entities that the camera should frame. When nothing is selected, the whole scene
+// is framed instead.
+#[derive(Component)]
+pub struct Selected;
+
+pub struct FrameRequested;
+
+#[derive(Default)]
+struct CameraFlight {
+    from: Vec3,
+    to: Vec3,
+    // None when the camera isn't moving.
+    elapsed: Option<f32>,
+}
+
+pub struct CameraFocusPlugin;
+
+impl Plugin for CameraFocusPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<FrameRequested>();
+        app.insert_resource(CameraFlight::default());
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(start_framing)
+                .with_system(fly_camera),
+        );
+    }
+}
+
+// How far back the camera has to be for a sphere around the bounds to fill the view.
+pub(crate) fn framing_distance(aabb: &Aabb) -> f32 {
+    let radius = aabb.size().length() * 0.5;
+    (radius / (FIELD_OF_VIEW * 0.5).sin()).max(MIN_FRAME_DISTANCE)
+}
+
+fn start_framing(
+    mut requests: EventReader<FrameRequested>,
+    mut flight: ResMut<CameraFlight>,
+    camera_rig: Res<dolly::rig::CameraRig>,
+    selected_q: Query<&Bounds, With<Selected>>,
+    scene_q: Query<&Bounds, Without<LocalPlayer>>,
+) {
+    if requests.iter().count() == 0 {
+        return;
+    }
+
+    let union = |bounds: &mut dyn Iterator<Item = &Bounds>| {
+        bounds.map(|bounds| bounds.0).reduce(|a, b| a.union(&b))
+    };
+
+    let aabb = match union(&mut selected_q.iter()).or_else(|| union(&mut scene_q.iter())) {
+        Some(aabb) => aabb,
+        None => {
+            log::info!("Nothing to frame");
+            return;
+        }
+    };
+
+    // The camera keeps looking in the same direction and only moves.
+    let forward = camera_rig.final_transform.rotation * -Vec3::Z;
+
+    flight.from = camera_rig.final_transform.position;
+    flight.to = aabb.center() - forward * framing_distance(&aabb);
+    flight.elapsed = Some(0.0);
+}
+
+fn fly_camera(
+    mut flight: ResMut<CameraFlight>,
+    mut camera_rig: ResMut<dolly::rig::CameraRig>,
+    accessibility: Res<AccessibilitySettings>,
+) {
+    let elapsed = match &mut flight.elapsed {
+        Some(elapsed) => elapsed,
+        None => return,
+    };
+
+    *elapsed = if accessibility.reduced_motion {
+        FRAME_SECONDS
+    } else {
+        (*elapsed + DELTA_TIME).min(FRAME_SECONDS)
+    };
+
+    let t = *elapsed / FRAME_SECONDS;
+    let done = t >= 1.0;
+    // Smoothstep, so that the camera eases in and out.
+    let t = t * t * (3.0 - 2.0 * t);
+
+    camera_rig.driver_mut::<dolly::drivers::Position>().position = flight.from.lerp(flight.to, t);
+
+    if done {
+        flight.elapsed = None;
+    }
+}
@@ -0,0 +1,269 @@
+use std::collections::VecDeque;
+
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+    resources::Camera,
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::i18n::Locale;
+use crate::protocol::PlayerId;
+use crate::sequencer::Sequencer;
+use crate::{schedule, DELTA_TIME};
+
+const MAX_CAPTIONS: usize = 3;
+// Captions stay up for a base time plus a little per character, so longer ones can be read.
+const BASE_SECONDS: f32 = 2.0;
+const SECONDS_PER_CHARACTER: f32 = 0.06;
+const MAX_SECONDS: f32 = 8.0;
+// Sounds closer than this are all around the player, so they get no direction.
+const MIN_DIRECTION_DISTANCE: f32 = 0.5;
+
+#[derive(Clone, Debug)]
+pub enum CaptionSource {
+    // A transcription of something a player said. The playground has no voice chat of its own,
+    // so these come from apps that add voice chat and a transcription service.
+    Speech(PlayerId),
+    // A sound effect, such as "footsteps nearby", with an arrow towards where it came from if it
+    // came from somewhere in the scene. There's no audio yet, so these also come from apps.
+    Sound(Option<Vec3>),
+}
+
+// Shows a caption, if captions are turned on in the accessibility settings. Cutscene dialogue
+// is captioned from the sequencer's dialogue tracks instead.
+pub struct CaptionRequested {
+    pub source: CaptionSource,
+    pub text: String,
+}
+
+struct Caption {
+    source: CaptionSource,
+    text: String,
+    remaining: f32,
+}
+
+// The captions on screen, oldest first.
+#[derive(Default)]
+pub struct Captions {
+    queue: VecDeque<Caption>,
+    dialogue: Option<String>,
+    lines: Vec<String>,
+}
+
+impl Captions {
+    pub fn lines(&self) -> &[String] {
+        &self.lines
+    }
+}
+
+pub struct CaptionPlugin;
+
+impl Plugin for CaptionPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<CaptionRequested>();
+        app.insert_resource(Captions::default());
+
+        app.add_system_set(schedule::pre_render().with_system(update_captions));
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(dom::CaptionDom::new());
+            app.add_system_set(schedule::pre_render().with_system(dom::update_caption_dom));
+        }
+    }
+}
+
+fn display_seconds(text: &str) -> f32 {
+    (BASE_SECONDS + text.chars().count() as f32 * SECONDS_PER_CHARACTER).min(MAX_SECONDS)
+}
+
+// An arrow pointing from the camera's view towards a position.
+fn direction_arrow(camera: &Camera, position: Vec3) -> &'static str {
+    let local = camera.rotation.inverse() * (position - camera.position);
+
+    if local.length() < MIN_DIRECTION_DISTANCE {
+        return "";
+    }
+
+    let angle = local.x.atan2(-local.z).to_degrees();
+
+    if angle.abs() <= 45.0 {
+        "↑"
+    } else if angle.abs() >= 135.0 {
+        "↓"
+    } else if angle > 0.0 {
+        "→"
+    } else {
+        "←"
+    }
+}
+
+fn format_caption(caption: &Caption, locale: &Locale, camera: &Camera) -> String {
+    match &caption.source {
+        CaptionSource::Speech(player) => {
+            let speaker = locale.format("caption.player", &[("player", player)]);
+
+            locale.format(
+                "caption.speech",
+                &[("speaker", &speaker), ("text", &caption.text)],
+            )
+        }
+        CaptionSource::Sound(position) => {
+            let direction = position.map_or("", |position| direction_arrow(camera, position));
+
+            locale
+                .format(
+                    "caption.sound",
+                    &[("direction", &direction), ("text", &caption.text)],
+                )
+                .trim()
+                .to_string()
+        }
+    }
+}
+
+fn update_captions(
+    mut requests: EventReader<CaptionRequested>,
+    mut captions: ResMut<Captions>,
+    accessibility: Res<AccessibilitySettings>,
+    locale: Res<Locale>,
+    sequencer: Res<Sequencer>,
+    camera: Res<Camera>,
+) {
+    if !accessibility.captions {
+        for _ in requests.iter() {}
+
+        if !captions.lines.is_empty() {
+            *captions = Captions::default();
+        }
+
+        return;
+    }
+
+    let captions = &mut *captions;
+
+    for request in requests.iter() {
+        let caption = Caption {
+            source: request.source.clone(),
+            text: request.text.clone(),
+            remaining: display_seconds(&request.text),
+        };
+
+        // There's no text rendering on desktop or in XR, so captions are logged there instead.
+        #[cfg(not(feature = "wasm"))]
+        log::info!("{}", format_caption(&caption, &locale, &camera));
+
+        captions.queue.push_back(caption);
+
+        while captions.queue.len() > MAX_CAPTIONS {
+            captions.queue.pop_front();
+        }
+    }
+
+    for caption in &mut captions.queue {
+        caption.remaining -= DELTA_TIME;
+    }
+
+    captions.queue.retain(|caption| caption.remaining > 0.0);
+
+    let dialogue = sequencer.dialogue().map(|line| {
+        locale.format(
+            "caption.speech",
+            &[("speaker", &line.speaker), ("text", &line.text)],
+        )
+    });
+
+    if dialogue != captions.dialogue {
+        #[cfg(not(feature = "wasm"))]
+        if let Some(dialogue) = &dialogue {
+            log::info!("{}", dialogue);
+        }
+
+        captions.dialogue = dialogue;
+    }
+
+    captions.lines = captions
+        .dialogue
+        .clone()
+        .into_iter()
+        .chain(
+            captions
+                .queue
+                .iter()
+                .map(|caption| format_caption(caption, &locale, &camera)),
+        )
+        .collect();
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use superconductor::bevy_ecs::prelude::{NonSendMut, Res};
+
+    use super::Captions;
+    use crate::accessibility::AccessibilitySettings;
+    use crate::chat::dom::create_element;
+
+    const STYLE: &str = "position: fixed; left: 50%; bottom: 80px; \
+                         transform: translate(-50%, 0); max-width: 80%; text-align: center; \
+                         color: white; background: rgba(0, 0, 0, 0.6); padding: 4px 8px; \
+                         font-family: sans-serif; pointer-events: none;";
+
+    pub struct CaptionDom {
+        element: web_sys::HtmlElement,
+        shown: String,
+    }
+
+    impl CaptionDom {
+        pub fn new() -> Self {
+            let element = create_element("div");
+
+            web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .append_child(&element)
+                .unwrap();
+
+            element.set_attribute("style", "display: none;").unwrap();
+
+            Self {
+                element,
+                shown: String::new(),
+            }
+        }
+    }
+
+    pub fn update_caption_dom(
+        mut dom: NonSendMut<CaptionDom>,
+        captions: Res<Captions>,
+        accessibility: Res<AccessibilitySettings>,
+    ) {
+        // The captions are rebuilt every frame, so they're compared with what's shown instead
+        // of going by change detection.
+        let text = captions.lines().join("\n");
+
+        if text == dom.shown && !accessibility.is_changed() {
+            return;
+        }
+
+        if text.is_empty() {
+            dom.element
+                .set_attribute("style", "display: none;")
+                .unwrap();
+        } else {
+            dom.element.set_inner_text(&text);
+            dom.element
+                .set_attribute(
+                    "style",
+                    &format!("{} {}", STYLE, accessibility.overlay_text_style()),
+                )
+                .unwrap();
+        }
+
+        dom.shown = text;
+    }
+}
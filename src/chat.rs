@@ -0,0 +1,291 @@
+use std::collections::{HashMap, VecDeque};
+
+use crossbeam_channel::Receiver;
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, ResMut, With},
+    components, Vec3,
+};
+
+use crate::networking::{NetClient, RemotePlayers};
+use crate::protocol::{ClientMessage, PlayerId, ServerMessage};
+use crate::schedule;
+use crate::text::TextLabel;
+use crate::{LocalPlayer, DELTA_TIME};
+
+const MAX_LOG_ENTRIES: usize = 50;
+const BUBBLE_SECONDS: f32 = 5.0;
+// Above the speaker's feet, in meters.
+const BUBBLE_HEIGHT: f32 = 2.2;
+
+pub struct ChatEntry {
+    pub player_id: PlayerId,
+    pub text: String,
+}
+
+#[derive(Default)]
+pub struct ChatLog {
+    pub entries: VecDeque<ChatEntry>,
+}
+
+#[derive(Component)]
+pub struct SpeechBubble {
+    pub text: String,
+    pub remaining: f32,
+}
+
+struct ChatInput(Receiver<String>);
+
+// The label entity showing each speaker's bubble.
+#[derive(Default)]
+struct BubbleLabels(HashMap<Entity, Entity>);
+
+pub struct ChatPlugin;
+
+impl Plugin for ChatPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        #[cfg(not(feature = "wasm"))]
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lines() {
+                let line = match line {
+                    Ok(line) => line,
+                    Err(_) => break,
+                };
+
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        #[cfg(feature = "wasm")]
+        {
+            app.init_resource::<crate::i18n::Locale>();
+            let locale = app.world.get_resource::<crate::i18n::Locale>().unwrap();
+            let dom = dom::ChatDom::new(sender, locale);
+            app.insert_non_send_resource(dom);
+        }
+
+        app.insert_resource(ChatInput(receiver));
+        app.insert_resource(ChatLog::default());
+        app.insert_resource(BubbleLabels::default());
+
+        app.add_system(send_chat_input);
+        app.add_system(receive_chat_messages);
+        app.add_system(tick_speech_bubbles);
+        app.add_system_set(schedule::pre_render().with_system(update_bubble_labels));
+
+        #[cfg(feature = "wasm")]
+        app.add_system(dom::update_chat_dom);
+    }
+}
+
+fn send_chat_input(input: Res<ChatInput>, client: Res<NetClient>) {
+    for text in input.0.try_iter() {
+        let text = text.trim();
+
+        if !text.is_empty() {
+            client.send(ClientMessage::Chat { text: text.into() });
+        }
+    }
+}
+
+fn receive_chat_messages(
+    mut commands: Commands,
+    mut server_messages: EventReader<ServerMessage>,
+    mut chat_log: ResMut<ChatLog>,
+    client: Res<NetClient>,
+    remote_players: Res<RemotePlayers>,
+    local_q: Query<Entity, With<LocalPlayer>>,
+) {
+    for message in server_messages.iter() {
+        let (player_id, text) = match message {
+            ServerMessage::Chat { player_id, text } => (*player_id, text),
+            _ => continue,
+        };
+
+        log::info!("<player {}> {}", player_id, text);
+
+        let speaker = if client.player_id == Some(player_id) {
+            local_q.get_single().ok()
+        } else {
+            remote_players.0.get(&player_id).copied()
+        };
+
+        if let Some(speaker) = speaker {
+            commands.entity(speaker).insert(SpeechBubble {
+                text: text.clone(),
+                remaining: BUBBLE_SECONDS,
+            });
+        }
+
+        chat_log.entries.push_back(ChatEntry {
+            player_id,
+            text: text.clone(),
+        });
+
+        while chat_log.entries.len() > MAX_LOG_ENTRIES {
+            chat_log.entries.pop_front();
+        }
+    }
+}
+
+fn tick_speech_bubbles(mut commands: Commands, mut bubble_q: Query<(Entity, &mut SpeechBubble)>) {
+    for (entity, mut bubble) in bubble_q.iter_mut() {
+        bubble.remaining -= DELTA_TIME;
+
+        if bubble.remaining <= 0.0 {
+            commands.entity(entity).remove::<SpeechBubble>();
+        }
+    }
+}
+
+// Shows each bubble as a text label above its speaker, drawn in the scene so that it works
+// on desktop and in XR as well as on the web.
+fn update_bubble_labels(
+    mut commands: Commands,
+    mut labels: ResMut<BubbleLabels>,
+    bubble_q: Query<(Entity, &SpeechBubble, &components::Instance)>,
+    mut label_q: Query<&mut TextLabel>,
+) {
+    labels.0.retain(|&speaker, &mut label| {
+        let keep = bubble_q.get(speaker).is_ok();
+
+        if !keep {
+            commands.entity(label).despawn();
+        }
+
+        keep
+    });
+
+    for (speaker, bubble, instance) in bubble_q.iter() {
+        let position = instance.0.position + Vec3::new(0.0, BUBBLE_HEIGHT, 0.0);
+
+        let label = match labels.0.get(&speaker) {
+            Some(&label) => label,
+            None => {
+                let label = commands
+                    .spawn()
+                    .insert(TextLabel::new(bubble.text.clone(), position))
+                    .id();
+                labels.0.insert(speaker, label);
+                continue;
+            }
+        };
+
+        if let Ok(mut label) = label_q.get_mut(label) {
+            // Only touched when something changed, so that the texture isn't drawn again.
+            if label.text != bubble.text {
+                label.text = bubble.text.clone();
+            }
+
+            if label.position != position {
+                label.position = position;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+pub(crate) mod dom {
+    use crossbeam_channel::Sender;
+    use superconductor::bevy_ecs::prelude::{NonSendMut, Res};
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    use super::ChatLog;
+    use crate::accessibility::AccessibilitySettings;
+    use crate::i18n::Locale;
+
+    const LOG_STYLE: &str = "position: fixed; left: 8px; bottom: 40px; width: 320px; \
+                             max-height: 200px; overflow-y: auto; color: white; \
+                             font-family: sans-serif; text-shadow: 0 0 2px black; \
+                             pointer-events: none;";
+    const INPUT_STYLE: &str = "position: fixed; left: 8px; bottom: 8px; width: 320px;";
+
+    pub struct ChatDom {
+        log: web_sys::HtmlElement,
+        input: web_sys::HtmlInputElement,
+    }
+
+    impl ChatDom {
+        pub fn new(sender: Sender<String>, locale: &Locale) -> Self {
+            let document = web_sys::window().unwrap().document().unwrap();
+            let body = document.body().unwrap();
+
+            // Styled by `update_chat_dom`, which also follows the accessibility settings.
+            let log = create_element("div");
+            let input: web_sys::HtmlInputElement = create_element("input").unchecked_into();
+            input.set_placeholder(locale.text("chat.placeholder"));
+
+            let onkeydown = {
+                let input = input.clone();
+
+                Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+                    if event.key() == "Enter" {
+                        let _ = sender.send(input.value());
+                        input.set_value("");
+                    }
+                }) as Box<dyn FnMut(web_sys::KeyboardEvent)>)
+            };
+
+            input.set_onkeydown(Some(onkeydown.as_ref().unchecked_ref()));
+            onkeydown.forget();
+
+            body.append_child(&log).unwrap();
+            body.append_child(&input).unwrap();
+
+            Self { log, input }
+        }
+    }
+
+    pub(crate) fn create_element(tag: &str) -> web_sys::HtmlElement {
+        web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element(tag)
+            .unwrap()
+            .unchecked_into()
+    }
+
+    pub fn update_chat_dom(
+        mut dom: NonSendMut<ChatDom>,
+        chat_log: Res<ChatLog>,
+        locale: Res<Locale>,
+        accessibility: Res<AccessibilitySettings>,
+    ) {
+        let text_style = accessibility.overlay_text_style();
+
+        if accessibility.is_changed() {
+            dom.log
+                .set_attribute("style", &format!("{} {}", LOG_STYLE, text_style))
+                .unwrap();
+            dom.input
+                .set_attribute("style", &format!("{} {}", INPUT_STYLE, text_style))
+                .unwrap();
+        }
+
+        if locale.is_changed() {
+            dom.input.set_placeholder(locale.text("chat.placeholder"));
+        }
+
+        if chat_log.is_changed() || locale.is_changed() {
+            let text = chat_log
+                .entries
+                .iter()
+                .map(|entry| {
+                    locale.format(
+                        "chat.line",
+                        &[("player", &entry.player_id), ("text", &entry.text)],
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            dom.log.set_inner_text(&text);
+            dom.log.set_scroll_top(dom.log.scroll_height());
+        }
+    }
+}
@@ -0,0 +1,419 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, ResMut, With, Without, World},
+    components, renderer_core,
+    renderer_core::glam::{Mat4, Quat},
+    Vec3,
+};
+
+use crate::bounds::{instance_transform, Bounds};
+use crate::console::Console;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+use crate::shared_physics::GRAVITY;
+use crate::skeletons::ModelSkeleton;
+use crate::time_scale::TimeScale;
+use crate::wind::WindField;
+use crate::LocalPlayer;
+
+// Passes over the links per step. More make the cloth stiffer.
+const ITERATIONS: usize = 4;
+
+// Where a cloth hangs from, in the model space of its anchor entity.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AnchorPoint {
+    Offset(Vec3),
+    // Where the named joint is in the model's rest pose. Bones can't be read back from the
+    // renderer, so the cloth follows the instance rather than the animated joint.
+    Joint(String),
+}
+
+// A lightweight cloth or chain hanging from an entity, like a cape or a tentacle. It's a grid
+// of particles held together by links, with the top row pinned to the anchor, simulated with
+// verlet integration. Each link is drawn as a thin capsule.
+#[derive(Component, Clone, Debug)]
+pub struct Cloth {
+    pub anchor: Entity,
+    pub point: AnchorPoint,
+    // The top row is spread along the anchor's x axis, and the rest hang below it. One column
+    // makes a chain.
+    pub columns: usize,
+    pub rows: usize,
+    // Between neighbouring particles, in meters.
+    pub spacing: f32,
+    pub thickness: f32,
+    pub color: [f32; 3],
+    // The fraction of the velocity lost per second.
+    pub damping: f32,
+    // Added to the global wind, like for a draft that only this cloth is in, and how strongly
+    // the air drags the cloth along.
+    pub wind: Vec3,
+    pub drag: f32,
+}
+
+impl Cloth {
+    pub fn tentacle(anchor: Entity, offset: Vec3) -> Self {
+        Self {
+            anchor,
+            point: AnchorPoint::Offset(offset),
+            columns: 1,
+            rows: 8,
+            spacing: 0.06,
+            thickness: 0.025,
+            color: [0.9, 0.35, 0.45],
+            damping: 1.5,
+            wind: Vec3::ZERO,
+            drag: 0.5,
+        }
+    }
+
+    pub fn cape(anchor: Entity, offset: Vec3) -> Self {
+        Self {
+            anchor,
+            point: AnchorPoint::Offset(offset),
+            columns: 5,
+            rows: 7,
+            spacing: 0.1,
+            thickness: 0.015,
+            color: [0.6, 0.1, 0.15],
+            damping: 1.0,
+            wind: Vec3::ZERO,
+            drag: 1.0,
+        }
+    }
+
+    fn links(&self) -> Vec<(usize, usize)> {
+        let index = |column: usize, row: usize| row * self.columns + column;
+        let mut links = Vec::new();
+
+        for row in 0..self.rows {
+            for column in 0..self.columns {
+                if column + 1 < self.columns {
+                    links.push((index(column, row), index(column + 1, row)));
+                }
+
+                if row + 1 < self.rows {
+                    links.push((index(column, row), index(column, row + 1)));
+                }
+            }
+        }
+
+        links
+    }
+
+    // Where the particle would hang at rest, relative to the anchor point in model space.
+    fn rest_offset(&self, particle: usize) -> Vec3 {
+        let (column, row) = (particle % self.columns, particle / self.columns);
+        let width = (self.columns - 1) as f32 * self.spacing;
+
+        Vec3::new(
+            column as f32 * self.spacing - width * 0.5,
+            -(row as f32) * self.spacing,
+            0.0,
+        )
+    }
+}
+
+// Spheres around an anchor, in its model space, that its cloths can't pass through. Anchors
+// without any use a sphere around the middle of their bounds.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ClothColliders(pub Vec<(Vec3, f32)>);
+
+struct ClothState {
+    model: Entity,
+    particles: Vec<Vec3>,
+    previous: Vec<Vec3>,
+    links: Vec<(usize, usize)>,
+    segments: Vec<Entity>,
+}
+
+#[derive(Default)]
+struct ClothSimulations {
+    cloths: HashMap<Entity, ClothState>,
+}
+
+pub struct ClothPlugin;
+
+impl Plugin for ClothPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ClothSimulations::default());
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register("cloth", "cloth <tentacles | cape | clear>", cloth_command);
+
+        app.add_system_set(schedule::simulation().with_system(simulate_cloth));
+    }
+}
+
+// Dresses the local player up, for trying the simulation out.
+fn cloth_command(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let player = world
+        .query_filtered::<Entity, With<LocalPlayer>>()
+        .iter(world)
+        .next()
+        .ok_or("No local player")?;
+
+    let cloths: Vec<Cloth> = match args.get(0).copied() {
+        Some("tentacles") => (0..6)
+            .map(|index| {
+                let angle = index as f32 / 6.0 * std::f32::consts::TAU;
+                let offset = Vec3::new(angle.cos() * 0.12, 0.45, angle.sin() * 0.12);
+                Cloth::tentacle(player, offset)
+            })
+            .collect(),
+        Some("cape") => vec![Cloth::cape(player, Vec3::new(0.0, 1.4, -0.12))],
+        Some("clear") => Vec::new(),
+        _ => return Err("Expected tentacles, cape or clear".into()),
+    };
+
+    let existing: Vec<Entity> = world
+        .query::<(Entity, &Cloth)>()
+        .iter(world)
+        .filter(|(_, cloth)| cloth.anchor == player)
+        .map(|(entity, _)| entity)
+        .collect();
+
+    for entity in existing {
+        world.despawn(entity);
+    }
+
+    for cloth in cloths {
+        world.spawn().insert(cloth);
+    }
+
+    Ok(String::new())
+}
+
+// The anchor's transform, the cloth's anchor point in its model space, and the spheres the
+// cloth collides with in world space.
+fn anchor_transform(
+    cloth: &Cloth,
+    instance: &components::Instance,
+    skeleton: Option<&ModelSkeleton>,
+    bounds: Option<&Bounds>,
+    colliders: Option<&ClothColliders>,
+) -> (Mat4, Vec3, Vec<(Vec3, f32)>) {
+    let transform = instance_transform(&instance.0);
+
+    let point = match &cloth.point {
+        AnchorPoint::Offset(offset) => *offset,
+        AnchorPoint::Joint(name) => skeleton
+            .and_then(|skeleton| skeleton.joints.iter().find(|joint| &joint.name == name))
+            .map_or(Vec3::ZERO, |joint| joint.rest),
+    };
+
+    let spheres = match (colliders, bounds) {
+        (Some(colliders), _) => colliders
+            .0
+            .iter()
+            .map(|&(center, radius)| {
+                (
+                    transform.transform_point3(center),
+                    radius * instance.0.scale,
+                )
+            })
+            .collect(),
+        (None, Some(bounds)) => {
+            let size = bounds.0.size();
+            vec![(bounds.0.center(), size.x.min(size.z) * 0.5)]
+        }
+        (None, None) => Vec::new(),
+    };
+
+    (transform, point, spheres)
+}
+
+#[allow(clippy::type_complexity)]
+fn simulate_cloth(
+    mut commands: Commands,
+    mut simulations: ResMut<ClothSimulations>,
+    time_scale: Res<TimeScale>,
+    wind: Res<WindField>,
+    cloth_q: Query<(Entity, &Cloth)>,
+    anchor_q: Query<
+        (
+            &components::Instance,
+            Option<&components::InstanceOf>,
+            Option<&Bounds>,
+            Option<&ClothColliders>,
+        ),
+        Without<ClothSegment>,
+    >,
+    skeleton_q: Query<&ModelSkeleton>,
+    mut segment_q: Query<&mut components::Instance, With<ClothSegment>>,
+) {
+    let delta = time_scale.delta();
+
+    // Cloths whose anchor is gone go too.
+    simulations.cloths.retain(|entity, state| {
+        let keep = cloth_q
+            .get(*entity)
+            .map_or(false, |(_, cloth)| anchor_q.get(cloth.anchor).is_ok());
+
+        if !keep {
+            for segment in state.segments.drain(..) {
+                commands.entity(segment).despawn();
+            }
+
+            commands.entity(state.model).despawn();
+        }
+
+        keep
+    });
+
+    for (entity, cloth) in cloth_q.iter() {
+        if cloth.columns == 0 || cloth.rows == 0 {
+            continue;
+        }
+
+        let (instance, instance_of, bounds, colliders) = match anchor_q.get(cloth.anchor) {
+            Ok(anchor) => anchor,
+            Err(_) => continue,
+        };
+
+        let scale = instance.0.scale;
+
+        // Hidden.
+        if scale <= 0.0 {
+            continue;
+        }
+
+        let skeleton = instance_of.and_then(|instance_of| skeleton_q.get(instance_of.0).ok());
+        let (transform, point, spheres) =
+            anchor_transform(cloth, instance, skeleton, bounds, colliders);
+
+        let count = cloth.columns * cloth.rows;
+        let rest = |particle: usize| {
+            transform.transform_point3(point + cloth.rest_offset(particle) / scale)
+        };
+
+        let state = simulations.cloths.entry(entity).or_insert_with(|| {
+            let model = spawn_primitive(
+                &mut commands,
+                Primitive::Capsule {
+                    radius: cloth.thickness,
+                    height: cloth.spacing,
+                    sectors: 8,
+                    stacks: 2,
+                },
+                MaterialDescriptor::color([cloth.color[0], cloth.color[1], cloth.color[2], 1.0]),
+            );
+
+            let links = cloth.links();
+            let segments = links
+                .iter()
+                .map(|_| {
+                    commands
+                        .spawn()
+                        .insert(ClothSegment)
+                        .insert(components::InstanceOf(model))
+                        .insert(components::Instance(renderer_core::Instance::new(
+                            Default::default(),
+                            1.0,
+                            Default::default(),
+                        )))
+                        .id()
+                })
+                .collect();
+
+            let particles: Vec<Vec3> = (0..count).map(rest).collect();
+
+            ClothState {
+                model,
+                previous: particles.clone(),
+                particles,
+                links,
+                segments,
+            }
+        });
+
+        if delta > 0.0 {
+            let damping = (1.0 - cloth.damping * delta).max(0.0);
+
+            for (index, (particle, previous)) in state
+                .particles
+                .iter_mut()
+                .zip(&mut state.previous)
+                .enumerate()
+            {
+                // The top row goes wherever the anchor is.
+                if index < cloth.columns {
+                    *previous = *particle;
+                    *particle = rest(index);
+                    continue;
+                }
+
+                let velocity = (*particle - *previous) / delta;
+                let air = cloth.wind + wind.velocity_at(*particle);
+                let acceleration = Vec3::new(0.0, -GRAVITY, 0.0) + (air - velocity) * cloth.drag;
+
+                *previous = *particle;
+                *particle += velocity * damping * delta + acceleration * delta * delta;
+            }
+
+            for _ in 0..ITERATIONS {
+                for &(a, b) in &state.links {
+                    let offset = state.particles[b] - state.particles[a];
+                    let distance = offset.length();
+
+                    if distance <= f32::EPSILON {
+                        continue;
+                    }
+
+                    let correction = offset * ((distance - cloth.spacing) / distance);
+
+                    // Pinned particles don't move.
+                    match (a < cloth.columns, b < cloth.columns) {
+                        (true, true) => {}
+                        (true, false) => state.particles[b] -= correction,
+                        (false, true) => state.particles[a] += correction,
+                        (false, false) => {
+                            state.particles[a] += correction * 0.5;
+                            state.particles[b] -= correction * 0.5;
+                        }
+                    }
+                }
+
+                for particle in state.particles.iter_mut().skip(cloth.columns) {
+                    for &(center, radius) in &spheres {
+                        let offset = *particle - center;
+                        let distance = offset.length();
+                        let radius = radius + cloth.thickness;
+
+                        if distance < radius && distance > f32::EPSILON {
+                            *particle = center + offset / distance * radius;
+                        }
+                    }
+                }
+            }
+        }
+
+        for (&(a, b), &segment) in state.links.iter().zip(&state.segments) {
+            let (from, to) = (state.particles[a], state.particles[b]);
+            let direction = (to - from).normalize_or_zero();
+
+            let position = (from + to) * 0.5;
+            // The capsule primitive is along +Y.
+            let rotation = if direction == Vec3::ZERO {
+                Quat::IDENTITY
+            } else {
+                Quat::from_rotation_arc(Vec3::Y, direction)
+            };
+
+            if let Ok(mut instance) = segment_q.get_mut(segment) {
+                if instance.0.position != position || instance.0.rotation != rotation {
+                    instance.0.position = position;
+                    instance.0.rotation = rotation;
+                }
+            }
+        }
+    }
+}
+
+// Marks the instances that draw a cloth's links.
+#[derive(Component)]
+struct ClothSegment;
@@ -0,0 +1,557 @@
+use std::collections::BTreeMap;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, CoreStage, EventReader, Events, Plugin},
+    bevy_ecs::{
+        prelude::{Commands, Entity, IntoExclusiveSystem, Mut, Res, ResMut, With, World},
+        system::CommandQueue,
+    },
+    components, renderer_core,
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::assets::{AssetServer, Environment, Handle, Model};
+use crate::input::KeyPressed;
+use crate::inspector::InspectorEdit;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::{schedule, LocalPlayer};
+
+// How many lines of output are kept.
+const MAX_OUTPUT: usize = 100;
+// How far in front of the camera `spawn` puts models without a position.
+const SPAWN_DISTANCE: f32 = 2.0;
+
+// Runs a command with its arguments, returning what to print.
+pub type CommandFn = fn(&mut World, &[&str]) -> Result<String, String>;
+
+#[derive(Clone, Copy)]
+pub struct ConsoleCommand {
+    pub usage: &'static str,
+    pub run: CommandFn,
+}
+
+// A dev console for running commands like `spawn` and `teleport` while the playground runs.
+// The Console binding (~ by default) shows it on the web, desktop builds read commands from
+// stdin, and the host page can call `run_console_command`. Other plugins register their own
+// commands with `register`.
+#[derive(Default)]
+pub struct Console {
+    pub visible: bool,
+    commands: BTreeMap<&'static str, ConsoleCommand>,
+    history: Vec<String>,
+    output: Vec<String>,
+    pending: Vec<String>,
+}
+
+impl Console {
+    pub fn register(&mut self, name: &'static str, usage: &'static str, run: CommandFn) {
+        self.commands.insert(name, ConsoleCommand { usage, run });
+    }
+
+    // Queues a line to be run at the start of the next frame.
+    pub fn run(&mut self, line: impl Into<String>) {
+        self.pending.push(line.into());
+    }
+
+    pub fn history(&self) -> &[String] {
+        &self.history
+    }
+
+    pub fn output(&self) -> &[String] {
+        &self.output
+    }
+
+    pub fn command_names(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.commands.keys().copied()
+    }
+
+    // Completes the command name at the start of `line`, as far as it's unambiguous.
+    pub fn complete(&self, line: &str) -> Option<String> {
+        if line.contains(char::is_whitespace) {
+            return None;
+        }
+
+        let mut matches = self.command_names().filter(|name| name.starts_with(line));
+        let first = matches.next()?;
+
+        let common = matches.fold(first, |common, name| {
+            let length = common
+                .chars()
+                .zip(name.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            &common[..length]
+        });
+
+        Some(common.to_string())
+    }
+
+    fn print(&mut self, text: String) {
+        self.output.push(text);
+
+        if self.output.len() > MAX_OUTPUT {
+            self.output.remove(0);
+        }
+    }
+}
+
+struct ConsoleInput {
+    // Kept so that the channel stays open when nothing else sends to it.
+    _sender: Sender<String>,
+    receiver: Receiver<String>,
+}
+
+// Handles to the models spawned from the console, which keep them loaded.
+#[derive(Default)]
+struct ConsoleModels(Vec<Handle<Model>>, Vec<Handle<Environment>>);
+
+pub struct ConsolePlugin;
+
+impl Plugin for ConsolePlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        #[cfg(not(feature = "wasm"))]
+        read_stdin(sender.clone());
+
+        #[cfg(feature = "wasm")]
+        {
+            js::set_sender(sender.clone());
+            app.insert_non_send_resource(dom::ConsoleDom::new(sender.clone()));
+            app.add_system_set(schedule::pre_render().with_system(dom::update_console_dom));
+        }
+
+        let mut console = app.world.remove_resource::<Console>().unwrap_or_default();
+
+        console.register("help", "help", help);
+        console.register("spawn", "spawn <url> [x y z]", spawn);
+        console.register("load_scene", "load_scene <url>", load_scene);
+        console.register("teleport", "teleport <x> <y> <z>", teleport);
+        console.register(
+            "set_animation",
+            "set_animation <player | entity> <index>",
+            set_animation,
+        );
+
+        app.insert_resource(console);
+        app.insert_resource(ConsoleModels::default());
+        app.insert_resource(ConsoleInput {
+            _sender: sender,
+            receiver,
+        });
+
+        app.add_system_set(schedule::input().with_system(handle_console_key));
+        app.add_system_to_stage(
+            CoreStage::PreUpdate,
+            run_console_commands.exclusive_system(),
+        );
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn read_stdin(sender: Sender<String>) {
+    std::thread::spawn(move || {
+        use std::io::BufRead;
+
+        for line in std::io::stdin().lock().lines() {
+            match line {
+                Ok(line) if sender.send(line).is_ok() => {}
+                _ => break,
+            }
+        }
+    });
+}
+
+fn handle_console_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut console: ResMut<Console>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::Console) {
+            console.visible = !console.visible;
+
+            #[cfg(not(feature = "wasm"))]
+            if console.visible {
+                log::info!("Type console commands into the terminal on desktop");
+            }
+        }
+    }
+}
+
+fn run_console_commands(world: &mut World) {
+    let received: Vec<String> = world
+        .resource::<ConsoleInput>()
+        .receiver
+        .try_iter()
+        .collect();
+
+    let mut lines = std::mem::take(&mut world.resource_mut::<Console>().pending);
+    lines.extend(received);
+
+    for line in lines {
+        let mut words = line.split_whitespace();
+
+        let name = match words.next() {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let args: Vec<&str> = words.collect();
+
+        let result = match world.resource::<Console>().commands.get(name).copied() {
+            Some(command) => (command.run)(world, &args)
+                .map_err(|error| format!("{} (usage: {})", error, command.usage)),
+            None => Err(format!("Unknown command {:?}, try help", name)),
+        };
+
+        let mut console = world.resource_mut::<Console>();
+        console.history.push(line.clone());
+        console.print(format!("> {}", line));
+
+        match result {
+            Ok(text) if text.is_empty() => {}
+            Ok(text) => {
+                log::info!("{}", text);
+                console.print(text);
+            }
+            Err(error) => {
+                log::warn!("{}", error);
+                console.print(error);
+            }
+        }
+    }
+}
+
+fn parse<T: std::str::FromStr>(arg: Option<&&str>, name: &str) -> Result<T, String> {
+    let arg = arg.ok_or_else(|| format!("Missing {}", name))?;
+    arg.parse()
+        .map_err(|_| format!("Invalid {} {:?}", name, arg))
+}
+
+fn parse_vec3(args: &[&str]) -> Result<Vec3, String> {
+    Ok(Vec3::new(
+        parse(args.get(0), "x")?,
+        parse(args.get(1), "y")?,
+        parse(args.get(2), "z")?,
+    ))
+}
+
+fn parse_url(arg: Option<&&str>) -> Result<Url, String> {
+    let arg = arg.ok_or("Missing url")?;
+    Url::parse(arg).map_err(|error| format!("Invalid url {:?}: {}", arg, error))
+}
+
+// `player` for the local player, or an entity id like the ones in game events.
+fn parse_entity(world: &mut World, arg: Option<&&str>) -> Result<Entity, String> {
+    match arg {
+        Some(&"player") => world
+            .query_filtered::<Entity, With<LocalPlayer>>()
+            .iter(world)
+            .next()
+            .ok_or_else(|| "There's no local player".to_string()),
+        _ => {
+            let entity = Entity::from_bits(parse(arg, "entity")?);
+
+            match world.get_entity(entity) {
+                Some(_) => Ok(entity),
+                None => Err(format!("There's no entity {}", entity.to_bits())),
+            }
+        }
+    }
+}
+
+fn help(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    let console = world.resource::<Console>();
+
+    Ok(console
+        .commands
+        .values()
+        .map(|command| command.usage)
+        .collect::<Vec<_>>()
+        .join("\n"))
+}
+
+fn spawn(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let url = parse_url(args.get(0))?;
+
+    let position = if args.len() > 1 {
+        parse_vec3(&args[1..])?
+    } else {
+        let camera = world.resource::<Camera>();
+        camera.position + camera.rotation * (-Vec3::Z * SPAWN_DISTANCE)
+    };
+
+    let mut queue = CommandQueue::default();
+
+    let instance = world.resource_scope(|world, mut server: Mut<AssetServer>| {
+        let mut commands = Commands::new(&mut queue, world);
+        let handle: Handle<Model> = server.load(&mut commands, url.clone());
+
+        let instance = commands
+            .spawn()
+            .insert(components::InstanceOf(handle.entity()))
+            .insert(components::Instance(renderer_core::Instance::new(
+                position,
+                1.0,
+                Default::default(),
+            )))
+            .id();
+
+        (handle, instance)
+    });
+
+    queue.apply(world);
+
+    let (handle, instance) = instance;
+    world.resource_mut::<ConsoleModels>().0.push(handle);
+
+    Ok(format!("Spawned {} as {}", url, instance.to_bits()))
+}
+
+fn load_scene(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let url = parse_url(args.get(0))?;
+
+    let mut queue = CommandQueue::default();
+
+    let handle = world.resource_scope(|world, mut server: Mut<AssetServer>| {
+        let mut commands = Commands::new(&mut queue, world);
+        let handle: Handle<Environment> = server.load(&mut commands, url.clone());
+
+        commands
+            .spawn()
+            .insert(components::InstanceOf(handle.entity()))
+            .insert(components::Instance(renderer_core::Instance::new(
+                Vec3::ZERO,
+                1.0,
+                Default::default(),
+            )));
+
+        handle
+    });
+
+    queue.apply(world);
+
+    world.resource_mut::<ConsoleModels>().1.push(handle);
+
+    Ok(format!("Loading {}", url))
+}
+
+// Goes by the camera rig, which is what moves the player.
+fn teleport(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let position = parse_vec3(args)?;
+
+    let mut camera_rig = world
+        .get_resource_mut::<dolly::rig::CameraRig>()
+        .ok_or("There's no camera rig to move")?;
+
+    camera_rig.driver_mut::<dolly::drivers::Position>().position = position;
+
+    Ok(String::new())
+}
+
+// Goes through the inspector, which knows to change a player's state rather than its
+// animation.
+fn set_animation(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let entity = parse_entity(world, args.get(0))?;
+    let index = parse(args.get(1), "index")?;
+
+    world
+        .resource_mut::<Events<InspectorEdit>>()
+        .send(InspectorEdit::Animation(entity, index));
+
+    Ok(String::new())
+}
+
+#[cfg(feature = "wasm")]
+mod js {
+    use std::cell::RefCell;
+
+    use crossbeam_channel::Sender;
+    use wasm_bindgen::prelude::wasm_bindgen;
+
+    thread_local! {
+        static SENDER: RefCell<Option<Sender<String>>> = RefCell::new(None);
+    }
+
+    pub(super) fn set_sender(sender: Sender<String>) {
+        SENDER.with(|cell| *cell.borrow_mut() = Some(sender));
+    }
+
+    // Called by the host page to run a console command, e.g. `run_console_command("help")`.
+    // The output goes to the console and the browser's log.
+    #[wasm_bindgen]
+    pub fn run_console_command(line: String) {
+        SENDER.with(|cell| match cell.borrow().as_ref() {
+            Some(sender) => {
+                let _ = sender.send(line);
+            }
+            None => log::warn!("The console isn't running"),
+        });
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crossbeam_channel::Sender;
+    use superconductor::bevy_ecs::prelude::{NonSend, Res, ResMut};
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    use super::Console;
+    use crate::chat::dom::create_element;
+    use crate::photo_mode::PhotoMode;
+
+    const LINES_SHOWN: usize = 20;
+    const OUTPUT_STYLE: &str = "position: fixed; left: 8px; top: 8px; width: 480px; \
+                                white-space: pre-wrap; color: white; font: 12px monospace; \
+                                background: rgba(0, 0, 0, 0.7); pointer-events: none;";
+    const INPUT_STYLE: &str = "position: fixed; left: 8px; top: 8px; width: 480px; \
+                               font: 12px monospace; transform: translateY(-100%);";
+
+    #[derive(Default)]
+    struct Shared {
+        history: Vec<String>,
+        // How far back up the history the input is, when it's showing an old command.
+        history_index: Option<usize>,
+        names: Vec<&'static str>,
+        // Set when the console is closed from the input, which has the keyboard focus.
+        closed: bool,
+    }
+
+    pub struct ConsoleDom {
+        output: web_sys::HtmlElement,
+        input: web_sys::HtmlInputElement,
+        shared: Rc<RefCell<Shared>>,
+    }
+
+    impl ConsoleDom {
+        pub fn new(sender: Sender<String>) -> Self {
+            let output = create_element("div");
+            let input: web_sys::HtmlInputElement = create_element("input").unchecked_into();
+            let shared = Rc::new(RefCell::new(Shared::default()));
+
+            output.set_attribute("style", "display: none;").unwrap();
+            input.set_attribute("style", "display: none;").unwrap();
+
+            let onkeydown = {
+                let input = input.clone();
+                let shared = shared.clone();
+
+                Closure::wrap(Box::new(move |event: web_sys::KeyboardEvent| {
+                    let mut shared = shared.borrow_mut();
+                    let shared = &mut *shared;
+
+                    match event.key().as_str() {
+                        "Enter" => {
+                            let _ = sender.send(input.value());
+                            input.set_value("");
+                            shared.history_index = None;
+                        }
+                        "ArrowUp" | "ArrowDown" if !shared.history.is_empty() => {
+                            let last = shared.history.len() - 1;
+
+                            let index = match (event.key().as_str(), shared.history_index) {
+                                ("ArrowUp", None) => last,
+                                ("ArrowUp", Some(index)) => index.saturating_sub(1),
+                                (_, Some(index)) if index < last => index + 1,
+                                _ => {
+                                    shared.history_index = None;
+                                    input.set_value("");
+                                    return;
+                                }
+                            };
+
+                            shared.history_index = Some(index);
+                            input.set_value(&shared.history[index]);
+                            event.prevent_default();
+                        }
+                        "Escape" | "`" | "~" => {
+                            shared.closed = true;
+                            input.blur().unwrap();
+                            event.prevent_default();
+                        }
+                        "Tab" => {
+                            let value = input.value();
+                            let mut matches =
+                                shared.names.iter().filter(|name| name.starts_with(&value));
+
+                            if let (Some(name), None) = (matches.next(), matches.next()) {
+                                input.set_value(&format!("{} ", name));
+                            }
+
+                            event.prevent_default();
+                        }
+                        _ => {}
+                    }
+
+                    // So that typing doesn't also move the player.
+                    event.stop_propagation();
+                }) as Box<dyn FnMut(web_sys::KeyboardEvent)>)
+            };
+
+            input.set_onkeydown(Some(onkeydown.as_ref().unchecked_ref()));
+            onkeydown.forget();
+
+            let body = web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap();
+
+            body.append_child(&output).unwrap();
+            body.append_child(&input).unwrap();
+
+            Self {
+                output,
+                input,
+                shared,
+            }
+        }
+    }
+
+    pub fn update_console_dom(
+        dom: NonSend<ConsoleDom>,
+        mut console: ResMut<Console>,
+        photo_mode: Res<PhotoMode>,
+    ) {
+        if std::mem::take(&mut dom.shared.borrow_mut().closed) {
+            console.visible = false;
+        }
+
+        if !console.is_changed() && !photo_mode.is_changed() {
+            return;
+        }
+
+        if !console.visible || photo_mode.hides_ui() {
+            dom.output.set_attribute("style", "display: none;").unwrap();
+            dom.input.set_attribute("style", "display: none;").unwrap();
+            return;
+        }
+
+        {
+            let mut shared = dom.shared.borrow_mut();
+            shared.history = console.history().to_vec();
+            shared.names = console.command_names().collect();
+        }
+
+        let output = console.output();
+        let shown = &output[output.len().saturating_sub(LINES_SHOWN)..];
+
+        dom.output.set_inner_text(&shown.join("\n"));
+        dom.output.set_attribute("style", OUTPUT_STYLE).unwrap();
+        dom.input.set_attribute("style", INPUT_STYLE).unwrap();
+        let _ = dom.input.focus();
+    }
+}
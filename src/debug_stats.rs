@@ -0,0 +1,21 @@
+use std::collections::BTreeMap;
+
+use superconductor::url::Url;
+
+#[cfg(feature = "allocation_stats")]
+use crate::allocations::AllocationStats;
+use crate::occlusion::OcclusionStats;
+use crate::render_stats::RenderStats;
+use crate::textures::{TextureStats, TranscodeTarget};
+
+// Things that are useful to look at when something renders wrong, collected in one place.
+#[derive(Default)]
+pub struct DebugStats {
+    // A guess, see `TranscodeTarget`.
+    pub transcode_target: Option<TranscodeTarget>,
+    pub textures: BTreeMap<Url, TextureStats>,
+    pub render: RenderStats,
+    pub occlusion: Option<OcclusionStats>,
+    #[cfg(feature = "allocation_stats")]
+    pub allocations: Option<AllocationStats>,
+}
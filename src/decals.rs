@@ -0,0 +1,161 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+
+// How far each sort order lifts a decal off the surface, so that decals don't flicker against
+// the surface or each other.
+const SORT_OFFSET: f32 = 0.002;
+
+// An image placed flat against a surface, like a target marker on the floor or a poster on a
+// wall. The renderer can't project onto the geometry underneath, so decals are textured quads
+// that lie on the surface and don't wrap around corners.
+#[derive(Component, Clone, Debug)]
+pub struct Decal {
+    pub texture: Url,
+    pub position: Vec3,
+    // Pointing away from the surface.
+    pub normal: Vec3,
+    // The size and texture can't be changed after the decal has been added.
+    pub width: f32,
+    pub height: f32,
+    // Around the normal, in radians.
+    pub rotation: f32,
+    // Hidden while the camera is further away than this. Instances have no opacity, so they
+    // pop out rather than fade.
+    pub fade_distance: Option<f32>,
+    // Higher orders are drawn on top of lower ones.
+    pub sort_order: u8,
+}
+
+impl Decal {
+    pub fn new(texture: Url, position: Vec3, normal: Vec3, size: f32) -> Self {
+        Self {
+            texture,
+            position,
+            normal,
+            width: size,
+            height: size,
+            rotation: 0.0,
+            fade_distance: None,
+            sort_order: 0,
+        }
+    }
+
+    fn transform(&self) -> (Vec3, Quat) {
+        let normal = self.normal.normalize_or_zero();
+        let normal = if normal == Vec3::ZERO {
+            Vec3::Y
+        } else {
+            normal
+        };
+
+        let rotation =
+            Quat::from_rotation_arc(Vec3::Y, normal) * Quat::from_rotation_y(self.rotation);
+
+        let offset = normal * SORT_OFFSET * (self.sort_order as f32 + 1.0);
+
+        (self.position + offset, rotation)
+    }
+}
+
+// The quad model of each decal.
+#[derive(Default)]
+struct DecalModels(HashMap<Entity, Entity>);
+
+pub struct DecalPlugin;
+
+impl Plugin for DecalPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DecalModels::default());
+
+        app.add_system(spawn_decal_models);
+        app.add_system(despawn_decal_models);
+        app.add_system_set(schedule::pre_render().with_system(update_decals));
+    }
+}
+
+fn spawn_decal_models(
+    mut commands: Commands,
+    mut models: ResMut<DecalModels>,
+    decal_q: Query<(Entity, &Decal), Added<Decal>>,
+) {
+    for (entity, decal) in decal_q.iter() {
+        let model = spawn_primitive(
+            &mut commands,
+            Primitive::Plane {
+                width: decal.width,
+                depth: decal.height,
+            },
+            MaterialDescriptor {
+                base_color_texture: Some(decal.texture.clone()),
+                blend: true,
+                ..Default::default()
+            },
+        );
+
+        let (position, rotation) = decal.transform();
+
+        commands
+            .entity(entity)
+            .insert(components::InstanceOf(model))
+            .insert(components::Instance(renderer_core::Instance::new(
+                position, 1.0, rotation,
+            )));
+
+        if let Some(previous) = models.0.insert(entity, model) {
+            commands.entity(previous).despawn();
+        }
+    }
+}
+
+fn despawn_decal_models(
+    mut commands: Commands,
+    mut models: ResMut<DecalModels>,
+    decal_q: Query<(), With<Decal>>,
+) {
+    models.0.retain(|&decal, &mut model| {
+        let keep = decal_q.get(decal).is_ok();
+
+        if !keep {
+            commands.entity(model).despawn();
+        }
+
+        keep
+    });
+}
+
+fn update_decals(camera: Res<Camera>, mut decal_q: Query<(&Decal, &mut components::Instance)>) {
+    for (decal, mut instance) in decal_q.iter_mut() {
+        let (position, rotation) = decal.transform();
+
+        let visible = match decal.fade_distance {
+            Some(distance) => camera.position.distance(decal.position) <= distance,
+            None => true,
+        };
+
+        let scale = if visible { 1.0 } else { 0.0 };
+
+        // Only touched when something changed, so that the renderer isn't sent every decal
+        // again each frame.
+        if instance.0.position != position
+            || instance.0.rotation != rotation
+            || instance.0.scale != scale
+        {
+            instance.0.position = position;
+            instance.0.rotation = rotation;
+            instance.0.scale = scale;
+        }
+    }
+}
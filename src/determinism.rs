@@ -0,0 +1,152 @@
+use superconductor::{
+    bevy_app::{App, CoreStage, Plugin},
+    bevy_ecs::schedule::{SingleThreadedExecutor, SystemStage},
+};
+
+use crate::DELTA_TIME;
+
+// Makes runs reproducible, for replaying recorded input and for automated tests. Every
+// system already steps by a fixed `DELTA_TIME` rather than the measured frame time.
+#[derive(Clone, Copy, Debug)]
+pub struct DeterminismConfig {
+    pub enabled: bool,
+    pub seed: u64,
+    pub fixed_timestep: f32,
+}
+
+impl DeterminismConfig {
+    // Turned on by setting a seed with DETERMINISTIC_SEED on desktop or ?seed= on the web.
+    pub fn from_env() -> Self {
+        #[cfg(not(feature = "wasm"))]
+        let seed = std::env::var("DETERMINISTIC_SEED").ok();
+
+        #[cfg(feature = "wasm")]
+        let seed = crate::query_parameter("seed");
+
+        let seed = seed.and_then(|seed| match seed.parse() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                log::warn!("Invalid seed {:?}", seed);
+                None
+            }
+        });
+
+        Self {
+            enabled: seed.is_some(),
+            seed: seed.unwrap_or_else(random_seed),
+            fixed_timestep: DELTA_TIME,
+        }
+    }
+}
+
+fn random_seed() -> u64 {
+    #[cfg(not(feature = "wasm"))]
+    {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or_default()
+    }
+
+    #[cfg(feature = "wasm")]
+    {
+        (js_sys::Math::random() * u64::MAX as f64) as u64
+    }
+}
+
+// A small SplitMix64 generator. Gameplay systems should draw their randomness from this
+// resource so that seeded runs turn out the same.
+pub struct SimRng {
+    state: u64,
+}
+
+impl SimRng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9e3779b97f4a7c15);
+
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+
+    // In [0, 1).
+    pub fn next_f32(&mut self) -> f32 {
+        (self.next_u64() >> 40) as f32 / (1u64 << 24) as f32
+    }
+
+    pub fn range(&mut self, min: f32, max: f32) -> f32 {
+        min + (max - min) * self.next_f32()
+    }
+}
+
+pub struct DeterminismPlugin;
+
+impl Plugin for DeterminismPlugin {
+    fn build(&self, app: &mut App) {
+        let config = DeterminismConfig::from_env();
+
+        app.insert_resource(SimRng::new(config.seed));
+        app.insert_resource(config);
+
+        if !config.enabled {
+            return;
+        }
+
+        log::info!("Running deterministically with seed {}", config.seed);
+
+        // Systems that aren't explicitly ordered run in the order they were added, instead of
+        // whichever order the parallel executor happens to pick.
+        if let Some(stage) = app
+            .schedule
+            .get_stage_mut::<SystemStage>(&CoreStage::Update)
+        {
+            stage.set_executor(Box::new(SingleThreadedExecutor::default()));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_splitmix64() {
+        let mut rng = SimRng::new(0);
+
+        assert_eq!(rng.next_u64(), 0xe220a8397b1dcdaf);
+        assert_eq!(rng.next_u64(), 0x6e789e6aa1b965f4);
+        assert_eq!(rng.next_u64(), 0x06c45d188009454f);
+    }
+
+    #[test]
+    fn same_seed_same_sequence() {
+        let mut a = SimRng::new(1234);
+        let mut b = SimRng::new(1234);
+        let mut c = SimRng::new(1235);
+
+        let a: Vec<u64> = (0..16).map(|_| a.next_u64()).collect();
+        let b: Vec<u64> = (0..16).map(|_| b.next_u64()).collect();
+        let c: Vec<u64> = (0..16).map(|_| c.next_u64()).collect();
+
+        assert_eq!(a, b);
+        assert_ne!(a, c);
+    }
+
+    #[test]
+    fn floats_stay_in_range() {
+        let mut rng = SimRng::new(7);
+
+        for _ in 0..1000 {
+            let value = rng.next_f32();
+            assert!((0.0..1.0).contains(&value));
+
+            let value = rng.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+        }
+    }
+}
@@ -0,0 +1,500 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Query, Res, ResMut, With, Without},
+    components, url,
+};
+
+use crate::alpha_modes::AlphaModeOverride;
+use crate::asset_cache::{AssetCache, AssetError};
+use crate::material_extensions::{self, MaterialFallback};
+use crate::mesh_compression::{self, GltfDocument};
+use crate::node_hierarchy::{self, ExpandNodes, NodeDescription};
+use crate::textures::Ktx2Info;
+
+pub const DEFAULT_MAX_CONCURRENT_DOWNLOADS: usize = 4;
+const PROGRESS_LOG_INTERVAL_FRAMES: u32 = 120;
+
+// Lower priorities are downloaded first.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DownloadPriority {
+    Avatar,
+    Props,
+    Environment,
+}
+
+// Inserted instead of a model url so that the download manager decides when the renderer
+// gets to start loading it.
+#[derive(Component, Clone)]
+pub struct QueuedModel {
+    pub url: url::Url,
+    pub animated: bool,
+    pub priority: DownloadPriority,
+}
+
+// Downloaded into the cache without being handed to the renderer. The entity is despawned
+// once the download finishes.
+#[derive(Component)]
+pub struct PrefetchAsset {
+    pub url: url::Url,
+    pub priority: DownloadPriority,
+}
+
+#[derive(Component)]
+pub struct DownloadFailed;
+
+// Left behind on a model once its download has finished, so that it can be queued again.
+#[derive(Component)]
+pub struct ModelSource(pub QueuedModel);
+
+#[derive(Default)]
+struct Progress {
+    received: AtomicU64,
+    // Zero when the size is unknown.
+    total: AtomicU64,
+}
+
+// Sent once a model's file has been downloaded and parsed, for everything that reads something
+// out of it. The document has been decompressed, but still has the model's own materials.
+// Models that were despawned in the meantime still get one.
+pub struct ModelInspected {
+    pub entity: Entity,
+    pub url: url::Url,
+    pub document: GltfDocument,
+    // The material extensions the model uses that the renderer only has fallbacks for.
+    pub fallbacks: Vec<MaterialFallback>,
+    // Only for models with `ExpandNodes`, which the renderer gets as their parts instead.
+    pub nodes: Option<Vec<NodeDescription>>,
+}
+
+// Sent once a prefetched KTX2 texture has been downloaded.
+pub struct TextureInspected {
+    pub url: url::Url,
+    pub info: Ktx2Info,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct DownloadProgress {
+    pub received: u64,
+    pub total: Option<u64>,
+}
+
+struct ActiveDownload {
+    url: url::Url,
+    progress: Arc<Progress>,
+    cancelled: Arc<AtomicBool>,
+    #[cfg(not(feature = "wasm"))]
+    task: tokio::task::JoinHandle<()>,
+}
+
+pub struct DownloadManager {
+    pub max_concurrent: usize,
+    cache: Arc<AssetCache>,
+    active: HashMap<Entity, ActiveDownload>,
+    // A model's successful download comes with the url of the copy the renderer loads,
+    // unless it was expanded into parts.
+    results: (
+        Sender<(Entity, Result<Option<url::Url>, AssetError>)>,
+        Receiver<(Entity, Result<Option<url::Url>, AssetError>)>,
+    ),
+    models: (Sender<ModelInspected>, Receiver<ModelInspected>),
+    textures: (Sender<TextureInspected>, Receiver<TextureInspected>),
+    #[cfg(not(feature = "wasm"))]
+    runtime: tokio::runtime::Handle,
+}
+
+impl DownloadManager {
+    fn new(max_concurrent: usize) -> Self {
+        Self {
+            max_concurrent,
+            cache: Arc::new(AssetCache::default()),
+            active: Default::default(),
+            results: crossbeam_channel::unbounded(),
+            models: crossbeam_channel::unbounded(),
+            textures: crossbeam_channel::unbounded(),
+            #[cfg(not(feature = "wasm"))]
+            runtime: tokio::runtime::Handle::current(),
+        }
+    }
+
+    pub fn progress(&self, entity: Entity) -> Option<DownloadProgress> {
+        self.active.get(&entity).map(|download| {
+            let total = download.progress.total.load(Ordering::Relaxed);
+
+            DownloadProgress {
+                received: download.progress.received.load(Ordering::Relaxed),
+                total: if total == 0 { None } else { Some(total) },
+            }
+        })
+    }
+
+    pub fn cache(&self) -> Arc<AssetCache> {
+        self.cache.clone()
+    }
+
+    #[cfg(not(feature = "wasm"))]
+    pub fn spawn<F: std::future::Future<Output = ()> + Send + 'static>(&self, future: F) {
+        self.runtime.spawn(future);
+    }
+
+    #[cfg(feature = "wasm")]
+    pub fn spawn<F: std::future::Future<Output = ()> + 'static>(&self, future: F) {
+        wasm_bindgen_futures::spawn_local(future);
+    }
+
+    pub fn active_downloads(&self) -> impl Iterator<Item = (&url::Url, DownloadProgress)> {
+        self.active
+            .iter()
+            .filter_map(|(&entity, download)| Some((&download.url, self.progress(entity)?)))
+    }
+
+    fn start(
+        &mut self,
+        entity: Entity,
+        url: url::Url,
+        prefetch: bool,
+        alpha_mode: Option<AlphaModeOverride>,
+        expand: bool,
+    ) {
+        let progress = Arc::new(Progress::default());
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let sender = self.results.0.clone();
+        let model_sender = self.models.0.clone();
+        let texture_sender = self.textures.0.clone();
+        let cache = self.cache.clone();
+
+        let future = {
+            let url = url.clone();
+            let progress = progress.clone();
+            let cancelled = cancelled.clone();
+
+            async move {
+                let result = cache
+                    .fetch_with_progress(&url, &|received, total| {
+                        progress.received.store(received, Ordering::Relaxed);
+                        progress.total.store(total.unwrap_or(0), Ordering::Relaxed);
+                    })
+                    .await;
+
+                let result = result.and_then(|bytes| {
+                    if prefetch {
+                        if let Some(info) = Ktx2Info::parse(&bytes) {
+                            let _ = texture_sender.send(TextureInspected {
+                                url: url.clone(),
+                                info,
+                            });
+                        }
+
+                        return Ok(None);
+                    }
+
+                    let mut document = GltfDocument::parse(&bytes).map_err(AssetError::Decode)?;
+                    let extensions = mesh_compression::compression_extensions(&document.json);
+
+                    if !extensions.is_empty() {
+                        log::info!("Decompressing {} ({:?})", url, extensions);
+                        mesh_compression::decompress(&mut document).map_err(AssetError::Decode)?;
+                    }
+
+                    let fallbacks = material_extensions::material_fallbacks(&document.json);
+
+                    for fallback in &fallbacks {
+                        log::warn!(
+                            "{} uses {} in {} materials, which isn't supported: {}",
+                            url,
+                            fallback.extension,
+                            fallback.materials,
+                            fallback.description()
+                        );
+                    }
+
+                    let mut gltf = document.json.clone();
+
+                    if !fallbacks.is_empty() {
+                        material_extensions::apply_fallbacks(&mut gltf);
+                    }
+
+                    // After the fallbacks, so that it still wins over transmission.
+                    if let Some(alpha_mode) = alpha_mode {
+                        alpha_mode.apply_to_json(&mut gltf);
+                    }
+
+                    mesh_compression::make_uris_absolute(&mut gltf, &url);
+
+                    // The fallbacks only depend on the model, so they don't need a key of their
+                    // own.
+                    let key = match alpha_mode {
+                        Some(alpha_mode) => alpha_mode.derived_key(&url),
+                        None => url.clone(),
+                    };
+
+                    // The renderer never gets an expanded model as a whole. Other models are
+                    // loaded from a copy of the cached file rather than fetched again, though
+                    // buffers and images that it refers to by url are still the renderer's to
+                    // fetch.
+                    let (replacement, nodes) = if expand {
+                        let bin = document.bin.as_deref();
+                        let nodes = node_hierarchy::split_nodes(gltf, bin, &key, &cache)?;
+                        (None, Some(nodes))
+                    } else {
+                        let bytes = document.write(&gltf);
+                        let stored = cache.store_derived(&key, document.extension(), &bytes)?;
+                        (Some(stored), None)
+                    };
+
+                    let _ = model_sender.send(ModelInspected {
+                        entity,
+                        url: url.clone(),
+                        document,
+                        fallbacks,
+                        nodes,
+                    });
+
+                    Ok(replacement)
+                });
+
+                if !cancelled.load(Ordering::Relaxed) {
+                    let _ = sender.send((entity, result));
+                }
+            }
+        };
+
+        #[cfg(not(feature = "wasm"))]
+        let task = self.runtime.spawn(future);
+
+        #[cfg(feature = "wasm")]
+        wasm_bindgen_futures::spawn_local(future);
+
+        self.active.insert(
+            entity,
+            ActiveDownload {
+                url,
+                progress,
+                cancelled,
+                #[cfg(not(feature = "wasm"))]
+                task,
+            },
+        );
+    }
+
+    fn cancel(&mut self, entity: Entity) {
+        if let Some(download) = self.active.remove(&entity) {
+            log::info!("Cancelled download of {}", download.url);
+            download.cancelled.store(true, Ordering::Relaxed);
+
+            #[cfg(not(feature = "wasm"))]
+            download.task.abort();
+        }
+    }
+}
+
+pub struct DownloadPlugin {
+    pub max_concurrent: usize,
+}
+
+impl Default for DownloadPlugin {
+    fn default() -> Self {
+        Self {
+            max_concurrent: DEFAULT_MAX_CONCURRENT_DOWNLOADS,
+        }
+    }
+}
+
+impl Plugin for DownloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(DownloadManager::new(self.max_concurrent));
+        app.add_event::<ModelInspected>();
+        app.add_event::<TextureInspected>();
+
+        app.add_system(cancel_despawned_downloads);
+        app.add_system(finish_downloads);
+        app.add_system(start_downloads);
+        app.add_system(log_download_progress);
+
+        #[cfg(feature = "wasm")]
+        app.add_system(release_loaded_models);
+    }
+}
+
+fn cancel_despawned_downloads(
+    mut manager: ResMut<DownloadManager>,
+    queued_q: Query<&QueuedModel>,
+    prefetch_q: Query<&PrefetchAsset>,
+) {
+    let despawned: Vec<Entity> = manager
+        .active
+        .keys()
+        .copied()
+        .filter(|&entity| queued_q.get(entity).is_err() && prefetch_q.get(entity).is_err())
+        .collect();
+
+    for entity in despawned {
+        manager.cancel(entity);
+    }
+}
+
+fn start_downloads(
+    mut manager: ResMut<DownloadManager>,
+    queued_q: Query<(Entity, &QueuedModel), Without<DownloadFailed>>,
+    prefetch_q: Query<(Entity, &PrefetchAsset)>,
+    alpha_mode_q: Query<&AlphaModeOverride>,
+    expand_q: Query<(), With<ExpandNodes>>,
+) {
+    let _span = tracing::info_span!("start_downloads").entered();
+
+    let free_slots = manager.max_concurrent.saturating_sub(manager.active.len());
+
+    if free_slots == 0 {
+        return;
+    }
+
+    let mut waiting: Vec<(Entity, &url::Url, DownloadPriority)> = queued_q
+        .iter()
+        .map(|(entity, queued)| (entity, &queued.url, queued.priority))
+        .chain(
+            prefetch_q
+                .iter()
+                .map(|(entity, prefetch)| (entity, &prefetch.url, prefetch.priority)),
+        )
+        .filter(|(entity, _, _)| !manager.active.contains_key(entity))
+        .collect();
+
+    // Entity ids break ties so that assets queued first are fetched first.
+    waiting.sort_by_key(|&(entity, _, priority)| (priority, entity.id()));
+
+    for (entity, url, priority) in waiting.into_iter().take(free_slots) {
+        log::info!("Downloading {} ({:?} priority)", url, priority);
+        let expand = expand_q.get(entity).is_ok()
+            && queued_q
+                .get(entity)
+                .map_or(false, |(_, queued)| !queued.animated);
+
+        manager.start(
+            entity,
+            url.clone(),
+            prefetch_q.get(entity).is_ok(),
+            alpha_mode_q.get(entity).ok().copied(),
+            expand,
+        );
+    }
+}
+
+fn finish_downloads(
+    mut commands: Commands,
+    mut manager: ResMut<DownloadManager>,
+    mut model_events: EventWriter<ModelInspected>,
+    mut texture_events: EventWriter<TextureInspected>,
+    queued_q: Query<&QueuedModel>,
+    prefetch_q: Query<(), With<PrefetchAsset>>,
+) {
+    let _span = tracing::info_span!("finish_downloads").entered();
+
+    for inspected in manager.models.1.try_iter() {
+        model_events.send(inspected);
+    }
+
+    for inspected in manager.textures.1.try_iter() {
+        texture_events.send(inspected);
+    }
+
+    let results: Vec<_> = manager.results.1.try_iter().collect();
+
+    for (entity, result) in results {
+        let download = match manager.active.remove(&entity) {
+            Some(download) => download,
+            None => continue,
+        };
+
+        if prefetch_q.get(entity).is_ok() {
+            if let Err(error) = result {
+                log::warn!("Failed to prefetch {}: {}", download.url, error);
+            }
+
+            commands.entity(entity).despawn();
+            continue;
+        }
+
+        let queued = match queued_q.get(entity) {
+            Ok(queued) => queued,
+            Err(_) => continue,
+        };
+
+        match result {
+            Ok(replacement) => {
+                let mut entity_commands = commands.entity(entity);
+
+                entity_commands
+                    .remove::<QueuedModel>()
+                    .insert(ModelSource(queued.clone()));
+
+                // Expanded models are drawn as their parts instead.
+                let url = match replacement {
+                    Some(url) => url,
+                    None => continue,
+                };
+
+                if queued.animated {
+                    entity_commands.insert(components::AnimatedModelUrl(url));
+                } else {
+                    entity_commands.insert(components::ModelUrl(url));
+                }
+            }
+            Err(error) => {
+                log::warn!("Failed to download {}: {}", download.url, error);
+                commands.entity(entity).insert(DownloadFailed);
+            }
+        }
+    }
+}
+
+fn log_download_progress(manager: Res<DownloadManager>, mut frame: Local<u32>) {
+    *frame += 1;
+
+    if *frame % PROGRESS_LOG_INTERVAL_FRAMES != 0 {
+        return;
+    }
+
+    for (url, progress) in manager.active_downloads() {
+        match progress.total {
+            Some(total) => log::info!(
+                "{}: {}/{} KiB ({:.0}%)",
+                url,
+                progress.received / 1024,
+                total / 1024,
+                progress.received as f32 / total as f32 * 100.0
+            ),
+            None => log::info!("{}: {} KiB", url, progress.received / 1024),
+        }
+    }
+}
+
+// A downloaded model's blob url holds on to its bytes, so it's released once the renderer has
+// loaded them. Reloading the model (after a gpu reset or hot reload) queues it again, which
+// stores a new one.
+#[cfg(feature = "wasm")]
+fn release_loaded_models(
+    manager: Res<DownloadManager>,
+    loaded_q: Query<
+        (
+            Option<&components::ModelUrl>,
+            Option<&components::AnimatedModelUrl>,
+        ),
+        (
+            With<ModelSource>,
+            superconductor::bevy_ecs::prelude::Or<(
+                superconductor::bevy_ecs::prelude::Added<components::Model>,
+                superconductor::bevy_ecs::prelude::Added<components::AnimatedModel>,
+            )>,
+        ),
+    >,
+) {
+    for (url, animated_url) in loaded_q.iter() {
+        if let Some(url) = url.map(|url| &url.0).or(animated_url.map(|url| &url.0)) {
+            manager.cache.release_derived(url);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, With},
+    components::{AnimationState, InstanceOf},
+};
+
+use crate::networking::{NetClient, RemotePlayers};
+use crate::protocol::{ClientMessage, ServerMessage};
+use crate::schedule;
+use crate::skeletons::ModelSkeleton;
+use crate::time_scale::TimeScale;
+use crate::{LocalPlayer, PlayerState, PlayerStates, PLAYER_STATES};
+
+pub struct Emote {
+    pub name: &'static str,
+    pub state: PlayerStates,
+}
+
+pub const EMOTES: [Emote; 9] = [
+    Emote {
+        name: "jump",
+        state: PlayerStates::Jump,
+    },
+    Emote {
+        name: "running jump",
+        state: PlayerStates::RunningJump,
+    },
+    Emote {
+        name: "roll",
+        state: PlayerStates::SprinttoRoll,
+    },
+    Emote {
+        name: "sit",
+        state: PlayerStates::SittingIdle,
+    },
+    Emote {
+        name: "turn left",
+        state: PlayerStates::LeftTurnFeet,
+    },
+    Emote {
+        name: "turn right",
+        state: PlayerStates::RightTurnFeet,
+    },
+    Emote {
+        name: "fall",
+        state: PlayerStates::Falling,
+    },
+    Emote {
+        name: "land",
+        state: PlayerStates::FallingToLanding,
+    },
+    Emote {
+        name: "start walking",
+        state: PlayerStates::StartWalking,
+    },
+];
+
+pub struct EmoteRequested(pub usize);
+
+pub struct AnimationFinished {
+    pub entity: Entity,
+    pub animation_index: usize,
+}
+
+// Plays until the end of its clip, as long as the avatar model's, once that's been loaded.
+#[derive(Component)]
+pub struct ActiveEmote {
+    pub index: usize,
+    // In seconds.
+    pub elapsed: f32,
+    finished: bool,
+    previous: Option<PlayerStates>,
+}
+
+impl ActiveEmote {
+    fn new(index: usize, previous: Option<PlayerStates>) -> Self {
+        Self {
+            index,
+            elapsed: 0.0,
+            finished: false,
+            previous,
+        }
+    }
+}
+
+pub struct EmotePlugin;
+
+impl Plugin for EmotePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<EmoteRequested>();
+        app.add_event::<AnimationFinished>();
+
+        app.add_system_set(
+            schedule::input()
+                .with_system(start_local_emotes)
+                .with_system(start_remote_emotes),
+        );
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(tick_emotes)
+                .with_system(return_to_locomotion),
+        );
+    }
+}
+
+fn animation_index(state: PlayerStates) -> usize {
+    PLAYER_STATES.iter().position(|p| *p == state).unwrap()
+}
+
+fn start_local_emotes(
+    mut commands: Commands,
+    mut requests: EventReader<EmoteRequested>,
+    client: Res<NetClient>,
+    mut local_q: Query<
+        (
+            Entity,
+            &mut PlayerState,
+            &mut AnimationState,
+            Option<&ActiveEmote>,
+        ),
+        With<LocalPlayer>,
+    >,
+) {
+    for &EmoteRequested(index) in requests.iter() {
+        let emote = match EMOTES.get(index) {
+            Some(emote) => emote,
+            None => continue,
+        };
+
+        let (entity, mut player_state, mut animation_state, active) = match local_q.get_single_mut()
+        {
+            Ok(local) => local,
+            Err(_) => continue,
+        };
+
+        // Chaining emotes should still return to whatever we were doing before the first one.
+        let previous = match active {
+            Some(active) => active.previous,
+            None => Some(player_state.0),
+        };
+
+        log::info!("Playing emote {:?}", emote.name);
+
+        player_state.0 = emote.state;
+        animation_state.animation_index = animation_index(emote.state);
+        animation_state.time = 0.0;
+
+        commands
+            .entity(entity)
+            .insert(ActiveEmote::new(index, previous));
+
+        client.send(ClientMessage::Emote { index: index as u8 });
+    }
+}
+
+fn start_remote_emotes(
+    mut commands: Commands,
+    mut server_messages: EventReader<ServerMessage>,
+    remote_players: Res<RemotePlayers>,
+    mut animation_q: Query<&mut AnimationState>,
+) {
+    for message in server_messages.iter() {
+        let (player_id, index) = match message {
+            ServerMessage::Emote { player_id, index } => (player_id, *index as usize),
+            _ => continue,
+        };
+
+        let (entity, emote) = match (remote_players.0.get(player_id), EMOTES.get(index)) {
+            (Some(entity), Some(emote)) => (*entity, emote),
+            _ => continue,
+        };
+
+        if let Ok(mut animation_state) = animation_q.get_mut(entity) {
+            animation_state.animation_index = animation_index(emote.state);
+            animation_state.time = 0.0;
+        }
+
+        commands
+            .entity(entity)
+            .insert(ActiveEmote::new(index, None));
+    }
+}
+
+fn tick_emotes(
+    mut emote_q: Query<(Entity, &mut ActiveEmote, &InstanceOf)>,
+    skeleton_q: Query<&ModelSkeleton>,
+    mut finished: EventWriter<AnimationFinished>,
+    time_scale: Res<TimeScale>,
+) {
+    for (entity, mut emote, instance_of) in emote_q.iter_mut() {
+        if emote.finished {
+            continue;
+        }
+
+        emote.elapsed += time_scale.delta();
+
+        let animation_index = animation_index(EMOTES[emote.index].state);
+
+        // Carries on until the model has loaded and the clip's length is known.
+        let duration = match skeleton_q
+            .get(instance_of.0)
+            .ok()
+            .and_then(|skeleton| skeleton.durations.get(animation_index))
+        {
+            Some(&duration) => duration,
+            None => continue,
+        };
+
+        if emote.elapsed >= duration {
+            emote.finished = true;
+
+            finished.send(AnimationFinished {
+                entity,
+                animation_index,
+            });
+        }
+    }
+}
+
+fn return_to_locomotion(
+    mut commands: Commands,
+    mut finished: EventReader<AnimationFinished>,
+    mut emote_q: Query<(&ActiveEmote, Option<&mut PlayerState>)>,
+) {
+    for event in finished.iter() {
+        let (emote, player_state) = match emote_q.get_mut(event.entity) {
+            Ok(emote) => emote,
+            Err(_) => continue,
+        };
+
+        // Another emote was started in the meantime.
+        if !emote.finished || event.animation_index != animation_index(EMOTES[emote.index].state) {
+            continue;
+        }
+
+        if let Some(mut player_state) = player_state {
+            player_state.0 = emote.previous.unwrap_or(PlayerStates::Idle);
+        }
+
+        commands.entity(event.entity).remove::<ActiveEmote>();
+    }
+}
@@ -0,0 +1,74 @@
+use superconductor::{
+    bevy_app::{App, Plugin},
+    components, renderer_core,
+    resources::{NewIblTextures, NewIblTexturesInner},
+    url::Url,
+    Vec3,
+};
+
+use crate::downloads::{DownloadPriority, PrefetchAsset, QueuedModel};
+use crate::preload::AfterPreload;
+use crate::sun::Sun;
+use crate::water::Water;
+
+const SCENE_URL: &str = "http://localhost:8000/assets/models/nyc/scene.gltf";
+const DIFFUSE_CUBEMAP_URL: &str =
+    "https://expenses.github.io/mateversum-web/environment_maps/helipad/diffuse_compressed.ktx2";
+const SPECULAR_CUBEMAP_URL: &str =
+    "https://expenses.github.io/mateversum-web/environment_maps/helipad/specular_compressed.ktx2";
+
+// The demo scene: the city, a pool of water, the sun and the image based lighting
+// cubemaps.
+pub struct EnvironmentPlugin;
+
+impl Plugin for EnvironmentPlugin {
+    fn build(&self, app: &mut App) {
+        let environment = app
+            .world
+            .spawn()
+            .insert(AfterPreload(QueuedModel {
+                url: Url::parse(SCENE_URL).unwrap(),
+                animated: false,
+                priority: DownloadPriority::Environment,
+            }))
+            .insert(components::Instances(Default::default()))
+            .insert(components::InstanceRange(Default::default()))
+            .id();
+
+        app.world
+            .spawn()
+            .insert(components::InstanceOf(environment))
+            .insert(components::Instance(renderer_core::Instance::new(
+                Vec3::ZERO,
+                1.0,
+                Default::default(),
+            )));
+
+        // Behind the spawn point.
+        app.world
+            .spawn()
+            .insert(Water::new(Vec3::new(0.0, 0.05, 6.0), 4.0, 4.0));
+
+        // High up in front of the spawn point, so that it's in view from the start.
+        app.world.spawn().insert(Sun {
+            direction: -Vec3::new(0.3, 0.45, -1.0).normalize(),
+            color: Vec3::new(1.0, 0.95, 0.85),
+        });
+
+        let diffuse_cubemap = Url::parse(DIFFUSE_CUBEMAP_URL).unwrap();
+        let specular_cubemap = Url::parse(SPECULAR_CUBEMAP_URL).unwrap();
+
+        // Fetched through the download queue as well so that their formats get reported.
+        for url in [&diffuse_cubemap, &specular_cubemap] {
+            app.world.spawn().insert(PrefetchAsset {
+                url: url.clone(),
+                priority: DownloadPriority::Environment,
+            });
+        }
+
+        app.insert_resource(NewIblTextures(Some(NewIblTexturesInner {
+            diffuse_cubemap,
+            specular_cubemap,
+        })));
+    }
+}
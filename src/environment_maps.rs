@@ -0,0 +1,618 @@
+use std::f32::consts::PI;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+    resources::{NewIblTextures, NewIblTexturesInner},
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::AssetError;
+use crate::downloads::DownloadManager;
+use crate::hot_reload::AssetChanged;
+
+const DIFFUSE_SIZE: usize = 32;
+const SPECULAR_SIZE: usize = 128;
+const SPECULAR_SAMPLES: u32 = 64;
+// Irradiance is very low frequency, so it's computed from a small copy of the panorama.
+const IRRADIANCE_SOURCE_WIDTH: usize = 128;
+// Bigger panoramas are halved down to this before anything else. The specular cubemap's faces
+// are only SPECULAR_SIZE across, so the extra detail would be averaged away anyway.
+const MAX_SOURCE_WIDTH: usize = 2048;
+
+const VK_FORMAT_R16G16B16A16_SFLOAT: u32 = 97;
+const KTX2_IDENTIFIER: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+
+// Set with ENVIRONMENT_MAP on desktop or ?environment= on the web, pointing at an
+// equirectangular .hdr or .exr (such as the ones on polyhaven).
+pub fn environment_map_url() -> Option<Url> {
+    #[cfg(not(feature = "wasm"))]
+    let url = std::env::var("ENVIRONMENT_MAP").ok();
+
+    #[cfg(feature = "wasm")]
+    let url = crate::query_parameter("environment");
+
+    let url = url?;
+
+    match Url::parse(&url) {
+        Ok(url) => Some(url),
+        Err(error) => {
+            log::warn!("Invalid environment map url {:?}: {}", url, error);
+            None
+        }
+    }
+}
+
+struct Panorama {
+    width: usize,
+    height: usize,
+    pixels: Vec<Vec3>,
+}
+
+impl Panorama {
+    fn half_size(&self) -> Self {
+        let width = (self.width / 2).max(1);
+        let height = (self.height / 2).max(1);
+
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| {
+                let mut sum = Vec3::ZERO;
+
+                for (dx, dy) in [(0, 0), (1, 0), (0, 1), (1, 1)] {
+                    let x = (x * 2 + dx).min(self.width - 1);
+                    let y = (y * 2 + dy).min(self.height - 1);
+                    sum += self.pixels[y * self.width + x];
+                }
+
+                sum / 4.0
+            })
+            .collect();
+
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn texel(&self, x: isize, y: isize) -> Vec3 {
+        let x = x.rem_euclid(self.width as isize) as usize;
+        let y = y.clamp(0, self.height as isize - 1) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    fn sample(&self, direction: Vec3) -> Vec3 {
+        let u = direction.z.atan2(direction.x) / (2.0 * PI) + 0.5;
+        let v = direction.y.clamp(-1.0, 1.0).acos() / PI;
+
+        let x = u * self.width as f32 - 0.5;
+        let y = v * self.height as f32 - 0.5;
+        let (fx, fy) = (x - x.floor(), y - y.floor());
+        let (x, y) = (x.floor() as isize, y.floor() as isize);
+
+        let top = self.texel(x, y).lerp(self.texel(x + 1, y), fx);
+        let bottom = self.texel(x, y + 1).lerp(self.texel(x + 1, y + 1), fx);
+
+        top.lerp(bottom, fy)
+    }
+}
+
+// Cube faces in the +X, -X, +Y, -Y, +Z, -Z order that KTX2 uses.
+fn face_direction(face: usize, u: f32, v: f32) -> Vec3 {
+    match face {
+        0 => Vec3::new(1.0, -v, -u),
+        1 => Vec3::new(-1.0, -v, u),
+        2 => Vec3::new(u, 1.0, v),
+        3 => Vec3::new(u, -1.0, -v),
+        4 => Vec3::new(u, -v, 1.0),
+        _ => Vec3::new(-u, -v, -1.0),
+    }
+    .normalize()
+}
+
+fn render_face(size: usize, mut texel: impl FnMut(Vec3) -> Vec3) -> Vec<Vec3> {
+    let mut pixels = Vec::with_capacity(6 * size * size);
+
+    for face in 0..6 {
+        for y in 0..size {
+            for x in 0..size {
+                let u = (x as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                let v = (y as f32 + 0.5) / size as f32 * 2.0 - 1.0;
+                pixels.push(texel(face_direction(face, u, v)));
+            }
+        }
+    }
+
+    pixels
+}
+
+fn spherical_harmonics(direction: Vec3) -> [f32; 9] {
+    let Vec3 { x, y, z } = direction;
+
+    [
+        0.282095,
+        0.488603 * y,
+        0.488603 * z,
+        0.488603 * x,
+        1.092548 * x * y,
+        1.092548 * y * z,
+        0.315392 * (3.0 * z * z - 1.0),
+        1.092548 * x * z,
+        0.546274 * (x * x - y * y),
+    ]
+}
+
+// Projects the panorama onto 9 spherical harmonics and evaluates the cosine convolution of them,
+// divided by pi so that it can be multiplied by the albedo directly.
+fn diffuse_cubemap(panorama: &Panorama) -> Vec<Vec3> {
+    let mut coefficients = [Vec3::ZERO; 9];
+
+    for y in 0..panorama.height {
+        let theta = (y as f32 + 0.5) / panorama.height as f32 * PI;
+        let solid_angle =
+            (2.0 * PI / panorama.width as f32) * (PI / panorama.height as f32) * theta.sin();
+
+        for x in 0..panorama.width {
+            let phi = ((x as f32 + 0.5) / panorama.width as f32 - 0.5) * 2.0 * PI;
+            let direction = Vec3::new(
+                theta.sin() * phi.cos(),
+                theta.cos(),
+                theta.sin() * phi.sin(),
+            );
+            let radiance = panorama.pixels[y * panorama.width + x];
+
+            for (coefficient, basis) in coefficients.iter_mut().zip(spherical_harmonics(direction))
+            {
+                *coefficient += radiance * basis * solid_angle;
+            }
+        }
+    }
+
+    let bands = [
+        1.0,
+        2.0 / 3.0,
+        2.0 / 3.0,
+        2.0 / 3.0,
+        0.25,
+        0.25,
+        0.25,
+        0.25,
+        0.25,
+    ];
+
+    render_face(DIFFUSE_SIZE, |direction| {
+        coefficients
+            .iter()
+            .zip(spherical_harmonics(direction))
+            .zip(bands)
+            .fold(Vec3::ZERO, |sum, ((coefficient, basis), band)| {
+                sum + *coefficient * basis * band
+            })
+            .max(Vec3::ZERO)
+    })
+}
+
+fn hammersley(index: u32, count: u32) -> (f32, f32) {
+    (
+        index as f32 / count as f32,
+        index.reverse_bits() as f32 / u32::MAX as f32,
+    )
+}
+
+fn importance_sample_ggx((xi_x, xi_y): (f32, f32), normal: Vec3, alpha: f32) -> Vec3 {
+    let phi = 2.0 * PI * xi_x;
+    let cos_theta = ((1.0 - xi_y) / (1.0 + (alpha * alpha - 1.0) * xi_y)).sqrt();
+    let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+    let up = if normal.z.abs() < 0.999 {
+        Vec3::Z
+    } else {
+        Vec3::X
+    };
+    let tangent = up.cross(normal).normalize();
+    let bitangent = normal.cross(tangent);
+
+    (tangent * phi.cos() * sin_theta + bitangent * phi.sin() * sin_theta + normal * cos_theta)
+        .normalize()
+}
+
+// GGX prefiltered mips, with roughness going linearly from 0 at the top mip to 1 at the bottom.
+// Samples are taken from blurrier copies of the panorama as their footprint grows, which keeps
+// the sample count low without fireflies.
+fn specular_cubemap(pyramid: &[Panorama]) -> Vec<Vec<Vec3>> {
+    let levels = SPECULAR_SIZE.trailing_zeros() as usize + 1;
+    let texel_solid_angle = 4.0 * PI / (pyramid[0].width * pyramid[0].height) as f32;
+
+    (0..levels)
+        .map(|level| {
+            let size = SPECULAR_SIZE >> level;
+            let roughness = level as f32 / (levels - 1) as f32;
+            let alpha = roughness * roughness;
+
+            // The mirror-like top mip still wants a copy of the panorama close to its own
+            // resolution, as an 8k one would alias badly.
+            if level == 0 {
+                let source = pyramid
+                    .iter()
+                    .find(|panorama| panorama.width <= size * 4)
+                    .unwrap_or_else(|| pyramid.last().unwrap());
+
+                return render_face(size, |direction| source.sample(direction));
+            }
+
+            render_face(size, |normal| {
+                let mut sum = Vec3::ZERO;
+                let mut weight = 0.0;
+
+                for index in 0..SPECULAR_SAMPLES {
+                    let half =
+                        importance_sample_ggx(hammersley(index, SPECULAR_SAMPLES), normal, alpha);
+                    let light = 2.0 * normal.dot(half) * half - normal;
+                    let n_dot_l = normal.dot(light);
+
+                    if n_dot_l <= 0.0 {
+                        continue;
+                    }
+
+                    let n_dot_h = normal.dot(half).max(0.0);
+                    let a2 = alpha * alpha;
+                    let d = a2 / (PI * (n_dot_h * n_dot_h * (a2 - 1.0) + 1.0).powi(2));
+                    let sample_solid_angle = 4.0 / (SPECULAR_SAMPLES as f32 * d).max(f32::EPSILON);
+
+                    let lod = (0.5 * (sample_solid_angle / texel_solid_angle).log2() + 1.0)
+                        .clamp(0.0, (pyramid.len() - 1) as f32);
+
+                    sum += pyramid[lod.round() as usize].sample(light) * n_dot_l;
+                    weight += n_dot_l;
+                }
+
+                sum / weight.max(f32::EPSILON)
+            })
+        })
+        .collect()
+}
+
+fn f16_bits(value: f32) -> u16 {
+    let sign = ((value.to_bits() >> 16) & 0x8000) as u16;
+    let value = value.abs().min(65504.0);
+
+    // Subnormals (and zero).
+    if value < 6.103_515_6e-5 {
+        return sign | (value / 5.960_464_5e-8).round() as u16;
+    }
+
+    let bits = value.to_bits();
+    let exponent = ((bits >> 23) as i32 - 127 + 15) as u16;
+    let mantissa = ((bits >> 13) & 0x3ff) as u16;
+
+    sign | (exponent << 10) | mantissa
+}
+
+// An uncompressed rgba16f cubemap, with `levels` being the face-major pixels of each mip.
+fn write_ktx2_cubemap(size: usize, levels: &[Vec<Vec3>]) -> Vec<u8> {
+    const TEXEL_BYTES: usize = 8;
+    const DFD_LENGTH: usize = 4 + 24 + 16 * 4;
+
+    let dfd_offset = 80 + 24 * levels.len();
+
+    let mut output = Vec::new();
+    output.extend_from_slice(KTX2_IDENTIFIER);
+
+    for value in [
+        VK_FORMAT_R16G16B16A16_SFLOAT,
+        2,
+        size as u32,
+        size as u32,
+        0,
+        0,
+        6,
+        levels.len() as u32,
+        0,
+        dfd_offset as u32,
+        DFD_LENGTH as u32,
+        0,
+        0,
+    ] {
+        output.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // No supercompression global data.
+    output.extend_from_slice(&[0; 16]);
+
+    // The level index comes before the data, so reserve it and fill it in afterwards.
+    let level_index = output.len();
+    output.resize(level_index + 24 * levels.len(), 0);
+
+    // A basic data format descriptor: linear BT709 rgba, each channel a signed 16 bit float.
+    output.extend_from_slice(&(DFD_LENGTH as u32).to_le_bytes());
+    output.extend_from_slice(&0u32.to_le_bytes());
+    output.extend_from_slice(&2u16.to_le_bytes());
+    output.extend_from_slice(&((DFD_LENGTH - 4) as u16).to_le_bytes());
+    output.extend_from_slice(&[
+        1,
+        1,
+        1,
+        0,
+        0,
+        0,
+        0,
+        0,
+        TEXEL_BYTES as u8,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+        0,
+    ]);
+
+    for channel in 0..4u8 {
+        let channel_id = if channel == 3 { 15 } else { channel };
+
+        output.extend_from_slice(&(channel as u16 * 16).to_le_bytes());
+        output.extend_from_slice(&[15, channel_id | 0xC0, 0, 0, 0, 0]);
+        output.extend_from_slice(&(-1.0f32).to_bits().to_le_bytes());
+        output.extend_from_slice(&1.0f32.to_bits().to_le_bytes());
+    }
+
+    // Mip data is stored smallest first.
+    for (level, pixels) in levels.iter().enumerate().rev() {
+        while output.len() % TEXEL_BYTES != 0 {
+            output.push(0);
+        }
+
+        let offset = output.len();
+
+        for pixel in pixels {
+            for value in [pixel.x, pixel.y, pixel.z, 1.0] {
+                output.extend_from_slice(&f16_bits(value).to_le_bytes());
+            }
+        }
+
+        let length = (output.len() - offset) as u64;
+        let entry = level_index + level * 24;
+
+        output[entry..entry + 8].copy_from_slice(&(offset as u64).to_le_bytes());
+        output[entry + 8..entry + 16].copy_from_slice(&length.to_le_bytes());
+        output[entry + 16..entry + 24].copy_from_slice(&length.to_le_bytes());
+    }
+
+    output
+}
+
+// Returns the diffuse and specular cubemaps as ktx2 files.
+fn convert(bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+    let _span = tracing::info_span!("convert_environment_map").entered();
+
+    let image = image::load_from_memory(bytes)
+        .map_err(|error| error.to_string())?
+        .to_rgb32f();
+
+    let mut panorama = Panorama {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        pixels: image
+            .pixels()
+            .map(|pixel| Vec3::from(pixel.0).max(Vec3::ZERO))
+            .collect(),
+    };
+
+    drop(image);
+
+    while panorama.width > MAX_SOURCE_WIDTH {
+        panorama = panorama.half_size();
+    }
+
+    let mut pyramid = vec![panorama];
+
+    while pyramid.last().unwrap().height > 1 {
+        let next = pyramid.last().unwrap().half_size();
+        pyramid.push(next);
+    }
+
+    let irradiance_source = pyramid
+        .iter()
+        .find(|panorama| panorama.width <= IRRADIANCE_SOURCE_WIDTH)
+        .unwrap_or_else(|| pyramid.last().unwrap());
+
+    let diffuse = write_ktx2_cubemap(DIFFUSE_SIZE, &[diffuse_cubemap(irradiance_source)]);
+    let specular = write_ktx2_cubemap(SPECULAR_SIZE, &specular_cubemap(&pyramid));
+
+    Ok((diffuse, specular))
+}
+
+pub(crate) struct EnvironmentMaps {
+    source: Option<Url>,
+    sender: Sender<Result<NewIblTexturesInner, AssetError>>,
+    receiver: Receiver<Result<NewIblTexturesInner, AssetError>>,
+}
+
+impl EnvironmentMaps {
+    pub fn source(&self) -> Option<&Url> {
+        self.source.as_ref()
+    }
+
+    fn generate(&self, manager: &DownloadManager) {
+        let url = match &self.source {
+            Some(url) => url.clone(),
+            None => return,
+        };
+
+        let cache = manager.cache();
+        let sender = self.sender.clone();
+
+        log::info!("Generating cubemaps from {}", url);
+
+        manager.spawn(async move {
+            let result: Result<_, AssetError> = async {
+                let bytes = cache.fetch(&url).await?;
+
+                #[cfg(not(feature = "wasm"))]
+                let converted = tokio::task::spawn_blocking(move || convert(&bytes))
+                    .await
+                    .map_err(|error| AssetError::Decode(error.to_string()))?;
+
+                #[cfg(feature = "wasm")]
+                let converted = worker::convert(&bytes).await;
+
+                let (diffuse, specular) = converted.map_err(AssetError::Decode)?;
+
+                Ok(NewIblTexturesInner {
+                    diffuse_cubemap: cache.store_derived(&url, "diffuse.ktx2", &diffuse)?,
+                    specular_cubemap: cache.store_derived(&url, "specular.ktx2", &specular)?,
+                })
+            }
+            .await;
+
+            if let Err(error) = &result {
+                log::warn!("Failed to generate cubemaps from {}: {}", url, error);
+            }
+
+            let _ = sender.send(result);
+        });
+    }
+}
+
+// The worker's side of `worker::convert`, returning the diffuse and specular cubemaps.
+#[cfg(feature = "wasm")]
+#[wasm_bindgen::prelude::wasm_bindgen]
+pub fn convert_environment_map(bytes: &[u8]) -> Result<js_sys::Array, wasm_bindgen::JsValue> {
+    let (diffuse, specular) = convert(bytes).map_err(wasm_bindgen::JsValue::from)?;
+
+    Ok(js_sys::Array::of2(
+        &js_sys::Uint8Array::from(diffuse.as_slice()),
+        &js_sys::Uint8Array::from(specular.as_slice()),
+    ))
+}
+
+#[cfg(feature = "wasm")]
+mod worker {
+    use wasm_bindgen::{JsCast, JsValue};
+    use wasm_bindgen_futures::JsFuture;
+
+    // Next to the page, and loads the module from pkg/ itself.
+    const SCRIPT: &str = "environment_worker.js";
+
+    fn js_error(error: JsValue) -> String {
+        format!("{:?}", error)
+    }
+
+    // Decoding and prefiltering a panorama takes seconds, so on the web it's done in a worker
+    // with an instance of the module of its own, rather than holding up the main thread. The
+    // worker only lives for the one conversion.
+    pub async fn convert(bytes: &[u8]) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let mut options = web_sys::WorkerOptions::new();
+        options.type_(web_sys::WorkerType::Module);
+
+        let worker = web_sys::Worker::new_with_options(SCRIPT, &options).map_err(js_error)?;
+
+        let reply = js_sys::Promise::new(&mut |resolve, reject| {
+            worker.set_onmessage(Some(&resolve));
+            worker.set_onerror(Some(&reject));
+        });
+
+        let array = js_sys::Uint8Array::from(bytes);
+
+        worker
+            .post_message_with_transfer(&array, &js_sys::Array::of1(&array.buffer()))
+            .map_err(js_error)?;
+
+        let reply = JsFuture::from(reply).await;
+        worker.terminate();
+
+        let data = reply
+            .map_err(|_| format!("{} failed to load", SCRIPT))?
+            .unchecked_into::<web_sys::MessageEvent>()
+            .data();
+
+        let field = |name: &str| {
+            js_sys::Reflect::get(&data, &name.into())
+                .ok()
+                .filter(|value| !value.is_undefined())
+        };
+
+        if let Some(error) = field("error") {
+            return Err(error.as_string().unwrap_or_else(|| js_error(error)));
+        }
+
+        let cubemap = |name: &str| {
+            field(name)
+                .map(|value| js_sys::Uint8Array::new(&value).to_vec())
+                .ok_or_else(|| format!("the worker didn't send back the {} cubemap", name))
+        };
+
+        Ok((cubemap("diffuse")?, cubemap("specular")?))
+    }
+}
+
+// Converts an equirectangular panorama into the cubemaps used for image based lighting. The
+// default cubemaps stay in use until the conversion is done.
+pub struct EnvironmentMapPlugin;
+
+impl Plugin for EnvironmentMapPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        let maps = EnvironmentMaps {
+            source: environment_map_url(),
+            sender,
+            receiver,
+        };
+
+        maps.generate(app.world.resource::<DownloadManager>());
+
+        app.insert_resource(maps);
+
+        app.add_system(regenerate_changed_environment_map);
+        app.add_system(apply_environment_maps);
+    }
+}
+
+fn regenerate_changed_environment_map(
+    mut changes: EventReader<AssetChanged>,
+    maps: Res<EnvironmentMaps>,
+    manager: Res<DownloadManager>,
+) {
+    if changes
+        .iter()
+        .any(|changed| Some(&changed.0) == maps.source.as_ref())
+    {
+        maps.generate(&manager);
+    }
+}
+
+fn apply_environment_maps(maps: Res<EnvironmentMaps>, mut ibl: ResMut<NewIblTextures>) {
+    for textures in maps.receiver.try_iter().flatten() {
+        ibl.0 = Some(textures);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn half_floats() {
+        assert_eq!(f16_bits(0.0), 0x0000);
+        assert_eq!(f16_bits(-0.0), 0x8000);
+        assert_eq!(f16_bits(1.0), 0x3c00);
+        assert_eq!(f16_bits(0.5), 0x3800);
+        assert_eq!(f16_bits(-2.0), 0xc000);
+        assert_eq!(f16_bits(1.0 + 1.0 / 1024.0), 0x3c01);
+        assert_eq!(f16_bits(65504.0), 0x7bff);
+    }
+
+    #[test]
+    fn half_float_edges() {
+        // Too big for a half float, so clamped rather than turned into infinity.
+        assert_eq!(f16_bits(1.0e6), 0x7bff);
+        assert_eq!(f16_bits(-1.0e6), 0xfbff);
+        // The smallest subnormal, and the smallest normal number.
+        assert_eq!(f16_bits(5.960_464_5e-8), 0x0001);
+        assert_eq!(f16_bits(6.103_515_6e-5), 0x0400);
+    }
+}
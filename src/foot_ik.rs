@@ -0,0 +1,347 @@
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Or, Query, Res, With, Without, World},
+    components,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::bounds::{Aabb, Bounds, BoundsOutline};
+use crate::console::Console;
+use crate::env_setting;
+use crate::schedule;
+use crate::settings::{self, Setting};
+use crate::terrain::{Heightfield, TerrainChunk};
+use crate::time_scale::TimeScale;
+use crate::{LocalPlayer, PlayerState, PlayerStates, RemotePlayer};
+
+// For avatars without bounds yet, in meters before scaling.
+const DEFAULT_HEIGHT: f32 = 1.7;
+// Proportions of the avatar's height.
+const HIP_HEIGHT: f32 = 0.5;
+const FOOT_SPACING: f32 = 0.1;
+const UPPER_LEG: f32 = 0.25;
+const LOWER_LEG: f32 = 0.25;
+// How far the pelvis can drop to reach the lower foot's ground, or rise onto a step, as a
+// share of the avatar's height.
+const MAX_DROP: f32 = 0.3;
+const MAX_RISE: f32 = 0.3;
+// How far around each foot the ground is sampled for its slope, in meters.
+const SLOPE_SAMPLE: f32 = 0.1;
+// How quickly the pelvis follows the ground, per second.
+const PELVIS_SPEED: f32 = 10.0;
+// Avatars further than this from the camera keep their last placement.
+const MAX_DISTANCE: f32 = 20.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FootIkSettings {
+    pub enabled: bool,
+}
+
+impl Setting for FootIkSettings {
+    const KEY: &'static str = "foot_ik";
+}
+
+impl FootIkSettings {
+    // FOOT_IK=0 on desktop or ?foot_ik=0 on the web turns it off, to save the raycasts.
+    pub fn from_env() -> Self {
+        Self {
+            enabled: env_setting("FOOT_IK", "foot_ik").map_or(true, |value| value != "0"),
+        }
+    }
+}
+
+// Where a leg should be for its foot to rest on the ground, in world space.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LegPose {
+    pub hip: Vec3,
+    pub knee: Vec3,
+    pub foot: Vec3,
+    // The ground's normal under the foot, for tilting it onto the slope.
+    pub ground_normal: Vec3,
+}
+
+// The foot placement of a grounded avatar. The pelvis is lowered or raised by moving the
+// avatar's instance, as `pelvis_offset` says, so that the lower foot rests on the ground. The
+// leg poses are solved too, but the renderer skins with the animation's bones and there's no
+// way to set bones from game code, so the other foot isn't bent onto the ground until it can.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct FootPlacement {
+    pub left: Option<LegPose>,
+    pub right: Option<LegPose>,
+    pub pelvis_offset: f32,
+    // Where the instance would be without the offset, as set by whatever moves the avatar.
+    base: Vec3,
+}
+
+pub struct FootIkPlugin;
+
+impl Plugin for FootIkPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(FootIkSettings::from_env());
+        settings::persist::<FootIkSettings>(app);
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register("foot_ik", "foot_ik <on | off>", set_foot_ik);
+
+        app.add_system_set(schedule::animation_sync().with_system(place_feet));
+    }
+}
+
+fn set_foot_ik(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let enabled = match args.get(0).copied() {
+        Some("on") => true,
+        Some("off") => false,
+        _ => return Err("Expected on or off".into()),
+    };
+
+    world.resource_mut::<FootIkSettings>().enabled = enabled;
+
+    Ok(String::new())
+}
+
+fn is_grounded(state: PlayerStates) -> bool {
+    matches!(
+        state,
+        PlayerStates::Idle
+            | PlayerStates::LeftTurnFeet
+            | PlayerStates::RightTurnFeet
+            | PlayerStates::Running
+            | PlayerStates::Standin
+            | PlayerStates::StandingPose
+            | PlayerStates::StartWalking
+            | PlayerStates::Walking
+    )
+}
+
+// Casts a ray straight down from `from`, against the terrain and the boxes around everything
+// else, and returns the height of the first thing it hits. Boxes the ray starts inside of are
+// skipped, so that walking through a tree doesn't put the avatar on top of it.
+pub(crate) fn ground_height(
+    from: Vec3,
+    heightfields: &[&Heightfield],
+    colliders: &[Aabb],
+) -> Option<f32> {
+    let terrain = heightfields
+        .iter()
+        .filter_map(|heightfield| heightfield.height_at(from.x, from.z))
+        .filter(|&height| height <= from.y);
+
+    let boxes = colliders
+        .iter()
+        .filter_map(|aabb| aabb.ray_distance(from, -Vec3::Y))
+        .filter(|&distance| distance > 0.0)
+        .map(|distance| from.y - distance);
+
+    terrain.chain(boxes).reduce(f32::max)
+}
+
+fn ground_normal(
+    point: Vec3,
+    from_y: f32,
+    heightfields: &[&Heightfield],
+    colliders: &[Aabb],
+) -> Vec3 {
+    let height = |dx: f32, dz: f32| {
+        ground_height(
+            Vec3::new(point.x + dx, from_y, point.z + dz),
+            heightfields,
+            colliders,
+        )
+        .unwrap_or(point.y)
+    };
+
+    let dx = height(SLOPE_SAMPLE, 0.0) - height(-SLOPE_SAMPLE, 0.0);
+    let dz = height(0.0, SLOPE_SAMPLE) - height(0.0, -SLOPE_SAMPLE);
+
+    Vec3::new(-dx, 2.0 * SLOPE_SAMPLE, -dz).normalize()
+}
+
+// Solves a two bone chain from `root` towards `target`, bending towards `pole`, and returns
+// the middle joint and where the end reaches. Targets out of reach are stretched towards.
+pub fn solve_two_bone(
+    root: Vec3,
+    target: Vec3,
+    pole: Vec3,
+    upper: f32,
+    lower: f32,
+) -> (Vec3, Vec3) {
+    let to_target = target - root;
+    let distance = to_target
+        .length()
+        .clamp((upper - lower).abs() + 0.001, upper + lower - 0.001);
+    let direction = to_target.normalize_or_zero();
+
+    // The law of cosines gives how far along the chain the middle joint is, and how far out.
+    let along = (upper * upper - lower * lower + distance * distance) / (2.0 * distance);
+    let out = (upper * upper - along * along).max(0.0).sqrt();
+
+    let bend = (pole - direction * pole.dot(direction)).normalize_or_zero();
+    let middle = root + direction * along + bend * out;
+
+    (middle, root + direction * distance)
+}
+
+#[allow(clippy::type_complexity)]
+fn place_feet(
+    mut commands: Commands,
+    settings: Res<FootIkSettings>,
+    time_scale: Res<TimeScale>,
+    camera: Res<Camera>,
+    heightfield_q: Query<&Heightfield>,
+    collider_q: Query<
+        &Bounds,
+        (
+            Without<LocalPlayer>,
+            Without<RemotePlayer>,
+            Without<TerrainChunk>,
+            Without<BoundsOutline>,
+        ),
+    >,
+    mut avatar_q: Query<
+        (
+            Entity,
+            &mut components::Instance,
+            Option<&PlayerState>,
+            Option<&Bounds>,
+            Option<&mut FootPlacement>,
+        ),
+        Or<(With<LocalPlayer>, With<RemotePlayer>)>,
+    >,
+) {
+    if !settings.enabled {
+        // Puts the avatars back where they'd be without it.
+        for (entity, mut instance, _, _, placement) in avatar_q.iter_mut() {
+            if let Some(placement) = placement {
+                if instance.0.position == placement.base + Vec3::Y * placement.pelvis_offset {
+                    instance.0.position = placement.base;
+                }
+
+                commands.entity(entity).remove::<FootPlacement>();
+            }
+        }
+
+        return;
+    }
+
+    let heightfields: Vec<&Heightfield> = heightfield_q.iter().collect();
+    let colliders: Vec<Aabb> = collider_q
+        .iter()
+        .map(|bounds| bounds.0)
+        .filter(|aabb| aabb.size().max_element() > 0.0)
+        .collect();
+
+    let t = (PELVIS_SPEED * time_scale.delta()).min(1.0);
+
+    for (entity, mut instance, state, bounds, placement) in avatar_q.iter_mut() {
+        if instance.0.position.distance(camera.position) > MAX_DISTANCE {
+            continue;
+        }
+
+        let mut current = match placement.as_deref() {
+            Some(placement) => *placement,
+            None => FootPlacement {
+                left: None,
+                right: None,
+                pelvis_offset: 0.0,
+                base: instance.0.position,
+            },
+        };
+
+        // Something else moved the avatar, so that's where it is now. Only the height has the
+        // offset in it, so moving across the ground (by root motion, say) keeps it.
+        let expected = current.base + Vec3::Y * current.pelvis_offset;
+
+        if instance.0.position.x != expected.x || instance.0.position.z != expected.z {
+            current.base.x = instance.0.position.x;
+            current.base.z = instance.0.position.z;
+        }
+
+        if instance.0.position.y != expected.y {
+            current.base.y = instance.0.position.y;
+        }
+
+        let grounded = state.map_or(true, |state| is_grounded(state.0));
+
+        let height = bounds
+            .map(|bounds| bounds.0.size().y)
+            .filter(|&height| height > 0.0)
+            .unwrap_or(DEFAULT_HEIGHT * instance.0.scale);
+
+        let base = current.base;
+        let hip_y = base.y + height * HIP_HEIGHT;
+        let side = instance.0.rotation * Vec3::X * height * FOOT_SPACING;
+        // Knees bend forwards, and models face +Z.
+        let forward = instance.0.rotation * Vec3::Z;
+
+        let leg = |side: Vec3| -> Option<(f32, Vec3)> {
+            let hip = Vec3::new(base.x, hip_y, base.z) + side;
+            let ground = ground_height(hip, &heightfields, &colliders)?;
+
+            if ground < base.y - height * MAX_DROP || ground > base.y + height * MAX_RISE {
+                return None;
+            }
+
+            let foot = Vec3::new(hip.x, ground, hip.z);
+            let normal = ground_normal(foot, hip_y, &heightfields, &colliders);
+
+            Some((ground, normal))
+        };
+
+        let (left, right) = if grounded {
+            (leg(side), leg(-side))
+        } else {
+            (None, None)
+        };
+
+        // The lower foot reaches the ground and the other one bends.
+        let target = left
+            .into_iter()
+            .chain(right)
+            .map(|(ground, _)| ground - base.y)
+            .reduce(f32::min)
+            .unwrap_or(0.0);
+
+        current.pelvis_offset += (target - current.pelvis_offset) * t;
+
+        let pelvis_y = hip_y + current.pelvis_offset;
+
+        let pose = |side: Vec3, ground: Option<(f32, Vec3)>| {
+            ground.map(|(ground, ground_normal)| {
+                let hip = Vec3::new(base.x, pelvis_y, base.z) + side;
+                let target = Vec3::new(hip.x, ground, hip.z);
+                let (knee, foot) =
+                    solve_two_bone(hip, target, forward, height * UPPER_LEG, height * LOWER_LEG);
+
+                LegPose {
+                    hip,
+                    knee,
+                    foot,
+                    ground_normal,
+                }
+            })
+        };
+
+        current.left = pose(side, left);
+        current.right = pose(-side, right);
+
+        let position = base + Vec3::Y * current.pelvis_offset;
+
+        if instance.0.position != position {
+            instance.0.position = position;
+        }
+
+        match placement {
+            Some(mut placement) => {
+                if *placement != current {
+                    *placement = current;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(current);
+            }
+        }
+    }
+}
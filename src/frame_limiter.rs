@@ -0,0 +1,112 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Local, Res, ResMut},
+};
+
+use crate::input::WindowFocused;
+use crate::schedule::{self, SimulationSteps};
+use crate::DELTA_TIME;
+
+pub const DEFAULT_UNFOCUSED_FPS: f32 = 10.0;
+
+// How many simulation steps a capped frame can catch up on, so that a stalled frame doesn't
+// turn into a long burst of steps.
+const MAX_STEPS_PER_FRAME: u32 = 8;
+
+// Caps how often frames are rendered, so that the playground doesn't keep the gpu busy when
+// nobody is looking at it. Desktop only: on the web, frames come from superconductor's
+// requestAnimationFrame loop, which the playground can't pause or reschedule, so the
+// browser's own throttling of hidden tabs is all there is.
+pub struct FrameLimiter {
+    // None for uncapped (vsync permitting).
+    pub target_fps: Option<f32>,
+    // Used instead of the target while the window doesn't have focus.
+    pub unfocused_fps: Option<f32>,
+    pub focused: bool,
+}
+
+impl FrameLimiter {
+    // Set with FPS_LIMIT and UNFOCUSED_FPS_LIMIT. A limit of 0 turns the cap off.
+    pub fn from_env() -> Self {
+        let parse = |name: &str, default: Option<f32>| match std::env::var(name) {
+            Ok(value) => match value.parse::<f32>() {
+                Ok(fps) if fps > 0.0 => Some(fps),
+                Ok(_) => None,
+                Err(_) => {
+                    log::warn!("Invalid frame rate limit {:?}", value);
+                    default
+                }
+            },
+            Err(_) => default,
+        };
+
+        Self {
+            target_fps: parse("FPS_LIMIT", None),
+            unfocused_fps: parse("UNFOCUSED_FPS_LIMIT", Some(DEFAULT_UNFOCUSED_FPS)),
+            focused: true,
+        }
+    }
+
+    pub fn effective_fps(&self) -> Option<f32> {
+        if self.focused {
+            self.target_fps
+        } else {
+            self.unfocused_fps.or(self.target_fps)
+        }
+    }
+
+    // How many `DELTA_TIME` steps the simulation takes each frame, so that it keeps to real
+    // time while frames are capped below 60 fps. Uncapped frames take a single step, as they
+    // always have.
+    pub fn simulation_steps(&self) -> u32 {
+        match self.effective_fps() {
+            Some(fps) => ((1.0 / (fps * DELTA_TIME)).round() as u32).clamp(1, MAX_STEPS_PER_FRAME),
+            None => 1,
+        }
+    }
+}
+
+pub struct FrameLimiterPlugin;
+
+impl Plugin for FrameLimiterPlugin {
+    fn build(&self, app: &mut App) {
+        let limiter = FrameLimiter::from_env();
+
+        if let Some(fps) = limiter.target_fps {
+            log::info!("Limiting the frame rate to {} fps", fps);
+        }
+
+        app.insert_resource(limiter);
+        app.init_resource::<SimulationSteps>();
+
+        app.add_system_set(schedule::input().with_system(track_focus));
+        app.add_system(limit_frame_rate);
+    }
+}
+
+fn track_focus(
+    mut focus_changes: EventReader<WindowFocused>,
+    mut limiter: ResMut<FrameLimiter>,
+    mut steps: ResMut<SimulationSteps>,
+) {
+    for WindowFocused(focused) in focus_changes.iter() {
+        limiter.focused = *focused;
+    }
+
+    steps.0 = limiter.simulation_steps();
+}
+
+// Sleeps away whatever is left of the frame's time budget.
+fn limit_frame_rate(limiter: Res<FrameLimiter>, mut last_frame: Local<Option<std::time::Instant>>) {
+    let _span = tracing::info_span!("limit_frame_rate").entered();
+
+    if let (Some(fps), Some(last_frame)) = (limiter.effective_fps(), *last_frame) {
+        let budget = std::time::Duration::from_secs_f32(1.0 / fps);
+
+        if let Some(remaining) = budget.checked_sub(last_frame.elapsed()) {
+            std::thread::sleep(remaining);
+        }
+    }
+
+    *last_frame = Some(std::time::Instant::now());
+}
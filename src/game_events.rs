@@ -0,0 +1,243 @@
+use serde::Serialize;
+use superconductor::{
+    bevy_app::{App, CoreStage, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{
+        Added, Changed, Component, Entity, Or, Query, RemovedComponents, Res, With,
+    },
+    components,
+    resources::Camera,
+};
+
+use crate::bounds::Aabb;
+use crate::camera_focus::Selected;
+use crate::downloads::{ModelInspected, ModelSource};
+use crate::{schedule, LocalPlayer, PlayerState, PlayerStates};
+
+// Things that happen in the scene that the rest of the app, or the page hosting it, might want
+// to react to. Entities are identified by `Entity::to_bits`.
+#[derive(Clone, Debug, Serialize)]
+#[serde(tag = "type")]
+pub enum GameEvent {
+    PlayerJumped {
+        entity: u64,
+    },
+    ModelLoaded {
+        entity: u64,
+        url: String,
+    },
+    // The camera moved into the trigger's volume.
+    TriggerEntered {
+        trigger: u64,
+    },
+    TriggerExited {
+        trigger: u64,
+    },
+    // The model's materials use extensions that the renderer doesn't have, so they were
+    // approximated or dropped.
+    MaterialFallbacks {
+        entity: u64,
+        url: String,
+        extensions: Vec<String>,
+    },
+    // Every selected entity, after the change.
+    SelectionChanged {
+        selected: Vec<u64>,
+    },
+    // Sent before the app starts, when the browser has neither WebGPU nor WebGL2.
+    GraphicsUnsupported {
+        webgpu: bool,
+        webgl2: bool,
+    },
+}
+
+// A world space volume that sends `TriggerEntered` and `TriggerExited` as the camera moves in
+// and out of it.
+#[derive(Component)]
+pub struct Trigger {
+    pub volume: Aabb,
+    inside: bool,
+}
+
+impl Trigger {
+    pub fn new(volume: Aabb) -> Self {
+        Self {
+            volume,
+            inside: false,
+        }
+    }
+}
+
+pub struct GameEventPlugin;
+
+impl Plugin for GameEventPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<GameEvent>();
+
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(send_player_jumped)
+                .with_system(send_model_loaded)
+                .with_system(send_material_fallbacks)
+                .with_system(send_trigger_events)
+                .with_system(send_selection_changed),
+        );
+
+        #[cfg(feature = "wasm")]
+        app.add_system_to_stage(CoreStage::PostUpdate, js::forward_game_events);
+
+        #[cfg(not(feature = "wasm"))]
+        app.add_system_to_stage(CoreStage::PostUpdate, log_game_events);
+    }
+}
+
+fn send_player_jumped(
+    mut events: EventWriter<GameEvent>,
+    player_q: Query<(Entity, &PlayerState), (With<LocalPlayer>, Changed<PlayerState>)>,
+) {
+    for (entity, state) in player_q.iter() {
+        if state.0 == PlayerStates::Jump {
+            events.send(GameEvent::PlayerJumped {
+                entity: entity.to_bits(),
+            });
+        }
+    }
+}
+
+fn send_model_loaded(
+    mut events: EventWriter<GameEvent>,
+    model_q: Query<
+        (Entity, &ModelSource),
+        Or<(Added<components::Model>, Added<components::AnimatedModel>)>,
+    >,
+) {
+    for (entity, source) in model_q.iter() {
+        events.send(GameEvent::ModelLoaded {
+            entity: entity.to_bits(),
+            url: source.0.url.to_string(),
+        });
+    }
+}
+
+fn send_material_fallbacks(
+    mut events: EventWriter<GameEvent>,
+    mut inspected: EventReader<ModelInspected>,
+) {
+    for model in inspected.iter().filter(|model| !model.fallbacks.is_empty()) {
+        events.send(GameEvent::MaterialFallbacks {
+            entity: model.entity.to_bits(),
+            url: model.url.to_string(),
+            extensions: model
+                .fallbacks
+                .iter()
+                .map(|fallback| fallback.extension.to_string())
+                .collect(),
+        });
+    }
+}
+
+fn send_trigger_events(
+    mut events: EventWriter<GameEvent>,
+    camera: Res<Camera>,
+    mut trigger_q: Query<(Entity, &mut Trigger)>,
+) {
+    for (entity, mut trigger) in trigger_q.iter_mut() {
+        let volume = trigger.volume;
+        let inside =
+            camera.position.cmpge(volume.min).all() && camera.position.cmple(volume.max).all();
+
+        if inside == trigger.inside {
+            continue;
+        }
+
+        trigger.inside = inside;
+
+        let trigger = entity.to_bits();
+
+        events.send(if inside {
+            GameEvent::TriggerEntered { trigger }
+        } else {
+            GameEvent::TriggerExited { trigger }
+        });
+    }
+}
+
+fn send_selection_changed(
+    mut events: EventWriter<GameEvent>,
+    added_q: Query<(), Added<Selected>>,
+    removed: RemovedComponents<Selected>,
+    selected_q: Query<Entity, With<Selected>>,
+) {
+    if added_q.is_empty() && removed.iter().next().is_none() {
+        return;
+    }
+
+    events.send(GameEvent::SelectionChanged {
+        selected: selected_q.iter().map(Entity::to_bits).collect(),
+    });
+}
+
+#[cfg(not(feature = "wasm"))]
+fn log_game_events(mut events: EventReader<GameEvent>) {
+    for event in events.iter() {
+        log::debug!("{:?}", event);
+    }
+}
+
+#[cfg(feature = "wasm")]
+pub(crate) use js::send_before_start;
+
+#[cfg(feature = "wasm")]
+mod js {
+    use std::cell::RefCell;
+
+    use wasm_bindgen::prelude::{wasm_bindgen, JsValue};
+
+    use super::{EventReader, GameEvent};
+
+    thread_local! {
+        static CALLBACK: RefCell<Option<js_sys::Function>> = RefCell::new(None);
+        // Events from before the app started, held until there's a callback.
+        static PENDING: RefCell<Vec<GameEvent>> = RefCell::new(Vec::new());
+    }
+
+    // Called by the host page with a function that receives every game event as an object
+    // with a `type` field, e.g. `{ type: "ModelLoaded", entity: 12, url: "..." }`.
+    #[wasm_bindgen]
+    pub fn set_game_event_callback(callback: Option<js_sys::Function>) {
+        if let Some(callback) = &callback {
+            for event in PENDING.with(|pending| pending.take()) {
+                call(callback, &event);
+            }
+        }
+
+        CALLBACK.with(|cell| *cell.borrow_mut() = callback);
+    }
+
+    fn call(callback: &js_sys::Function, event: &GameEvent) {
+        let value = serde_json::to_string(event)
+            .ok()
+            .and_then(|json| js_sys::JSON::parse(&json).ok())
+            .unwrap_or(JsValue::NULL);
+
+        if let Err(error) = callback.call1(&JsValue::NULL, &value) {
+            log::warn!("Game event callback failed: {:?}", error);
+        }
+    }
+
+    pub(crate) fn send_before_start(event: GameEvent) {
+        CALLBACK.with(|cell| match cell.borrow().as_ref() {
+            Some(callback) => call(callback, &event),
+            None => PENDING.with(|pending| pending.borrow_mut().push(event)),
+        });
+    }
+
+    pub(super) fn forward_game_events(mut events: EventReader<GameEvent>) {
+        CALLBACK.with(|cell| {
+            if let Some(callback) = cell.borrow().as_ref() {
+                for event in events.iter() {
+                    call(callback, event);
+                }
+            }
+        });
+    }
+}
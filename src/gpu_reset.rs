@@ -0,0 +1,123 @@
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res},
+    components,
+};
+
+use crate::downloads::ModelSource;
+
+// The renderer's gpu resources were lost, like when the device was reset or the WebGL
+// context was restored, and have been recreated empty. The ECS world is untouched, so models
+// are just loaded again. Their files come out of the asset cache, but the renderer fetches
+// the buffers and images that they refer to by url again.
+//
+// The web sends this when the canvas' WebGL context is restored. WebGPU's device loss and
+// native surface and device errors are handled inside superconductor's render loop, which
+// doesn't report them yet, so anything that does find out can send this itself.
+pub struct GpuReset;
+
+struct ResetChannel {
+    // Kept so that the channel stays open on desktop, where nothing sends to it yet.
+    _sender: Sender<()>,
+    receiver: Receiver<()>,
+}
+
+pub struct GpuResetPlugin;
+
+impl Plugin for GpuResetPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        #[cfg(feature = "wasm")]
+        web::listen_for_context_restored(sender.clone());
+
+        app.insert_resource(ResetChannel {
+            _sender: sender,
+            receiver,
+        });
+        app.add_event::<GpuReset>();
+
+        app.add_system(send_gpu_resets);
+        app.add_system(reload_models_after_reset);
+    }
+}
+
+fn send_gpu_resets(channel: Res<ResetChannel>, mut resets: EventWriter<GpuReset>) {
+    if channel.receiver.try_iter().count() > 0 {
+        log::warn!("The gpu was reset, reloading everything");
+        resets.send(GpuReset);
+    }
+}
+
+fn reload_models_after_reset(
+    mut commands: Commands,
+    mut resets: EventReader<GpuReset>,
+    model_q: Query<(Entity, &ModelSource)>,
+    url_q: Query<(Entity, &components::ModelUrl)>,
+) {
+    if resets.iter().count() == 0 {
+        return;
+    }
+
+    // Downloaded models are queued again, just like hot reloading does.
+    for (entity, source) in model_q.iter() {
+        commands
+            .entity(entity)
+            .remove::<ModelSource>()
+            .remove::<components::ModelUrl>()
+            .remove::<components::AnimatedModelUrl>()
+            .remove::<components::Model>()
+            .remove::<components::AnimatedModel>()
+            .insert(source.0.clone());
+    }
+
+    // Generated ones, like primitives and terrain, are already in the cache under their url.
+    for (entity, url) in url_q.iter() {
+        if model_q.get(entity).is_err() {
+            commands
+                .entity(entity)
+                .remove::<components::Model>()
+                .remove::<components::ModelUrl>()
+                .insert(components::ModelUrl(url.0.clone()));
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod web {
+    use crossbeam_channel::Sender;
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    pub fn listen_for_context_restored(sender: Sender<()>) {
+        let canvas = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.query_selector("canvas:not(#mini-map)").ok())
+            .flatten();
+
+        let canvas = match canvas {
+            Some(canvas) => canvas,
+            None => return,
+        };
+
+        // The context is only restored if the default of giving up on it is prevented.
+        let onlost = Closure::wrap(Box::new(move |event: web_sys::Event| {
+            log::warn!("Lost the WebGL context");
+            event.prevent_default();
+        }) as Box<dyn FnMut(web_sys::Event)>);
+
+        let onrestored = Closure::wrap(Box::new(move || {
+            let _ = sender.send(());
+        }) as Box<dyn FnMut()>);
+
+        let _ = canvas
+            .add_event_listener_with_callback("webglcontextlost", onlost.as_ref().unchecked_ref());
+        let _ = canvas.add_event_listener_with_callback(
+            "webglcontextrestored",
+            onrestored.as_ref().unchecked_ref(),
+        );
+
+        onlost.forget();
+        onrestored.forget();
+    }
+}
@@ -0,0 +1,91 @@
+#[cfg(feature = "wasm")]
+fn webgpu_available() -> bool {
+    web_sys::window()
+        .and_then(|window| {
+            js_sys::Reflect::get(&window.navigator(), &"gpu".into())
+                .ok()
+                .filter(|gpu| !gpu.is_undefined())
+        })
+        .is_some()
+}
+
+#[cfg(feature = "wasm")]
+fn webgl2_available() -> bool {
+    web_sys::window()
+        .and_then(|window| window.document())
+        .and_then(|document| document.create_element("canvas").ok())
+        .and_then(|canvas| {
+            use wasm_bindgen::JsCast;
+
+            canvas
+                .unchecked_into::<web_sys::HtmlCanvasElement>()
+                .get_context("webgl2")
+                .ok()
+                .flatten()
+        })
+        .is_some()
+}
+
+// Checks that the browser has WebGPU, or WebGL2 to fall back to. When it has neither, a
+// message with the browser requirements replaces the page and a `GraphicsUnsupported` game
+// event goes to the host page, which gets it even if it sets its callback later. Which of
+// the two gets used, and the adapter, are up to superconductor.
+#[cfg(feature = "wasm")]
+pub fn supported() -> bool {
+    let webgpu = webgpu_available();
+    let webgl2 = webgl2_available();
+
+    if webgpu || webgl2 {
+        return true;
+    }
+
+    log::error!("The browser supports neither WebGPU nor WebGL2");
+
+    let language = crate::settings::load::<crate::i18n::Language>().unwrap_or_default();
+    dom::show_unsupported(&crate::i18n::Locale::select(&language));
+
+    crate::game_events::send_before_start(crate::game_events::GameEvent::GraphicsUnsupported {
+        webgpu,
+        webgl2,
+    });
+
+    false
+}
+
+#[cfg(not(feature = "wasm"))]
+pub fn supported() -> bool {
+    true
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use crate::chat::dom::create_element;
+    use crate::i18n::Locale;
+
+    const STYLE: &str = "max-width: 32em; margin: 4em auto; padding: 1em; \
+                         font-family: sans-serif; line-height: 1.5;";
+
+    pub fn show_unsupported(locale: &Locale) {
+        let body = match web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.body())
+        {
+            Some(body) => body,
+            None => return,
+        };
+
+        let message = create_element("div");
+        let _ = message.set_attribute("style", STYLE);
+        let _ = message.set_attribute("role", "alert");
+
+        let title = create_element("h2");
+        title.set_inner_text(locale.text("unsupported.title"));
+
+        let requirements = create_element("p");
+        requirements.set_inner_text(locale.text("unsupported.requirements"));
+
+        let _ = message.append_child(&title);
+        let _ = message.append_child(&requirements);
+        let _ = body.append_child(&message);
+    }
+}
@@ -0,0 +1,349 @@
+use std::collections::HashSet;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res, With},
+    components,
+    url::Url,
+};
+
+use crate::downloads::{DownloadFailed, ModelSource, QueuedModel};
+
+#[cfg(feature = "wasm")]
+const POLL_INTERVAL_FRAMES: u32 = 120;
+
+// Sent whenever a watched asset changes on the server. Only happens in dev mode.
+pub struct AssetChanged(pub Url);
+
+struct HotReload {
+    receiver: Receiver<Url>,
+    // Dropping the watcher stops it.
+    #[cfg(not(feature = "wasm"))]
+    _watcher: Option<notify::RecommendedWatcher>,
+    #[cfg(feature = "wasm")]
+    poller: Option<web::Poller>,
+}
+
+// Dev mode is opted into with HOT_RELOAD_DIR on desktop, which should be the directory that
+// is being served (along with HOT_RELOAD_URL if it isn't served from localhost:8000). On the
+// web, ?hot_reload polls the server for changes to the assets that are in use instead.
+pub struct HotReloadPlugin;
+
+impl Plugin for HotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        app.add_event::<AssetChanged>();
+
+        #[cfg(not(feature = "wasm"))]
+        app.insert_resource(HotReload {
+            receiver,
+            _watcher: native::watch(sender),
+        });
+
+        #[cfg(feature = "wasm")]
+        app.insert_resource(HotReload {
+            receiver,
+            poller: crate::query_parameter("hot_reload").map(|_| web::Poller::new(sender)),
+        });
+
+        app.add_system(send_asset_changes);
+        app.add_system(reload_changed_models);
+
+        #[cfg(feature = "wasm")]
+        app.add_system(web::poll_watched_assets);
+    }
+}
+
+fn send_asset_changes(hot_reload: Res<HotReload>, mut changes: EventWriter<AssetChanged>) {
+    // Editors tend to write a file several times when saving it.
+    let changed: HashSet<Url> = hot_reload.receiver.try_iter().collect();
+
+    for url in changed {
+        log::info!("{} changed", url);
+        changes.send(AssetChanged(url));
+    }
+}
+
+fn is_model(url: &Url) -> bool {
+    let path = url.path().to_ascii_lowercase();
+    path.ends_with(".glb") || path.ends_with(".gltf")
+}
+
+// Besides the model file itself, glTF models depend on buffers and images that are
+// (almost always) stored next to them.
+fn depends_on(model: &Url, changed: &Url) -> bool {
+    if model == changed {
+        return true;
+    }
+
+    if is_model(changed) {
+        return false;
+    }
+
+    match model.join(".") {
+        Ok(directory) => changed.as_str().starts_with(directory.as_str()),
+        Err(_) => false,
+    }
+}
+
+fn reload_changed_models(
+    mut commands: Commands,
+    mut changes: EventReader<AssetChanged>,
+    model_q: Query<(Entity, &ModelSource)>,
+    failed_q: Query<(Entity, &QueuedModel), With<DownloadFailed>>,
+) {
+    let changed: Vec<&Url> = changes.iter().map(|changed| &changed.0).collect();
+
+    if changed.is_empty() {
+        return;
+    }
+
+    for (entity, source) in model_q.iter() {
+        if !changed.iter().any(|url| depends_on(&source.0.url, url)) {
+            continue;
+        }
+
+        log::info!("Reloading {}", source.0.url);
+
+        // Queued again so that the new version goes through the cache (and any
+        // decompression) just like the original did. The instances stay as they are.
+        commands
+            .entity(entity)
+            .remove::<ModelSource>()
+            .remove::<components::ModelUrl>()
+            .remove::<components::AnimatedModelUrl>()
+            .remove::<components::Model>()
+            .remove::<components::AnimatedModel>()
+            .insert(source.0.clone());
+    }
+
+    // Models that failed to download get another go, in case the change fixed them.
+    for (entity, queued) in failed_q.iter() {
+        if changed.iter().any(|url| depends_on(&queued.url, url)) {
+            log::info!("Retrying {}", queued.url);
+            commands.entity(entity).remove::<DownloadFailed>();
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+mod native {
+    use std::path::PathBuf;
+
+    use notify::{EventKind, RecursiveMode, Watcher};
+
+    use super::{Sender, Url};
+
+    const DEFAULT_BASE_URL: &str = "http://localhost:8000/";
+
+    pub fn watch(sender: Sender<Url>) -> Option<notify::RecommendedWatcher> {
+        let directory = std::env::var("HOT_RELOAD_DIR").ok()?;
+
+        let mut base = std::env::var("HOT_RELOAD_URL").unwrap_or_else(|_| DEFAULT_BASE_URL.into());
+
+        if !base.ends_with('/') {
+            base.push('/');
+        }
+
+        let base = match Url::parse(&base) {
+            Ok(base) => base,
+            Err(error) => {
+                log::warn!("Invalid hot reload url {:?}: {}", base, error);
+                return None;
+            }
+        };
+
+        let root = match PathBuf::from(&directory).canonicalize() {
+            Ok(root) => root,
+            Err(error) => {
+                log::warn!("Can't watch {:?} for changes: {}", directory, error);
+                return None;
+            }
+        };
+
+        let watched_root = root.clone();
+
+        let watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+            let event = match event {
+                Ok(event) => event,
+                Err(error) => {
+                    log::warn!("Hot reload watcher error: {}", error);
+                    return;
+                }
+            };
+
+            if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                return;
+            }
+
+            for path in event.paths {
+                let relative = match path.strip_prefix(&watched_root) {
+                    Ok(relative) => relative,
+                    Err(_) => continue,
+                };
+
+                let relative: Vec<_> = relative
+                    .components()
+                    .map(|component| component.as_os_str().to_string_lossy())
+                    .collect();
+
+                if let Ok(url) = base.join(&relative.join("/")) {
+                    let _ = sender.send(url);
+                }
+            }
+        });
+
+        let mut watcher = match watcher {
+            Ok(watcher) => watcher,
+            Err(error) => {
+                log::warn!("Failed to start the hot reload watcher: {}", error);
+                return None;
+            }
+        };
+
+        if let Err(error) = watcher.watch(&root, RecursiveMode::Recursive) {
+            log::warn!("Can't watch {:?} for changes: {}", root, error);
+            return None;
+        }
+
+        log::info!("Watching {:?} for changes", root);
+
+        Some(watcher)
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod web {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use superconductor::bevy_ecs::prelude::{Local, Query, Res, ResMut};
+    use wasm_bindgen::JsCast;
+    use wasm_bindgen_futures::JsFuture;
+
+    use super::{HotReload, ModelSource, Receiver, Sender, Url, POLL_INTERVAL_FRAMES};
+    use crate::asset_cache::fnv1a;
+    use crate::debug_stats::DebugStats;
+    use crate::downloads::DownloadManager;
+    use crate::environment_maps::EnvironmentMaps;
+    use crate::scripting::Scripts;
+
+    pub struct Poller {
+        changes: Sender<Url>,
+        versions: HashMap<Url, String>,
+        results: (Sender<(Url, String)>, Receiver<(Url, String)>),
+        in_flight: Arc<AtomicBool>,
+    }
+
+    impl Poller {
+        pub fn new(changes: Sender<Url>) -> Self {
+            log::info!("Polling assets for changes");
+
+            Self {
+                changes,
+                versions: Default::default(),
+                results: crossbeam_channel::unbounded(),
+                in_flight: Default::default(),
+            }
+        }
+    }
+
+    // Prefers the etag or modification time from a HEAD request, and only hashes the body
+    // when the server sends neither.
+    async fn version(url: &Url) -> Option<String> {
+        let window = web_sys::window()?;
+
+        let headers = web_sys::Headers::new().ok()?;
+        headers.set("Cache-Control", "no-cache").ok()?;
+
+        let mut init = web_sys::RequestInit::new();
+        init.method("HEAD");
+        init.headers(&headers);
+
+        let response: web_sys::Response =
+            JsFuture::from(window.fetch_with_str_and_init(url.as_str(), &init))
+                .await
+                .ok()?
+                .unchecked_into();
+
+        if !response.ok() {
+            return None;
+        }
+
+        let header = |name: &str| response.headers().get(name).ok().flatten();
+
+        if let Some(version) = header("ETag").or_else(|| header("Last-Modified")) {
+            return Some(version);
+        }
+
+        let mut init = web_sys::RequestInit::new();
+        init.headers(&headers);
+
+        let response: web_sys::Response =
+            JsFuture::from(window.fetch_with_str_and_init(url.as_str(), &init))
+                .await
+                .ok()?
+                .unchecked_into();
+
+        let buffer = JsFuture::from(response.array_buffer().ok()?).await.ok()?;
+
+        Some(format!(
+            "{:016x}",
+            fnv1a(&js_sys::Uint8Array::new(&buffer).to_vec())
+        ))
+    }
+
+    pub fn poll_watched_assets(
+        mut hot_reload: ResMut<HotReload>,
+        manager: Res<DownloadManager>,
+        environment_maps: Res<EnvironmentMaps>,
+        stats: Res<DebugStats>,
+        scripts: Res<Scripts>,
+        model_q: Query<&ModelSource>,
+        mut frame: Local<u32>,
+    ) {
+        let poller = match &mut hot_reload.poller {
+            Some(poller) => poller,
+            None => return,
+        };
+
+        for (url, version) in poller.results.1.try_iter() {
+            match poller.versions.insert(url.clone(), version.clone()) {
+                Some(previous) if previous != version => {
+                    let _ = poller.changes.send(url);
+                }
+                _ => {}
+            }
+        }
+
+        *frame += 1;
+
+        if *frame % POLL_INTERVAL_FRAMES != 0 || poller.in_flight.swap(true, Ordering::Relaxed) {
+            return;
+        }
+
+        let urls: Vec<Url> = model_q
+            .iter()
+            .map(|source| source.0.url.clone())
+            .chain(stats.textures.keys().cloned())
+            .chain(environment_maps.source().cloned())
+            .chain(scripts.urls().cloned())
+            .collect();
+
+        let results = poller.results.0.clone();
+        let in_flight = poller.in_flight.clone();
+
+        manager.spawn(async move {
+            for url in urls {
+                if let Some(version) = version(&url).await {
+                    let _ = results.send((url, version));
+                }
+            }
+
+            in_flight.store(false, Ordering::Relaxed);
+        });
+    }
+}
@@ -0,0 +1,156 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+};
+
+use crate::env_setting;
+use crate::schedule;
+use crate::settings::{self, Setting};
+
+const DEFAULT_LANGUAGE: &str = "en";
+
+// The built in locale bundles, by language tag. Each is a flat json object from string ids to
+// text, where `{name}` is replaced with the argument of that name. Strings that are missing
+// from a bundle fall back to English, so bundles can be translated a few strings at a time.
+const BUNDLES: &[(&str, &str)] = &[
+    ("en", include_str!("../locales/en.json")),
+    ("de", include_str!("../locales/de.json")),
+    ("fr", include_str!("../locales/fr.json")),
+];
+
+// The language picked in the settings, or None to follow LANGUAGE on desktop or ?language= on
+// the web, then the system or browser language.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Language(pub Option<String>);
+
+impl Setting for Language {
+    const KEY: &'static str = "language";
+}
+
+impl Language {
+    fn preferred(&self) -> Vec<String> {
+        self.0
+            .clone()
+            .into_iter()
+            .chain(env_setting("LANGUAGE", "language"))
+            .chain(system_language())
+            .collect()
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn system_language() -> Option<String> {
+    // Along the lines of de_DE.UTF-8.
+    let lang = std::env::var("LANG").ok()?;
+    let tag = lang.split('.').next()?;
+
+    Some(tag.replace('_', "-"))
+}
+
+#[cfg(feature = "wasm")]
+fn system_language() -> Option<String> {
+    web_sys::window()?.navigator().language()
+}
+
+fn parse_bundle(language: &str, json: &str) -> HashMap<String, String> {
+    serde_json::from_str(json).unwrap_or_else(|error| {
+        log::warn!("Failed to parse the {} locale bundle: {}", language, error);
+        HashMap::new()
+    })
+}
+
+// The user facing strings in the current language.
+#[derive(Clone, Debug)]
+pub struct Locale {
+    pub language: &'static str,
+    strings: HashMap<String, String>,
+    fallback: HashMap<String, String>,
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::new(DEFAULT_LANGUAGE).unwrap()
+    }
+}
+
+impl Locale {
+    // Matches a language tag such as de-AT against the bundles, first as a whole and then by
+    // its primary language.
+    pub fn new(tag: &str) -> Option<Self> {
+        let primary = tag.split('-').next().unwrap_or(tag);
+
+        let (language, json) = BUNDLES
+            .iter()
+            .find(|(language, _)| language.eq_ignore_ascii_case(tag))
+            .or_else(|| {
+                BUNDLES
+                    .iter()
+                    .find(|(language, _)| language.eq_ignore_ascii_case(primary))
+            })?;
+
+        let fallback = BUNDLES
+            .iter()
+            .find(|(language, _)| *language == DEFAULT_LANGUAGE)
+            .map(|(language, json)| parse_bundle(language, json))
+            .unwrap_or_default();
+
+        Some(Self {
+            language,
+            strings: parse_bundle(language, json),
+            fallback,
+        })
+    }
+
+    // The first of the preferred languages that there's a bundle for, or English.
+    pub fn select(language: &Language) -> Self {
+        language
+            .preferred()
+            .iter()
+            .find_map(|tag| Self::new(tag))
+            .unwrap_or_default()
+    }
+
+    // Falls back to the id itself if no bundle has it, so that missing strings stand out.
+    pub fn text<'a>(&'a self, id: &'a str) -> &'a str {
+        self.strings
+            .get(id)
+            .or_else(|| self.fallback.get(id))
+            .map(|text| text.as_str())
+            .unwrap_or(id)
+    }
+
+    pub fn format(&self, id: &str, args: &[(&str, &dyn Display)]) -> String {
+        args.iter()
+            .fold(self.text(id).to_string(), |text, (name, value)| {
+                text.replace(&format!("{{{}}}", name), &value.to_string())
+            })
+    }
+}
+
+pub struct I18nPlugin;
+
+impl Plugin for I18nPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Language::default());
+        settings::persist::<Language>(app);
+
+        let language = app.world.get_resource::<Language>().unwrap();
+        let locale = Locale::select(language);
+        log::info!("Using the {} locale", locale.language);
+
+        app.insert_resource(locale);
+        app.add_system_set(schedule::simulation().with_system(reload_locale));
+    }
+}
+
+// Text that's already been put on screen picks up the new locale through change detection;
+// the start buttons are gone by the time the language can be changed.
+fn reload_locale(language: Res<Language>, mut locale: ResMut<Locale>) {
+    if language.is_changed() && !language.is_added() {
+        *locale = Locale::select(&language);
+    }
+}
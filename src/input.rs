@@ -0,0 +1,218 @@
+use superconductor::{
+    bevy_app::{App, EventWriter, Plugin},
+    bevy_ecs::prelude::ResMut,
+    renderer_core::glam::Vec2,
+    resources::{EventQueue, WindowChanges},
+    winit,
+    winit::event::{ElementState, MouseButton, VirtualKeyCode},
+};
+
+use crate::schedule;
+
+#[derive(Default)]
+pub struct KeyboardState {
+    pub forwards: bool,
+    pub right: bool,
+    pub left: bool,
+    pub backwards: bool,
+    pub cursor_grab: bool,
+    // Mouse movement since the last frame, only while the cursor is grabbed.
+    pub look_delta: Vec2,
+}
+
+// Sent for every key press that isn't a movement key, so that other plugins can bind
+// shortcuts without all going through the window events themselves.
+pub struct KeyPressed(pub VirtualKeyCode);
+
+pub struct WindowFocused(pub bool);
+
+pub struct MouseButtonPressed(pub MouseButton);
+
+// The size of the window or canvas in physical pixels. Superconductor resizes its surface and
+// depth targets and updates the projection's aspect ratio itself, so this is for layouts that
+// depend on the size. The scale factor is only tracked on the web, as winit's scale factor
+// changes don't make it through the event queue.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    pub width: u32,
+    pub height: u32,
+    pub scale_factor: f64,
+}
+
+impl Viewport {
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height.max(1) as f32
+    }
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width: 0,
+            height: 0,
+            scale_factor: 1.0,
+        }
+    }
+}
+
+// Sent whenever the viewport's size or scale factor changes, with the new viewport.
+pub struct ViewportChanged(pub Viewport);
+
+// Turns window events into the keyboard state and key press events. W, A, S, D and the arrow
+// keys move, and G grabs the cursor for mouse look.
+pub struct InputPlugin;
+
+impl Plugin for InputPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(KeyboardState::default());
+        app.add_event::<KeyPressed>();
+        app.add_event::<WindowFocused>();
+        app.add_event::<MouseButtonPressed>();
+        app.insert_resource(Viewport::default());
+        app.add_event::<ViewportChanged>();
+
+        app.add_system_set(schedule::input().with_system(handle_keyboard_input));
+
+        // Winit doesn't report the canvas being resized by css or moving to a screen with a
+        // different pixel ratio, so it's checked every frame instead.
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(web::ViewportCanvas::new());
+            app.add_system_set(schedule::input().with_system(web::check_canvas_size));
+        }
+    }
+}
+
+fn handle_keyboard_input(
+    mut events: ResMut<EventQueue>,
+    mut keyboard_state: ResMut<KeyboardState>,
+    mut window_changes: ResMut<WindowChanges>,
+    mut key_presses: EventWriter<KeyPressed>,
+    mut focus_changes: EventWriter<WindowFocused>,
+    mut mouse_presses: EventWriter<MouseButtonPressed>,
+    mut viewport: ResMut<Viewport>,
+    mut viewport_changes: EventWriter<ViewportChanged>,
+) {
+    keyboard_state.look_delta = Vec2::ZERO;
+
+    for event in events.0.drain(..) {
+        match event {
+            winit::event::Event::WindowEvent { event, .. } => match event {
+                winit::event::WindowEvent::KeyboardInput { input, .. } => {
+                    let pressed = input.state == ElementState::Pressed;
+
+                    match input.virtual_keycode {
+                        Some(VirtualKeyCode::W | VirtualKeyCode::Up) => {
+                            keyboard_state.forwards = pressed;
+                        }
+                        Some(VirtualKeyCode::A | VirtualKeyCode::Left) => {
+                            keyboard_state.left = pressed;
+                        }
+                        Some(VirtualKeyCode::S | VirtualKeyCode::Down) => {
+                            keyboard_state.backwards = pressed;
+                        }
+                        Some(VirtualKeyCode::D | VirtualKeyCode::Right) => {
+                            keyboard_state.right = pressed;
+                        }
+                        Some(VirtualKeyCode::G) => {
+                            if pressed {
+                                keyboard_state.cursor_grab = !keyboard_state.cursor_grab;
+                                window_changes.cursor_grab = Some(keyboard_state.cursor_grab);
+                                window_changes.cursor_visible = Some(!keyboard_state.cursor_grab);
+                            }
+                        }
+                        Some(key) => {
+                            if pressed {
+                                key_presses.send(KeyPressed(key));
+                            }
+                        }
+                        None => {}
+                    }
+                }
+                winit::event::WindowEvent::MouseInput {
+                    state: ElementState::Pressed,
+                    button,
+                    ..
+                } => {
+                    mouse_presses.send(MouseButtonPressed(button));
+                }
+                winit::event::WindowEvent::Focused(focused) => {
+                    focus_changes.send(WindowFocused(focused));
+                }
+                winit::event::WindowEvent::Resized(size) => {
+                    let resized = Viewport {
+                        width: size.width,
+                        height: size.height,
+                        ..*viewport
+                    };
+
+                    if resized != *viewport {
+                        *viewport = resized;
+                        viewport_changes.send(ViewportChanged(resized));
+                    }
+                }
+                _ => {}
+            },
+            winit::event::Event::DeviceEvent { event, .. } => match event {
+                winit::event::DeviceEvent::MouseMotion {
+                    delta: (delta_x, delta_y),
+                } => {
+                    if keyboard_state.cursor_grab {
+                        keyboard_state.look_delta += Vec2::new(delta_x as f32, delta_y as f32);
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod web {
+    use superconductor::{
+        bevy_app::EventWriter,
+        bevy_ecs::prelude::{NonSend, ResMut},
+    };
+    use wasm_bindgen::JsCast;
+
+    use super::{Viewport, ViewportChanged};
+
+    pub struct ViewportCanvas(Option<web_sys::HtmlCanvasElement>);
+
+    impl ViewportCanvas {
+        pub fn new() -> Self {
+            Self(
+                web_sys::window()
+                    .and_then(|window| window.document())
+                    .and_then(|document| document.query_selector("canvas:not(#mini-map)").ok())
+                    .flatten()
+                    .map(|canvas| canvas.unchecked_into()),
+            )
+        }
+    }
+
+    pub fn check_canvas_size(
+        canvas: NonSend<ViewportCanvas>,
+        mut viewport: ResMut<Viewport>,
+        mut viewport_changes: EventWriter<ViewportChanged>,
+    ) {
+        let (canvas, window) = match (&canvas.0, web_sys::window()) {
+            (Some(canvas), Some(window)) => (canvas, window),
+            _ => return,
+        };
+
+        let scale_factor = window.device_pixel_ratio();
+
+        let resized = Viewport {
+            width: (canvas.client_width() as f64 * scale_factor).round() as u32,
+            height: (canvas.client_height() as f64 * scale_factor).round() as u32,
+            scale_factor,
+        };
+
+        if resized != *viewport {
+            *viewport = resized;
+            viewport_changes.send(ViewportChanged(resized));
+        }
+    }
+}
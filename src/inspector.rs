@@ -0,0 +1,565 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Added, Entity, Query, Res, ResMut, Without},
+    components,
+    renderer_core::glam::{EulerRot, Quat},
+    Vec3,
+};
+
+use crate::bounds::BoundsOutline;
+use crate::camera_focus::Selected;
+use crate::downloads::ModelSource;
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::particles::ParticleInstance;
+use crate::skeletons::ModelSkeleton;
+use crate::text::TextLabel;
+use crate::{schedule, LocalPlayer, PlayerState, RemotePlayer, PLAYER_STATES};
+
+// Big scenes, like scattered grass, have far more instances than are worth listing.
+const MAX_LISTED: usize = 200;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct InspectedEntity {
+    pub entity: Entity,
+    pub label: String,
+    pub position: Vec3,
+    pub yaw_degrees: f32,
+    pub scale: f32,
+    pub animation_index: Option<usize>,
+    pub player_state: Option<usize>,
+}
+
+// A change made in the inspector. Anything else can send these too.
+#[derive(Clone, Copy, Debug)]
+pub enum InspectorEdit {
+    Select(Option<Entity>),
+    Position(Entity, Vec3),
+    Yaw(Entity, f32),
+    Scale(Entity, f32),
+    // Restarts the animation. Players get their state changed instead, which picks the
+    // animation for it, as their models have an animation for each state in the same order.
+    Animation(Entity, usize),
+}
+
+// Lists the instances in the scene, with the transform, animation and player state of the
+// selected one, which can be edited live. Particles and text labels come and go too often to
+// be worth listing. It follows the selection when something is selected
+// in the scene. The Inspector binding (I by default) shows or hides it. Only the web has a
+// panel for it so far.
+#[derive(Default)]
+pub struct Inspector {
+    pub visible: bool,
+    pub selected: Option<Entity>,
+    pub entities: Vec<InspectedEntity>,
+    // The names of the animations in the selected entity's model.
+    pub animations: Vec<String>,
+}
+
+impl Inspector {
+    pub fn selected(&self) -> Option<&InspectedEntity> {
+        let selected = self.selected?;
+
+        self.entities
+            .iter()
+            .find(|inspected| inspected.entity == selected)
+    }
+}
+
+pub struct InspectorPlugin;
+
+impl Plugin for InspectorPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Inspector::default());
+        app.add_event::<InspectorEdit>();
+
+        app.add_system_set(
+            schedule::input()
+                .with_system(handle_inspector_key)
+                .with_system(apply_inspector_edits),
+        );
+        app.add_system_set(schedule::pre_render().with_system(update_inspector));
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(dom::InspectorDom::new());
+            app.add_system_set(schedule::input().with_system(dom::receive_dom_edits));
+            app.add_system_set(schedule::pre_render().with_system(dom::update_inspector_dom));
+        }
+    }
+}
+
+fn handle_inspector_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut inspector: ResMut<Inspector>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::Inspector) {
+            inspector.visible = !inspector.visible;
+
+            #[cfg(not(feature = "wasm"))]
+            if inspector.visible {
+                log::warn!("The inspector panel is only shown on the web so far");
+            }
+        }
+    }
+}
+
+fn yaw_degrees(rotation: Quat) -> f32 {
+    rotation.to_euler(EulerRot::YXZ).0.to_degrees()
+}
+
+fn apply_inspector_edits(
+    mut edits: EventReader<InspectorEdit>,
+    mut inspector: ResMut<Inspector>,
+    mut instance_q: Query<&mut components::Instance>,
+    mut animation_q: Query<&mut components::AnimationState>,
+    mut player_q: Query<&mut PlayerState>,
+) {
+    for edit in edits.iter() {
+        match *edit {
+            InspectorEdit::Select(entity) => inspector.selected = entity,
+            InspectorEdit::Position(entity, position) => {
+                if let Ok(mut instance) = instance_q.get_mut(entity) {
+                    instance.0.position = position;
+                }
+            }
+            // Keeps any pitch and roll the instance already has.
+            InspectorEdit::Yaw(entity, yaw_degrees) => {
+                if let Ok(mut instance) = instance_q.get_mut(entity) {
+                    let (_, pitch, roll) = instance.0.rotation.to_euler(EulerRot::YXZ);
+                    instance.0.rotation =
+                        Quat::from_euler(EulerRot::YXZ, yaw_degrees.to_radians(), pitch, roll);
+                }
+            }
+            InspectorEdit::Scale(entity, scale) => {
+                if let Ok(mut instance) = instance_q.get_mut(entity) {
+                    instance.0.scale = scale;
+                }
+            }
+            InspectorEdit::Animation(entity, index) => {
+                if let (Ok(mut state), Some(&player_state)) =
+                    (player_q.get_mut(entity), PLAYER_STATES.get(index))
+                {
+                    state.0 = player_state;
+                } else if let Ok(mut animation) = animation_q.get_mut(entity) {
+                    animation.animation_index = index;
+                    animation.time = 0.0;
+                }
+            }
+        }
+    }
+}
+
+fn update_inspector(
+    mut inspector: ResMut<Inspector>,
+    selected_q: Query<Entity, Added<Selected>>,
+    instance_q: Query<
+        (
+            Entity,
+            &components::InstanceOf,
+            &components::Instance,
+            Option<&components::AnimationState>,
+            Option<&PlayerState>,
+        ),
+        (
+            Without<BoundsOutline>,
+            Without<ParticleInstance>,
+            Without<TextLabel>,
+        ),
+    >,
+    source_q: Query<&ModelSource>,
+    skeleton_q: Query<&ModelSkeleton>,
+    marker_q: Query<(Option<&LocalPlayer>, Option<&RemotePlayer>)>,
+) {
+    if let Some(entity) = selected_q.iter().last() {
+        inspector.selected = Some(entity);
+    }
+
+    if !inspector.visible {
+        return;
+    }
+
+    let _span = tracing::info_span!("update_inspector").entered();
+
+    let entities: Vec<InspectedEntity> = instance_q
+        .iter()
+        .take(MAX_LISTED)
+        .map(|(entity, instance_of, instance, animation, player_state)| {
+            let label = match marker_q.get(entity) {
+                Ok((Some(_), _)) => "Local player".to_string(),
+                Ok((_, Some(remote))) => format!("Player {}", remote.0),
+                _ => match source_q.get(instance_of.0) {
+                    Ok(source) => source
+                        .0
+                        .url
+                        .path_segments()
+                        .and_then(|segments| segments.last())
+                        .unwrap_or("Model")
+                        .to_string(),
+                    Err(_) => format!("Entity {}", entity.id()),
+                },
+            };
+
+            InspectedEntity {
+                entity,
+                label,
+                position: instance.0.position,
+                yaw_degrees: yaw_degrees(instance.0.rotation),
+                scale: instance.0.scale,
+                animation_index: animation.map(|animation| animation.animation_index),
+                player_state: player_state
+                    .and_then(|state| PLAYER_STATES.iter().position(|p| *p == state.0)),
+            }
+        })
+        .collect();
+
+    let animations = inspector
+        .selected
+        .and_then(|selected| instance_q.get(selected).ok())
+        .and_then(|(_, instance_of, ..)| skeleton_q.get(instance_of.0).ok())
+        .map(|skeleton| skeleton.animation_names.clone())
+        .unwrap_or_default();
+
+    // Only touched when something changed, so that the panel isn't redrawn every frame.
+    if inspector.entities != entities {
+        inspector.entities = entities;
+    }
+
+    if inspector.animations != animations {
+        inspector.animations = animations;
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use std::cell::Cell;
+    use std::collections::HashMap;
+    use std::rc::Rc;
+
+    use crossbeam_channel::Receiver;
+    use superconductor::{
+        bevy_app::EventWriter,
+        bevy_ecs::prelude::{Entity, NonSend, NonSendMut, Res},
+        Vec3,
+    };
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    use super::{InspectedEntity, Inspector, InspectorEdit};
+    use crate::chat::dom::create_element;
+    use crate::photo_mode::PhotoMode;
+
+    const STYLE: &str = "position: fixed; right: 8px; top: 8px; width: 280px; max-height: 80vh; \
+                         overflow-y: auto; padding: 8px; border-radius: 8px; color: white; \
+                         background: rgba(0, 0, 0, 0.7); font: 12px sans-serif;";
+    const BUTTON_STYLE: &str = "display: block; width: 100%; text-align: left;";
+    const FIELDS: [&str; 5] = ["x", "y", "z", "yaw", "scale"];
+
+    pub struct InspectorDom {
+        root: web_sys::HtmlElement,
+        list: web_sys::HtmlElement,
+        editor: web_sys::HtmlElement,
+        fields: Vec<web_sys::HtmlInputElement>,
+        animation: web_sys::HtmlSelectElement,
+        // The button for each listed entity, along with the label it shows.
+        buttons: HashMap<Entity, (String, web_sys::HtmlElement)>,
+        // The listed entities, in the order their buttons are in.
+        listed: Vec<Entity>,
+        // What the animation dropdown was last filled with.
+        animations: Vec<String>,
+        // Read by the change handlers, which are made once rather than for each selection.
+        selected: Rc<Cell<Option<Entity>>>,
+        receiver: Receiver<InspectorEdit>,
+        // Kept alive for as long as the elements they're attached to.
+        _list_onclick: Closure<dyn FnMut(web_sys::Event)>,
+        _onchanges: Vec<Closure<dyn FnMut()>>,
+    }
+
+    impl InspectorDom {
+        pub fn new() -> Self {
+            let (sender, receiver) = crossbeam_channel::unbounded();
+            let selected = Rc::new(Cell::new(None));
+
+            let root = create_element("div");
+            let list = create_element("div");
+            let editor = create_element("div");
+
+            root.set_attribute("style", "display: none;").unwrap();
+
+            // A single handler for the whole list, which finds the entity from the button.
+            let list_onclick = {
+                let sender = sender.clone();
+
+                Closure::wrap(Box::new(move |event: web_sys::Event| {
+                    let entity = event
+                        .target()
+                        .and_then(|target| target.dyn_into::<web_sys::Element>().ok())
+                        .and_then(|element| element.get_attribute("data-entity"))
+                        .and_then(|bits| bits.parse().ok())
+                        .map(Entity::from_bits);
+
+                    if let Some(entity) = entity {
+                        let _ = sender.send(InspectorEdit::Select(Some(entity)));
+                    }
+                }) as Box<dyn FnMut(web_sys::Event)>)
+            };
+
+            list.set_onclick(Some(list_onclick.as_ref().unchecked_ref()));
+
+            let fields: Vec<web_sys::HtmlInputElement> = FIELDS
+                .iter()
+                .map(|name| {
+                    let label = create_element("label");
+                    label.set_inner_text(name);
+                    label.set_attribute("style", "display: block;").unwrap();
+
+                    let input: web_sys::HtmlInputElement = create_element("input").unchecked_into();
+                    input.set_type("number");
+                    input.set_step("0.1");
+                    input.set_attribute("style", "width: 100%;").unwrap();
+
+                    label.append_child(&input).unwrap();
+                    editor.append_child(&label).unwrap();
+
+                    input
+                })
+                .collect();
+
+            let animation: web_sys::HtmlSelectElement = create_element("select").unchecked_into();
+            editor.append_child(&animation).unwrap();
+
+            let mut onchanges = Vec::new();
+
+            for (index, input) in fields.iter().enumerate() {
+                let onchange = {
+                    let sender = sender.clone();
+                    let fields = fields.clone();
+                    let selected = selected.clone();
+
+                    Closure::wrap(Box::new(move || {
+                        let entity = match selected.get() {
+                            Some(entity) => entity,
+                            None => return,
+                        };
+
+                        let value = |index: usize| fields[index].value_as_number() as f32;
+
+                        let edit = match index {
+                            0..=2 => InspectorEdit::Position(
+                                entity,
+                                Vec3::new(value(0), value(1), value(2)),
+                            ),
+                            3 => InspectorEdit::Yaw(entity, value(3)),
+                            _ => InspectorEdit::Scale(entity, value(4)),
+                        };
+
+                        let _ = sender.send(edit);
+                    }) as Box<dyn FnMut()>)
+                };
+
+                input.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+                onchanges.push(onchange);
+            }
+
+            let onchange = {
+                let animation = animation.clone();
+                let selected = selected.clone();
+
+                Closure::wrap(Box::new(move || {
+                    if let (Some(entity), Ok(index)) = (selected.get(), animation.value().parse()) {
+                        let _ = sender.send(InspectorEdit::Animation(entity, index));
+                    }
+                }) as Box<dyn FnMut()>)
+            };
+
+            animation.set_onchange(Some(onchange.as_ref().unchecked_ref()));
+            onchanges.push(onchange);
+
+            root.append_child(&list).unwrap();
+            root.append_child(&editor).unwrap();
+
+            web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .append_child(&root)
+                .unwrap();
+
+            Self {
+                root,
+                list,
+                editor,
+                fields,
+                animation,
+                buttons: HashMap::new(),
+                listed: Vec::new(),
+                animations: Vec::new(),
+                selected,
+                receiver,
+                _list_onclick: list_onclick,
+                _onchanges: onchanges,
+            }
+        }
+
+        // Only adds, relabels, moves and removes the buttons that need it, so that the list
+        // isn't built again whenever something moves.
+        fn update_list(&mut self, entities: &[InspectedEntity]) {
+            let listed = &mut self.listed;
+
+            self.buttons.retain(|entity, (_, button)| {
+                let keep = entities.iter().any(|inspected| inspected.entity == *entity);
+
+                if !keep {
+                    button.remove();
+                    listed.retain(|listed| listed != entity);
+                }
+
+                keep
+            });
+
+            for (index, inspected) in entities.iter().enumerate() {
+                let button = {
+                    let (label, button) =
+                        self.buttons.entry(inspected.entity).or_insert_with(|| {
+                            let button = create_element("button");
+                            button.set_attribute("style", BUTTON_STYLE).unwrap();
+                            button
+                                .set_attribute(
+                                    "data-entity",
+                                    &inspected.entity.to_bits().to_string(),
+                                )
+                                .unwrap();
+
+                            (String::new(), button)
+                        });
+
+                    if *label != inspected.label {
+                        button.set_inner_text(&inspected.label);
+                        *label = inspected.label.clone();
+                    }
+
+                    button.clone()
+                };
+
+                if listed.get(index) == Some(&inspected.entity) {
+                    continue;
+                }
+
+                let next = listed
+                    .get(index)
+                    .and_then(|next| self.buttons.get(next))
+                    .map(|(_, next)| next.clone());
+
+                // Moves the button if it's already in the list.
+                self.list
+                    .insert_before(&button, next.as_ref().map(|next| next.unchecked_ref()))
+                    .unwrap();
+
+                listed.retain(|listed| *listed != inspected.entity);
+                listed.insert(index, inspected.entity);
+            }
+        }
+
+        fn update_animations(&mut self, animations: &[String]) {
+            if self.animations == animations {
+                return;
+            }
+
+            self.animation.set_inner_html("");
+
+            for (index, name) in animations.iter().enumerate() {
+                let option =
+                    web_sys::HtmlOptionElement::new_with_text_and_value(name, &index.to_string())
+                        .unwrap();
+
+                self.animation.append_child(&option).unwrap();
+            }
+
+            self.animations = animations.to_vec();
+        }
+    }
+
+    pub fn receive_dom_edits(dom: NonSend<InspectorDom>, mut edits: EventWriter<InspectorEdit>) {
+        for edit in dom.receiver.try_iter() {
+            edits.send(edit);
+        }
+    }
+
+    pub fn update_inspector_dom(
+        mut dom: NonSendMut<InspectorDom>,
+        inspector: Res<Inspector>,
+        photo_mode: Res<PhotoMode>,
+    ) {
+        if !inspector.is_changed() && !photo_mode.is_changed() {
+            return;
+        }
+
+        if !inspector.visible || photo_mode.hides_ui() {
+            dom.root.set_attribute("style", "display: none;").unwrap();
+            return;
+        }
+
+        dom.root.set_attribute("style", STYLE).unwrap();
+
+        dom.update_list(&inspector.entities);
+
+        let selected = match inspector.selected() {
+            Some(selected) => selected,
+            None => {
+                dom.editor.set_attribute("style", "display: none;").unwrap();
+                dom.selected.set(None);
+                return;
+            }
+        };
+
+        dom.editor
+            .set_attribute("style", "margin-top: 8px;")
+            .unwrap();
+
+        dom.selected.set(Some(selected.entity));
+
+        let values = [
+            selected.position.x,
+            selected.position.y,
+            selected.position.z,
+            selected.yaw_degrees,
+            selected.scale,
+        ];
+
+        let active = web_sys::window()
+            .and_then(|window| window.document())
+            .and_then(|document| document.active_element());
+
+        // The field being typed into is left alone.
+        for (input, value) in dom.fields.iter().zip(values) {
+            let focused = active.as_ref().map_or(false, |active| {
+                active == input.unchecked_ref::<web_sys::Element>()
+            });
+
+            if !focused {
+                input.set_value_as_number(value as f64);
+            }
+        }
+
+        dom.update_animations(&inspector.animations);
+
+        match selected.player_state.or(selected.animation_index) {
+            Some(index) if index < inspector.animations.len() => {
+                dom.animation.set_attribute("style", "").unwrap();
+                dom.animation.set_value(&index.to_string());
+            }
+            _ => dom
+                .animation
+                .set_attribute("style", "display: none;")
+                .unwrap(),
+        }
+    }
+}
@@ -0,0 +1,146 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use superconductor::winit::event::VirtualKeyCode;
+
+use crate::settings::Setting;
+
+// The demo's keyboard shortcuts. Movement (W, A, S, D and the arrow keys), cursor grabbing (G)
+// and the emotes on 1 to 9 are fixed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum KeyAction {
+    Grab,
+    ToggleBounds,
+    FrameSelection,
+    ToggleProfiling,
+    Jump,
+    Run,
+    Place,
+    Measure,
+    Recenter,
+    CalibrateHeight,
+    ToggleSeated,
+    ShowControls,
+    PhotoMode,
+    Record,
+    Turntable,
+    MiniMap,
+    Inspector,
+    Console,
+    SlowDown,
+    SpeedUp,
+    Pause,
+    FrameStep,
+    Scoreboard,
+}
+
+macro_rules! key_names {
+    ($($key:ident),* $(,)?) => {
+        // The keys that can be bound, by the names that they're saved under.
+        const KEY_NAMES: &[(&str, VirtualKeyCode)] =
+            &[$((stringify!($key), VirtualKeyCode::$key)),*];
+    };
+}
+
+key_names!(
+    A, B, C, D, E, F, G, H, I, J, K, L, M, N, O, P, Q, R, S, T, U, V, W, X, Y, Z, Key0, Key1, Key2,
+    Key3, Key4, Key5, Key6, Key7, Key8, Key9, F1, F2, F3, F4, F5, F6, F7, F8, F9, F10, F11, F12,
+    Space, Return, Escape, Tab, Back, Delete, Insert, Home, End, PageUp, PageDown, LShift, RShift,
+    LControl, RControl, LAlt, RAlt, Grave, LBracket, RBracket, Backslash, Period,
+);
+
+// A key that can be saved by name, as winit's key codes can't be serialized themselves.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Key(pub VirtualKeyCode);
+
+impl Key {
+    // The name that the key is saved under, or None if it can't be bound.
+    pub fn name(&self) -> Option<&'static str> {
+        KEY_NAMES
+            .iter()
+            .find(|(_, key)| *key == self.0)
+            .map(|(name, _)| *name)
+    }
+}
+
+impl Serialize for Key {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self.name() {
+            Some(name) => serializer.serialize_str(name),
+            None => Err(serde::ser::Error::custom(format!(
+                "{:?} can't be bound",
+                self.0
+            ))),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Key {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+
+        KEY_NAMES
+            .iter()
+            .find(|(key_name, _)| *key_name == name)
+            .map(|(_, key)| Key(*key))
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown key {:?}", name)))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct KeyBindings(pub BTreeMap<KeyAction, Key>);
+
+// Actions that were added since the bindings were saved get their default keys.
+impl<'de> Deserialize<'de> for KeyBindings {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let saved = BTreeMap::<KeyAction, Key>::deserialize(deserializer)?;
+
+        let mut bindings = Self::default();
+        bindings.0.extend(saved);
+
+        Ok(bindings)
+    }
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self(BTreeMap::from([
+            (KeyAction::Grab, Key(VirtualKeyCode::E)),
+            (KeyAction::ToggleBounds, Key(VirtualKeyCode::B)),
+            (KeyAction::FrameSelection, Key(VirtualKeyCode::F)),
+            (KeyAction::ToggleProfiling, Key(VirtualKeyCode::P)),
+            (KeyAction::Jump, Key(VirtualKeyCode::Space)),
+            (KeyAction::Run, Key(VirtualKeyCode::LShift)),
+            (KeyAction::Place, Key(VirtualKeyCode::N)),
+            (KeyAction::Measure, Key(VirtualKeyCode::M)),
+            (KeyAction::Recenter, Key(VirtualKeyCode::R)),
+            (KeyAction::CalibrateHeight, Key(VirtualKeyCode::C)),
+            (KeyAction::ToggleSeated, Key(VirtualKeyCode::H)),
+            (KeyAction::ShowControls, Key(VirtualKeyCode::F1)),
+            (KeyAction::PhotoMode, Key(VirtualKeyCode::O)),
+            (KeyAction::Record, Key(VirtualKeyCode::F9)),
+            (KeyAction::Turntable, Key(VirtualKeyCode::K)),
+            (KeyAction::MiniMap, Key(VirtualKeyCode::U)),
+            (KeyAction::Inspector, Key(VirtualKeyCode::I)),
+            (KeyAction::Console, Key(VirtualKeyCode::Grave)),
+            (KeyAction::SlowDown, Key(VirtualKeyCode::LBracket)),
+            (KeyAction::SpeedUp, Key(VirtualKeyCode::RBracket)),
+            (KeyAction::Pause, Key(VirtualKeyCode::Backslash)),
+            (KeyAction::FrameStep, Key(VirtualKeyCode::Period)),
+            (KeyAction::Scoreboard, Key(VirtualKeyCode::Tab)),
+        ]))
+    }
+}
+
+impl Setting for KeyBindings {
+    const KEY: &'static str = "key_bindings";
+}
+
+impl KeyBindings {
+    pub fn action(&self, key: VirtualKeyCode) -> Option<KeyAction> {
+        self.0
+            .iter()
+            .find(|(_, bound)| bound.0 == key)
+            .map(|(action, _)| *action)
+    }
+}
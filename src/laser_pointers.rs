@@ -0,0 +1,318 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    Vec3,
+};
+
+use crate::bounds::Bounds;
+use crate::materials::MaterialDescriptor;
+use crate::ownership::SharedObject;
+use crate::photo_mode::PhotoMode;
+use crate::pointers::{PointerRay, PointerRays, PointerSource};
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+use crate::ui_panels::UiPanel;
+
+const MAX_POINTER_DISTANCE: f32 = 20.0;
+const GROUND_HEIGHT: f32 = 0.0;
+const EYE_HEIGHT: f32 = 1.75;
+const LASER_LENGTH: f32 = 5.0;
+const LASER_THICKNESS: f32 = 0.004;
+const LASER_COLOR: [f32; 3] = [0.3, 0.6, 1.0];
+const LASER_HOVER_COLOR: [f32; 3] = [1.0, 1.0, 1.0];
+
+// The pose of a tracked controller, in world space.
+#[derive(Clone, Copy, Debug)]
+pub struct ControllerPose {
+    pub position: Vec3,
+    pub rotation: Quat,
+    pub trigger: bool,
+    pub menu_button: bool,
+}
+
+// One entry per controller, `None` while it isn't tracked. The renderer's XR plugin doesn't
+// expose its input sources, so this is left for whatever does have them to fill in, e.g. an
+// embedding app. Each controller gets a pointer ray and a laser drawn along it. The left
+// controller comes first.
+#[derive(Default)]
+pub struct Controllers(pub Vec<Option<ControllerPose>>);
+
+pub const LEFT_CONTROLLER: usize = 0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PointerTarget {
+    Panel(Entity),
+    // A shared object that can be grabbed.
+    Object(Entity),
+    Ground(Vec3),
+}
+
+// Sent when what a pointer is pointing at changes, for the mouse as well as controllers.
+pub struct PointerHovered {
+    pub source: PointerSource,
+    pub target: Option<PointerTarget>,
+}
+
+pub struct PointerClicked {
+    pub source: PointerSource,
+    pub target: PointerTarget,
+    pub point: Vec3,
+}
+
+struct Laser {
+    idle: Entity,
+    hover: Entity,
+    instance: Entity,
+    current: Entity,
+}
+
+#[derive(Default)]
+struct LaserPointers {
+    lasers: HashMap<usize, Laser>,
+    // Controllers whose trigger was down last frame, so that holding it is a single click.
+    triggers: Vec<bool>,
+    hovered: HashMap<PointerSource, PointerTarget>,
+}
+
+pub struct LaserPointerPlugin;
+
+impl Plugin for LaserPointerPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PointerHovered>();
+        app.add_event::<PointerClicked>();
+        app.insert_resource(Controllers::default());
+        app.insert_resource(LaserPointers::default());
+
+        app.add_system_set(schedule::input().with_system(update_controller_pointers));
+        app.add_system_set(schedule::simulation().with_system(pick_pointer_targets));
+        app.add_system_set(schedule::pre_render().with_system(hide_lasers_in_photo_mode));
+    }
+}
+
+fn hide_lasers_in_photo_mode(
+    photo_mode: Res<PhotoMode>,
+    lasers: Res<LaserPointers>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    let scale = if photo_mode.hides_ui() { 0.0 } else { 1.0 };
+
+    for laser in lasers.lasers.values() {
+        if let Ok(mut instance) = instance_q.get_mut(laser.instance) {
+            if instance.0.scale != scale {
+                instance.0.scale = scale;
+            }
+        }
+    }
+}
+
+fn update_controller_pointers(
+    controllers: Res<Controllers>,
+    mut lasers: ResMut<LaserPointers>,
+    mut pointers: ResMut<PointerRays>,
+) {
+    pointers
+        .0
+        .retain(|pointer| !matches!(pointer.source, PointerSource::Controller(_)));
+
+    lasers.triggers.resize(controllers.0.len(), false);
+
+    for (index, pose) in controllers.0.iter().enumerate() {
+        let pose = match pose {
+            Some(pose) => pose,
+            None => {
+                lasers.triggers[index] = false;
+                continue;
+            }
+        };
+
+        let clicked = pose.trigger && !lasers.triggers[index];
+        lasers.triggers[index] = pose.trigger;
+
+        pointers.0.push(PointerRay {
+            source: PointerSource::Controller(index),
+            origin: pose.position,
+            direction: pose.rotation * -Vec3::Z,
+            clicked,
+        });
+    }
+}
+
+fn pick(
+    pointer: &PointerRay,
+    panel_q: &Query<(Entity, &UiPanel)>,
+    object_q: &Query<(Entity, &Bounds), With<SharedObject>>,
+) -> Option<(PointerTarget, f32)> {
+    let panels = panel_q.iter().filter_map(|(entity, panel)| {
+        panel
+            .ray_hit(pointer.origin, pointer.direction)
+            .map(|(distance, _)| (PointerTarget::Panel(entity), distance))
+    });
+
+    let objects = object_q.iter().filter_map(|(entity, bounds)| {
+        bounds
+            .0
+            .ray_distance(pointer.origin, pointer.direction)
+            .map(|distance| (PointerTarget::Object(entity), distance))
+    });
+
+    let ground = if pointer.direction.y < -f32::EPSILON {
+        let distance = (GROUND_HEIGHT - pointer.origin.y) / pointer.direction.y;
+        let point = pointer.origin + pointer.direction * distance;
+        Some((PointerTarget::Ground(point), distance))
+    } else {
+        None
+    };
+
+    panels
+        .chain(objects)
+        .chain(ground)
+        .filter(|(_, distance)| *distance <= MAX_POINTER_DISTANCE)
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))
+}
+
+fn spawn_laser(commands: &mut Commands) -> Laser {
+    let primitive = Primitive::Cuboid {
+        size: Vec3::new(LASER_THICKNESS, LASER_THICKNESS, LASER_LENGTH),
+    };
+
+    let idle = spawn_primitive(
+        commands,
+        primitive,
+        MaterialDescriptor::emissive(LASER_COLOR),
+    );
+    let hover = spawn_primitive(
+        commands,
+        primitive,
+        MaterialDescriptor::emissive(LASER_HOVER_COLOR),
+    );
+
+    let instance = commands
+        .spawn()
+        .insert(components::InstanceOf(idle))
+        .insert(components::Instance(renderer_core::Instance::new(
+            Vec3::ZERO,
+            1.0,
+            Quat::IDENTITY,
+        )))
+        .id();
+
+    Laser {
+        idle,
+        hover,
+        instance,
+        current: idle,
+    }
+}
+
+fn pick_pointer_targets(
+    mut commands: Commands,
+    mut lasers: ResMut<LaserPointers>,
+    mut hovers: EventWriter<PointerHovered>,
+    mut clicks: EventWriter<PointerClicked>,
+    mut camera_rig: Option<ResMut<dolly::rig::CameraRig>>,
+    pointers: Res<PointerRays>,
+    panel_q: Query<(Entity, &UiPanel)>,
+    object_q: Query<(Entity, &Bounds), With<SharedObject>>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    let _span = tracing::info_span!("pick_pointer_targets").entered();
+
+    let lasers = &mut *lasers;
+
+    let mut seen = Vec::new();
+
+    for pointer in &pointers.0 {
+        let hit = pick(pointer, &panel_q, &object_q);
+        let target = hit.map(|(target, _)| target);
+
+        seen.push(pointer.source);
+
+        // Ground targets move with the pointer, so only a change of what's pointed at counts.
+        let changed = match (lasers.hovered.get(&pointer.source), target) {
+            (Some(PointerTarget::Ground(_)), Some(PointerTarget::Ground(_))) => false,
+            (previous, target) => previous.copied() != target,
+        };
+
+        match target {
+            Some(target) => lasers.hovered.insert(pointer.source, target),
+            None => lasers.hovered.remove(&pointer.source),
+        };
+
+        if changed {
+            hovers.send(PointerHovered {
+                source: pointer.source,
+                target,
+            });
+        }
+
+        if let (true, Some((target, distance))) = (pointer.clicked, hit) {
+            let point = pointer.origin + pointer.direction * distance;
+
+            clicks.send(PointerClicked {
+                source: pointer.source,
+                target,
+                point,
+            });
+
+            // Controllers teleport to where they point on the ground. Mouse clicks on the
+            // ground are left for placing and measuring.
+            if let (PointerSource::Controller(_), PointerTarget::Ground(point)) =
+                (pointer.source, target)
+            {
+                if let Some(rig) = camera_rig.as_mut() {
+                    rig.driver_mut::<dolly::drivers::Position>().position =
+                        point + Vec3::new(0.0, EYE_HEIGHT, 0.0);
+                }
+            }
+        }
+
+        let index = match pointer.source {
+            PointerSource::Controller(index) => index,
+            PointerSource::Mouse => continue,
+        };
+
+        let laser = lasers
+            .lasers
+            .entry(index)
+            .or_insert_with(|| spawn_laser(&mut commands));
+
+        // Object and panel hovers light the laser up, pointing at the ground doesn't.
+        let model = match target {
+            Some(PointerTarget::Panel(_) | PointerTarget::Object(_)) => laser.hover,
+            _ => laser.idle,
+        };
+
+        if laser.current != model {
+            laser.current = model;
+            commands
+                .entity(laser.instance)
+                .insert(components::InstanceOf(model));
+        }
+
+        if let Ok(mut instance) = instance_q.get_mut(laser.instance) {
+            let rotation = Quat::from_rotation_arc(-Vec3::Z, pointer.direction.normalize_or_zero());
+
+            // The laser's cuboid is centered, so it's pushed forwards by half its length.
+            instance.0.position = pointer.origin + pointer.direction * (LASER_LENGTH * 0.5);
+            instance.0.rotation = rotation;
+        }
+    }
+
+    lasers.hovered.retain(|source, _| seen.contains(source));
+
+    lasers.lasers.retain(|&index, laser| {
+        let keep = seen.contains(&PointerSource::Controller(index));
+
+        if !keep {
+            commands.entity(laser.idle).despawn();
+            commands.entity(laser.hover).despawn();
+            commands.entity(laser.instance).despawn();
+        }
+
+        keep
+    });
+}
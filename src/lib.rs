@@ -1,111 +1,334 @@
 #[cfg(feature = "wasm")]
 use wasm_bindgen::prelude::wasm_bindgen;
 
+pub mod accessibility;
+#[cfg(feature = "allocation_stats")]
+mod allocations;
+pub mod alpha_modes;
+mod asset_cache;
+pub mod assets;
+pub mod avatars;
+pub mod bounds;
+pub mod camera_control;
+pub mod camera_focus;
+pub mod captions;
+mod chat;
+pub mod cloth;
+pub mod console;
+mod debug_stats;
+pub mod decals;
+pub mod determinism;
+mod downloads;
+mod emotes;
+pub mod environment;
+mod environment_maps;
+pub mod foot_ik;
+#[cfg(not(feature = "wasm"))]
+mod frame_limiter;
+pub mod game_events;
+pub mod gpu_reset;
+pub mod graphics;
+mod hot_reload;
+pub mod i18n;
+pub mod input;
+pub mod inspector;
+pub mod key_bindings;
+pub mod laser_pointers;
+pub mod material_extensions;
+pub mod materials;
+pub mod measurement;
+mod mesh_compression;
+pub mod mini_map;
+mod network_conditions;
+mod networking;
+pub mod node_hierarchy;
+pub mod occlusion;
+pub mod onboarding;
+mod ownership;
+mod parallelism;
+pub mod particles;
+pub mod photo_mode;
+pub mod placement;
+pub mod player_height;
+pub mod pointers;
+mod preload;
+pub mod primitives;
+mod profiling;
+pub mod protocol;
+pub mod ragdoll;
+pub mod recording;
+pub mod reflection_probes;
+mod render_stats;
+mod room_state;
+pub mod root_motion;
+pub mod scatter;
+pub mod schedule;
+mod scoreboard;
+mod scripting;
+pub mod sequencer;
+pub mod settings;
+mod shared_physics;
+pub mod skeletons;
+pub mod sun;
+pub mod terrain;
+pub mod text;
+mod textures;
+pub mod time_scale;
+pub mod turntable;
+pub mod ui_panels;
+pub mod vignette;
+pub mod water;
+pub mod weather;
+pub mod wind;
+pub mod world_streaming;
+pub mod wrist_menu;
+
 use superconductor::{
-    bevy_app,
-    bevy_ecs::{self, prelude::Changed},
-    components::{self, AnimationState},
-    renderer_core,
-    resources::{Camera, EventQueue, NewIblTextures, NewIblTexturesInner, WindowChanges},
-    url, winit,
-    winit::event::{ElementState, VirtualKeyCode},
-    Mode, Vec3,
+    bevy_app, bevy_ecs, components, renderer_core, resources::NewIblTextures, url,
+    winit::event::VirtualKeyCode, Mode,
 };
 
-#[cfg(feature = "wasm")]
+// Embedding apps on the web have their own entry point and call `run_library` from it.
+#[cfg(all(feature = "wasm", not(feature = "library")))]
 #[wasm_bindgen(start)]
 pub fn main() {
+    // The environment map worker loads the module as well, just to call into it.
+    if web_sys::window().is_none() {
+        return;
+    }
+
     console_error_panic_hook::set_once();
     console_log::init_with_level(log::Level::Info).unwrap();
     wasm_bindgen_futures::spawn_local(run());
 }
 
-pub async fn run() {
+async fn select_mode() -> Mode {
     #[cfg(feature = "wasm")]
-    let mode = select_mode_via_buttons().await;
-
+    let mode = {
+        let language = settings::load::<i18n::Language>().unwrap_or_default();
+        select_mode_via_buttons(&i18n::Locale::select(&language)).await
+    };
+
+    // Superconductor only starts XR sessions through WebXR, so VR on desktop has to wait for it
+    // to get an OpenXR backend. The camera, locomotion and laser pointer systems already work
+    // from the `Mode` and `Controllers`, so they'd carry over as they are.
     #[cfg(not(feature = "wasm"))]
-    let mode = Mode::Desktop;
+    let mode = {
+        if let Ok(requested) = std::env::var("MODE") {
+            if !requested.eq_ignore_ascii_case("desktop") {
+                log::warn!(
+                    "MODE={} isn't supported on desktop yet, as there's no OpenXR backend",
+                    requested
+                );
+            }
+        }
+
+        Mode::Desktop
+    };
+
+    mode
+}
+
+pub async fn run() {
+    if !graphics::supported() {
+        return;
+    }
+
+    let mode = select_mode().await;
 
     let initialised_state = superconductor::initialise(mode).await;
 
     let mut app = bevy_app::App::new();
 
-    app.add_plugin(SuperconductorPlugin::new(mode));
+    add_plugins(&mut app, SuperconductorPlugin::new(mode));
+    app.add_plugin(networking::NetworkPlugin::default());
+    app.add_plugin(room_state::RoomStatePlugin);
+    app.add_plugin(scoreboard::ScoreboardPlugin);
+    app.add_plugin(chat::ChatPlugin);
+    app.add_plugin(emotes::EmotePlugin);
+    app.add_plugin(ownership::OwnershipPlugin);
+    app.add_plugin(shared_physics::SharedPhysicsPlugin);
 
     superconductor::run_rendering_loop(app, initialised_state);
 }
 
-use bevy_app::{App, Plugin};
-use bevy_ecs::prelude::{Component, Query, Res, ResMut, With};
+// Runs the renderer and the asset pipeline with an empty scene, for using this crate as a
+// library. `build` adds the app's own plugins, which spawn all of the content. The
+// multiplayer plugins are left out, as they need the demo avatar.
+pub async fn run_library(build: impl FnOnce(&mut App)) {
+    if !graphics::supported() {
+        return;
+    }
+
+    let mode = select_mode().await;
 
+    let initialised_state = superconductor::initialise(mode).await;
+
+    let mut app = bevy_app::App::new();
+
+    add_plugins(&mut app, SuperconductorPlugin::library(mode));
+    build(&mut app);
+
+    superconductor::run_rendering_loop(app, initialised_state);
+}
+
+fn add_plugins(app: &mut App, superconductor_plugin: SuperconductorPlugin) {
+    app.add_plugin(parallelism::ParallelismPlugin);
+    #[cfg(feature = "allocation_stats")]
+    app.add_plugin(allocations::AllocationStatsPlugin);
+    app.add_plugin(profiling::ProfilingPlugin);
+    app.add_plugin(i18n::I18nPlugin);
+    app.add_plugin(accessibility::AccessibilityPlugin);
+    app.add_plugin(determinism::DeterminismPlugin);
+    app.add_plugin(time_scale::TimeScalePlugin);
+    app.add_plugin(superconductor_plugin);
+    #[cfg(not(feature = "wasm"))]
+    app.add_plugin(frame_limiter::FrameLimiterPlugin);
+    app.add_plugin(downloads::DownloadPlugin::default());
+    app.add_plugin(assets::AssetPlugin);
+    app.add_plugin(primitives::PrimitivePlugin);
+    app.add_plugin(text::TextPlugin);
+    app.add_plugin(particles::ParticlePlugin);
+    app.add_plugin(decals::DecalPlugin);
+    app.add_plugin(water::WaterPlugin);
+    app.add_plugin(terrain::TerrainPlugin);
+    app.add_plugin(scatter::ScatterPlugin);
+    app.add_plugin(bounds::BoundsPlugin);
+    app.add_plugin(node_hierarchy::NodeHierarchyPlugin);
+    app.add_plugin(foot_ik::FootIkPlugin);
+    app.add_plugin(root_motion::RootMotionPlugin);
+    app.add_plugin(skeletons::SkeletonPlugin);
+    app.add_plugin(ragdoll::RagdollPlugin);
+    app.add_plugin(cloth::ClothPlugin);
+    app.add_plugin(wind::WindPlugin);
+    app.add_plugin(weather::WeatherPlugin);
+    app.add_plugin(sun::SunPlugin);
+    app.add_plugin(placement::PlacementPlugin);
+    app.add_plugin(measurement::MeasurementPlugin);
+    app.add_plugin(pointers::PointerPlugin);
+    app.add_plugin(ui_panels::UiPanelPlugin);
+    app.add_plugin(laser_pointers::LaserPointerPlugin);
+    app.add_plugin(wrist_menu::WristMenuPlugin);
+    app.add_plugin(camera_focus::CameraFocusPlugin);
+    app.add_plugin(photo_mode::PhotoModePlugin);
+    app.add_plugin(recording::RecordingPlugin);
+    app.add_plugin(turntable::TurntablePlugin);
+    app.add_plugin(mini_map::MiniMapPlugin);
+    app.add_plugin(inspector::InspectorPlugin);
+    app.add_plugin(console::ConsolePlugin);
+    app.add_plugin(game_events::GameEventPlugin);
+    app.add_plugin(sequencer::SequencerPlugin);
+    app.add_plugin(captions::CaptionPlugin);
+    app.add_plugin(render_stats::RenderStatsPlugin);
+    app.add_plugin(preload::PreloadPlugin::default());
+    app.add_plugin(textures::TexturePlugin);
+    app.add_plugin(hot_reload::HotReloadPlugin);
+    app.add_plugin(gpu_reset::GpuResetPlugin);
+    app.add_plugin(scripting::ScriptingPlugin);
+    app.add_plugin(environment_maps::EnvironmentMapPlugin);
+    app.add_plugin(reflection_probes::ReflectionProbePlugin);
+    app.add_plugin(world_streaming::WorldStreamingPlugin::default());
+    app.add_plugin(occlusion::OcclusionPlugin);
+}
+
+use bevy_app::{App, EventReader, EventWriter, Plugin};
+use bevy_ecs::prelude::{Component, Entity, Query, Res, ResMut, With};
+use key_bindings::{KeyAction, KeyBindings};
+
+// The whole demo: every sub-plugin below plus the key bindings that connect the input plugin
+// to the rest. Add the sub-plugins individually instead to leave some of them out.
 pub struct SuperconductorPlugin {
     mode: Mode,
+    // None when there's no demo content.
+    avatar: Option<protocol::AvatarProfile>,
 }
 
 impl SuperconductorPlugin {
-    fn new(mode: Mode) -> Self {
-        Self { mode }
+    pub fn new(mode: Mode) -> Self {
+        Self {
+            mode,
+            avatar: Some(avatars::local_profile()),
+        }
+    }
+
+    // Just the renderer, input and camera: no avatar, scene, lighting or demo key bindings.
+    pub fn library(mode: Mode) -> Self {
+        Self { mode, avatar: None }
     }
 }
 
 impl Plugin for SuperconductorPlugin {
     fn build(&self, app: &mut App) {
-        let avatar = app
-            .world
-            .spawn()
-            .insert(components::AnimatedModelUrl(
-                url::Url::parse("http://localhost:8000/assets/models/avatar/squid6.glb").unwrap(),
-            ))
-            .insert(components::Instances(Default::default()))
-            .insert(components::InstanceRange(Default::default()))
-            .id();
-
-        app.world
-            .spawn()
-            .insert(components::InstanceOf(avatar))
-            .insert(components::Instance(renderer_core::Instance::new(
-                Vec3::new(0.0, 1.0, -3.0),
-                1.0,
-                Default::default(),
-            )))
-            .insert(components::AnimationState {
-                time: 0.5,
-                animation_index: 5,
-            })
-            .insert(PlayerState(PlayerStates::Idle));
-
-        let camera_rig: dolly::rig::CameraRig = dolly::rig::CameraRig::builder()
-            .with(dolly::drivers::Position::new(Vec3::new(0.0, 1.75, 0.0)))
-            .with(dolly::drivers::YawPitch::new().pitch_degrees(0.0))
-            .build();
-
-        app.insert_resource(KeyboardState::default());
-        app.insert_resource(camera_rig);
-
-        app.add_system(rotate_entities);
-        app.add_system(handle_keyboard_input);
-        app.add_system(update_camera);
-        app.add_system(sync_animation);
+        app.add_plugin(input::InputPlugin);
+        app.add_plugin(camera_control::CameraControlPlugin {
+            spawn: Default::default(),
+            controls: camera_control::ControlSettings::from_env(self.mode),
+        });
+        app.add_plugin(player_height::PlayerHeightPlugin {
+            xr: matches!(self.mode, Mode::Vr | Mode::Ar),
+        });
+        app.add_plugin(vignette::ComfortVignettePlugin);
+
+        match &self.avatar {
+            Some(avatar) => {
+                app.add_plugin(avatars::AvatarPlugin {
+                    profile: avatar.clone(),
+                });
+                app.add_plugin(environment::EnvironmentPlugin);
+
+                app.insert_resource(KeyBindings::default());
+                settings::persist::<KeyBindings>(app);
+                app.add_plugin(onboarding::OnboardingPlugin);
+
+                app.add_system_set(schedule::simulation().with_system(rotate_entities));
+                app.add_system_set(
+                    schedule::input()
+                        .with_system(handle_key_bindings)
+                        .with_system(handle_player_state_keys)
+                        .with_system(handle_seated_key),
+                );
+            }
+            None => {
+                app.insert_resource(NewIblTextures(None));
+            }
+        }
 
         let plugin: superconductor::XrPlugin = superconductor::XrPlugin::new(self.mode);
 
         plugin.build(app);
-
-        app.insert_resource(NewIblTextures(Some(NewIblTexturesInner {
-            diffuse_cubemap: url::Url::parse("https://expenses.github.io/mateversum-web/environment_maps/helipad/diffuse_compressed.ktx2").unwrap(),
-            specular_cubemap: url::Url::parse("https://expenses.github.io/mateversum-web/environment_maps/helipad/specular_compressed.ktx2").unwrap()
-        })));
     }
 }
 
+// Reads a setting from an environment variable on desktop, or a query parameter on the web.
+pub(crate) fn env_setting(_desktop: &str, _web: &str) -> Option<String> {
+    #[cfg(not(feature = "wasm"))]
+    let value = std::env::var(_desktop).ok();
+
+    #[cfg(feature = "wasm")]
+    let value = query_parameter(_web);
+
+    value
+}
+
+#[cfg(feature = "wasm")]
+pub(crate) fn query_parameter(name: &str) -> Option<String> {
+    let search = web_sys::window()?.location().search().ok()?;
+
+    search
+        .trim_start_matches('?')
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .find(|(key, _)| *key == name)
+        .map(|(_, value)| value.to_string())
+}
+
 #[cfg(feature = "wasm")]
-pub async fn select_mode_via_buttons() -> superconductor::Mode {
+pub async fn select_mode_via_buttons(locale: &i18n::Locale) -> superconductor::Mode {
     use futures::FutureExt;
 
-    let vr_button = create_button("Start VR");
-    let ar_button = create_button("Start AR");
-    let desktop_button = create_button("Start Desktop");
+    let vr_button = create_button(locale.text("start.vr"));
+    let ar_button = create_button(locale.text("start.ar"));
+    let desktop_button = create_button(locale.text("start.desktop"));
 
     let start_vr_future = button_click_future(&vr_button);
     let start_ar_future = button_click_future(&ar_button);
@@ -157,120 +380,128 @@ fn create_button(text: &str) -> web_sys::HtmlButtonElement {
 #[derive(Component)]
 struct Spinning;
 
-#[derive(Default)]
-struct KeyboardState {
-    forwards: bool,
-    right: bool,
-    left: bool,
-    backwards: bool,
-    cursor_grab: bool,
-}
+#[derive(Component)]
+pub struct LocalPlayer;
+
+#[derive(Component)]
+pub struct RemotePlayer(pub protocol::PlayerId);
+
+pub struct AvatarModel(pub Entity);
+
+pub struct LocalAvatarProfile(pub protocol::AvatarProfile);
+
+#[cfg(feature = "allocation_stats")]
+#[global_allocator]
+static ALLOCATOR: allocations::CountingAllocator = allocations::CountingAllocator;
+
+const DELTA_TIME: f32 = 1.0 / 60.0;
+
+fn rotate_entities(
+    accessibility: Res<accessibility::AccessibilitySettings>,
+    time_scale: Res<time_scale::TimeScale>,
+    mut query: Query<&mut components::Instance, With<Spinning>>,
+) {
+    if accessibility.reduced_motion {
+        return;
+    }
 
-fn rotate_entities(mut query: Query<&mut components::Instance, With<Spinning>>) {
     query.for_each_mut(|mut instance| {
-        instance.0.rotation *= renderer_core::glam::Quat::from_rotation_y(0.01)
+        instance.0.rotation *= renderer_core::glam::Quat::from_rotation_y(0.01 * time_scale.scale)
     });
 }
 
-fn sync_animation(mut anim_q: Query<(&PlayerState, &mut AnimationState), Changed<PlayerState>>) {
-    for (p_state, mut anim_state) in anim_q.iter_mut() {
-        anim_state.animation_index = PLAYER_STATES.iter().position(|p| *p == p_state.0).unwrap();
+// Split up by what each one writes to, so that they can run alongside other systems.
+fn handle_key_bindings(
+    mut key_presses: EventReader<input::KeyPressed>,
+    mut emote_requests: EventWriter<emotes::EmoteRequested>,
+    mut grab_toggles: EventWriter<ownership::GrabToggled>,
+    mut bounds_toggles: EventWriter<bounds::BoundsToggled>,
+    mut frame_requests: EventWriter<camera_focus::FrameRequested>,
+    mut profiling_toggles: EventWriter<profiling::ProfilingToggled>,
+    mut placement_requests: EventWriter<placement::PlacementRequested>,
+    mut measure_toggles: EventWriter<measurement::MeasureToggled>,
+    mut recenter_requests: EventWriter<camera_control::RecenterRequested>,
+    mut calibrations: EventWriter<player_height::CalibrateHeight>,
+    key_bindings: Res<KeyBindings>,
+) {
+    for input::KeyPressed(key) in key_presses.iter() {
+        let action = match key_bindings.action(*key) {
+            Some(action) => action,
+            None => {
+                if (VirtualKeyCode::Key1..=VirtualKeyCode::Key9).contains(key) {
+                    emote_requests.send(emotes::EmoteRequested(
+                        *key as usize - VirtualKeyCode::Key1 as usize,
+                    ));
+                }
+
+                continue;
+            }
+        };
+
+        match action {
+            KeyAction::Grab => grab_toggles.send(ownership::GrabToggled),
+            KeyAction::ToggleBounds => bounds_toggles.send(bounds::BoundsToggled),
+            KeyAction::FrameSelection => frame_requests.send(camera_focus::FrameRequested),
+            KeyAction::ToggleProfiling => profiling_toggles.send(profiling::ProfilingToggled),
+            KeyAction::Recenter => recenter_requests.send(camera_control::RecenterRequested),
+            KeyAction::CalibrateHeight => calibrations.send(player_height::CalibrateHeight),
+            KeyAction::Measure => measure_toggles.send(measurement::MeasureToggled),
+            KeyAction::Place => placement_requests.send(placement::PlacementRequested(
+                url::Url::parse(ownership::FERRIS_URL).unwrap(),
+            )),
+            KeyAction::Jump | KeyAction::Run | KeyAction::ToggleSeated => {}
+            // Handled by their own plugins, as the photo mode and recording bindings have to
+            // work while the simulation is paused.
+            KeyAction::ShowControls
+            | KeyAction::PhotoMode
+            | KeyAction::Record
+            | KeyAction::Turntable
+            | KeyAction::MiniMap
+            | KeyAction::Inspector
+            | KeyAction::Console
+            | KeyAction::SlowDown
+            | KeyAction::SpeedUp
+            | KeyAction::Pause
+            | KeyAction::FrameStep
+            | KeyAction::Scoreboard => {}
+        }
     }
 }
 
-fn handle_keyboard_input(
-    mut events: ResMut<EventQueue>,
-    mut keyboard_state: ResMut<KeyboardState>,
-    mut camera_rig: ResMut<dolly::rig::CameraRig>,
-    mut window_changes: ResMut<WindowChanges>,
-    mut anim_state_q: Query<&mut PlayerState>,
+fn handle_player_state_keys(
+    mut key_presses: EventReader<input::KeyPressed>,
+    mut player_state_q: Query<&mut PlayerState, With<LocalPlayer>>,
+    key_bindings: Res<KeyBindings>,
 ) {
-    for event in events.0.drain(..) {
-        match event {
-            winit::event::Event::WindowEvent { event, .. } => match event {
-                winit::event::WindowEvent::KeyboardInput { input, .. } => {
-                    let pressed = input.state == ElementState::Pressed;
-
-                    match input.virtual_keycode {
-                        Some(VirtualKeyCode::W | VirtualKeyCode::Up) => {
-                            keyboard_state.forwards = pressed;
-                        }
-                        Some(VirtualKeyCode::A | VirtualKeyCode::Left) => {
-                            keyboard_state.left = pressed;
-                        }
-                        Some(VirtualKeyCode::S | VirtualKeyCode::Down) => {
-                            keyboard_state.backwards = pressed;
-                        }
-                        Some(VirtualKeyCode::D | VirtualKeyCode::Right) => {
-                            keyboard_state.right = pressed;
-                        }
-                        Some(VirtualKeyCode::G) => {
-                            if pressed {
-                                keyboard_state.cursor_grab = !keyboard_state.cursor_grab;
-                                window_changes.cursor_grab = Some(keyboard_state.cursor_grab);
-                                window_changes.cursor_visible = Some(!keyboard_state.cursor_grab);
-                            }
-                        }
-                        Some(VirtualKeyCode::Space) => {
-                            if pressed {
-                                anim_state_q.single_mut().0 = PlayerStates::Jump;
-                            }
-                        }
-                        Some(VirtualKeyCode::LShift) => {
-                            if pressed {
-                                anim_state_q.single_mut().0 = PlayerStates::Running;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                _ => {}
-            },
-            winit::event::Event::DeviceEvent { event, .. } => match event {
-                winit::event::DeviceEvent::MouseMotion {
-                    delta: (delta_x, delta_y),
-                } => {
-                    if keyboard_state.cursor_grab {
-                        camera_rig
-                            .driver_mut::<dolly::drivers::YawPitch>()
-                            .rotate_yaw_pitch(-0.1 * delta_x as f32, -0.1 * delta_y as f32);
-                    }
-                }
-                _ => {}
-            },
-            _ => {}
+    for input::KeyPressed(key) in key_presses.iter() {
+        let state = match key_bindings.action(*key) {
+            Some(KeyAction::Jump) => PlayerStates::Jump,
+            Some(KeyAction::Run) => PlayerStates::Running,
+            _ => continue,
+        };
+
+        if let Ok(mut player_state) = player_state_q.get_single_mut() {
+            player_state.0 = state;
         }
     }
 }
 
-fn update_camera(
-    keyboard_state: Res<KeyboardState>,
-    mut camera: ResMut<Camera>,
-    mut camera_rig: ResMut<dolly::rig::CameraRig>,
+fn handle_seated_key(
+    mut key_presses: EventReader<input::KeyPressed>,
+    mut player_height: ResMut<player_height::PlayerHeight>,
+    key_bindings: Res<KeyBindings>,
 ) {
-    let forwards = keyboard_state.forwards as i32 - keyboard_state.backwards as i32;
-    let right = keyboard_state.right as i32 - keyboard_state.left as i32;
-
-    let move_vec = camera_rig.final_transform.rotation
-        * Vec3::new(right as f32, 0.0, -forwards as f32).clamp_length_max(1.0);
-
-    let delta_time = 1.0 / 60.0;
-    let speed = 3.0;
-
-    camera_rig
-        .driver_mut::<dolly::drivers::Position>()
-        .translate(move_vec * delta_time * speed);
-
-    camera_rig.update(delta_time);
-
-    camera.position = camera_rig.final_transform.position;
-    camera.rotation = camera_rig.final_transform.rotation;
+    for input::KeyPressed(key) in key_presses.iter() {
+        if key_bindings.action(*key) == Some(KeyAction::ToggleSeated) {
+            player_height.seated = !player_height.seated;
+        }
+    }
 }
 
 #[derive(Component, PartialEq, Eq)]
 pub struct PlayerState(PlayerStates);
 
-#[derive(PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub enum PlayerStates {
     Falling,
     FallingToLanding,
@@ -1,7 +1,5 @@
-mod lib;
-
 #[tokio::main]
 async fn main() {
     env_logger::init();
-    lib::run().await;
+    times_square_superconductor::run().await;
 }
@@ -0,0 +1,115 @@
+use serde_json::Value;
+
+pub const CLEARCOAT: &str = "KHR_materials_clearcoat";
+pub const TRANSMISSION: &str = "KHR_materials_transmission";
+pub const EMISSIVE_STRENGTH: &str = "KHR_materials_emissive_strength";
+pub const IOR: &str = "KHR_materials_ior";
+
+const EXTENSIONS: [&str; 4] = [CLEARCOAT, TRANSMISSION, EMISSIVE_STRENGTH, IOR];
+
+// The glTF default, which is what the renderer's fresnel assumes for every material.
+const DEFAULT_IOR: f64 = 1.5;
+// The least opaque a fully transmissive material is blended at, so that glass stays visible.
+const MIN_TRANSMISSIVE_ALPHA: f64 = 0.25;
+
+// What a model's materials lose or have approximated, as the renderer only has the core
+// metallic-roughness model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialFallback {
+    pub extension: &'static str,
+    // How many of the model's materials use it.
+    pub materials: usize,
+}
+
+impl MaterialFallback {
+    pub fn description(&self) -> &'static str {
+        match self.extension {
+            CLEARCOAT => "the clear coat layer is dropped",
+            TRANSMISSION => "approximated with alpha blending",
+            EMISSIVE_STRENGTH => "multiplied into the emissive factor",
+            _ => "only the default IOR of 1.5 is used",
+        }
+    }
+}
+
+// Which of the material extensions the renderer doesn't have the model's materials use. An IOR
+// of 1.5 is left out, as it looks the same without the extension.
+pub fn material_fallbacks(gltf: &Value) -> Vec<MaterialFallback> {
+    let materials = match gltf["materials"].as_array() {
+        Some(materials) => materials,
+        None => return Vec::new(),
+    };
+
+    EXTENSIONS
+        .into_iter()
+        .map(|extension| MaterialFallback {
+            extension,
+            materials: materials
+                .iter()
+                .filter(|material| {
+                    let value = &material["extensions"][extension];
+
+                    match extension {
+                        IOR => value["ior"]
+                            .as_f64()
+                            .map_or(false, |ior| ior != DEFAULT_IOR),
+                        _ => !value.is_null(),
+                    }
+                })
+                .count(),
+        })
+        .filter(|fallback| fallback.materials > 0)
+        .collect()
+}
+
+// Rewrites the materials so that the renderer gets as close as it can without the extensions,
+// and removes them so that a model that requires them still loads.
+pub fn apply_fallbacks(gltf: &mut Value) {
+    for material in gltf["materials"].as_array_mut().into_iter().flatten() {
+        let strength = material["extensions"][EMISSIVE_STRENGTH]["emissiveStrength"].as_f64();
+
+        if let (Some(strength), Some(factor)) =
+            (strength, material["emissiveFactor"].as_array_mut())
+        {
+            for channel in factor.iter_mut() {
+                *channel = (channel.as_f64().unwrap_or_default() * strength).into();
+            }
+        }
+
+        let transmission = &material["extensions"][TRANSMISSION];
+
+        // Already blended or masked materials are left as they are.
+        if !transmission.is_null() && material["alphaMode"].as_str().unwrap_or("OPAQUE") == "OPAQUE"
+        {
+            let factor = transmission["transmissionFactor"]
+                .as_f64()
+                .unwrap_or_default();
+
+            if factor > 0.0 {
+                let pbr = &mut material["pbrMetallicRoughness"];
+
+                if !pbr["baseColorFactor"].is_array() {
+                    pbr["baseColorFactor"] = serde_json::json!([1.0, 1.0, 1.0, 1.0]);
+                }
+
+                let alpha = pbr["baseColorFactor"][3].as_f64().unwrap_or(1.0);
+                let opacity = 1.0 - factor * (1.0 - MIN_TRANSMISSIVE_ALPHA);
+
+                pbr["baseColorFactor"][3] = (alpha * opacity).into();
+                material["alphaMode"] = "BLEND".into();
+            }
+        }
+
+        if let Some(extensions) = material["extensions"].as_object_mut() {
+            for extension in EXTENSIONS {
+                extensions.remove(extension);
+            }
+        }
+    }
+
+    for key in ["extensionsUsed", "extensionsRequired"] {
+        if let Some(list) = gltf[key].as_array_mut() {
+            list.retain(|extension| !EXTENSIONS.contains(&extension.as_str().unwrap_or_default()));
+        }
+    }
+}
@@ -0,0 +1,138 @@
+use serde_json::{json, Value};
+use superconductor::url::Url;
+
+// A metallic-roughness PBR material for generated meshes, written into the model the same
+// way a glTF exporter would.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MaterialDescriptor {
+    // Linear rgba.
+    pub base_color: [f32; 4],
+    pub metallic: f32,
+    pub roughness: f32,
+    // Linear rgb.
+    pub emissive: [f32; 3],
+    pub base_color_texture: Option<Url>,
+    // Metalness in the blue channel and roughness in the green channel, as in glTF.
+    pub metallic_roughness_texture: Option<Url>,
+    pub normal_texture: Option<Url>,
+    // Baked ambient occlusion in the red channel.
+    pub occlusion_texture: Option<Url>,
+    pub emissive_texture: Option<Url>,
+    pub double_sided: bool,
+    // Blends with what's behind it using the base colour alpha. Always on when the base colour
+    // factor itself is translucent, so only needed for textures with transparency.
+    pub blend: bool,
+}
+
+// Matte white, as the glTF default of a fully metallic material isn't very useful for
+// blocking things out.
+impl Default for MaterialDescriptor {
+    fn default() -> Self {
+        Self {
+            base_color: [1.0; 4],
+            metallic: 0.0,
+            roughness: 1.0,
+            emissive: [0.0; 3],
+            base_color_texture: None,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            occlusion_texture: None,
+            emissive_texture: None,
+            double_sided: false,
+            blend: false,
+        }
+    }
+}
+
+impl MaterialDescriptor {
+    pub fn color(base_color: [f32; 4]) -> Self {
+        Self {
+            base_color,
+            ..Default::default()
+        }
+    }
+
+    pub fn emissive(emissive: [f32; 3]) -> Self {
+        Self {
+            base_color: [0.0, 0.0, 0.0, 1.0],
+            emissive,
+            ..Default::default()
+        }
+    }
+
+    // Adds the material (and any textures it uses) to a glTF document, returning its index.
+    pub(crate) fn write_gltf(&self, gltf: &mut Value) -> usize {
+        let mut texture = |url: &Option<Url>| -> Option<Value> {
+            let url = url.as_ref()?;
+
+            let images = gltf["images"].as_array().map(Vec::len).unwrap_or(0);
+
+            push(gltf, "images", json!({ "uri": url.as_str() }));
+
+            if gltf["samplers"]
+                .as_array()
+                .map(Vec::is_empty)
+                .unwrap_or(true)
+            {
+                // Linear filtering with mipmaps, repeating.
+                push(
+                    gltf,
+                    "samplers",
+                    json!({ "magFilter": 9729, "minFilter": 9987 }),
+                );
+            }
+
+            let index = push(gltf, "textures", json!({ "source": images, "sampler": 0 }));
+
+            Some(json!({ "index": index }))
+        };
+
+        let mut pbr = json!({
+            "baseColorFactor": self.base_color,
+            "metallicFactor": self.metallic,
+            "roughnessFactor": self.roughness,
+        });
+
+        if let Some(info) = texture(&self.base_color_texture) {
+            pbr["baseColorTexture"] = info;
+        }
+
+        if let Some(info) = texture(&self.metallic_roughness_texture) {
+            pbr["metallicRoughnessTexture"] = info;
+        }
+
+        let mut material = json!({
+            "pbrMetallicRoughness": pbr,
+            "emissiveFactor": self.emissive,
+            "doubleSided": self.double_sided,
+        });
+
+        if self.blend || self.base_color[3] < 1.0 {
+            material["alphaMode"] = "BLEND".into();
+        }
+
+        if let Some(info) = texture(&self.normal_texture) {
+            material["normalTexture"] = info;
+        }
+
+        if let Some(info) = texture(&self.occlusion_texture) {
+            material["occlusionTexture"] = info;
+        }
+
+        if let Some(info) = texture(&self.emissive_texture) {
+            material["emissiveTexture"] = info;
+        }
+
+        push(gltf, "materials", material)
+    }
+}
+
+fn push(gltf: &mut Value, key: &str, value: Value) -> usize {
+    if !gltf[key].is_array() {
+        gltf[key] = Value::Array(Vec::new());
+    }
+
+    let array = gltf[key].as_array_mut().unwrap();
+    array.push(value);
+    array.len() - 1
+}
@@ -0,0 +1,212 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res, ResMut},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    winit::event::MouseButton,
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::bounds::Bounds;
+use crate::downloads::{DownloadPriority, ModelSource};
+use crate::i18n::Locale;
+use crate::input::MouseButtonPressed;
+use crate::materials::MaterialDescriptor;
+use crate::placement::Placement;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+use crate::text::TextLabel;
+
+const MAX_MEASURE_DISTANCE: f32 = 50.0;
+const GROUND_HEIGHT: f32 = 0.0;
+const LINE_THICKNESS: f32 = 0.01;
+const MARKER_RADIUS: f32 = 0.03;
+// Above the middle of the line, in meters.
+const LABEL_OFFSET: f32 = 0.05;
+
+pub struct MeasureToggled;
+
+// While enabled, each click picks the point that the camera is looking at, on a prop or
+// else on the ground. Two points get a line between them and a label with their distance in
+// meters. Controller positions aren't available, so in VR points are picked with the head the
+// same way.
+#[derive(Default)]
+pub struct Measurement {
+    pub enabled: bool,
+    pub points: Vec<Vec3>,
+    // The line and end markers, as (model, instance) pairs.
+    models: Vec<(Entity, Entity)>,
+    label: Option<Entity>,
+}
+
+impl Measurement {
+    pub fn distance(&self) -> Option<f32> {
+        match self.points[..] {
+            [a, b] => Some(a.distance(b)),
+            _ => None,
+        }
+    }
+}
+
+pub struct MeasurementPlugin;
+
+impl Plugin for MeasurementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<MeasureToggled>();
+        app.insert_resource(Measurement::default());
+
+        app.add_system_set(schedule::simulation().with_system(update_measurement));
+    }
+}
+
+fn pick_point(
+    camera: &Camera,
+    bounds_q: &Query<(&Bounds, &components::InstanceOf)>,
+    source_q: &Query<&ModelSource>,
+) -> Option<Vec3> {
+    let forward = camera.rotation * -Vec3::Z;
+
+    // Only props are picked, as the environment's bounds cover everything.
+    let prop = bounds_q
+        .iter()
+        .filter(|(_, instance_of)| {
+            source_q
+                .get(instance_of.0)
+                .map_or(false, |source| source.0.priority == DownloadPriority::Props)
+        })
+        .filter_map(|(bounds, _)| bounds.0.ray_distance(camera.position, forward))
+        .min_by(|a, b| a.total_cmp(b));
+
+    let ground = if forward.y < -f32::EPSILON {
+        Some((GROUND_HEIGHT - camera.position.y) / forward.y)
+    } else {
+        None
+    };
+
+    let distance = match (prop, ground) {
+        (Some(prop), Some(ground)) => prop.min(ground),
+        (prop, ground) => prop.or(ground)?,
+    };
+
+    if distance > MAX_MEASURE_DISTANCE {
+        return None;
+    }
+
+    Some(camera.position + forward * distance)
+}
+
+fn spawn_shape(
+    commands: &mut Commands,
+    primitive: Primitive,
+    color: [f32; 3],
+    position: Vec3,
+    rotation: Quat,
+) -> (Entity, Entity) {
+    let model = spawn_primitive(commands, primitive, MaterialDescriptor::emissive(color));
+
+    let instance = commands
+        .spawn()
+        .insert(components::InstanceOf(model))
+        .insert(components::Instance(renderer_core::Instance::new(
+            position, 1.0, rotation,
+        )))
+        .id();
+
+    (model, instance)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_measurement(
+    mut commands: Commands,
+    mut measurement: ResMut<Measurement>,
+    mut toggles: EventReader<MeasureToggled>,
+    mut mouse_presses: EventReader<MouseButtonPressed>,
+    placement: Res<Placement>,
+    accessibility: Res<AccessibilitySettings>,
+    locale: Res<Locale>,
+    camera: Res<Camera>,
+    bounds_q: Query<(&Bounds, &components::InstanceOf)>,
+    source_q: Query<&ModelSource>,
+) {
+    let _span = tracing::info_span!("update_measurement").entered();
+
+    let mut changed = false;
+
+    for _ in toggles.iter() {
+        measurement.enabled = !measurement.enabled;
+        measurement.points.clear();
+        changed = true;
+    }
+
+    for MouseButtonPressed(button) in mouse_presses.iter() {
+        // Clicks belong to placement while something is being placed.
+        if !measurement.enabled || placement.is_placing() || *button != MouseButton::Left {
+            continue;
+        }
+
+        if let Some(point) = pick_point(&camera, &bounds_q, &source_q) {
+            if measurement.points.len() == 2 {
+                measurement.points.clear();
+            }
+
+            measurement.points.push(point);
+            changed = true;
+        }
+    }
+
+    if !changed && !accessibility.is_changed() && !locale.is_changed() {
+        return;
+    }
+
+    for (model, instance) in measurement.models.drain(..) {
+        commands.entity(model).despawn();
+        commands.entity(instance).despawn();
+    }
+
+    if let Some(label) = measurement.label.take() {
+        commands.entity(label).despawn();
+    }
+
+    let color = accessibility.palette().accent;
+    let mut models = Vec::new();
+
+    for &point in &measurement.points {
+        models.push(spawn_shape(
+            &mut commands,
+            Primitive::Sphere {
+                radius: MARKER_RADIUS,
+                sectors: 8,
+                stacks: 4,
+            },
+            color,
+            point,
+            Quat::IDENTITY,
+        ));
+    }
+
+    if let [a, b] = measurement.points[..] {
+        let length = a.distance(b);
+
+        models.push(spawn_shape(
+            &mut commands,
+            Primitive::Cuboid {
+                size: Vec3::new(LINE_THICKNESS, LINE_THICKNESS, length),
+            },
+            color,
+            (a + b) * 0.5,
+            Quat::from_rotation_arc(Vec3::Z, (b - a).normalize_or_zero()),
+        ));
+
+        log::info!("Measured {:.3} m", length);
+
+        let distance = format!("{:.2}", length);
+        let text = locale.format("measurement.distance", &[("distance", &distance)]);
+        let position = (a + b) * 0.5 + Vec3::new(0.0, LABEL_OFFSET, 0.0);
+
+        measurement.label = Some(commands.spawn().insert(TextLabel::new(text, position)).id());
+    }
+
+    measurement.models = models;
+}
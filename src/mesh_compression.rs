@@ -0,0 +1,462 @@
+use serde_json::Value;
+use superconductor::url::Url;
+
+pub const DRACO: &str = "KHR_draco_mesh_compression";
+pub const MESHOPT: &str = "EXT_meshopt_compression";
+
+const GLB_MAGIC: &[u8; 4] = b"glTF";
+const JSON_CHUNK: u32 = 0x4E4F534A;
+const BIN_CHUNK: u32 = 0x004E4942;
+
+fn read_u32(bytes: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        bytes.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+pub(crate) struct Glb {
+    pub json: Value,
+    pub bin: Option<Vec<u8>>,
+}
+
+impl Glb {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if !bytes.starts_with(GLB_MAGIC) {
+            return Err("not a glb file".into());
+        }
+
+        let mut offset = 12;
+        let mut json = None;
+        let mut bin = None;
+
+        while let (Some(length), Some(kind)) =
+            (read_u32(bytes, offset), read_u32(bytes, offset + 4))
+        {
+            let data = bytes
+                .get(offset + 8..offset + 8 + length as usize)
+                .ok_or("truncated glb chunk")?;
+
+            match kind {
+                JSON_CHUNK => {
+                    json = Some(serde_json::from_slice(data).map_err(|error| error.to_string())?)
+                }
+                BIN_CHUNK => bin = Some(data.to_vec()),
+                _ => {}
+            }
+
+            offset += 8 + length as usize;
+        }
+
+        Ok(Self {
+            json: json.ok_or("glb has no json chunk")?,
+            bin,
+        })
+    }
+
+    pub fn write(&self) -> Vec<u8> {
+        write_glb(&self.json, self.bin.as_deref())
+    }
+}
+
+fn write_glb(json: &Value, bin: Option<&[u8]>) -> Vec<u8> {
+    let mut json = serde_json::to_vec(json).unwrap();
+
+    while json.len() % 4 != 0 {
+        json.push(b' ');
+    }
+
+    let bin_length = bin.map(|bin| 8 + ((bin.len() + 3) & !3)).unwrap_or(0);
+
+    let total = 12 + 8 + json.len() + bin_length;
+
+    let mut output = Vec::with_capacity(total);
+    output.extend_from_slice(GLB_MAGIC);
+    output.extend_from_slice(&2u32.to_le_bytes());
+    output.extend_from_slice(&(total as u32).to_le_bytes());
+
+    output.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    output.extend_from_slice(&JSON_CHUNK.to_le_bytes());
+    output.extend_from_slice(&json);
+
+    if let Some(bin) = bin {
+        output.extend_from_slice(&(((bin.len() + 3) & !3) as u32).to_le_bytes());
+        output.extend_from_slice(&BIN_CHUNK.to_le_bytes());
+        output.extend_from_slice(bin);
+
+        while output.len() % 4 != 0 {
+            output.push(0);
+        }
+    }
+
+    output
+}
+
+// A downloaded glb or gltf file, parsed once and shared by everything that reads the model.
+pub struct GltfDocument {
+    pub json: Value,
+    // A glb's binary chunk. The buffers of a gltf file are left where they are.
+    pub bin: Option<Vec<u8>>,
+    binary: bool,
+}
+
+impl GltfDocument {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.starts_with(GLB_MAGIC) {
+            let glb = Glb::parse(bytes)?;
+
+            return Ok(Self {
+                json: glb.json,
+                bin: glb.bin,
+                binary: true,
+            });
+        }
+
+        Ok(Self {
+            json: serde_json::from_slice(bytes).map_err(|error| error.to_string())?,
+            bin: None,
+            binary: false,
+        })
+    }
+
+    pub fn extension(&self) -> &'static str {
+        if self.binary {
+            "glb"
+        } else {
+            "gltf"
+        }
+    }
+
+    // Writes the file back in the format it came in, with `json` in place of its own.
+    pub(crate) fn write(&self, json: &Value) -> Vec<u8> {
+        if self.binary {
+            write_glb(json, self.bin.as_deref())
+        } else {
+            serde_json::to_vec(json).unwrap()
+        }
+    }
+}
+
+// The file is stored somewhere else before the renderer gets it, so relative uris are made
+// absolute against the original url.
+pub(crate) fn make_uris_absolute(gltf: &mut Value, url: &Url) {
+    for key in ["buffers", "images"] {
+        for entry in gltf[key].as_array_mut().into_iter().flatten() {
+            let absolute = match entry["uri"].as_str() {
+                Some(uri) if !uri.starts_with("data:") => url.join(uri).ok(),
+                _ => None,
+            };
+
+            if let Some(absolute) = absolute {
+                entry["uri"] = absolute.as_str().into();
+            }
+        }
+    }
+}
+
+// Which of the mesh compression extensions a model uses.
+pub fn compression_extensions(gltf: &Value) -> Vec<&'static str> {
+    let used: Vec<&str> = gltf["extensionsUsed"]
+        .as_array()
+        .map(|used| used.iter().filter_map(|value| value.as_str()).collect())
+        .unwrap_or_default();
+
+    [DRACO, MESHOPT]
+        .into_iter()
+        .filter(|extension| used.contains(extension))
+        .collect()
+}
+
+// Decodes every compressed buffer view into the binary chunk, so that the renderer never sees
+// the extension. Compressed models are almost always distributed as glb files, which are the
+// only ones handled.
+pub fn decompress(document: &mut GltfDocument) -> Result<(), String> {
+    let _span = tracing::info_span!("decompress").entered();
+
+    let extensions = compression_extensions(&document.json);
+
+    // Draco's decoder is a large C++ library with no Rust port, so models that use it are
+    // turned away rather than decoded.
+    if extensions.contains(&DRACO) {
+        return Err(format!(
+            "{} isn't supported, compress the model with {} instead",
+            DRACO, MESHOPT
+        ));
+    }
+
+    if !extensions.contains(&MESHOPT) {
+        return Ok(());
+    }
+
+    if !document.binary {
+        return Err("compressed gltf files are not supported, only glb files".into());
+    }
+
+    #[cfg(feature = "meshopt")]
+    {
+        let glb = meshopt_decode(Glb {
+            json: std::mem::take(&mut document.json),
+            bin: document.bin.take(),
+        })?;
+
+        document.json = glb.json;
+        document.bin = glb.bin;
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "meshopt"))]
+    {
+        Err(format!(
+            "{} requires building with the `meshopt` feature",
+            MESHOPT
+        ))
+    }
+}
+
+#[cfg(feature = "meshopt")]
+fn meshopt_decode(mut glb: Glb) -> Result<Glb, String> {
+    use meshopt::ffi;
+
+    let mut bin = glb
+        .bin
+        .take()
+        .ok_or("compressed glb files without a binary chunk are not supported")?;
+
+    let buffers = glb.json["buffers"].as_array().cloned().unwrap_or_default();
+
+    let is_fallback =
+        |buffer: &Value| buffer["extensions"][MESHOPT]["fallback"].as_bool() == Some(true);
+
+    // Fallback buffers have no data of their own, so drop them and remap the rest.
+    let mut remap = Vec::with_capacity(buffers.len());
+    let mut kept = Vec::new();
+
+    for buffer in &buffers {
+        if is_fallback(buffer) {
+            remap.push(None);
+        } else {
+            remap.push(Some(kept.len()));
+            kept.push(buffer.clone());
+        }
+    }
+
+    if remap.first() != Some(&Some(0)) {
+        return Err("the first buffer of a glb must be its binary chunk".into());
+    }
+
+    let views = glb.json["bufferViews"]
+        .as_array_mut()
+        .ok_or("glb has no buffer views")?;
+
+    for view in views.iter_mut() {
+        let compression = view["extensions"][MESHOPT].clone();
+
+        if compression.is_null() {
+            let buffer = view["buffer"].as_u64().unwrap_or_default() as usize;
+            view["buffer"] = remap
+                .get(buffer)
+                .copied()
+                .flatten()
+                .ok_or("buffer view references a fallback buffer")?
+                .into();
+            continue;
+        }
+
+        let field = |name: &str| compression[name].as_u64().unwrap_or_default() as usize;
+
+        let source_buffer = field("buffer");
+        let offset = field("byteOffset");
+        let length = field("byteLength");
+        let stride = field("byteStride");
+        let count = field("count");
+
+        if source_buffer != 0 {
+            return Err("compressed data outside of the binary chunk is not supported".into());
+        }
+
+        let source = bin
+            .get(offset..offset + length)
+            .ok_or("compressed buffer view is out of bounds")?
+            .to_vec();
+
+        let mut decoded = vec![0u8; count * stride];
+
+        let result = unsafe {
+            match compression["mode"].as_str() {
+                Some("ATTRIBUTES") => ffi::meshopt_decodeVertexBuffer(
+                    decoded.as_mut_ptr() as *mut _,
+                    count,
+                    stride,
+                    source.as_ptr(),
+                    source.len(),
+                ),
+                Some("TRIANGLES") => ffi::meshopt_decodeIndexBuffer(
+                    decoded.as_mut_ptr() as *mut _,
+                    count,
+                    stride,
+                    source.as_ptr(),
+                    source.len(),
+                ),
+                Some("INDICES") => ffi::meshopt_decodeIndexSequence(
+                    decoded.as_mut_ptr() as *mut _,
+                    count,
+                    stride,
+                    source.as_ptr(),
+                    source.len(),
+                ),
+                mode => return Err(format!("unknown meshopt mode {:?}", mode)),
+            }
+        };
+
+        if result != 0 {
+            return Err(format!("meshopt decoding failed with code {}", result));
+        }
+
+        unsafe {
+            match compression["filter"].as_str() {
+                None | Some("NONE") => {}
+                Some("OCTAHEDRAL") => {
+                    ffi::meshopt_decodeFilterOct(decoded.as_mut_ptr() as *mut _, count, stride)
+                }
+                Some("QUATERNION") => {
+                    ffi::meshopt_decodeFilterQuat(decoded.as_mut_ptr() as *mut _, count, stride)
+                }
+                Some("EXPONENTIAL") => {
+                    ffi::meshopt_decodeFilterExp(decoded.as_mut_ptr() as *mut _, count, stride)
+                }
+                Some(filter) => return Err(format!("unknown meshopt filter {:?}", filter)),
+            }
+        }
+
+        while bin.len() % 4 != 0 {
+            bin.push(0);
+        }
+
+        let decoded_offset = bin.len();
+        bin.extend_from_slice(&decoded);
+
+        view["buffer"] = 0.into();
+        view["byteOffset"] = decoded_offset.into();
+        view["byteLength"] = decoded.len().into();
+
+        if let Some(extensions) = view["extensions"].as_object_mut() {
+            extensions.remove(MESHOPT);
+        }
+    }
+
+    kept[0]["byteLength"] = bin.len().into();
+    glb.json["buffers"] = kept.into();
+
+    for key in ["extensionsUsed", "extensionsRequired"] {
+        if let Some(list) = glb.json[key].as_array_mut() {
+            list.retain(|extension| extension.as_str() != Some(MESHOPT));
+        }
+    }
+
+    glb.bin = Some(bin);
+
+    Ok(glb)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn glb_round_trip() {
+        let glb = Glb {
+            json: json!({ "asset": { "version": "2.0" }, "buffers": [{ "byteLength": 5 }] }),
+            bin: Some(vec![1, 2, 3, 4, 5]),
+        };
+
+        let bytes = glb.write();
+
+        assert!(bytes.starts_with(GLB_MAGIC));
+        assert_eq!(bytes.len() % 4, 0);
+        assert_eq!(read_u32(&bytes, 8), Some(bytes.len() as u32));
+
+        let parsed = Glb::parse(&bytes).unwrap();
+
+        assert_eq!(parsed.json, glb.json);
+        // The binary chunk is padded to four bytes.
+        assert_eq!(parsed.bin, Some(vec![1, 2, 3, 4, 5, 0, 0, 0]));
+    }
+
+    #[test]
+    fn glb_without_bin() {
+        let glb = Glb {
+            json: json!({ "asset": { "version": "2.0" } }),
+            bin: None,
+        };
+
+        let parsed = Glb::parse(&glb.write()).unwrap();
+
+        assert_eq!(parsed.json, glb.json);
+        assert!(parsed.bin.is_none());
+    }
+
+    #[test]
+    fn truncated_glb() {
+        let glb = Glb {
+            json: json!({ "asset": { "version": "2.0" } }),
+            bin: Some(vec![0; 16]),
+        };
+
+        let bytes = glb.write();
+
+        assert!(Glb::parse(&bytes[..bytes.len() - 4]).is_err());
+        assert!(Glb::parse(b"not a glb").is_err());
+    }
+
+    #[test]
+    fn documents_keep_their_format() {
+        let json = json!({ "asset": { "version": "2.0" } });
+
+        let glb = Glb {
+            json: json.clone(),
+            bin: Some(vec![7; 4]),
+        };
+
+        let document = GltfDocument::parse(&glb.write()).unwrap();
+        assert_eq!(document.extension(), "glb");
+        assert_eq!(document.bin, Some(vec![7; 4]));
+
+        let rewritten = json!({ "asset": { "version": "2.0", "generator": "test" } });
+        let reparsed = GltfDocument::parse(&document.write(&rewritten)).unwrap();
+        assert_eq!(reparsed.json, rewritten);
+        assert_eq!(reparsed.bin, Some(vec![7; 4]));
+
+        let document = GltfDocument::parse(&serde_json::to_vec(&json).unwrap()).unwrap();
+        assert_eq!(document.extension(), "gltf");
+        assert!(document.bin.is_none());
+        assert_eq!(
+            GltfDocument::parse(&document.write(&json)).unwrap().json,
+            json
+        );
+    }
+
+    #[test]
+    fn relative_uris_are_made_absolute() {
+        let mut gltf = json!({
+            "buffers": [{ "uri": "scene.bin" }, { "uri": "data:application/octet-stream;base64," }],
+            "images": [{ "uri": "textures/a.png" }, { "bufferView": 0 }],
+        });
+
+        make_uris_absolute(
+            &mut gltf,
+            &Url::parse("http://host/models/scene.gltf").unwrap(),
+        );
+
+        assert_eq!(gltf["buffers"][0]["uri"], "http://host/models/scene.bin");
+        assert_eq!(
+            gltf["buffers"][1]["uri"],
+            "data:application/octet-stream;base64,"
+        );
+        assert_eq!(
+            gltf["images"][0]["uri"],
+            "http://host/models/textures/a.png"
+        );
+        assert!(gltf["images"][1]["uri"].is_null());
+    }
+}
@@ -0,0 +1,291 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res, ResMut, With, Without},
+    components,
+    renderer_core::glam::Vec2,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::bounds::{Bounds, BoundsOutline};
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::{schedule, LocalPlayer, RemotePlayer};
+
+// The width and depth of the area shown, in meters.
+const VIEW_SIZE: f32 = 40.0;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MarkerKind {
+    LocalPlayer,
+    Player,
+    Npc,
+}
+
+// Shows an entity on the mini-map. Players are marked automatically; apps mark their own NPCs.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct MiniMapMarker(pub MarkerKind);
+
+#[derive(Clone, Copy, Debug)]
+pub struct MapMarker {
+    pub kind: MarkerKind,
+    // From 0 to 1 across the map, with -Z (north) at the top.
+    pub position: Vec2,
+    // Radians clockwise from north.
+    pub heading: f32,
+}
+
+// A top-down orthographic view of the scene around the local player, with the footprints of
+// everything that has bounds and a marker for each player and NPC. The renderer can't render
+// to textures yet, so instead of a second camera the view is drawn onto a 2D canvas in the
+// corner of the page. Only the web draws it so far. The MiniMap binding (U by default, as M
+// measures) shows or hides it.
+#[derive(Default)]
+pub struct MiniMap {
+    pub visible: bool,
+    // Min and max corners of each footprint, in the same coordinates as the markers.
+    pub footprints: Vec<(Vec2, Vec2)>,
+    pub markers: Vec<MapMarker>,
+}
+
+pub struct MiniMapPlugin;
+
+impl Plugin for MiniMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(MiniMap::default());
+
+        app.add_system_set(schedule::input().with_system(handle_mini_map_key));
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(mark_players)
+                .with_system(update_mini_map),
+        );
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(dom::MiniMapDom::new());
+            app.add_system_set(schedule::pre_render().with_system(dom::draw_mini_map));
+        }
+    }
+}
+
+fn handle_mini_map_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut mini_map: ResMut<MiniMap>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::MiniMap) {
+            mini_map.visible = !mini_map.visible;
+
+            #[cfg(not(feature = "wasm"))]
+            if mini_map.visible {
+                log::warn!("The mini-map is only drawn on the web so far");
+            }
+        }
+    }
+}
+
+fn mark_players(
+    mut commands: Commands,
+    local_q: Query<Entity, Added<LocalPlayer>>,
+    remote_q: Query<Entity, Added<RemotePlayer>>,
+) {
+    for entity in local_q.iter() {
+        commands
+            .entity(entity)
+            .insert(MiniMapMarker(MarkerKind::LocalPlayer));
+    }
+
+    for entity in remote_q.iter() {
+        commands
+            .entity(entity)
+            .insert(MiniMapMarker(MarkerKind::Player));
+    }
+}
+
+// Maps a world position onto the map around `center`.
+fn map_position(center: Vec3, position: Vec3) -> Vec2 {
+    Vec2::new(position.x - center.x, position.z - center.z) / VIEW_SIZE + Vec2::splat(0.5)
+}
+
+fn update_mini_map(
+    mut mini_map: ResMut<MiniMap>,
+    camera: Res<Camera>,
+    marker_q: Query<(&MiniMapMarker, &components::Instance)>,
+    local_q: Query<&components::Instance, With<LocalPlayer>>,
+    footprint_q: Query<&Bounds, (Without<MiniMapMarker>, Without<BoundsOutline>)>,
+) {
+    if !mini_map.visible {
+        return;
+    }
+
+    let center = local_q
+        .iter()
+        .next()
+        .map_or(camera.position, |instance| instance.0.position);
+
+    let in_view = |min: Vec2, max: Vec2| min.cmple(Vec2::ONE).all() && max.cmpge(Vec2::ZERO).all();
+
+    // Refilled rather than collected, so that the vectors are reused from frame to frame.
+    mini_map.footprints.clear();
+    mini_map.footprints.extend(
+        footprint_q
+            .iter()
+            .map(|bounds| {
+                (
+                    map_position(center, bounds.0.min),
+                    map_position(center, bounds.0.max),
+                )
+            })
+            .filter(|&(min, max)| in_view(min, max)),
+    );
+
+    mini_map.markers.clear();
+    mini_map.markers.extend(
+        marker_q
+            .iter()
+            .map(|(marker, instance)| {
+                let forward = instance.0.rotation * -Vec3::Z;
+
+                MapMarker {
+                    kind: marker.0,
+                    position: map_position(center, instance.0.position),
+                    heading: forward.x.atan2(-forward.z),
+                }
+            })
+            .filter(|marker| in_view(marker.position, marker.position)),
+    );
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use superconductor::bevy_ecs::prelude::{NonSend, Res};
+    use wasm_bindgen::{JsCast, JsValue};
+
+    use super::{MarkerKind, MiniMap};
+    use crate::accessibility::AccessibilitySettings;
+    use crate::chat::dom::create_element;
+    use crate::photo_mode::PhotoMode;
+
+    // In css pixels.
+    const SIZE: f64 = 160.0;
+    const MARKER_RADIUS: f64 = 4.0;
+    const STYLE: &str = "position: fixed; left: 8px; bottom: 8px; width: 160px; height: 160px; \
+                         border-radius: 8px; pointer-events: none;";
+
+    pub struct MiniMapDom {
+        canvas: web_sys::HtmlCanvasElement,
+        context: web_sys::CanvasRenderingContext2d,
+    }
+
+    impl MiniMapDom {
+        pub fn new() -> Self {
+            let canvas: web_sys::HtmlCanvasElement = create_element("canvas").unchecked_into();
+
+            // So that it isn't mistaken for the canvas the scene is rendered to.
+            canvas.set_id("mini-map");
+            canvas.set_width(SIZE as u32);
+            canvas.set_height(SIZE as u32);
+            canvas.set_attribute("style", "display: none;").unwrap();
+
+            let context = canvas.get_context("2d").unwrap().unwrap().unchecked_into();
+
+            web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .append_child(&canvas)
+                .unwrap();
+
+            Self { canvas, context }
+        }
+    }
+
+    fn css_color([r, g, b]: [f32; 3]) -> JsValue {
+        JsValue::from_str(&format!(
+            "rgb({}, {}, {})",
+            (r * 255.0) as u8,
+            (g * 255.0) as u8,
+            (b * 255.0) as u8
+        ))
+    }
+
+    pub fn draw_mini_map(
+        dom: NonSend<MiniMapDom>,
+        mini_map: Res<MiniMap>,
+        photo_mode: Res<PhotoMode>,
+        accessibility: Res<AccessibilitySettings>,
+    ) {
+        // Hidden along with the rest of the ui in photo mode.
+        if !mini_map.visible || photo_mode.hides_ui() {
+            if mini_map.is_changed() || photo_mode.is_changed() {
+                dom.canvas.set_attribute("style", "display: none;").unwrap();
+            }
+
+            return;
+        }
+
+        dom.canvas.set_attribute("style", STYLE).unwrap();
+
+        let context = &dom.context;
+        let palette = accessibility.palette();
+
+        let (background, footprint) = if accessibility.high_contrast {
+            ("black", "white")
+        } else {
+            ("rgba(0, 0, 0, 0.6)", "rgba(255, 255, 255, 0.4)")
+        };
+
+        context.set_fill_style(&JsValue::from_str(background));
+        context.fill_rect(0.0, 0.0, SIZE, SIZE);
+
+        context.set_fill_style(&JsValue::from_str(footprint));
+
+        for (min, max) in &mini_map.footprints {
+            let (x, y) = (min.x as f64 * SIZE, min.y as f64 * SIZE);
+            // At least a pixel, so that small props still show up.
+            let width = ((max.x - min.x) as f64 * SIZE).max(1.0);
+            let depth = ((max.y - min.y) as f64 * SIZE).max(1.0);
+
+            context.fill_rect(x, y, width, depth);
+        }
+
+        for marker in &mini_map.markers {
+            let color = match marker.kind {
+                MarkerKind::LocalPlayer => palette.accent,
+                MarkerKind::Player => palette.valid,
+                MarkerKind::Npc => palette.invalid,
+            };
+
+            context.save();
+            let _ = context.translate(
+                marker.position.x as f64 * SIZE,
+                marker.position.y as f64 * SIZE,
+            );
+            let _ = context.rotate(marker.heading as f64);
+
+            // A triangle pointing the way the marked entity faces.
+            context.begin_path();
+            context.move_to(0.0, -MARKER_RADIUS * 1.5);
+            context.line_to(MARKER_RADIUS, MARKER_RADIUS);
+            context.line_to(-MARKER_RADIUS, MARKER_RADIUS);
+            context.close_path();
+
+            context.set_fill_style(&css_color(color));
+            context.fill();
+            context.restore();
+        }
+
+        context.set_fill_style(&JsValue::from_str("white"));
+        context.set_font("10px sans-serif");
+        let _ = context.fill_text("N", SIZE / 2.0 - 3.0, 10.0);
+    }
+}
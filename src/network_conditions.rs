@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+
+use superconductor::bevy_ecs::prelude::World;
+
+use crate::determinism::SimRng;
+use crate::protocol::{ClientMessage, ServerMessage};
+
+// A bad network, faked on top of the real one so that the remote player interpolation can be
+// tried out locally. Set from the console with e.g. `net_sim latency=150 jitter=30 loss=0.1`,
+// and `net_sim off` goes back to the real network.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    // Added to each message, in each direction.
+    pub latency_ms: f32,
+    // Up to this much more or less than the latency.
+    pub jitter_ms: f32,
+    // The chance of an avatar state update being dropped. The other messages would be resent
+    // on a real network, so they're only ever delayed.
+    pub packet_loss: f32,
+    // The chance of a message being held back behind the ones sent after it.
+    pub reordering: f32,
+}
+
+impl NetworkConditions {
+    pub fn is_simulated(&self) -> bool {
+        *self != Self::default()
+    }
+}
+
+// Messages that are waiting for the time they'd arrive at, in the order they'll arrive in.
+struct DelayQueue<T> {
+    queue: VecDeque<(f64, T)>,
+    last_due: f64,
+}
+
+impl<T> Default for DelayQueue<T> {
+    fn default() -> Self {
+        Self {
+            queue: VecDeque::new(),
+            last_due: 0.0,
+        }
+    }
+}
+
+impl<T> DelayQueue<T> {
+    fn push(
+        &mut self,
+        message: T,
+        droppable: bool,
+        conditions: &NetworkConditions,
+        rng: &mut SimRng,
+        now: f64,
+    ) {
+        if droppable && rng.next_f32() < conditions.packet_loss {
+            return;
+        }
+
+        let jitter = rng.range(-conditions.jitter_ms, conditions.jitter_ms);
+        let mut due = now + (conditions.latency_ms + jitter).max(0.0) as f64;
+
+        if rng.next_f32() < conditions.reordering {
+            // Held back for long enough that the next few messages overtake it.
+            due += (conditions.latency_ms + conditions.jitter_ms).max(50.0) as f64;
+        } else {
+            // Otherwise messages stay in order, as they would over a websocket.
+            due = due.max(self.last_due);
+            self.last_due = due;
+        }
+
+        let index = self.queue.partition_point(|(other, _)| *other <= due);
+        self.queue.insert(index, (due, message));
+    }
+
+    fn pop_due(&mut self, now: f64) -> Option<T> {
+        match self.queue.front() {
+            Some((due, _)) if *due <= now => self.queue.pop_front().map(|(_, message)| message),
+            _ => None,
+        }
+    }
+}
+
+pub struct NetworkSimulator {
+    pub conditions: NetworkConditions,
+    // Separate from the simulation's, so that a deterministic run plays out the same way with
+    // the network conditions changed.
+    rng: SimRng,
+    outgoing: DelayQueue<ClientMessage>,
+    incoming: DelayQueue<ServerMessage>,
+}
+
+impl NetworkSimulator {
+    pub fn new(seed: u64) -> Self {
+        Self {
+            conditions: NetworkConditions::default(),
+            rng: SimRng::new(seed),
+            outgoing: DelayQueue::default(),
+            incoming: DelayQueue::default(),
+        }
+    }
+
+    pub fn send(&mut self, message: ClientMessage, now: f64) {
+        let droppable = matches!(message, ClientMessage::AvatarState(_));
+
+        self.outgoing
+            .push(message, droppable, &self.conditions, &mut self.rng, now);
+    }
+
+    pub fn receive(&mut self, message: ServerMessage, now: f64) {
+        let droppable = matches!(message, ServerMessage::AvatarState { .. });
+
+        self.incoming
+            .push(message, droppable, &self.conditions, &mut self.rng, now);
+    }
+
+    pub fn sent(&mut self, now: f64) -> Option<ClientMessage> {
+        self.outgoing.pop_due(now)
+    }
+
+    pub fn received(&mut self, now: f64) -> Option<ServerMessage> {
+        self.incoming.pop_due(now)
+    }
+}
+
+pub const USAGE: &str = "net_sim [off] [latency=ms] [jitter=ms] [loss=0-1] [reorder=0-1]";
+
+pub fn net_sim(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let mut simulator = world
+        .get_resource_mut::<NetworkSimulator>()
+        .ok_or("There's no multiplayer connection")?;
+
+    let mut conditions = simulator.conditions;
+
+    for arg in args {
+        if *arg == "off" {
+            conditions = NetworkConditions::default();
+            continue;
+        }
+
+        let (name, value) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("Expected name=value, not {:?}", arg))?;
+
+        let value: f32 = value
+            .parse()
+            .map_err(|_| format!("Invalid {} {:?}", name, value))?;
+
+        match name {
+            "latency" => conditions.latency_ms = value.max(0.0),
+            "jitter" => conditions.jitter_ms = value.max(0.0),
+            "loss" => conditions.packet_loss = value.clamp(0.0, 1.0),
+            "reorder" => conditions.reordering = value.clamp(0.0, 1.0),
+            _ => return Err(format!("Unknown setting {:?}", name)),
+        }
+    }
+
+    simulator.conditions = conditions;
+
+    Ok(if conditions.is_simulated() {
+        format!(
+            "Simulating {}ms latency, {}ms jitter, {}% loss and {}% reordering",
+            conditions.latency_ms,
+            conditions.jitter_ms,
+            conditions.packet_loss * 100.0,
+            conditions.reordering * 100.0
+        )
+    } else {
+        "Not simulating network conditions".to_string()
+    })
+}
@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, CoreStage, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Local, Query, Res, ResMut, With},
+    components::{self, AnimationState},
+    renderer_core::{
+        self,
+        glam::{EulerRot, Quat},
+    },
+    resources::Camera,
+    Vec3,
+};
+
+use crate::avatars::PendingAvatarProfile;
+use crate::console::Console;
+use crate::determinism::DeterminismConfig;
+use crate::network_conditions::{self, NetworkSimulator};
+use crate::protocol::{AvatarState, ClientMessage, PlayerId, ServerMessage};
+use crate::render_stats::now_ms;
+use crate::schedule;
+use crate::{AvatarModel, LocalAvatarProfile, LocalPlayer, RemotePlayer};
+
+const SEND_INTERVAL_FRAMES: u32 = 6;
+const EYE_HEIGHT: f32 = 0.75;
+
+pub struct NetworkConfig {
+    pub server_url: String,
+    pub room: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        let default_url = format!("ws://localhost:{}", crate::protocol::DEFAULT_PORT);
+
+        #[cfg(not(feature = "wasm"))]
+        let server_url = std::env::var("SERVER_URL").unwrap_or(default_url);
+
+        #[cfg(feature = "wasm")]
+        let server_url = default_url;
+
+        #[cfg(not(feature = "wasm"))]
+        let room = std::env::var("ROOM").ok();
+
+        #[cfg(feature = "wasm")]
+        let room = crate::query_parameter("room");
+
+        Self { server_url, room }
+    }
+}
+
+// Messages go through the NetworkSimulator on their way to and from the connection, which
+// passes them straight on unless it's been given some network conditions.
+pub struct NetClient {
+    outgoing: Sender<ClientMessage>,
+    queued: Receiver<ClientMessage>,
+    connection: Sender<ClientMessage>,
+    incoming: Receiver<ServerMessage>,
+    pub player_id: Option<PlayerId>,
+    pub room: Option<String>,
+    pub host: Option<PlayerId>,
+}
+
+impl NetClient {
+    pub fn send(&self, message: ClientMessage) {
+        let _ = self.outgoing.send(message);
+    }
+
+    pub fn is_host(&self) -> bool {
+        self.player_id.is_some() && self.host == self.player_id
+    }
+}
+
+#[derive(Default)]
+pub struct RemotePlayers(pub HashMap<PlayerId, Entity>);
+
+#[derive(Default)]
+pub struct NetworkPlugin {
+    pub config: NetworkConfig,
+}
+
+impl Plugin for NetworkPlugin {
+    fn build(&self, app: &mut App) {
+        let (outgoing_sender, outgoing_receiver) = crossbeam_channel::unbounded();
+        let (connection_sender, connection_receiver) = crossbeam_channel::unbounded();
+        let (incoming_sender, incoming_receiver) = crossbeam_channel::unbounded();
+
+        #[cfg(not(feature = "wasm"))]
+        spawn_native_connection(
+            self.config.server_url.clone(),
+            incoming_sender,
+            connection_receiver,
+        );
+
+        #[cfg(feature = "wasm")]
+        app.insert_non_send_resource(connect_wasm(
+            &self.config.server_url,
+            incoming_sender,
+            connection_receiver,
+        ));
+
+        let client = NetClient {
+            outgoing: outgoing_sender,
+            queued: outgoing_receiver,
+            connection: connection_sender,
+            incoming: incoming_receiver,
+            player_id: None,
+            room: None,
+            host: None,
+        };
+
+        client.send(match &self.config.room {
+            Some(room) => ClientMessage::JoinRoom { room: room.clone() },
+            None => ClientMessage::QuickJoin,
+        });
+
+        let seed = app
+            .world
+            .get_resource::<DeterminismConfig>()
+            .map_or(0, |config| config.seed);
+
+        app.insert_resource(client);
+        app.insert_resource(NetworkSimulator::new(seed));
+        app.insert_resource(RemotePlayers::default());
+        app.add_event::<ServerMessage>();
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register(
+                "net_sim",
+                network_conditions::USAGE,
+                network_conditions::net_sim,
+            );
+
+        app.add_system_set(
+            schedule::input()
+                .with_system(receive_server_messages)
+                .with_system(sync_remote_avatars),
+        );
+        app.add_system_set(schedule::pre_render().with_system(send_local_avatar_state));
+        app.add_system_to_stage(CoreStage::PostUpdate, send_client_messages);
+
+        #[cfg(feature = "wasm")]
+        app.add_system_to_stage(CoreStage::Last, flush_wasm_socket);
+    }
+}
+
+fn receive_server_messages(
+    mut client: ResMut<NetClient>,
+    mut simulator: ResMut<NetworkSimulator>,
+    mut server_messages: EventWriter<ServerMessage>,
+    local_profile: Res<LocalAvatarProfile>,
+) {
+    let _span = tracing::info_span!("receive_server_messages").entered();
+
+    let now = now_ms();
+
+    for message in client.incoming.try_iter() {
+        simulator.receive(message, now);
+    }
+
+    while let Some(message) = simulator.received(now) {
+        match &message {
+            ServerMessage::Joined { room, player_id } => {
+                log::info!("Joined room {:?} as player {}", room, player_id);
+                client.room = Some(room.clone());
+                client.player_id = Some(*player_id);
+                client.send(ClientMessage::AvatarProfile(local_profile.0.clone()));
+            }
+            ServerMessage::HostChanged { host } => {
+                client.host = *host;
+
+                if client.is_host() {
+                    log::info!("This client is now the room host");
+                }
+            }
+            ServerMessage::JoinRejected { room, reason } => {
+                log::warn!("Could not join room {:?}: {}", room, reason);
+            }
+            _ => {}
+        }
+
+        server_messages.send(message);
+    }
+}
+
+fn sync_remote_avatars(
+    mut commands: Commands,
+    mut server_messages: EventReader<ServerMessage>,
+    mut remote_players: ResMut<RemotePlayers>,
+    avatar_model: Res<AvatarModel>,
+    mut avatar_q: Query<(&mut components::Instance, &mut AnimationState), With<RemotePlayer>>,
+) {
+    let _span = tracing::info_span!("sync_remote_avatars").entered();
+
+    for message in server_messages.iter() {
+        match message {
+            ServerMessage::Joined { .. } => {
+                for (_, entity) in remote_players.0.drain() {
+                    commands.entity(entity).despawn();
+                }
+            }
+            ServerMessage::PlayerEntered { player_id } => {
+                remote_players.0.entry(*player_id).or_insert_with(|| {
+                    commands
+                        .spawn()
+                        .insert(components::InstanceOf(avatar_model.0))
+                        .insert(components::Instance(renderer_core::Instance::new(
+                            Vec3::ZERO,
+                            1.0,
+                            Default::default(),
+                        )))
+                        .insert(AnimationState {
+                            time: 0.0,
+                            animation_index: 0,
+                        })
+                        .insert(RemotePlayer(*player_id))
+                        .id()
+                });
+            }
+            ServerMessage::AvatarProfile { player_id, profile } => {
+                if let Some(entity) = remote_players.0.get(player_id) {
+                    commands
+                        .entity(*entity)
+                        .insert(PendingAvatarProfile(profile.clone()));
+                }
+            }
+            ServerMessage::PlayerLeft { player_id } | ServerMessage::PlayerExited { player_id } => {
+                if let Some(entity) = remote_players.0.remove(player_id) {
+                    commands.entity(entity).despawn();
+                }
+            }
+            ServerMessage::AvatarState { player_id, state } => {
+                let entity = match remote_players.0.get(player_id) {
+                    Some(entity) => *entity,
+                    None => continue,
+                };
+
+                if let Ok((mut instance, mut animation_state)) = avatar_q.get_mut(entity) {
+                    instance.0.position = Vec3::from(state.position);
+                    instance.0.rotation = Quat::from_rotation_y(state.yaw);
+
+                    let animation_index = state.animation_index as usize;
+
+                    if animation_state.animation_index != animation_index {
+                        animation_state.animation_index = animation_index;
+                        animation_state.time = 0.0;
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn send_local_avatar_state(
+    client: Res<NetClient>,
+    camera: Res<Camera>,
+    local_q: Query<&AnimationState, With<LocalPlayer>>,
+    mut frame: Local<u32>,
+) {
+    *frame += 1;
+
+    if *frame % SEND_INTERVAL_FRAMES != 0 || client.room.is_none() {
+        return;
+    }
+
+    let animation_index = local_q
+        .get_single()
+        .map(|state| state.animation_index as u32)
+        .unwrap_or_default();
+
+    let (yaw, _, _) = camera.rotation.to_euler(EulerRot::YXZ);
+
+    client.send(ClientMessage::AvatarState(AvatarState {
+        position: (camera.position - Vec3::new(0.0, EYE_HEIGHT, 0.0)).into(),
+        yaw,
+        animation_index,
+    }));
+}
+
+// Runs after the update stage, so that messages sent during it go out the same frame.
+fn send_client_messages(client: Res<NetClient>, mut simulator: ResMut<NetworkSimulator>) {
+    let now = now_ms();
+
+    for message in client.queued.try_iter() {
+        simulator.send(message, now);
+    }
+
+    while let Some(message) = simulator.sent(now) {
+        let _ = client.connection.send(message);
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn spawn_native_connection(
+    url: String,
+    incoming: Sender<ServerMessage>,
+    outgoing: Receiver<ClientMessage>,
+) {
+    use futures::{SinkExt, StreamExt};
+    use tokio_tungstenite::tungstenite::Message;
+
+    std::thread::spawn(move || {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+
+        runtime.block_on(async move {
+            let socket = match tokio_tungstenite::connect_async(url.as_str()).await {
+                Ok((socket, _)) => socket,
+                Err(error) => {
+                    log::error!("Failed to connect to {}: {}", url, error);
+                    return;
+                }
+            };
+
+            let (mut sink, mut stream) = socket.split();
+
+            // The ECS side uses blocking channels, so bridge them onto an async one.
+            let (forward_sender, mut forward_receiver) = tokio::sync::mpsc::unbounded_channel();
+
+            std::thread::spawn(move || {
+                for message in outgoing.iter() {
+                    if forward_sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            loop {
+                tokio::select! {
+                    message = forward_receiver.recv() => {
+                        let message = match message {
+                            Some(message) => message,
+                            None => break,
+                        };
+
+                        let text = serde_json::to_string(&message).unwrap();
+
+                        if sink.send(Message::Text(text)).await.is_err() {
+                            break;
+                        }
+                    }
+                    message = stream.next() => {
+                        match message {
+                            Some(Ok(Message::Text(text))) => match serde_json::from_str(&text) {
+                                Ok(message) => {
+                                    if incoming.send(message).is_err() {
+                                        break;
+                                    }
+                                }
+                                Err(error) => log::warn!("Invalid server message: {}", error),
+                            },
+                            Some(Ok(_)) => {}
+                            _ => break,
+                        }
+                    }
+                }
+            }
+
+            log::warn!("Disconnected from {}", url);
+        });
+    });
+}
+
+#[cfg(feature = "wasm")]
+struct WasmSocket {
+    socket: web_sys::WebSocket,
+    outgoing: Receiver<ClientMessage>,
+}
+
+#[cfg(feature = "wasm")]
+fn connect_wasm(
+    url: &str,
+    incoming: Sender<ServerMessage>,
+    outgoing: Receiver<ClientMessage>,
+) -> WasmSocket {
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    let socket = web_sys::WebSocket::new(url).unwrap();
+
+    let onmessage = Closure::wrap(Box::new(move |event: web_sys::MessageEvent| {
+        if let Some(text) = event.data().as_string() {
+            match serde_json::from_str(&text) {
+                Ok(message) => {
+                    let _ = incoming.send(message);
+                }
+                Err(error) => log::warn!("Invalid server message: {}", error),
+            }
+        }
+    }) as Box<dyn FnMut(web_sys::MessageEvent)>);
+
+    socket.set_onmessage(Some(onmessage.as_ref().unchecked_ref()));
+    onmessage.forget();
+
+    WasmSocket { socket, outgoing }
+}
+
+#[cfg(feature = "wasm")]
+fn flush_wasm_socket(socket: superconductor::bevy_ecs::prelude::NonSend<WasmSocket>) {
+    if socket.socket.ready_state() != web_sys::WebSocket::OPEN {
+        return;
+    }
+
+    for message in socket.outgoing.try_iter() {
+        let text = serde_json::to_string(&message).unwrap();
+
+        if let Err(error) = socket.socket.send_with_str(&text) {
+            log::warn!("Failed to send message: {:?}", error);
+        }
+    }
+}
@@ -0,0 +1,388 @@
+use std::collections::HashMap;
+
+use serde_json::{json, Value};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Changed, Commands, Component, Entity, Query, Res, ResMut, With, Without},
+    components, renderer_core,
+    renderer_core::glam::Mat4,
+    url::Url,
+};
+
+use crate::asset_cache::{AssetCache, AssetError};
+use crate::bounds::{instance_transform, node_transform};
+use crate::downloads::ModelInspected;
+use crate::schedule;
+
+// Put on a model entity before its download starts to load each node with a mesh as a model
+// of its own, so that the parts can be moved and hidden separately. Every instance of the
+// model then gets an entity with a `GltfNode` for each node in the default scene, in place of
+// drawing the model whole. Animated models are always loaded whole, as skinning and animation
+// channels span nodes.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ExpandNodes;
+
+// A node of an expanded model, as it is in the file. Parents come before their children.
+#[derive(Clone, Debug)]
+pub struct NodeDescription {
+    pub index: usize,
+    pub name: Option<String>,
+    // Into the list of descriptions, not the file's nodes.
+    pub parent: Option<usize>,
+    pub transform: Mat4,
+    // The node's mesh on its own, for nodes with one.
+    pub mesh_url: Option<Url>,
+}
+
+// A node of an instance of an expanded model. Game code can change its transform (relative to
+// the parent node) and hide it, which hides its children too.
+#[derive(Component, Clone, Debug)]
+pub struct GltfNode {
+    pub name: Option<String>,
+    pub index: usize,
+    pub instance: Entity,
+    pub parent: Option<Entity>,
+    pub transform: Mat4,
+    pub visible: bool,
+}
+
+// The nodes of an expanded model, along with the models of their meshes.
+#[derive(Component, Clone, Debug)]
+pub struct ModelNodes {
+    pub nodes: Vec<NodeDescription>,
+    pub parts: Vec<Option<Entity>>,
+}
+
+// The node entities of each instance of an expanded model, in the same order as its
+// descriptions, and the part models of each expanded model.
+#[derive(Default)]
+pub struct ExpandedNodes {
+    instances: HashMap<Entity, Vec<Entity>>,
+    models: HashMap<Entity, Vec<Entity>>,
+}
+
+impl ExpandedNodes {
+    pub fn nodes(&self, instance: Entity) -> &[Entity] {
+        self.instances
+            .get(&instance)
+            .map(|nodes| nodes.as_slice())
+            .unwrap_or_default()
+    }
+}
+
+fn describe_node(
+    gltf: &Value,
+    index: usize,
+    parent: Option<usize>,
+    depth: usize,
+    output: &mut Vec<(usize, Option<usize>)>,
+) {
+    // Guards against cycles in broken files.
+    if depth > 64 {
+        return;
+    }
+
+    let position = output.len();
+    output.push((index, parent));
+
+    for child in gltf["nodes"][index]["children"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(child) = child.as_u64() {
+            describe_node(gltf, child as usize, Some(position), depth + 1, output);
+        }
+    }
+}
+
+// A copy of the file whose default scene is just the node's mesh, at the origin.
+fn part_json(gltf: &Value, index: usize) -> Value {
+    let mut part = gltf.clone();
+
+    part["scene"] = 0.into();
+    part["scenes"] = json!([{ "nodes": [index] }]);
+
+    if let Some(node) = part["nodes"][index].as_object_mut() {
+        for key in [
+            "matrix",
+            "translation",
+            "rotation",
+            "scale",
+            "children",
+            "skin",
+            "camera",
+        ] {
+            node.remove(key);
+        }
+    }
+
+    if let Some(part) = part.as_object_mut() {
+        part.remove("animations");
+    }
+
+    part
+}
+
+fn derived_key(key: &Url, suffix: &str) -> Url {
+    let mut key = key.clone();
+
+    let fragment = match key.fragment() {
+        Some(fragment) => format!("{},{}", fragment, suffix),
+        None => suffix.to_string(),
+    };
+
+    key.set_fragment(Some(&fragment));
+    key
+}
+
+// Stores a model of each mesh node under `key` and describes the nodes of the default scene.
+// `gltf` is the model's json with its uris already made absolute. A glb's binary chunk is
+// stored once and shared by all of its parts.
+pub(crate) fn split_nodes(
+    mut gltf: Value,
+    bin: Option<&[u8]>,
+    key: &Url,
+    cache: &AssetCache,
+) -> Result<Vec<NodeDescription>, AssetError> {
+    if let (Some(bin), true) = (bin, gltf["buffers"][0].is_object()) {
+        let bin_url = cache.store_derived(&derived_key(key, "bin"), "bin", bin)?;
+        gltf["buffers"][0]["uri"] = bin_url.as_str().into();
+    }
+
+    let scene = gltf["scene"].as_u64().unwrap_or(0) as usize;
+    let mut order = Vec::new();
+
+    for node in gltf["scenes"][scene]["nodes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if let Some(node) = node.as_u64() {
+            describe_node(&gltf, node as usize, None, 0, &mut order);
+        }
+    }
+
+    order
+        .into_iter()
+        .map(|(index, parent)| {
+            let node = &gltf["nodes"][index];
+
+            let mesh_url = if node["mesh"].is_u64() {
+                let part = serde_json::to_vec(&part_json(&gltf, index))
+                    .map_err(|error| AssetError::Decode(error.to_string()))?;
+
+                Some(cache.store_derived(
+                    &derived_key(key, &format!("node={}", index)),
+                    "gltf",
+                    &part,
+                )?)
+            } else {
+                None
+            };
+
+            Ok(NodeDescription {
+                index,
+                name: node["name"].as_str().map(|name| name.to_string()),
+                parent,
+                transform: node_transform(node),
+                mesh_url,
+            })
+        })
+        .collect()
+}
+
+pub struct NodeHierarchyPlugin;
+
+impl Plugin for NodeHierarchyPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ExpandedNodes::default());
+
+        app.add_system(insert_model_nodes);
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(spawn_instance_nodes)
+                .with_system(update_node_instances),
+        );
+    }
+}
+
+fn insert_model_nodes(
+    mut commands: Commands,
+    mut inspected: EventReader<ModelInspected>,
+    mut expanded: ResMut<ExpandedNodes>,
+    model_q: Query<(), With<components::Instances>>,
+) {
+    for model in inspected.iter() {
+        let (entity, nodes) = match (model_q.get(model.entity), &model.nodes) {
+            (Ok(()), Some(nodes)) => (model.entity, nodes.clone()),
+            _ => continue,
+        };
+
+        let parts: Vec<Option<Entity>> = nodes
+            .iter()
+            .map(|node| {
+                let mesh_url = node.mesh_url.clone()?;
+
+                Some(
+                    commands
+                        .spawn()
+                        .insert(components::ModelUrl(mesh_url))
+                        .insert(components::Instances(Default::default()))
+                        .insert(components::InstanceRange(Default::default()))
+                        .id(),
+                )
+            })
+            .collect();
+
+        expanded
+            .models
+            .insert(entity, parts.iter().flatten().copied().collect());
+
+        commands.entity(entity).insert(ModelNodes { nodes, parts });
+    }
+
+    // The parts go along with the model.
+    expanded.models.retain(|model, parts| {
+        let keep = model_q.get(*model).is_ok();
+
+        if !keep {
+            for part in parts.drain(..) {
+                commands.entity(part).despawn();
+            }
+        }
+
+        keep
+    });
+}
+
+// Spawns the nodes of each instance of an expanded model, and despawns them along with the
+// instance.
+fn spawn_instance_nodes(
+    mut commands: Commands,
+    mut expanded: ResMut<ExpandedNodes>,
+    model_q: Query<&ModelNodes>,
+    instance_q: Query<(Entity, &components::InstanceOf)>,
+) {
+    expanded.instances.retain(|instance, nodes| {
+        let keep = instance_q
+            .get(*instance)
+            .map_or(false, |(_, instance_of)| model_q.get(instance_of.0).is_ok());
+
+        if !keep {
+            for node in nodes.drain(..) {
+                commands.entity(node).despawn();
+            }
+        }
+
+        keep
+    });
+
+    for (instance, instance_of) in instance_q.iter() {
+        if expanded.instances.contains_key(&instance) {
+            continue;
+        }
+
+        let model = match model_q.get(instance_of.0) {
+            Ok(model) => model,
+            Err(_) => continue,
+        };
+
+        let mut nodes: Vec<Entity> = Vec::with_capacity(model.nodes.len());
+
+        for (node, part) in model.nodes.iter().zip(&model.parts) {
+            let mut node_commands = commands.spawn();
+
+            node_commands.insert(GltfNode {
+                name: node.name.clone(),
+                index: node.index,
+                instance,
+                parent: node.parent.map(|parent| nodes[parent]),
+                transform: node.transform,
+                visible: true,
+            });
+
+            // Placed by `update_node_instances`.
+            if let Some(part) = part {
+                node_commands
+                    .insert(components::InstanceOf(*part))
+                    .insert(components::Instance(renderer_core::Instance::new(
+                        Default::default(),
+                        0.0,
+                        Default::default(),
+                    )));
+            }
+
+            nodes.push(node_commands.id());
+        }
+
+        expanded.instances.insert(instance, nodes);
+    }
+}
+
+// Places the parts of every instance that moved or had a node changed. Hidden nodes are shrunk
+// to nothing, as instances can't be hidden otherwise.
+fn update_node_instances(
+    expanded: Res<ExpandedNodes>,
+    instance_q: Query<&components::Instance, Without<GltfNode>>,
+    changed_instance_q: Query<(), (Changed<components::Instance>, Without<GltfNode>)>,
+    changed_node_q: Query<&GltfNode, Changed<GltfNode>>,
+    mut node_q: Query<(&GltfNode, Option<&mut components::Instance>)>,
+) {
+    let mut changed: Vec<Entity> = changed_node_q.iter().map(|node| node.instance).collect();
+
+    changed.extend(
+        expanded
+            .instances
+            .keys()
+            .copied()
+            .filter(|&instance| changed_instance_q.get(instance).is_ok()),
+    );
+
+    changed.sort_unstable();
+    changed.dedup();
+
+    for instance in changed {
+        let root = match instance_q.get(instance) {
+            Ok(root) => instance_transform(&root.0),
+            Err(_) => continue,
+        };
+
+        let nodes = expanded.nodes(instance);
+        let mut world: Vec<(Mat4, bool)> = Vec::with_capacity(nodes.len());
+
+        for &entity in nodes {
+            let (node, instance) = match node_q.get_mut(entity) {
+                Ok(node) => node,
+                // Spawned this frame.
+                Err(_) => break,
+            };
+
+            let (parent, parent_visible) = node
+                .parent
+                .and_then(|parent| nodes.iter().position(|&other| other == parent))
+                .and_then(|parent| world.get(parent).copied())
+                .unwrap_or((root, true));
+
+            let transform = parent * node.transform;
+            let visible = parent_visible && node.visible;
+
+            world.push((transform, visible));
+
+            if let Some(mut instance) = instance {
+                let (scale, rotation, position) = transform.to_scale_rotation_translation();
+                // Instances only have a uniform scale.
+                let scale = if visible { scale.max_element() } else { 0.0 };
+
+                if instance.0.position != position
+                    || instance.0.rotation != rotation
+                    || instance.0.scale != scale
+                {
+                    instance.0.position = position;
+                    instance.0.rotation = rotation;
+                    instance.0.scale = scale;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,186 @@
+use std::collections::{HashSet, VecDeque};
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Or, Query, Res, ResMut, With},
+    components,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::bounds::{Aabb, Bounds};
+use crate::debug_stats::DebugStats;
+use crate::schedule;
+
+// Wider than the desktop view, so that portals at the edge of a wide window or a headset's
+// view aren't culled too early.
+const VIEW_HALF_ANGLE_DEGREES: f32 = 60.0;
+
+// A room or other enclosed space. Instances whose bounds are centered inside it are only drawn
+// when it can be seen from the camera's cell through portals.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct OcclusionCell(pub Aabb);
+
+// A doorway or window between two cells.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct CellPortal {
+    pub cells: (Entity, Entity),
+    pub aabb: Aabb,
+}
+
+// Holds the model of an instance that's been culled, so that the renderer skips it.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Culled(pub Entity);
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct OcclusionStats {
+    pub cells: usize,
+    pub visible_cells: usize,
+    pub culled_instances: usize,
+}
+
+pub struct OcclusionPlugin;
+
+impl Plugin for OcclusionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<DebugStats>();
+
+        app.add_system_set(schedule::pre_render().with_system(cull_occluded_instances));
+    }
+}
+
+fn contains(aabb: &Aabb, point: Vec3) -> bool {
+    point.cmpge(aabb.min).all() && point.cmple(aabb.max).all()
+}
+
+// A cone test against the portal's bounding sphere, rather than a proper frustum. Portals
+// don't narrow the view for the cells behind them either, so this errs towards drawing more.
+fn in_view(camera: &Camera, aabb: &Aabb) -> bool {
+    let offset = aabb.center() - camera.position;
+    let distance = offset.length();
+    let radius = aabb.size().length() * 0.5;
+
+    if distance <= radius {
+        return true;
+    }
+
+    let forward = camera.rotation * -Vec3::Z;
+    let angle = forward.angle_between(offset);
+
+    angle <= VIEW_HALF_ANGLE_DEGREES.to_radians() + (radius / distance).asin()
+}
+
+// Fills `visible` with the cells that can be seen from the camera's. Returns false when the
+// camera is outside of every cell and nothing should be culled.
+fn find_visible_cells(
+    camera: &Camera,
+    cell_q: &Query<(Entity, &OcclusionCell)>,
+    portal_q: &Query<&CellPortal>,
+    visible: &mut HashSet<Entity>,
+    queue: &mut VecDeque<Entity>,
+) -> bool {
+    visible.clear();
+    queue.clear();
+
+    // The smallest cell, for cells nested inside bigger ones.
+    let start = match cell_q
+        .iter()
+        .filter(|(_, cell)| contains(&cell.0, camera.position))
+        .min_by(|(_, a), (_, b)| a.0.size().length().total_cmp(&b.0.size().length()))
+    {
+        Some((start, _)) => start,
+        None => return false,
+    };
+
+    visible.insert(start);
+    queue.push_back(start);
+
+    while let Some(cell) = queue.pop_front() {
+        for portal in portal_q.iter() {
+            let next = match portal.cells {
+                (a, b) if a == cell => b,
+                (a, b) if b == cell => a,
+                _ => continue,
+            };
+
+            if !visible.contains(&next) && in_view(camera, &portal.aabb) {
+                visible.insert(next);
+                queue.push_back(next);
+            }
+        }
+    }
+
+    true
+}
+
+fn cull_occluded_instances(
+    mut commands: Commands,
+    mut stats: ResMut<DebugStats>,
+    // Kept between frames so that they don't have to be allocated again.
+    mut buffers: Local<(HashSet<Entity>, VecDeque<Entity>)>,
+    camera: Res<Camera>,
+    cell_q: Query<(Entity, &OcclusionCell)>,
+    portal_q: Query<&CellPortal>,
+    instance_q: Query<
+        (
+            Entity,
+            &Bounds,
+            Option<&components::InstanceOf>,
+            Option<&Culled>,
+        ),
+        Or<(With<components::InstanceOf>, With<Culled>)>,
+    >,
+) {
+    let _span = tracing::info_span!("cull_occluded_instances").entered();
+
+    let (visible, queue) = &mut *buffers;
+    let any_visible = find_visible_cells(&camera, &cell_q, &portal_q, visible, queue);
+
+    let mut culled_instances = 0;
+
+    for (entity, bounds, instance_of, culled) in instance_q.iter() {
+        let center = bounds.0.center();
+
+        let hidden = any_visible && {
+            let mut cells = cell_q
+                .iter()
+                .filter(|(_, cell)| contains(&cell.0, center))
+                .peekable();
+
+            // Instances outside of every cell are never culled.
+            cells.peek().is_some() && !cells.any(|(cell, _)| visible.contains(&cell))
+        };
+
+        match (hidden, instance_of, culled) {
+            (true, Some(instance_of), _) => {
+                commands
+                    .entity(entity)
+                    .remove::<components::InstanceOf>()
+                    .insert(Culled(instance_of.0));
+            }
+            (false, None, Some(culled)) => {
+                commands
+                    .entity(entity)
+                    .remove::<Culled>()
+                    .insert(components::InstanceOf(culled.0));
+            }
+            // The model was swapped while it was culled.
+            (false, Some(_), Some(_)) => {
+                commands.entity(entity).remove::<Culled>();
+            }
+            _ => {}
+        }
+
+        culled_instances += hidden as usize;
+    }
+
+    let occlusion = OcclusionStats {
+        cells: cell_q.iter().count(),
+        visible_cells: visible.len(),
+        culled_instances,
+    };
+
+    if stats.occlusion != Some(occlusion) {
+        stats.occlusion = Some(occlusion);
+    }
+}
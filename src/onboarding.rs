@@ -0,0 +1,238 @@
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+};
+
+use crate::i18n::Locale;
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::schedule;
+use crate::settings::{self, Setting};
+
+// Whether the controls overlay has been dismissed, so that it's only shown on the first run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Onboarding {
+    pub dismissed: bool,
+}
+
+impl Setting for Onboarding {
+    const KEY: &'static str = "onboarding";
+}
+
+// An overlay listing the controls, with the current key bindings. Shown on the first run, and
+// shown or hidden with the ShowControls binding (F1 by default) after that.
+pub struct ControlsOverlay {
+    pub visible: bool,
+}
+
+// Needs the demo's key bindings, so it's only added along with them.
+pub struct OnboardingPlugin;
+
+impl Plugin for OnboardingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Onboarding::default());
+        settings::persist::<Onboarding>(app);
+
+        let dismissed = app.world.get_resource::<Onboarding>().unwrap().dismissed;
+        app.insert_resource(ControlsOverlay {
+            visible: !dismissed,
+        });
+
+        app.add_system_set(schedule::simulation().with_system(toggle_controls_overlay));
+
+        #[cfg(not(feature = "wasm"))]
+        app.add_system_set(schedule::pre_render().with_system(log_controls));
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(dom::ControlsDom::new());
+            app.add_system_set(schedule::pre_render().with_system(dom::update_controls_dom));
+        }
+    }
+}
+
+fn key_name(bindings: &KeyBindings, action: KeyAction) -> &'static str {
+    bindings
+        .0
+        .get(&action)
+        .and_then(|key| key.name())
+        .unwrap_or("?")
+}
+
+// The overlay's title and lines, in the current language.
+fn controls_text(locale: &Locale, bindings: &KeyBindings) -> (String, Vec<String>) {
+    let mut lines: Vec<String> = bindings
+        .0
+        .iter()
+        .map(|(action, key)| {
+            let action = locale.text(&format!("action.{:?}", action)).to_string();
+
+            locale.format(
+                "controls.binding",
+                &[("key", &key.name().unwrap_or("?")), ("action", &action)],
+            )
+        })
+        .collect();
+
+    for id in [
+        "controls.move",
+        "controls.grab_cursor",
+        "controls.emote",
+        "controls.controllers",
+    ] {
+        lines.push(locale.text(id).to_string());
+    }
+
+    let title = format!(
+        "{} ({})",
+        locale.text("controls.title"),
+        key_name(bindings, KeyAction::ShowControls)
+    );
+
+    (title, lines)
+}
+
+fn toggle_controls_overlay(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Res<KeyBindings>,
+    mut overlay: ResMut<ControlsOverlay>,
+    mut onboarding: ResMut<Onboarding>,
+) {
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) != Some(KeyAction::ShowControls) {
+            continue;
+        }
+
+        overlay.visible = !overlay.visible;
+
+        if !overlay.visible && !onboarding.dismissed {
+            onboarding.dismissed = true;
+        }
+    }
+}
+
+// There's no text rendering on desktop or in XR, so the controls are logged there instead.
+#[cfg(not(feature = "wasm"))]
+fn log_controls(overlay: Res<ControlsOverlay>, bindings: Res<KeyBindings>, locale: Res<Locale>) {
+    let changed = overlay.is_changed() || bindings.is_changed() || locale.is_changed();
+
+    if !overlay.visible || !changed {
+        return;
+    }
+
+    let (title, lines) = controls_text(&locale, &bindings);
+
+    log::info!("{}\n{}", title, lines.join("\n"));
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use superconductor::bevy_ecs::prelude::{NonSend, Res, ResMut};
+    use wasm_bindgen::{closure::Closure, JsCast};
+
+    use super::{controls_text, ControlsOverlay, Onboarding};
+    use crate::accessibility::AccessibilitySettings;
+    use crate::chat::dom::create_element;
+    use crate::i18n::Locale;
+    use crate::key_bindings::KeyBindings;
+
+    const STYLE: &str = "position: fixed; right: 8px; top: 8px; width: 360px; color: white; \
+                         background: rgba(0, 0, 0, 0.75); border-radius: 8px; padding: 8px 12px; \
+                         font-family: sans-serif;";
+
+    pub struct ControlsDom {
+        root: web_sys::HtmlElement,
+        title: web_sys::HtmlElement,
+        list: web_sys::HtmlElement,
+        dismiss: web_sys::HtmlElement,
+        dismissed: Rc<Cell<bool>>,
+    }
+
+    impl ControlsDom {
+        pub fn new() -> Self {
+            let root = create_element("div");
+            let title = create_element("h3");
+            let list = create_element("div");
+            let dismiss = create_element("button");
+
+            root.append_child(&title).unwrap();
+            root.append_child(&list).unwrap();
+            root.append_child(&dismiss).unwrap();
+            root.set_attribute("style", "display: none;").unwrap();
+
+            let dismissed = Rc::new(Cell::new(false));
+
+            let onclick = {
+                let dismissed = dismissed.clone();
+                Closure::wrap(Box::new(move || dismissed.set(true)) as Box<dyn FnMut()>)
+            };
+
+            dismiss.set_onclick(Some(onclick.as_ref().unchecked_ref()));
+            onclick.forget();
+
+            web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .append_child(&root)
+                .unwrap();
+
+            Self {
+                root,
+                title,
+                list,
+                dismiss,
+                dismissed,
+            }
+        }
+    }
+
+    pub fn update_controls_dom(
+        dom: NonSend<ControlsDom>,
+        mut overlay: ResMut<ControlsOverlay>,
+        mut onboarding: ResMut<Onboarding>,
+        bindings: Res<KeyBindings>,
+        locale: Res<Locale>,
+        accessibility: Res<AccessibilitySettings>,
+    ) {
+        if dom.dismissed.replace(false) {
+            overlay.visible = false;
+
+            if !onboarding.dismissed {
+                onboarding.dismissed = true;
+            }
+        }
+
+        let changed = overlay.is_changed()
+            || bindings.is_changed()
+            || locale.is_changed()
+            || accessibility.is_changed();
+
+        if !changed {
+            return;
+        }
+
+        if !overlay.visible {
+            dom.root.set_attribute("style", "display: none;").unwrap();
+            return;
+        }
+
+        let (title, lines) = controls_text(&locale, &bindings);
+
+        dom.title.set_inner_text(&title);
+        dom.list.set_inner_text(&lines.join("\n"));
+        dom.dismiss.set_inner_text(locale.text("controls.dismiss"));
+        dom.root
+            .set_attribute(
+                "style",
+                &format!("{} {}", STYLE, accessibility.overlay_text_style()),
+            )
+            .unwrap();
+    }
+}
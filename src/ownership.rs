@@ -0,0 +1,269 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Query, Res, With},
+    components, renderer_core,
+    renderer_core::glam::{EulerRot, Quat},
+    resources::Camera,
+    url, Vec3,
+};
+
+use crate::downloads::{DownloadPriority, QueuedModel};
+use crate::networking::NetClient;
+use crate::protocol::{ClientMessage, ObjectId, ObjectTransform, PlayerId, ServerMessage};
+use crate::schedule;
+use crate::shared_physics::Interpolated;
+
+pub const FERRIS_URL: &str = "http://localhost:8000/assets/models/ferris/ferris.gltf";
+
+const GRAB_DISTANCE: f32 = 3.0;
+const HOLD_DISTANCE: f32 = 1.5;
+const SEND_INTERVAL_FRAMES: u32 = 3;
+const HOST_SNAPSHOT_INTERVAL_FRAMES: u32 = 60;
+
+#[derive(Component)]
+pub struct SharedObject {
+    pub id: ObjectId,
+    pub owner: Option<PlayerId>,
+}
+
+#[derive(Component)]
+pub struct Held;
+
+pub struct GrabToggled;
+
+pub struct OwnershipPlugin;
+
+impl Plugin for OwnershipPlugin {
+    fn build(&self, app: &mut App) {
+        let ferris = app
+            .world
+            .spawn()
+            .insert(QueuedModel {
+                url: url::Url::parse(FERRIS_URL).unwrap(),
+                animated: false,
+                priority: DownloadPriority::Props,
+            })
+            .insert(components::Instances(Default::default()))
+            .insert(components::InstanceRange(Default::default()))
+            .id();
+
+        let positions = [
+            Vec3::new(-1.5, 1.0, -2.0),
+            Vec3::new(0.0, 1.0, -2.0),
+            Vec3::new(1.5, 1.0, -2.0),
+        ];
+
+        for (id, position) in positions.into_iter().enumerate() {
+            app.world
+                .spawn()
+                .insert(components::InstanceOf(ferris))
+                .insert(components::Instance(renderer_core::Instance::new(
+                    position,
+                    1.0,
+                    Default::default(),
+                )))
+                .insert(SharedObject {
+                    id: id as ObjectId,
+                    owner: None,
+                });
+        }
+
+        app.add_event::<GrabToggled>();
+
+        app.add_system_set(schedule::input().with_system(receive_object_updates));
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(toggle_grab)
+                .with_system(follow_camera),
+        );
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(send_owned_transforms)
+                .with_system(send_host_snapshots),
+        );
+    }
+}
+
+fn toggle_grab(
+    mut commands: Commands,
+    mut events: EventReader<GrabToggled>,
+    client: Res<NetClient>,
+    camera: Res<Camera>,
+    mut object_q: Query<(
+        Entity,
+        &mut SharedObject,
+        &components::Instance,
+        Option<&Held>,
+    )>,
+) {
+    for _ in events.iter() {
+        let mut released_any = false;
+
+        for (entity, mut object, _, held) in object_q.iter_mut() {
+            if held.is_some() {
+                commands.entity(entity).remove::<Held>();
+                object.owner = None;
+                client.send(ClientMessage::ReleaseObject {
+                    object_id: object.id,
+                });
+                released_any = true;
+            }
+        }
+
+        if released_any {
+            continue;
+        }
+
+        let forward = camera.rotation * -Vec3::Z;
+
+        let nearest = object_q
+            .iter_mut()
+            .filter(|(_, _, instance, _)| {
+                let offset = instance.0.position - camera.position;
+                offset.length() < GRAB_DISTANCE && offset.normalize_or_zero().dot(forward) > 0.5
+            })
+            .min_by(|(_, _, a, _), (_, _, b, _)| {
+                let a = a.0.position.distance_squared(camera.position);
+                let b = b.0.position.distance_squared(camera.position);
+                a.partial_cmp(&b).unwrap()
+            });
+
+        if let Some((entity, mut object, _, _)) = nearest {
+            commands.entity(entity).insert(Held);
+            object.owner = client.player_id;
+            client.send(ClientMessage::ClaimObject {
+                object_id: object.id,
+            });
+        }
+    }
+}
+
+fn follow_camera(camera: Res<Camera>, mut held_q: Query<&mut components::Instance, With<Held>>) {
+    let (yaw, _, _) = camera.rotation.to_euler(EulerRot::YXZ);
+
+    let position = camera.position + camera.rotation * (-Vec3::Z * HOLD_DISTANCE);
+    let rotation = Quat::from_rotation_y(yaw);
+
+    for mut instance in held_q.iter_mut() {
+        // Only touched when the camera moved, so that the renderer isn't sent it again each
+        // frame.
+        if instance.0.position != position || instance.0.rotation != rotation {
+            instance.0.position = position;
+            instance.0.rotation = rotation;
+        }
+    }
+}
+
+fn send_owned_transforms(
+    client: Res<NetClient>,
+    held_q: Query<(&SharedObject, &components::Instance), With<Held>>,
+    mut frame: Local<u32>,
+) {
+    *frame += 1;
+
+    if *frame % SEND_INTERVAL_FRAMES != 0 {
+        return;
+    }
+
+    for (object, instance) in held_q.iter() {
+        if object.owner.is_none() || object.owner != client.player_id {
+            continue;
+        }
+
+        client.send(ClientMessage::ObjectTransform {
+            object_id: object.id,
+            transform: ObjectTransform {
+                position: instance.0.position.to_array(),
+                rotation: instance.0.rotation.to_array(),
+            },
+        });
+    }
+}
+
+// The host is authoritative for everything nobody is holding, so it periodically re-sends that
+// state. If the host leaves, the next lowest id takes over with the state the server replays.
+fn send_host_snapshots(
+    client: Res<NetClient>,
+    object_q: Query<(&SharedObject, &components::Instance)>,
+    mut frame: Local<u32>,
+) {
+    *frame += 1;
+
+    if *frame % HOST_SNAPSHOT_INTERVAL_FRAMES != 0 || !client.is_host() {
+        return;
+    }
+
+    for (object, instance) in object_q.iter() {
+        if object.owner.is_some() {
+            continue;
+        }
+
+        client.send(ClientMessage::ObjectTransform {
+            object_id: object.id,
+            transform: ObjectTransform {
+                position: instance.0.position.to_array(),
+                rotation: instance.0.rotation.to_array(),
+            },
+        });
+    }
+}
+
+fn receive_object_updates(
+    mut commands: Commands,
+    mut server_messages: EventReader<ServerMessage>,
+    client: Res<NetClient>,
+    mut object_q: Query<(
+        Entity,
+        &mut SharedObject,
+        &mut components::Instance,
+        Option<&mut Interpolated>,
+    )>,
+) {
+    for message in server_messages.iter() {
+        match message {
+            ServerMessage::ObjectOwner { object_id, owner } => {
+                for (entity, mut object, _, _) in object_q.iter_mut() {
+                    if object.id != *object_id {
+                        continue;
+                    }
+
+                    object.owner = *owner;
+
+                    // Someone else grabbed it out of our hands.
+                    if owner.is_some() && *owner != client.player_id {
+                        commands.entity(entity).remove::<Held>();
+                    }
+                }
+            }
+            ServerMessage::ObjectTransform {
+                object_id,
+                transform,
+            } => {
+                for (_, object, mut instance, interpolated) in object_q.iter_mut() {
+                    // Unowned objects are applied even on the host, as that is how the server
+                    // replays the world state to a newly elected one.
+                    if object.id != *object_id
+                        || (object.owner.is_some() && object.owner == client.player_id)
+                    {
+                        continue;
+                    }
+
+                    let position = Vec3::from(transform.position);
+                    let rotation = Quat::from_array(transform.rotation);
+
+                    match interpolated {
+                        // Unless it's for the host, which simulates the unowned ones itself.
+                        Some(mut interpolated) if !(client.is_host() && object.owner.is_none()) => {
+                            interpolated.push(position, rotation);
+                        }
+                        _ => {
+                            instance.0.position = position;
+                            instance.0.rotation = rotation;
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,40 @@
+use superconductor::bevy_app::{App, Plugin};
+
+#[cfg(all(feature = "parallel", not(feature = "wasm")))]
+use bevy_tasks::{ComputeTaskPool, TaskPoolBuilder};
+
+// Picks how many threads the systems run on. Bevy's stages already run systems that don't
+// touch the same components or resources at the same time, but only as far as the compute
+// task pool lets them. Desktop builds with the `parallel` feature give it a thread per core,
+// and everything else keeps bevy's default pool, which shares the cores with the io and async
+// pools. The web is always serial, as its task pool runs on the main thread.
+//
+// Setting REPORT_AMBIGUITIES on desktop logs the systems that have no order between them but
+// access the same data, which are the ones to look at when something only breaks with the
+// feature on.
+pub struct ParallelismPlugin;
+
+impl Plugin for ParallelismPlugin {
+    fn build(&self, app: &mut App) {
+        #[cfg(not(feature = "wasm"))]
+        {
+            #[cfg(feature = "parallel")]
+            {
+                let pool = TaskPoolBuilder::new().thread_name("Systems".into()).build();
+
+                log::info!("Running systems on {} threads", pool.thread_num());
+
+                app.insert_resource(ComputeTaskPool(pool));
+            }
+
+            if std::env::var("REPORT_AMBIGUITIES").is_ok() {
+                app.insert_resource(
+                    superconductor::bevy_ecs::schedule::ReportExecutionOrderAmbiguities,
+                );
+            }
+        }
+
+        #[cfg(feature = "wasm")]
+        let _ = app;
+    }
+}
@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, ResMut},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::determinism::SimRng;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+use crate::time_scale::TimeScale;
+use crate::wind::WindField;
+
+// Instances can't be tinted individually, so colour over life is approximated by moving
+// particles between a few models of different colours as they age.
+const COLOR_STEPS: usize = 8;
+
+// Spawns camera facing quads that are simulated on the CPU and drawn as instances, the same
+// way as any other model. Emitters stay where they are put; move `position` to move one.
+#[derive(Component, Clone, Debug)]
+pub struct ParticleEmitter {
+    pub position: Vec3,
+    // Half the size of the box that particles spawn in.
+    pub spawn_extent: Vec3,
+    // Particles per second.
+    pub spawn_rate: f32,
+    // Spawned all at once when the emitter is added.
+    pub burst: u32,
+    pub max_particles: usize,
+    // In seconds.
+    pub lifetime: f32,
+    pub velocity: Vec3,
+    // Added to the velocity of each particle in a random direction, up to this length.
+    pub velocity_randomness: f32,
+    pub acceleration: Vec3,
+    // How quickly particles are carried along with the wind, as the fraction of the difference
+    // made up per second. Only horizontally, so that falling particles keep falling.
+    pub wind_response: f32,
+    // Sizes and linear rgb colours at the start and end of a particle's life.
+    pub size: [f32; 2],
+    pub color: [[f32; 3]; 2],
+    // Removes the emitter once it has stopped spawning and every particle has died.
+    pub despawn_when_done: bool,
+}
+
+impl Default for ParticleEmitter {
+    fn default() -> Self {
+        Self {
+            position: Vec3::ZERO,
+            spawn_extent: Vec3::ZERO,
+            spawn_rate: 10.0,
+            burst: 0,
+            max_particles: 256,
+            lifetime: 1.0,
+            velocity: Vec3::Y,
+            velocity_randomness: 0.0,
+            acceleration: Vec3::ZERO,
+            wind_response: 0.0,
+            size: [0.1, 0.1],
+            color: [[1.0; 3], [1.0; 3]],
+            despawn_when_done: false,
+        }
+    }
+}
+
+impl ParticleEmitter {
+    // A puff kicked up from the ground, e.g. on landing.
+    pub fn dust(position: Vec3) -> Self {
+        Self {
+            position,
+            spawn_extent: Vec3::new(0.2, 0.0, 0.2),
+            spawn_rate: 0.0,
+            burst: 24,
+            lifetime: 0.6,
+            velocity: Vec3::new(0.0, 0.4, 0.0),
+            velocity_randomness: 0.8,
+            acceleration: Vec3::new(0.0, -0.5, 0.0),
+            wind_response: 0.5,
+            size: [0.08, 0.2],
+            color: [[0.5, 0.45, 0.4], [0.15, 0.14, 0.12]],
+            despawn_when_done: true,
+            ..Default::default()
+        }
+    }
+
+    // A burst of glitter, e.g. on teleporting.
+    pub fn sparkles(position: Vec3) -> Self {
+        Self {
+            position,
+            spawn_extent: Vec3::new(0.3, 0.9, 0.3),
+            spawn_rate: 0.0,
+            burst: 48,
+            lifetime: 0.8,
+            velocity: Vec3::new(0.0, 0.5, 0.0),
+            velocity_randomness: 0.5,
+            size: [0.05, 0.0],
+            color: [[1.0, 0.9, 0.5], [0.4, 0.6, 1.0]],
+            despawn_when_done: true,
+            ..Default::default()
+        }
+    }
+
+    // Falls continuously over an area centered on `position`, from `height` above it.
+    pub fn snow(position: Vec3, half_size: f32, height: f32) -> Self {
+        Self {
+            position: position + Vec3::new(0.0, height, 0.0),
+            spawn_extent: Vec3::new(half_size, 0.0, half_size),
+            spawn_rate: half_size * half_size * 4.0,
+            max_particles: 2048,
+            lifetime: height / 0.8,
+            velocity: Vec3::new(0.0, -0.8, 0.0),
+            velocity_randomness: 0.15,
+            wind_response: 1.5,
+            size: [0.03, 0.03],
+            color: [[0.9, 0.9, 1.0], [0.9, 0.9, 1.0]],
+            ..Default::default()
+        }
+    }
+
+    // Falls fast and continuously over an area centered on `position`, from `height` above it.
+    // Quads can't be stretched into streaks, so the drops are small and many instead.
+    pub fn rain(position: Vec3, half_size: f32, height: f32) -> Self {
+        Self {
+            position: position + Vec3::new(0.0, height, 0.0),
+            spawn_extent: Vec3::new(half_size, 0.0, half_size),
+            spawn_rate: half_size * half_size * 20.0,
+            max_particles: 4096,
+            lifetime: height / 8.0,
+            velocity: Vec3::new(0.0, -8.0, 0.0),
+            velocity_randomness: 0.5,
+            wind_response: 0.3,
+            size: [0.015, 0.015],
+            color: [[0.6, 0.65, 0.75], [0.6, 0.65, 0.75]],
+            ..Default::default()
+        }
+    }
+
+    fn color_at(&self, step: usize) -> [f32; 3] {
+        let t = step as f32 / (COLOR_STEPS - 1) as f32;
+        let [from, to] = self.color;
+
+        [
+            from[0] + (to[0] - from[0]) * t,
+            from[1] + (to[1] - from[1]) * t,
+            from[2] + (to[2] - from[2]) * t,
+        ]
+    }
+}
+
+// On each particle's instance, so that particles can be told apart from other instances.
+#[derive(Component)]
+pub struct ParticleInstance;
+
+struct Particle {
+    entity: Entity,
+    velocity: Vec3,
+    age: f32,
+    step: usize,
+}
+
+struct EmitterState {
+    models: Vec<Entity>,
+    particles: Vec<Particle>,
+    // Fractional particles carried over between frames.
+    accumulator: f32,
+}
+
+#[derive(Default)]
+struct ParticleSystems {
+    emitters: HashMap<Entity, EmitterState>,
+}
+
+pub struct ParticlePlugin;
+
+impl Plugin for ParticlePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ParticleSystems::default());
+
+        app.add_system_set(schedule::simulation().with_system(update_particles));
+    }
+}
+
+fn random_direction(rng: &mut SimRng) -> Vec3 {
+    let z = rng.range(-1.0, 1.0);
+    let angle = rng.range(0.0, PI * 2.0);
+    let radius = (1.0 - z * z).sqrt();
+
+    Vec3::new(radius * angle.cos(), radius * angle.sin(), z)
+}
+
+fn spawn_particle(
+    commands: &mut Commands,
+    rng: &mut SimRng,
+    emitter: &ParticleEmitter,
+    state: &mut EmitterState,
+    rotation: Quat,
+) {
+    let offset = Vec3::new(
+        rng.range(-1.0, 1.0),
+        rng.range(-1.0, 1.0),
+        rng.range(-1.0, 1.0),
+    ) * emitter.spawn_extent;
+
+    let velocity =
+        emitter.velocity + random_direction(rng) * rng.range(0.0, emitter.velocity_randomness);
+
+    let entity = commands
+        .spawn()
+        .insert(ParticleInstance)
+        .insert(components::InstanceOf(state.models[0]))
+        .insert(components::Instance(renderer_core::Instance::new(
+            emitter.position + offset,
+            emitter.size[0],
+            rotation,
+        )))
+        .id();
+
+    state.particles.push(Particle {
+        entity,
+        velocity,
+        age: 0.0,
+        step: 0,
+    });
+}
+
+#[allow(clippy::too_many_arguments)]
+fn update_particles(
+    mut commands: Commands,
+    mut systems: ResMut<ParticleSystems>,
+    mut rng: ResMut<SimRng>,
+    camera: Res<Camera>,
+    time_scale: Res<TimeScale>,
+    wind: Res<WindField>,
+    emitter_q: Query<(Entity, &ParticleEmitter)>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    let _span = tracing::info_span!("update_particles").entered();
+
+    let delta = time_scale.delta();
+
+    // The plane primitive faces up, so it's turned towards the camera's forward axis first.
+    let rotation = camera.rotation * Quat::from_rotation_x(PI / 2.0);
+
+    systems.emitters.retain(|entity, state| {
+        if emitter_q.get(*entity).is_ok() {
+            return true;
+        }
+
+        for particle in state.particles.drain(..) {
+            commands.entity(particle.entity).despawn();
+        }
+
+        for &model in &state.models {
+            commands.entity(model).despawn();
+        }
+
+        false
+    });
+
+    for (entity, emitter) in emitter_q.iter() {
+        let mut new = false;
+
+        let state = systems.emitters.entry(entity).or_insert_with(|| {
+            new = true;
+
+            EmitterState {
+                models: (0..COLOR_STEPS)
+                    .map(|step| {
+                        spawn_primitive(
+                            &mut commands,
+                            Primitive::plane(1.0),
+                            MaterialDescriptor {
+                                double_sided: true,
+                                ..MaterialDescriptor::emissive(emitter.color_at(step))
+                            },
+                        )
+                    })
+                    .collect(),
+                particles: Vec::new(),
+                accumulator: 0.0,
+            }
+        });
+
+        let lifetime = emitter.lifetime.max(delta);
+
+        state.particles.retain_mut(|particle| {
+            particle.age += delta;
+
+            if particle.age >= lifetime {
+                commands.entity(particle.entity).despawn();
+                return false;
+            }
+
+            particle.velocity += emitter.acceleration * delta;
+
+            let t = particle.age / lifetime;
+            let step = ((t * COLOR_STEPS as f32) as usize).min(COLOR_STEPS - 1);
+
+            if step != particle.step {
+                particle.step = step;
+                commands
+                    .entity(particle.entity)
+                    .insert(components::InstanceOf(state.models[step]));
+            }
+
+            if let Ok(mut instance) = instance_q.get_mut(particle.entity) {
+                if emitter.wind_response > 0.0 {
+                    let drift = (wind.velocity_at(instance.0.position) - particle.velocity)
+                        * (emitter.wind_response * delta).min(1.0);
+                    particle.velocity.x += drift.x;
+                    particle.velocity.z += drift.z;
+                }
+
+                instance.0.position += particle.velocity * delta;
+                instance.0.scale = emitter.size[0] + (emitter.size[1] - emitter.size[0]) * t;
+                instance.0.rotation = rotation;
+            }
+
+            true
+        });
+
+        let mut count = if new { emitter.burst as usize } else { 0 };
+
+        state.accumulator += emitter.spawn_rate * delta;
+        count += state.accumulator as usize;
+        state.accumulator = state.accumulator.fract();
+
+        let count = count.min(emitter.max_particles.saturating_sub(state.particles.len()));
+
+        for _ in 0..count {
+            spawn_particle(&mut commands, &mut rng, emitter, state, rotation);
+        }
+
+        if emitter.despawn_when_done
+            && !new
+            && emitter.spawn_rate <= 0.0
+            && state.particles.is_empty()
+        {
+            commands.entity(entity).despawn();
+        }
+    }
+}
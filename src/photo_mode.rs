@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Entity, Query, Res, ResMut},
+    components,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    winit::event::VirtualKeyCode,
+    Vec3,
+};
+
+use crate::input::{KeyPressed, KeyboardState};
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::schedule::{self, SimulationPaused};
+use crate::wrist_menu::{WristMenuAction, WristMenuActivated};
+use crate::DELTA_TIME;
+
+const SPEED: f32 = 2.0;
+const LOOK_SENSITIVITY: f32 = 0.1;
+const ROLL_STEP_DEGREES: f32 = 5.0;
+const EXPOSURE_STEP: f32 = 0.5;
+const MAX_EXPOSURE: f32 = 4.0;
+const FOCUS_STEP: f32 = 1.25;
+const MIN_FOCUS_DISTANCE: f32 = 0.1;
+const MAX_FOCUS_DISTANCE: f32 = 1000.0;
+// Screenshots taken in photo mode are rendered at this many times the window resolution.
+const PHOTO_SUPERSAMPLING: u32 = 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DepthOfField {
+    // In meters.
+    pub focus_distance: f32,
+    pub f_stop: f32,
+}
+
+impl Default for DepthOfField {
+    fn default() -> Self {
+        Self {
+            focus_distance: 3.0,
+            f_stop: 2.8,
+        }
+    }
+}
+
+// Camera settings for photos. There's no post processing pass in the renderer, so depth of
+// field and exposure are kept here for it to pick up once there is one.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PhotoSettings {
+    pub depth_of_field: Option<DepthOfField>,
+    // In stops, added to the scene's exposure.
+    pub exposure: f32,
+}
+
+// Where the free camera is. Yaw, pitch and roll are in degrees.
+#[derive(Clone, Copy, Debug, Default)]
+struct FreeCamera {
+    position: Vec3,
+    yaw: f32,
+    pitch: f32,
+    roll: f32,
+}
+
+impl FreeCamera {
+    fn rotation(&self) -> Quat {
+        Quat::from_rotation_y(self.yaw.to_radians())
+            * Quat::from_rotation_x(self.pitch.to_radians())
+            * Quat::from_rotation_z(self.roll.to_radians())
+    }
+}
+
+// Pauses the simulation and detaches a free camera, with everything but the scene hidden.
+// W, A, S, D and mouse look (after grabbing the cursor with G) move it, Q and E roll it, Z and
+// X change the exposure, T turns depth of field on and off, page up and page down move the
+// focus and enter takes a screenshot. The PhotoMode binding (O by default) or escape leaves,
+// and the simulation carries on from where it was paused.
+#[derive(Default)]
+pub struct PhotoMode {
+    camera: Option<FreeCamera>,
+    // The time each animation was paused at. The renderer advances animations itself, so
+    // they're held there until photo mode is left.
+    animation_times: HashMap<Entity, f32>,
+    // Whether the simulation was already paused, so that it's left paused.
+    was_paused: bool,
+}
+
+impl PhotoMode {
+    pub fn is_active(&self) -> bool {
+        self.camera.is_some()
+    }
+
+    // HUD and UI elements are hidden while photo mode is active.
+    pub fn hides_ui(&self) -> bool {
+        self.is_active()
+    }
+}
+
+pub struct PhotoModeToggled;
+
+// Asks for a screenshot at `supersampling` times the window resolution. The renderer can't
+// read frames back yet, so capturing is left to whatever can; the playground only logs these.
+pub struct ScreenshotRequested {
+    pub supersampling: u32,
+}
+
+pub struct PhotoModePlugin;
+
+impl Plugin for PhotoModePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PhotoMode::default());
+        app.insert_resource(PhotoSettings::default());
+        app.insert_resource(SimulationPaused::default());
+        app.add_event::<PhotoModeToggled>();
+        app.add_event::<ScreenshotRequested>();
+
+        app.add_system_set(schedule::input().with_system(handle_photo_mode_keys));
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(toggle_photo_mode)
+                .with_system(move_free_camera)
+                .with_system(hold_animations)
+                .with_system(log_screenshot_requests),
+        );
+        app.add_system_set(schedule::simulation().with_system(wrist_menu_screenshots));
+
+        #[cfg(feature = "wasm")]
+        app.add_system_set(schedule::pre_render().with_system(dom::hide_dom_ui));
+    }
+}
+
+// Photo mode's own keys. These are read during the input systems, as the key bindings that
+// go through the simulation are paused along with it.
+fn handle_photo_mode_keys(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut photo_mode: ResMut<PhotoMode>,
+    mut settings: ResMut<PhotoSettings>,
+    mut toggles: EventWriter<PhotoModeToggled>,
+    mut screenshots: EventWriter<ScreenshotRequested>,
+) {
+    for KeyPressed(key) in key_presses.iter() {
+        let bound = bindings.as_ref().and_then(|bindings| bindings.action(*key));
+
+        if bound == Some(KeyAction::PhotoMode) {
+            toggles.send(PhotoModeToggled);
+            continue;
+        }
+
+        let camera = match photo_mode.camera.as_mut() {
+            Some(camera) => camera,
+            None => continue,
+        };
+
+        match key {
+            VirtualKeyCode::Escape => toggles.send(PhotoModeToggled),
+            VirtualKeyCode::Q => camera.roll += ROLL_STEP_DEGREES,
+            VirtualKeyCode::E => camera.roll -= ROLL_STEP_DEGREES,
+            VirtualKeyCode::Z | VirtualKeyCode::X => {
+                let step = if *key == VirtualKeyCode::Z {
+                    -EXPOSURE_STEP
+                } else {
+                    EXPOSURE_STEP
+                };
+
+                settings.exposure = (settings.exposure + step).clamp(-MAX_EXPOSURE, MAX_EXPOSURE);
+                log::info!("Exposure: {:+.1} stops", settings.exposure);
+            }
+            VirtualKeyCode::T => {
+                settings.depth_of_field = match settings.depth_of_field {
+                    Some(_) => None,
+                    None => Some(DepthOfField::default()),
+                };
+                log::info!("Depth of field: {:?}", settings.depth_of_field);
+            }
+            VirtualKeyCode::PageUp | VirtualKeyCode::PageDown => {
+                if let Some(depth_of_field) = settings.depth_of_field.as_mut() {
+                    let factor = if *key == VirtualKeyCode::PageUp {
+                        FOCUS_STEP
+                    } else {
+                        1.0 / FOCUS_STEP
+                    };
+
+                    depth_of_field.focus_distance = (depth_of_field.focus_distance * factor)
+                        .clamp(MIN_FOCUS_DISTANCE, MAX_FOCUS_DISTANCE);
+                    log::info!("Focus distance: {:.2} m", depth_of_field.focus_distance);
+                }
+            }
+            VirtualKeyCode::Return => screenshots.send(ScreenshotRequested {
+                supersampling: PHOTO_SUPERSAMPLING,
+            }),
+            _ => {}
+        }
+    }
+}
+
+fn toggle_photo_mode(
+    mut toggles: EventReader<PhotoModeToggled>,
+    mut photo_mode: ResMut<PhotoMode>,
+    mut paused: ResMut<SimulationPaused>,
+    camera: Res<Camera>,
+    camera_rig: Option<Res<dolly::rig::CameraRig>>,
+    animation_q: Query<(Entity, &components::AnimationState)>,
+) {
+    let toggled = toggles.iter().count() % 2 == 1;
+
+    if !toggled {
+        return;
+    }
+
+    if photo_mode.is_active() {
+        // The camera rig was left alone, so the camera goes straight back to it.
+        paused.0 = photo_mode.was_paused;
+        *photo_mode = PhotoMode::default();
+        log::info!("Left photo mode");
+        return;
+    }
+
+    let (yaw, pitch) = camera_rig.map_or((0.0, 0.0), |rig| {
+        let yaw_pitch = rig.driver::<dolly::drivers::YawPitch>();
+        (yaw_pitch.yaw_degrees, yaw_pitch.pitch_degrees)
+    });
+
+    photo_mode.camera = Some(FreeCamera {
+        position: camera.position,
+        yaw,
+        pitch,
+        roll: 0.0,
+    });
+    photo_mode.animation_times = animation_q
+        .iter()
+        .map(|(entity, state)| (entity, state.time))
+        .collect();
+    photo_mode.was_paused = paused.0;
+    paused.0 = true;
+    log::info!("Entered photo mode");
+}
+
+fn move_free_camera(
+    mut photo_mode: ResMut<PhotoMode>,
+    keyboard_state: Option<Res<KeyboardState>>,
+    mut camera: ResMut<Camera>,
+) {
+    let free_camera = match photo_mode.camera.as_mut() {
+        Some(free_camera) => free_camera,
+        None => return,
+    };
+
+    if let Some(keyboard_state) = keyboard_state {
+        let look = -LOOK_SENSITIVITY * keyboard_state.look_delta;
+
+        free_camera.yaw += look.x;
+        free_camera.pitch = (free_camera.pitch + look.y).clamp(-90.0, 90.0);
+
+        let forwards = keyboard_state.forwards as i32 - keyboard_state.backwards as i32;
+        let right = keyboard_state.right as i32 - keyboard_state.left as i32;
+
+        let move_vec = free_camera.rotation()
+            * Vec3::new(right as f32, 0.0, -forwards as f32).clamp_length_max(1.0);
+
+        free_camera.position += move_vec * DELTA_TIME * SPEED;
+    }
+
+    camera.position = free_camera.position;
+    camera.rotation = free_camera.rotation();
+}
+
+fn hold_animations(
+    photo_mode: Res<PhotoMode>,
+    mut animation_q: Query<(Entity, &mut components::AnimationState)>,
+) {
+    if !photo_mode.is_active() {
+        return;
+    }
+
+    for (entity, mut state) in animation_q.iter_mut() {
+        if let Some(&time) = photo_mode.animation_times.get(&entity) {
+            if state.time != time {
+                state.time = time;
+            }
+        }
+    }
+}
+
+fn wrist_menu_screenshots(
+    mut wrist_menu: EventReader<WristMenuActivated>,
+    mut screenshots: EventWriter<ScreenshotRequested>,
+) {
+    for WristMenuActivated(action) in wrist_menu.iter() {
+        if *action == WristMenuAction::Screenshot {
+            screenshots.send(ScreenshotRequested { supersampling: 1 });
+        }
+    }
+}
+
+fn log_screenshot_requests(mut screenshots: EventReader<ScreenshotRequested>) {
+    for screenshot in screenshots.iter() {
+        log::info!(
+            "Screenshot requested at {}x the window resolution",
+            screenshot.supersampling
+        );
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use superconductor::bevy_ecs::prelude::{Local, Res};
+
+    use super::PhotoMode;
+
+    // Hides everything on the page apart from the canvas, so that each overlay doesn't need to
+    // know about photo mode.
+    const STYLE: &str = "body.hide-ui > :not(canvas) { display: none !important; }";
+
+    // `hidden` is None until the style has been added.
+    pub fn hide_dom_ui(photo_mode: Res<PhotoMode>, mut hidden: Local<Option<bool>>) {
+        let hide = photo_mode.hides_ui();
+
+        if *hidden == Some(hide) {
+            return;
+        }
+
+        let document = web_sys::window().unwrap().document().unwrap();
+        let body = document.body().unwrap();
+
+        if hidden.is_none() {
+            let style = document.create_element("style").unwrap();
+            style.set_text_content(Some(STYLE));
+            body.append_child(&style).unwrap();
+        }
+
+        let class_list = body.class_list();
+
+        let _ = if hide {
+            class_list.add_1("hide-ui")
+        } else {
+            class_list.remove_1("hide-ui")
+        };
+
+        *hidden = Some(hide);
+    }
+}
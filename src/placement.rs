@@ -0,0 +1,273 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, ResMut, With, Without},
+    components, renderer_core,
+    resources::Camera,
+    url::Url,
+    winit::event::{MouseButton, VirtualKeyCode},
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::alpha_modes::AlphaModeOverride;
+use crate::assets::{AssetServer, Handle, Model};
+use crate::bounds::{spawn_outline, Aabb, Bounds, BoundsOutline, Outline};
+use crate::downloads::{DownloadPriority, ModelSource, QueuedModel};
+use crate::input::{KeyPressed, MouseButtonPressed};
+use crate::schedule;
+
+const GHOST_OPACITY: f32 = 0.5;
+// Objects can only be placed on the ground within this distance of the camera.
+const MAX_PLACE_DISTANCE: f32 = 10.0;
+// Where the ghost floats when the camera isn't looking at the ground.
+const HOLD_DISTANCE: f32 = 3.0;
+const GROUND_HEIGHT: f32 = 0.0;
+
+// Starts placing a model. A translucent ghost of it follows the point on the ground that the
+// camera is looking at, outlined in the highlight palette's valid colour (green by default)
+// where it can be placed and its invalid colour where it can't. Left click or enter places it,
+// right click or escape cancels. There's no AR hit testing in the renderer, so placement always
+// goes by the camera's forward ray.
+pub struct PlacementRequested(pub Url);
+
+// Marks the ghost's model and instance, so that it doesn't block its own placement.
+#[derive(Component)]
+struct PlacementGhost;
+
+struct ActivePlacement {
+    url: Url,
+    ghost_model: Entity,
+    ghost_instance: Entity,
+    outline: Option<(Outline, bool)>,
+    valid: bool,
+}
+
+#[derive(Default)]
+pub struct Placement {
+    active: Option<ActivePlacement>,
+    // Keeps the placed models loaded.
+    placed: Vec<Handle<Model>>,
+}
+
+impl Placement {
+    pub fn is_placing(&self) -> bool {
+        self.active.is_some()
+    }
+}
+
+pub struct PlacementPlugin;
+
+impl Plugin for PlacementPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PlacementRequested>();
+        app.insert_resource(Placement::default());
+
+        app.add_system_set(schedule::simulation().with_system(update_placement));
+    }
+}
+
+fn despawn_placement(commands: &mut Commands, active: ActivePlacement) {
+    commands.entity(active.ghost_model).despawn();
+    commands.entity(active.ghost_instance).despawn();
+
+    if let Some((outline, _)) = active.outline {
+        commands.entity(outline.model).despawn();
+        commands.entity(outline.instance).despawn();
+    }
+}
+
+fn start_placement(commands: &mut Commands, url: Url) -> ActivePlacement {
+    let ghost_model = commands
+        .spawn()
+        .insert(QueuedModel {
+            url: url.clone(),
+            animated: false,
+            priority: DownloadPriority::Props,
+        })
+        .insert(AlphaModeOverride::ghost(GHOST_OPACITY))
+        .insert(components::Instances(Default::default()))
+        .insert(components::InstanceRange(Default::default()))
+        .insert(PlacementGhost)
+        .id();
+
+    let ghost_instance = commands
+        .spawn()
+        .insert(components::InstanceOf(ghost_model))
+        .insert(components::Instance(renderer_core::Instance::new(
+            Vec3::ZERO,
+            1.0,
+            Default::default(),
+        )))
+        .insert(PlacementGhost)
+        .id();
+
+    ActivePlacement {
+        url,
+        ghost_model,
+        ghost_instance,
+        outline: None,
+        valid: false,
+    }
+}
+
+// Where the camera's forward ray hits the ground, if it does so within reach.
+fn ground_hit(camera: &Camera) -> Option<Vec3> {
+    let forward = camera.rotation * -Vec3::Z;
+
+    if forward.y >= -f32::EPSILON {
+        return None;
+    }
+
+    let distance = (GROUND_HEIGHT - camera.position.y) / forward.y;
+
+    if distance > MAX_PLACE_DISTANCE {
+        return None;
+    }
+
+    Some(camera.position + forward * distance)
+}
+
+fn update_placement(
+    mut commands: Commands,
+    mut placement: ResMut<Placement>,
+    mut asset_server: ResMut<AssetServer>,
+    mut requests: EventReader<PlacementRequested>,
+    mut key_presses: EventReader<KeyPressed>,
+    mut mouse_presses: EventReader<MouseButtonPressed>,
+    camera: Res<Camera>,
+    accessibility: Res<AccessibilitySettings>,
+    mut ghost_q: Query<(&mut components::Instance, Option<&Bounds>), With<PlacementGhost>>,
+    bounds_q: Query<
+        (&Bounds, &components::InstanceOf),
+        (Without<PlacementGhost>, Without<BoundsOutline>),
+    >,
+    source_q: Query<&ModelSource>,
+    mut outline_q: Query<&mut components::Instance, (With<BoundsOutline>, Without<PlacementGhost>)>,
+) {
+    let _span = tracing::info_span!("update_placement").entered();
+
+    let placement = &mut *placement;
+
+    if let Some(PlacementRequested(url)) = requests.iter().last() {
+        if let Some(active) = placement.active.take() {
+            despawn_placement(&mut commands, active);
+        }
+
+        placement.active = Some(start_placement(&mut commands, url.clone()));
+    }
+
+    let mut confirmed = false;
+    let mut cancelled = false;
+
+    for KeyPressed(key) in key_presses.iter() {
+        match key {
+            VirtualKeyCode::Return => confirmed = true,
+            VirtualKeyCode::Escape => cancelled = true,
+            _ => {}
+        }
+    }
+
+    for MouseButtonPressed(button) in mouse_presses.iter() {
+        match button {
+            MouseButton::Left => confirmed = true,
+            MouseButton::Right => cancelled = true,
+            _ => {}
+        }
+    }
+
+    if cancelled {
+        if let Some(active) = placement.active.take() {
+            despawn_placement(&mut commands, active);
+        }
+
+        return;
+    }
+
+    let active = match placement.active.as_mut() {
+        Some(active) => active,
+        None => return,
+    };
+
+    let hit = ground_hit(&camera);
+    let position =
+        hit.unwrap_or_else(|| camera.position + camera.rotation * -Vec3::Z * HOLD_DISTANCE);
+
+    let (mut ghost, ghost_bounds) = match ghost_q.get_mut(active.ghost_instance) {
+        Ok(ghost) => ghost,
+        Err(_) => return,
+    };
+
+    // The ghost's bounds are from the previous frame, so they're moved along with it.
+    let offset = position - ghost.0.position;
+    let ghost_bounds = ghost_bounds.map(|bounds| Aabb {
+        min: bounds.0.min + offset,
+        max: bounds.0.max + offset,
+    });
+
+    ghost.0.position = position;
+
+    // Only props get in the way, as the environment's bounds cover everything.
+    let blocked = ghost_bounds.map_or(false, |ghost_bounds| {
+        bounds_q.iter().any(|(bounds, instance_of)| {
+            let is_prop = source_q
+                .get(instance_of.0)
+                .map_or(false, |source| source.0.priority == DownloadPriority::Props);
+
+            is_prop && bounds.0.intersects(&ghost_bounds)
+        })
+    });
+
+    active.valid = hit.is_some() && !blocked;
+
+    if let Some(ghost_bounds) = ghost_bounds {
+        let recolor = match &active.outline {
+            Some((_, valid)) => *valid != active.valid || accessibility.is_changed(),
+            None => true,
+        };
+
+        if recolor {
+            if let Some((outline, _)) = active.outline.take() {
+                commands.entity(outline.model).despawn();
+                commands.entity(outline.instance).despawn();
+            }
+
+            let palette = accessibility.palette();
+
+            let color = if active.valid {
+                palette.valid
+            } else {
+                palette.invalid
+            };
+
+            active.outline = Some((
+                spawn_outline(&mut commands, &ghost_bounds, color),
+                active.valid,
+            ));
+        }
+
+        if let Some((outline, _)) = &active.outline {
+            if let Ok(mut instance) = outline_q.get_mut(outline.instance) {
+                instance.0.position = ghost_bounds.center();
+            }
+        }
+    }
+
+    if confirmed && active.valid {
+        let model = asset_server.load::<Model>(&mut commands, active.url.clone());
+
+        commands
+            .spawn()
+            .insert(components::InstanceOf(model.entity()))
+            .insert(components::Instance(renderer_core::Instance::new(
+                ghost.0.position,
+                ghost.0.scale,
+                ghost.0.rotation,
+            )));
+
+        placement.placed.push(model);
+
+        if let Some(active) = placement.active.take() {
+            despawn_placement(&mut commands, active);
+        }
+    }
+}
@@ -0,0 +1,122 @@
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+    resources::Camera,
+};
+
+use crate::camera_control::SpawnPose;
+use crate::schedule;
+use crate::settings::{self, Setting};
+
+const DEFAULT_EYE_HEIGHT: f32 = 1.75;
+const MIN_EYE_HEIGHT: f32 = 0.5;
+const MAX_EYE_HEIGHT: f32 = 2.5;
+
+// The player's eye height, saved between sessions with the other settings. On desktop the
+// camera rig is simply put at the eye height. In XR the headset's own height is added to the
+// rig, so the rig stands on the floor, and while seated it's raised by the difference between
+// the standing and seated height so that seated players see the world as if they were standing.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PlayerHeight {
+    pub eye_height: f32,
+    pub seated: bool,
+    // Measured by the last seated calibration.
+    pub seated_offset: f32,
+}
+
+impl Default for PlayerHeight {
+    fn default() -> Self {
+        Self {
+            eye_height: DEFAULT_EYE_HEIGHT,
+            seated: false,
+            seated_offset: 0.0,
+        }
+    }
+}
+
+impl Setting for PlayerHeight {
+    const KEY: &'static str = "player_height";
+}
+
+impl PlayerHeight {
+    // The height of the camera rig above the floor.
+    fn rig_height(&self, xr: bool) -> f32 {
+        match (xr, self.seated) {
+            (false, _) => self.eye_height,
+            (true, true) => self.seated_offset,
+            (true, false) => 0.0,
+        }
+    }
+}
+
+// Measures the player's current eye height. Standing, it becomes the eye height; seated, the
+// rig is raised to make up the difference. Only does anything in XR, as on desktop there's no
+// headset to measure.
+pub struct CalibrateHeight;
+
+struct PlayerHeightMode {
+    xr: bool,
+}
+
+pub struct PlayerHeightPlugin {
+    // Whether the camera follows a headset.
+    pub xr: bool,
+}
+
+impl Plugin for PlayerHeightPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PlayerHeight::default());
+        settings::persist::<PlayerHeight>(app);
+        app.insert_resource(PlayerHeightMode { xr: self.xr });
+        app.add_event::<CalibrateHeight>();
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(calibrate_height)
+                .with_system(apply_player_height),
+        );
+    }
+}
+
+fn calibrate_height(
+    mut calibrations: EventReader<CalibrateHeight>,
+    mut player_height: ResMut<PlayerHeight>,
+    mode: Res<PlayerHeightMode>,
+    camera: Res<Camera>,
+    camera_rig: Res<dolly::rig::CameraRig>,
+) {
+    if calibrations.iter().count() == 0 || !mode.xr {
+        return;
+    }
+
+    let rig_height = camera_rig.driver::<dolly::drivers::Position>().position.y;
+    let measured = camera.position.y - rig_height;
+
+    if player_height.seated {
+        player_height.seated_offset = (player_height.eye_height - measured).max(0.0);
+    } else {
+        player_height.eye_height = measured.clamp(MIN_EYE_HEIGHT, MAX_EYE_HEIGHT);
+    }
+
+    log::info!("Calibrated the player height: {:?}", *player_height);
+}
+
+fn apply_player_height(
+    player_height: Res<PlayerHeight>,
+    mode: Res<PlayerHeightMode>,
+    mut spawn: ResMut<SpawnPose>,
+    mut camera_rig: ResMut<dolly::rig::CameraRig>,
+) {
+    if !player_height.is_changed() {
+        return;
+    }
+
+    let height = player_height.rig_height(mode.xr);
+
+    spawn.position.y = height;
+    camera_rig
+        .driver_mut::<dolly::drivers::Position>()
+        .position
+        .y = height;
+}
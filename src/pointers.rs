@@ -0,0 +1,63 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+    resources::Camera,
+    winit::event::MouseButton,
+    Vec3,
+};
+
+use crate::input::MouseButtonPressed;
+use crate::schedule;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PointerSource {
+    Mouse,
+    Controller(usize),
+}
+
+// A ray that can point at things and click on them.
+#[derive(Clone, Copy, Debug)]
+pub struct PointerRay {
+    pub source: PointerSource,
+    pub origin: Vec3,
+    pub direction: Vec3,
+    // Whether it was clicked this frame.
+    pub clicked: bool,
+}
+
+// Every pointer for this frame, rebuilt in the input stage. The mouse pointer goes through
+// the middle of the screen, as the cursor is either grabbed for mouse look or hidden behind
+// it. Other plugins add their own pointers in the input stage too.
+#[derive(Default)]
+pub struct PointerRays(pub Vec<PointerRay>);
+
+pub struct PointerPlugin;
+
+impl Plugin for PointerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(PointerRays::default());
+
+        app.add_system_set(schedule::input().with_system(update_mouse_pointer));
+    }
+}
+
+fn update_mouse_pointer(
+    mut pointers: ResMut<PointerRays>,
+    mut mouse_presses: EventReader<MouseButtonPressed>,
+    camera: Res<Camera>,
+) {
+    let clicked = mouse_presses
+        .iter()
+        .any(|MouseButtonPressed(button)| *button == MouseButton::Left);
+
+    pointers
+        .0
+        .retain(|pointer| pointer.source != PointerSource::Mouse);
+
+    pointers.0.push(PointerRay {
+        source: PointerSource::Mouse,
+        origin: camera.position,
+        direction: camera.rotation * -Vec3::Z,
+        clicked,
+    });
+}
@@ -0,0 +1,425 @@
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use crossbeam_channel::{Receiver, Sender};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Query, Res, ResMut},
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::AssetError;
+use crate::downloads::{DownloadManager, QueuedModel};
+use crate::i18n::Locale;
+use crate::text::TextLabel;
+use crate::{schedule, DELTA_TIME};
+
+const PROGRESS_LOG_INTERVAL_FRAMES: u32 = 60;
+// Where the status label goes, relative to the camera.
+const STATUS_OFFSET: Vec3 = Vec3::new(0.0, -0.2, -1.5);
+const STATUS_COLUMNS: usize = 40;
+// How long a failure stays up for.
+const FAILURE_SECONDS: f32 = 10.0;
+
+#[derive(Deserialize)]
+pub struct ManifestEntry {
+    // Relative to the manifest.
+    pub url: String,
+    // Hex encoded.
+    pub sha256: String,
+    #[serde(default)]
+    pub size: Option<u64>,
+}
+
+#[derive(Deserialize)]
+pub struct Manifest {
+    pub assets: Vec<ManifestEntry>,
+}
+
+pub fn manifest_url() -> Option<Url> {
+    #[cfg(not(feature = "wasm"))]
+    let url = std::env::var("PRELOAD_MANIFEST").ok();
+
+    #[cfg(feature = "wasm")]
+    let url = crate::query_parameter("manifest");
+
+    let url = url?;
+
+    match Url::parse(&url) {
+        Ok(url) => Some(url),
+        Err(error) => {
+            log::warn!("Invalid preload manifest url {:?}: {}", url, error);
+            None
+        }
+    }
+}
+
+fn sha256_hex(bytes: &[u8]) -> String {
+    Sha256::digest(bytes)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+pub fn verify(bytes: &[u8], expected: &str) -> Result<(), AssetError> {
+    let actual = sha256_hex(bytes);
+
+    if actual.eq_ignore_ascii_case(expected) {
+        Ok(())
+    } else {
+        Err(AssetError::Integrity {
+            expected: expected.to_ascii_lowercase(),
+            actual,
+        })
+    }
+}
+
+// Sent once every asset in the manifest has been fetched and verified.
+pub struct PreloadFinished;
+
+// Sent for the first asset that couldn't be fetched or didn't match its hash. Nothing else
+// gets preloaded after this.
+pub struct PreloadFailed {
+    pub url: Url,
+    pub error: AssetError,
+}
+
+// Holds back a model's download until preloading is over, so that it's loaded from the copies
+// that were just verified. It still goes ahead if preloading fails, which the status label
+// shows.
+#[derive(Component)]
+pub struct AfterPreload(pub QueuedModel);
+
+#[derive(Default)]
+struct SharedProgress {
+    received: AtomicU64,
+    total: AtomicU64,
+    loaded: AtomicUsize,
+    count: AtomicUsize,
+}
+
+#[derive(Default, Clone, Copy, Debug)]
+pub struct PreloadProgress {
+    pub received: u64,
+    // Only counts the assets whose size is known, either from the manifest or the response.
+    pub total: u64,
+    pub loaded: usize,
+    pub count: usize,
+    pub finished: bool,
+    pub failed: bool,
+}
+
+impl PreloadProgress {
+    pub fn fraction(&self) -> f32 {
+        if self.total == 0 {
+            return if self.finished { 1.0 } else { 0.0 };
+        }
+
+        self.received as f32 / self.total as f32
+    }
+}
+
+enum PreloadResult {
+    Finished,
+    Failed(Url, AssetError),
+}
+
+struct Preloader {
+    progress: Arc<SharedProgress>,
+    cancelled: Arc<AtomicBool>,
+    results: Receiver<PreloadResult>,
+}
+
+fn preload(
+    manager: &DownloadManager,
+    manifest_url: Url,
+    progress: Arc<SharedProgress>,
+    cancelled: Arc<AtomicBool>,
+    sender: Sender<PreloadResult>,
+) {
+    let cache = manager.cache();
+    let max_concurrent = manager.max_concurrent.max(1);
+
+    log::info!("Preloading assets from {}", manifest_url);
+
+    let future = async move {
+        let manifest: Manifest = match cache.fetch(&manifest_url).await.and_then(|bytes| {
+            serde_json::from_slice(&bytes).map_err(|error| AssetError::Decode(error.to_string()))
+        }) {
+            Ok(manifest) => manifest,
+            Err(error) => {
+                let _ = sender.send(PreloadResult::Failed(manifest_url, error));
+                return;
+            }
+        };
+
+        let mut assets = Vec::with_capacity(manifest.assets.len());
+
+        for entry in manifest.assets {
+            match manifest_url.join(&entry.url) {
+                Ok(url) => assets.push((url, entry)),
+                Err(error) => {
+                    let _ = sender.send(PreloadResult::Failed(
+                        manifest_url,
+                        AssetError::Decode(format!("invalid url {:?}: {}", entry.url, error)),
+                    ));
+                    return;
+                }
+            }
+        }
+
+        progress.count.store(assets.len(), Ordering::Relaxed);
+        progress.total.store(
+            assets.iter().filter_map(|(_, entry)| entry.size).sum(),
+            Ordering::Relaxed,
+        );
+
+        for batch in assets.chunks(max_concurrent) {
+            let fetches = batch.iter().map(|(url, entry)| {
+                let cache = &cache;
+                let progress = &progress;
+
+                async move {
+                    let received = AtomicU64::new(0);
+                    let size_known = AtomicBool::new(entry.size.is_some());
+
+                    let result = cache
+                        .fetch_with_progress(url, &|now, total| {
+                            let previous = received.swap(now, Ordering::Relaxed);
+                            progress
+                                .received
+                                .fetch_add(now.saturating_sub(previous), Ordering::Relaxed);
+
+                            if let Some(total) = total {
+                                if !size_known.swap(true, Ordering::Relaxed) {
+                                    progress.total.fetch_add(total, Ordering::Relaxed);
+                                }
+                            }
+                        })
+                        .await
+                        .and_then(|bytes| verify(&bytes, &entry.sha256));
+
+                    // The cache stores bodies before they're verified, and would otherwise keep
+                    // serving the bad copy.
+                    if let Err(AssetError::Integrity { .. }) = &result {
+                        cache.evict(url).await;
+                    }
+
+                    progress.loaded.fetch_add(1, Ordering::Relaxed);
+
+                    result.map_err(|error| (url.clone(), error))
+                }
+            });
+
+            for result in futures::future::join_all(fetches).await {
+                if let Err((url, error)) = result {
+                    let _ = sender.send(PreloadResult::Failed(url, error));
+                    return;
+                }
+            }
+
+            if cancelled.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+
+        let _ = sender.send(PreloadResult::Finished);
+    };
+
+    manager.spawn(future);
+}
+
+// Fetches everything listed in the manifest up front, so that the scene doesn't pop in
+// piece by piece and a broken deployment is noticed straight away.
+#[derive(Default)]
+pub struct PreloadPlugin {
+    pub manifest_url: Option<Url>,
+}
+
+impl Plugin for PreloadPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<PreloadFinished>();
+        app.add_event::<PreloadFailed>();
+
+        app.add_system(release_after_preload);
+
+        let manifest_url = match self.manifest_url.clone().or_else(manifest_url) {
+            Some(url) => url,
+            None => {
+                app.insert_resource(PreloadProgress {
+                    finished: true,
+                    ..Default::default()
+                });
+                return;
+            }
+        };
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let progress = Arc::new(SharedProgress::default());
+        let cancelled = Arc::new(AtomicBool::new(false));
+
+        preload(
+            app.world.resource::<DownloadManager>(),
+            manifest_url,
+            progress.clone(),
+            cancelled.clone(),
+            sender,
+        );
+
+        app.insert_resource(Preloader {
+            progress,
+            cancelled,
+            results: receiver,
+        });
+        app.insert_resource(PreloadProgress::default());
+
+        app.add_system(update_preload_progress);
+        app.add_system(log_preload_progress);
+        app.add_system_set(schedule::pre_render().with_system(show_preload_status));
+    }
+}
+
+fn release_after_preload(
+    mut commands: Commands,
+    progress: Res<PreloadProgress>,
+    pending_q: Query<(Entity, &AfterPreload)>,
+) {
+    if !progress.finished && !progress.failed {
+        return;
+    }
+
+    for (entity, pending) in pending_q.iter() {
+        commands
+            .entity(entity)
+            .remove::<AfterPreload>()
+            .insert(pending.0.clone());
+    }
+}
+
+fn update_preload_progress(
+    preloader: Res<Preloader>,
+    mut progress: ResMut<PreloadProgress>,
+    mut finished: EventWriter<PreloadFinished>,
+    mut failed: EventWriter<PreloadFailed>,
+) {
+    if progress.finished || progress.failed {
+        return;
+    }
+
+    progress.received = preloader.progress.received.load(Ordering::Relaxed);
+    progress.total = preloader.progress.total.load(Ordering::Relaxed);
+    progress.loaded = preloader.progress.loaded.load(Ordering::Relaxed);
+    progress.count = preloader.progress.count.load(Ordering::Relaxed);
+
+    for result in preloader.results.try_iter() {
+        match result {
+            PreloadResult::Finished => {
+                log::info!("Preloaded {} assets", progress.count);
+                progress.finished = true;
+                finished.send(PreloadFinished);
+            }
+            PreloadResult::Failed(url, error) => {
+                log::error!("Failed to preload {}: {}", url, error);
+                preloader.cancelled.store(true, Ordering::Relaxed);
+                progress.failed = true;
+                failed.send(PreloadFailed { url, error });
+                return;
+            }
+        }
+    }
+}
+
+fn log_preload_progress(progress: Res<PreloadProgress>, mut frame: Local<u32>) {
+    if progress.finished || progress.failed {
+        return;
+    }
+
+    *frame += 1;
+
+    if *frame % PROGRESS_LOG_INTERVAL_FRAMES != 0 {
+        return;
+    }
+
+    log::info!(
+        "Preloading: {}/{} assets, {}/{} KiB ({:.0}%)",
+        progress.loaded,
+        progress.count,
+        progress.received / 1024,
+        progress.total / 1024,
+        progress.fraction() * 100.0
+    );
+}
+
+#[derive(Default)]
+struct PreloadStatus {
+    label: Option<Entity>,
+    // Counts down while a failure is shown.
+    failure_remaining: f32,
+}
+
+// A label in front of the camera with how far preloading has got, or why it failed.
+fn show_preload_status(
+    mut commands: Commands,
+    mut status: Local<PreloadStatus>,
+    mut failures: EventReader<PreloadFailed>,
+    progress: Res<PreloadProgress>,
+    locale: Res<Locale>,
+    camera: Res<Camera>,
+    mut label_q: Query<&mut TextLabel>,
+) {
+    let position = camera.position + camera.rotation * STATUS_OFFSET;
+
+    let text = match failures.iter().last() {
+        Some(failure) => {
+            status.failure_remaining = FAILURE_SECONDS;
+
+            locale.format(
+                "preload.failed",
+                &[("url", &failure.url), ("error", &failure.error)],
+            )
+        }
+        None if progress.finished || progress.failed => {
+            status.failure_remaining -= DELTA_TIME;
+
+            if status.failure_remaining <= 0.0 {
+                if let Some(label) = status.label.take() {
+                    commands.entity(label).despawn();
+                }
+            }
+
+            return;
+        }
+        None => {
+            let percent = format!("{:.0}", progress.fraction() * 100.0);
+
+            locale.format(
+                "preload.progress",
+                &[
+                    ("loaded", &progress.loaded),
+                    ("count", &progress.count),
+                    ("percent", &percent),
+                ],
+            )
+        }
+    };
+
+    match status.label.and_then(|label| label_q.get_mut(label).ok()) {
+        Some(mut label) => {
+            // Each change draws a new texture, so the label is left alone until the text does.
+            if label.text != text {
+                label.text = text;
+                label.position = position;
+            }
+        }
+        None => {
+            let mut label = TextLabel::new(text, position);
+            label.columns = STATUS_COLUMNS;
+
+            status.label = Some(commands.spawn().insert(label).id());
+        }
+    }
+}
@@ -0,0 +1,409 @@
+use std::f32::consts::PI;
+
+use serde_json::json;
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res},
+    components,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::fnv1a;
+use crate::downloads::{DownloadFailed, DownloadManager};
+use crate::materials::MaterialDescriptor;
+use crate::mesh_compression::Glb;
+
+const ARRAY_BUFFER: u32 = 34962;
+const ELEMENT_ARRAY_BUFFER: u32 = 34963;
+const FLOAT: u32 = 5126;
+const UNSIGNED_INT: u32 = 5125;
+
+// Simple shapes for blocking out scenes and visualising colliders. All of them are centered
+// on the origin.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Primitive {
+    Cuboid {
+        size: Vec3,
+    },
+    Sphere {
+        radius: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    // Facing up, along the x and z axes.
+    Plane {
+        width: f32,
+        depth: f32,
+    },
+    // The height is of the cylinder between the two hemispheres.
+    Capsule {
+        radius: f32,
+        height: f32,
+        sectors: u32,
+        stacks: u32,
+    },
+    // The edges of a cuboid, each one a thin cuboid itself.
+    BoxOutline {
+        size: Vec3,
+        thickness: f32,
+    },
+}
+
+impl Primitive {
+    pub fn cube(size: f32) -> Self {
+        Self::Cuboid {
+            size: Vec3::splat(size),
+        }
+    }
+
+    pub fn sphere(radius: f32) -> Self {
+        Self::Sphere {
+            radius,
+            sectors: 32,
+            stacks: 16,
+        }
+    }
+
+    pub fn plane(size: f32) -> Self {
+        Self::Plane {
+            width: size,
+            depth: size,
+        }
+    }
+
+    pub fn capsule(radius: f32, height: f32) -> Self {
+        Self::Capsule {
+            radius,
+            height,
+            sectors: 32,
+            stacks: 8,
+        }
+    }
+
+    pub fn mesh(&self) -> MeshData {
+        match *self {
+            Self::Cuboid { size } => cuboid(size * 0.5),
+            Self::Sphere {
+                radius,
+                sectors,
+                stacks,
+            } => capsule(radius, 0.0, sectors.max(3), stacks.max(1)),
+            Self::Plane { width, depth } => plane(width * 0.5, depth * 0.5),
+            Self::Capsule {
+                radius,
+                height,
+                sectors,
+                stacks,
+            } => capsule(radius, height * 0.5, sectors.max(3), stacks.max(1)),
+            Self::BoxOutline { size, thickness } => box_outline(size * 0.5, thickness),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MeshData {
+    pub positions: Vec<Vec3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<[f32; 2]>,
+    pub indices: Vec<u32>,
+}
+
+impl MeshData {
+    fn push_vertex(&mut self, position: Vec3, normal: Vec3, uv: [f32; 2]) {
+        self.positions.push(position);
+        self.normals.push(normal);
+        self.uvs.push(uv);
+    }
+
+    fn append(&mut self, other: MeshData, offset: Vec3) {
+        let base = self.positions.len() as u32;
+
+        self.positions.extend(
+            other
+                .positions
+                .into_iter()
+                .map(|position| position + offset),
+        );
+        self.normals.extend(other.normals);
+        self.uvs.extend(other.uvs);
+        self.indices
+            .extend(other.indices.into_iter().map(|index| index + base));
+    }
+
+    pub fn to_glb(&self, material: &MaterialDescriptor) -> Vec<u8> {
+        let mut bin = Vec::new();
+
+        let mut push_view = |bytes: &[u8], target: u32| {
+            let offset = bin.len();
+            bin.extend_from_slice(bytes);
+
+            while bin.len() % 4 != 0 {
+                bin.push(0);
+            }
+
+            json!({
+                "buffer": 0,
+                "byteOffset": offset,
+                "byteLength": bytes.len(),
+                "target": target,
+            })
+        };
+
+        let floats = |values: &mut dyn Iterator<Item = f32>| -> Vec<u8> {
+            values.flat_map(|value| value.to_le_bytes()).collect()
+        };
+
+        let views = vec![
+            push_view(
+                &floats(&mut self.positions.iter().flat_map(|p| p.to_array())),
+                ARRAY_BUFFER,
+            ),
+            push_view(
+                &floats(&mut self.normals.iter().flat_map(|n| n.to_array())),
+                ARRAY_BUFFER,
+            ),
+            push_view(
+                &floats(&mut self.uvs.iter().flat_map(|uv| *uv)),
+                ARRAY_BUFFER,
+            ),
+            push_view(
+                &self
+                    .indices
+                    .iter()
+                    .flat_map(|index| index.to_le_bytes())
+                    .collect::<Vec<u8>>(),
+                ELEMENT_ARRAY_BUFFER,
+            ),
+        ];
+
+        let (min, max) = self.positions.iter().fold(
+            (Vec3::splat(f32::MAX), Vec3::splat(f32::MIN)),
+            |(min, max), &position| (min.min(position), max.max(position)),
+        );
+
+        let count = self.positions.len();
+
+        let mut json = json!({
+            "asset": { "version": "2.0", "generator": "superconductor-playground primitives" },
+            "scene": 0,
+            "scenes": [{ "nodes": [0] }],
+            "nodes": [{ "mesh": 0 }],
+            "meshes": [{
+                "primitives": [{
+                    "attributes": { "POSITION": 0, "NORMAL": 1, "TEXCOORD_0": 2 },
+                    "indices": 3,
+                    "mode": 4,
+                }],
+            }],
+            "accessors": [
+                {
+                    "bufferView": 0,
+                    "componentType": FLOAT,
+                    "count": count,
+                    "type": "VEC3",
+                    "min": min.to_array(),
+                    "max": max.to_array(),
+                },
+                { "bufferView": 1, "componentType": FLOAT, "count": count, "type": "VEC3" },
+                { "bufferView": 2, "componentType": FLOAT, "count": count, "type": "VEC2" },
+                {
+                    "bufferView": 3,
+                    "componentType": UNSIGNED_INT,
+                    "count": self.indices.len(),
+                    "type": "SCALAR",
+                },
+            ],
+            "bufferViews": views,
+            "buffers": [{ "byteLength": bin.len() }],
+        });
+
+        let material = material.write_gltf(&mut json);
+        json["meshes"][0]["primitives"][0]["material"] = material.into();
+
+        Glb {
+            json,
+            bin: Some(bin),
+        }
+        .write()
+    }
+}
+
+fn cuboid(half: Vec3) -> MeshData {
+    let mut mesh = MeshData::default();
+
+    for normal in [Vec3::X, -Vec3::X, Vec3::Y, -Vec3::Y, Vec3::Z, -Vec3::Z] {
+        // Two axes spanning the face, picked so that the winding is counter-clockwise when
+        // looking at it from the outside.
+        let up = if normal.y.abs() > 0.5 {
+            Vec3::Z
+        } else {
+            Vec3::Y
+        };
+        let right = up.cross(normal);
+        let up = normal.cross(right);
+
+        let base = mesh.positions.len() as u32;
+
+        for (x, y) in [(-1.0, -1.0), (1.0, -1.0), (1.0, 1.0), (-1.0, 1.0)] {
+            let position = (normal + right * x + up * y) * half;
+            mesh.push_vertex(position, normal, [(x + 1.0) / 2.0, (1.0 - y) / 2.0]);
+        }
+
+        mesh.indices
+            .extend_from_slice(&[base, base + 1, base + 2, base, base + 2, base + 3]);
+    }
+
+    mesh
+}
+
+fn box_outline(half: Vec3, thickness: f32) -> MeshData {
+    let mut mesh = MeshData::default();
+
+    for axis in [Vec3::X, Vec3::Y, Vec3::Z] {
+        // Extended by the thickness so that the corners are filled in.
+        let edge = axis * (half * 2.0 + thickness) + (Vec3::ONE - axis) * thickness;
+
+        for (a, b) in [(-1.0, -1.0), (1.0, -1.0), (-1.0, 1.0), (1.0, 1.0)] {
+            let offset = Vec3::new(axis.y + axis.z, axis.x, 0.0) * a
+                + Vec3::new(0.0, axis.z, axis.x + axis.y) * b;
+
+            mesh.append(cuboid(edge * 0.5), offset * half);
+        }
+    }
+
+    mesh
+}
+
+fn plane(half_width: f32, half_depth: f32) -> MeshData {
+    let mut mesh = MeshData::default();
+
+    for (x, z) in [(-1.0, 1.0), (1.0, 1.0), (1.0, -1.0), (-1.0, -1.0)] {
+        mesh.push_vertex(
+            Vec3::new(x * half_width, 0.0, z * half_depth),
+            Vec3::Y,
+            [(x + 1.0) / 2.0, (z + 1.0) / 2.0],
+        );
+    }
+
+    mesh.indices.extend_from_slice(&[0, 1, 2, 0, 2, 3]);
+
+    mesh
+}
+
+// A sphere is a capsule without a cylinder. Each hemisphere gets `stacks` rings, and the
+// cylinder is the gap between the two rings at the equator.
+fn capsule(radius: f32, half_height: f32, sectors: u32, stacks: u32) -> MeshData {
+    let mut mesh = MeshData::default();
+
+    let rings = stacks * 2 + 2;
+    let total_height = radius * 2.0 + half_height * 2.0;
+
+    for ring in 0..rings {
+        let (latitude, offset) = if ring <= stacks {
+            (
+                PI / 2.0 - ring as f32 / stacks as f32 * PI / 2.0,
+                half_height,
+            )
+        } else {
+            (
+                -((ring - stacks - 1) as f32) / stacks as f32 * PI / 2.0,
+                -half_height,
+            )
+        };
+
+        for sector in 0..=sectors {
+            let longitude = sector as f32 / sectors as f32 * PI * 2.0;
+
+            let normal = Vec3::new(
+                latitude.cos() * longitude.cos(),
+                latitude.sin(),
+                -latitude.cos() * longitude.sin(),
+            );
+
+            let position = normal * radius + Vec3::new(0.0, offset, 0.0);
+            let v = (radius + half_height - position.y) / total_height;
+
+            mesh.push_vertex(position, normal, [sector as f32 / sectors as f32, v]);
+        }
+    }
+
+    let row = sectors + 1;
+
+    for ring in 0..rings - 1 {
+        for sector in 0..sectors {
+            let a = ring * row + sector;
+            let b = a + row;
+
+            mesh.indices
+                .extend_from_slice(&[a, b, a + 1, a + 1, b, b + 1]);
+        }
+    }
+
+    mesh
+}
+
+// Generated and handed to the renderer as a model, so that it can be used with `InstanceOf`
+// like any other.
+#[derive(Component)]
+pub struct ProceduralModel {
+    pub primitive: Primitive,
+    pub material: MaterialDescriptor,
+}
+
+impl ProceduralModel {
+    // Not a real url, but a stable name for the generated model to be stored under.
+    fn key(&self) -> Url {
+        Url::parse(&format!(
+            "procedural:{:016x}",
+            fnv1a(format!("{:?} {:?}", self.primitive, self.material).as_bytes())
+        ))
+        .unwrap()
+    }
+}
+
+pub fn spawn_primitive(
+    commands: &mut Commands,
+    primitive: Primitive,
+    material: MaterialDescriptor,
+) -> Entity {
+    commands
+        .spawn()
+        .insert(ProceduralModel {
+            primitive,
+            material,
+        })
+        .insert(components::Instances(Default::default()))
+        .insert(components::InstanceRange(Default::default()))
+        .id()
+}
+
+pub struct PrimitivePlugin;
+
+impl Plugin for PrimitivePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(generate_primitives);
+    }
+}
+
+fn generate_primitives(
+    mut commands: Commands,
+    manager: Res<DownloadManager>,
+    primitive_q: Query<(Entity, &ProceduralModel), Added<ProceduralModel>>,
+) {
+    let _span = tracing::info_span!("generate_primitives").entered();
+
+    for (entity, model) in primitive_q.iter() {
+        let glb = model.primitive.mesh().to_glb(&model.material);
+
+        match manager.cache().store_derived(&model.key(), "glb", &glb) {
+            Ok(url) => {
+                commands.entity(entity).insert(components::ModelUrl(url));
+            }
+            Err(error) => {
+                log::warn!("Failed to store {:?}: {}", model.primitive, error);
+                commands.entity(entity).insert(DownloadFailed);
+            }
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::Res,
+};
+use tracing::span;
+use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+use tracing_subscriber::registry::LookupSpan;
+
+// Records when spans are entered and exited while turned on. Desktop builds write the
+// recording out as a chrome://tracing (or Perfetto) json file, while the web uses
+// `console.time`, which shows up in the browser's own performance tools.
+pub struct Recorder {
+    recording: AtomicBool,
+    #[cfg(not(feature = "wasm"))]
+    native: native::Events,
+}
+
+impl Recorder {
+    fn new() -> Self {
+        Self {
+            recording: AtomicBool::new(false),
+            #[cfg(not(feature = "wasm"))]
+            native: native::Events::new(),
+        }
+    }
+
+    pub fn is_recording(&self) -> bool {
+        self.recording.load(Ordering::Relaxed)
+    }
+
+    pub fn toggle(&self) {
+        let recording = !self.recording.fetch_xor(true, Ordering::Relaxed);
+
+        if recording {
+            log::info!("Started recording a trace");
+            return;
+        }
+
+        #[cfg(not(feature = "wasm"))]
+        match self.native.export() {
+            Ok(path) => log::info!("Wrote a trace to {:?}", path),
+            Err(error) => log::warn!("Failed to write the trace: {}", error),
+        }
+
+        #[cfg(feature = "wasm")]
+        log::info!("Stopped recording a trace");
+    }
+
+    fn enter(&self, name: &'static str) {
+        if !self.is_recording() {
+            return;
+        }
+
+        #[cfg(not(feature = "wasm"))]
+        self.native.push(name, 'B');
+
+        #[cfg(feature = "wasm")]
+        web_sys::console::time_with_label(name);
+    }
+
+    fn exit(&self, name: &'static str) {
+        if !self.is_recording() {
+            return;
+        }
+
+        #[cfg(not(feature = "wasm"))]
+        self.native.push(name, 'E');
+
+        #[cfg(feature = "wasm")]
+        web_sys::console::time_end_with_label(name);
+    }
+}
+
+struct RecorderLayer(Arc<Recorder>);
+
+impl<S: tracing::Subscriber + for<'a> LookupSpan<'a>> Layer<S> for RecorderLayer {
+    fn on_enter(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(metadata) = ctx.metadata(id) {
+            self.0.enter(metadata.name());
+        }
+    }
+
+    fn on_exit(&self, id: &span::Id, ctx: Context<'_, S>) {
+        if let Some(metadata) = ctx.metadata(id) {
+            self.0.exit(metadata.name());
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+mod native {
+    use std::path::PathBuf;
+    use std::sync::Mutex;
+    use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+    use crate::asset_cache::fnv1a;
+
+    struct Event {
+        name: &'static str,
+        phase: char,
+        micros: u64,
+        thread: u64,
+    }
+
+    pub struct Events {
+        start: Instant,
+        events: Mutex<Vec<Event>>,
+    }
+
+    impl Events {
+        pub fn new() -> Self {
+            Self {
+                start: Instant::now(),
+                events: Mutex::new(Vec::new()),
+            }
+        }
+
+        pub fn push(&self, name: &'static str, phase: char) {
+            let event = Event {
+                name,
+                phase,
+                micros: self.start.elapsed().as_micros() as u64,
+                // Thread ids only need to be consistent within a trace, and the real ones
+                // aren't available as numbers on stable.
+                thread: fnv1a(format!("{:?}", std::thread::current().id()).as_bytes()) & 0xffff,
+            };
+
+            self.events.lock().unwrap().push(event);
+        }
+
+        // Written to TRACE_DIR, or the working directory.
+        pub fn export(&self) -> Result<PathBuf, String> {
+            let events = std::mem::take(&mut *self.events.lock().unwrap());
+
+            let trace_events: Vec<serde_json::Value> = events
+                .iter()
+                .map(|event| {
+                    serde_json::json!({
+                        "name": event.name,
+                        "ph": event.phase.to_string(),
+                        "ts": event.micros,
+                        "pid": 1,
+                        "tid": event.thread,
+                    })
+                })
+                .collect();
+
+            let json = serde_json::to_vec(&serde_json::json!({
+                "traceEvents": trace_events,
+                "displayTimeUnit": "ms",
+            }))
+            .map_err(|error| error.to_string())?;
+
+            let timestamp = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|duration| duration.as_secs())
+                .unwrap_or_default();
+
+            let path = PathBuf::from(std::env::var("TRACE_DIR").unwrap_or_else(|_| ".".into()))
+                .join(format!("trace-{}.json", timestamp));
+
+            std::fs::write(&path, json).map_err(|error| error.to_string())?;
+
+            Ok(path)
+        }
+    }
+}
+
+pub struct ProfilingToggled;
+
+pub struct Profiler(pub Arc<Recorder>);
+
+pub struct ProfilingPlugin;
+
+impl Plugin for ProfilingPlugin {
+    fn build(&self, app: &mut App) {
+        let recorder = Arc::new(Recorder::new());
+
+        let subscriber = tracing_subscriber::registry().with(RecorderLayer(recorder.clone()));
+
+        if let Err(error) = tracing::subscriber::set_global_default(subscriber) {
+            log::warn!("Failed to install the trace recorder: {}", error);
+        }
+
+        app.add_event::<ProfilingToggled>();
+        app.insert_resource(Profiler(recorder));
+
+        app.add_system(toggle_profiling);
+    }
+}
+
+fn toggle_profiling(mut toggles: EventReader<ProfilingToggled>, profiler: Res<Profiler>) {
+    for _ in toggles.iter() {
+        profiler.0.toggle();
+    }
+}
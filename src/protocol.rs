@@ -0,0 +1,179 @@
+use serde::{Deserialize, Serialize};
+
+pub type PlayerId = u32;
+pub type ObjectId = u32;
+
+pub const DEFAULT_PORT: u16 = 8080;
+pub const MAX_ROOM_NAME_LENGTH: usize = 32;
+pub const MAX_CHAT_LENGTH: usize = 256;
+pub const MAX_URL_LENGTH: usize = 512;
+pub const MAX_STATE_KEY_LENGTH: usize = 32;
+pub const MAX_STATE_VALUE_LENGTH: usize = 256;
+// For the room's own values and for each player's.
+pub const MAX_STATE_KEYS: usize = 64;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ClientMessage {
+    ListRooms,
+    JoinRoom {
+        room: String,
+    },
+    QuickJoin,
+    LeaveRoom,
+    AvatarState(AvatarState),
+    AvatarProfile(AvatarProfile),
+    Emote {
+        index: u8,
+    },
+    ClaimObject {
+        object_id: ObjectId,
+    },
+    ReleaseObject {
+        object_id: ObjectId,
+    },
+    ObjectTransform {
+        object_id: ObjectId,
+        transform: ObjectTransform,
+    },
+    Chat {
+        text: String,
+    },
+    // Sets (or with None, removes) a value shared with everyone in the room, either for the
+    // whole room or for the sending player.
+    SetRoomValue {
+        key: String,
+        value: Option<String>,
+    },
+    SetPlayerValue {
+        key: String,
+        value: Option<String>,
+    },
+    // Answered with a `Pong` with the same time, for measuring the round trip.
+    Ping {
+        time: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub enum ServerMessage {
+    RoomList(Vec<RoomInfo>),
+    Joined {
+        room: String,
+        player_id: PlayerId,
+    },
+    JoinRejected {
+        room: String,
+        reason: String,
+    },
+    PlayerJoined {
+        player_id: PlayerId,
+    },
+    PlayerLeft {
+        player_id: PlayerId,
+    },
+    // The player came into (or went out of) this client's interest radius on the server, and
+    // its avatar should be shown (or hidden). Everyone is in view when there's no radius.
+    PlayerEntered {
+        player_id: PlayerId,
+    },
+    PlayerExited {
+        player_id: PlayerId,
+    },
+    AvatarState {
+        player_id: PlayerId,
+        state: AvatarState,
+    },
+    AvatarProfile {
+        player_id: PlayerId,
+        profile: AvatarProfile,
+    },
+    Emote {
+        player_id: PlayerId,
+        index: u8,
+    },
+    HostChanged {
+        host: Option<PlayerId>,
+    },
+    ObjectOwner {
+        object_id: ObjectId,
+        owner: Option<PlayerId>,
+    },
+    ObjectTransform {
+        object_id: ObjectId,
+        transform: ObjectTransform,
+    },
+    Chat {
+        player_id: PlayerId,
+        text: String,
+    },
+    RoomValue {
+        key: String,
+        value: Option<String>,
+    },
+    PlayerValue {
+        player_id: PlayerId,
+        key: String,
+        value: Option<String>,
+    },
+    Pong {
+        time: f64,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct RoomInfo {
+    pub name: String,
+    pub players: usize,
+    pub capacity: usize,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct AvatarState {
+    pub position: [f32; 3],
+    pub yaw: f32,
+    pub animation_index: u32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default)]
+pub struct ObjectTransform {
+    pub position: [f32; 3],
+    pub rotation: [f32; 4],
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AvatarProfile {
+    pub model_url: String,
+    pub scale: f32,
+}
+
+pub fn is_valid_room_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= MAX_ROOM_NAME_LENGTH
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub fn is_valid_state_value(key: &str, value: Option<&str>) -> bool {
+    !key.is_empty()
+        && key.len() <= MAX_STATE_KEY_LENGTH
+        && value.map_or(true, |value| value.len() <= MAX_STATE_VALUE_LENGTH)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn room_names() {
+        assert!(is_valid_room_name("room-0"));
+        assert!(is_valid_room_name("Lobby_2"));
+        assert!(is_valid_room_name(&"a".repeat(MAX_ROOM_NAME_LENGTH)));
+
+        assert!(!is_valid_room_name(""));
+        assert!(!is_valid_room_name("two words"));
+        assert!(!is_valid_room_name("../room"));
+        assert!(!is_valid_room_name("café"));
+        assert!(!is_valid_room_name(&"a".repeat(MAX_ROOM_NAME_LENGTH + 1)));
+    }
+}
@@ -0,0 +1,417 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Query, Res, Without},
+    components,
+    renderer_core::glam::Quat,
+    Vec3,
+};
+
+use crate::bounds::{instance_transform, Aabb, Bounds, BoundsOutline};
+use crate::foot_ik::ground_height;
+use crate::schedule;
+use crate::shared_physics::{GRAVITY, GROUND_HEIGHT};
+use crate::skeletons::ModelSkeleton;
+use crate::terrain::{Heightfield, TerrainChunk};
+use crate::time_scale::TimeScale;
+use crate::{LocalPlayer, PlayerState, PlayerStates, RemotePlayer};
+
+// Falling for longer than this is a big fall, and the player goes limp.
+const BIG_FALL_SECONDS: f32 = 1.5;
+// Capsules are this thick for their length, within limits, in model space.
+const CAPSULE_THICKNESS: f32 = 0.2;
+const MIN_RADIUS: f32 = 0.03;
+const MAX_RADIUS: f32 = 0.15;
+// Passes over the joints per step. More make the limbs stiffer.
+const ITERATIONS: usize = 4;
+// The fraction of the velocity lost per second in the air, and along the ground.
+const AIR_DAMPING: f32 = 0.1;
+const GROUND_FRICTION: f32 = 6.0;
+// Ragdolls that have moved slower than this for the rest time get back up.
+const REST_SPEED: f32 = 0.1;
+const REST_SECONDS: f32 = 1.0;
+const RECOVER_SECONDS: f32 = 0.6;
+// For models without a skeleton, which go limp as a single capsule.
+const DEFAULT_HEIGHT: f32 = 1.7;
+
+// Knocks an avatar over, throwing it with the impulse (a change in velocity).
+pub struct RagdollRequested {
+    pub entity: Entity,
+    pub impulse: Vec3,
+}
+
+// Gets a ragdolled avatar back up before it comes to rest.
+pub struct RecoverRequested(pub Entity);
+
+// A bone between two joints, as a capsule that collides with the ground at its ends.
+#[derive(Clone, Copy, Debug)]
+pub struct Capsule {
+    pub from: usize,
+    pub to: usize,
+    pub length: f32,
+    pub radius: f32,
+}
+
+// An avatar that's been knocked over. Each joint is a particle, the bones between them hold
+// them at their rest lengths, and the whole avatar tumbles with its hips and highest joint. The
+// renderer skins with the animation's bones, which can't be set from game code, so the limbs
+// themselves don't go limp until they can be; the animation is held where it was instead.
+#[derive(Component, Clone, Debug)]
+pub struct Ragdoll {
+    pub particles: Vec<Vec3>,
+    previous: Vec<Vec3>,
+    radii: Vec<f32>,
+    pub capsules: Vec<Capsule>,
+    // In model space, from the skeleton.
+    rest: Vec<Vec3>,
+    root: usize,
+    top: usize,
+    upright: Quat,
+    animation_time: f32,
+    still_for: f32,
+    // From 0 to 1 while getting back up, from the fallen transform.
+    recovery: Option<(f32, Vec3, Quat)>,
+}
+
+impl Ragdoll {
+    fn new(
+        skeleton: Option<&ModelSkeleton>,
+        instance: &components::Instance,
+        animation_time: f32,
+        impulse: Vec3,
+        delta: f32,
+    ) -> Self {
+        let (rest, parents): (Vec<Vec3>, Vec<Option<usize>>) = match skeleton {
+            Some(skeleton) if skeleton.joints.len() > 1 => skeleton
+                .joints
+                .iter()
+                .map(|joint| (joint.rest, joint.parent))
+                .unzip(),
+            _ => (
+                vec![Vec3::ZERO, Vec3::Y * DEFAULT_HEIGHT],
+                vec![None, Some(0)],
+            ),
+        };
+
+        let scale = instance.0.scale;
+        let mut radii = vec![MIN_RADIUS * scale; rest.len()];
+
+        let capsules: Vec<Capsule> = parents
+            .iter()
+            .enumerate()
+            .filter_map(|(joint, parent)| {
+                let parent = (*parent)?;
+                let length = rest[joint].distance(rest[parent]) * scale;
+                let radius =
+                    (length * CAPSULE_THICKNESS).clamp(MIN_RADIUS * scale, MAX_RADIUS * scale);
+
+                radii[joint] = radii[joint].max(radius);
+                radii[parent] = radii[parent].max(radius);
+
+                Some(Capsule {
+                    from: parent,
+                    to: joint,
+                    length,
+                    radius,
+                })
+            })
+            .collect();
+
+        let root = parents
+            .iter()
+            .position(|parent| parent.is_none())
+            .unwrap_or(0);
+        let top = (0..rest.len())
+            .max_by(|&a, &b| rest[a].y.total_cmp(&rest[b].y))
+            .unwrap_or(0);
+
+        let transform = instance_transform(&instance.0);
+        let particles: Vec<Vec3> = rest
+            .iter()
+            .map(|&rest| transform.transform_point3(rest))
+            .collect();
+        // Verlet integration keeps the velocity as the distance from the last step.
+        let previous = particles
+            .iter()
+            .map(|&particle| particle - impulse * delta)
+            .collect();
+
+        Self {
+            particles,
+            previous,
+            radii,
+            capsules,
+            rest,
+            root,
+            top,
+            upright: instance.0.rotation,
+            animation_time,
+            still_for: 0.0,
+            recovery: None,
+        }
+    }
+
+    fn step(&mut self, delta: f32, heightfields: &[&Heightfield], colliders: &[Aabb]) {
+        let damping = (1.0 - AIR_DAMPING * delta).max(0.0);
+        let mut fastest: f32 = 0.0;
+
+        for (particle, previous) in self.particles.iter_mut().zip(&mut self.previous) {
+            let velocity = (*particle - *previous) * damping;
+            *previous = *particle;
+            *particle += velocity + Vec3::new(0.0, -GRAVITY, 0.0) * delta * delta;
+        }
+
+        let mut grounded = vec![false; self.particles.len()];
+
+        for _ in 0..ITERATIONS {
+            for capsule in &self.capsules {
+                let (from, to) = (self.particles[capsule.from], self.particles[capsule.to]);
+                let offset = to - from;
+                let distance = offset.length();
+
+                if distance <= f32::EPSILON {
+                    continue;
+                }
+
+                let correction = offset * ((distance - capsule.length) / distance * 0.5);
+                self.particles[capsule.from] += correction;
+                self.particles[capsule.to] -= correction;
+            }
+
+            for (index, particle) in self.particles.iter_mut().enumerate() {
+                let radius = self.radii[index];
+                let ground = match floor(*particle + Vec3::Y * radius, heightfields, colliders) {
+                    Some(ground) => ground,
+                    None => continue,
+                };
+
+                if particle.y - radius < ground {
+                    particle.y = ground + radius;
+                    grounded[index] = true;
+                }
+            }
+        }
+
+        // Slides to a stop along the ground.
+        let friction = (GROUND_FRICTION * delta).min(1.0);
+
+        for ((particle, previous), grounded) in
+            self.particles.iter().zip(&mut self.previous).zip(grounded)
+        {
+            if grounded {
+                previous.x += (particle.x - previous.x) * friction;
+                previous.z += (particle.z - previous.z) * friction;
+            }
+        }
+
+        for (particle, previous) in self.particles.iter().zip(&self.previous) {
+            fastest = fastest.max(particle.distance(*previous));
+        }
+
+        if fastest < REST_SPEED * delta {
+            self.still_for += delta;
+        } else {
+            self.still_for = 0.0;
+        }
+    }
+
+    // Turns the avatar so that its hips and highest joint line up with theirs in the ragdoll.
+    fn transform(&self, scale: f32) -> (Vec3, Quat) {
+        let rest_up = self.upright * (self.rest[self.top] - self.rest[self.root]);
+        let up = self.particles[self.top] - self.particles[self.root];
+
+        let rotation = if rest_up.length() > f32::EPSILON && up.length() > f32::EPSILON {
+            Quat::from_rotation_arc(rest_up.normalize(), up.normalize()) * self.upright
+        } else {
+            self.upright
+        };
+
+        let position = self.particles[self.root] - rotation * (self.rest[self.root] * scale);
+
+        (position, rotation)
+    }
+}
+
+// The ground under a point, which is the terrain or anything else below, or the ground plane
+// that the shared physics bodies land on.
+fn floor(from: Vec3, heightfields: &[&Heightfield], colliders: &[Aabb]) -> Option<f32> {
+    ground_height(from, heightfields, colliders)
+        .or_else(|| (from.y >= GROUND_HEIGHT).then_some(GROUND_HEIGHT))
+}
+
+pub struct RagdollPlugin;
+
+impl Plugin for RagdollPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<RagdollRequested>();
+        app.add_event::<RecoverRequested>();
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(start_ragdolls)
+                .with_system(simulate_ragdolls),
+        );
+    }
+}
+
+// Remote players are left alone, as their positions come from their own clients.
+#[allow(clippy::type_complexity)]
+fn start_ragdolls(
+    mut commands: Commands,
+    mut requests: EventReader<RagdollRequested>,
+    time_scale: Res<TimeScale>,
+    mut falling: Local<HashMap<Entity, f32>>,
+    model_q: Query<&ModelSkeleton>,
+    mut avatar_q: Query<
+        (
+            Entity,
+            &components::InstanceOf,
+            &components::Instance,
+            &components::AnimationState,
+            Option<&mut PlayerState>,
+            Option<&LocalPlayer>,
+        ),
+        (Without<Ragdoll>, Without<RemotePlayer>),
+    >,
+) {
+    let delta = time_scale.delta();
+    let mut knocked: HashMap<Entity, Vec3> = requests
+        .iter()
+        .map(|request| (request.entity, request.impulse))
+        .collect();
+
+    falling.retain(|entity, _| avatar_q.get(*entity).is_ok());
+
+    for (entity, _, _, _, state, local) in avatar_q.iter_mut() {
+        match state {
+            Some(state) if local.is_some() && state.0 == PlayerStates::Falling => {
+                let seconds = falling.entry(entity).or_default();
+                *seconds += delta;
+
+                if *seconds > BIG_FALL_SECONDS {
+                    knocked.entry(entity).or_insert(Vec3::ZERO);
+                }
+            }
+            _ => {
+                falling.remove(&entity);
+            }
+        }
+    }
+
+    for (entity, impulse) in knocked {
+        let (entity, instance_of, instance, animation, state, _) = match avatar_q.get_mut(entity) {
+            Ok(avatar) => avatar,
+            Err(_) => continue,
+        };
+
+        falling.remove(&entity);
+
+        // Stops foot placement and root motion from moving it.
+        if let Some(mut state) = state {
+            state.0 = PlayerStates::Falling;
+        }
+
+        commands.entity(entity).insert(Ragdoll::new(
+            model_q.get(instance_of.0).ok(),
+            instance,
+            animation.time,
+            impulse,
+            delta,
+        ));
+    }
+}
+
+#[allow(clippy::type_complexity)]
+fn simulate_ragdolls(
+    mut commands: Commands,
+    mut recover_requests: EventReader<RecoverRequested>,
+    time_scale: Res<TimeScale>,
+    heightfield_q: Query<&Heightfield>,
+    collider_q: Query<
+        &Bounds,
+        (
+            Without<Ragdoll>,
+            Without<LocalPlayer>,
+            Without<RemotePlayer>,
+            Without<TerrainChunk>,
+            Without<BoundsOutline>,
+        ),
+    >,
+    mut ragdoll_q: Query<(
+        Entity,
+        &mut Ragdoll,
+        &mut components::Instance,
+        &mut components::AnimationState,
+        Option<&mut PlayerState>,
+    )>,
+) {
+    let delta = time_scale.delta();
+
+    if delta <= 0.0 {
+        return;
+    }
+
+    let heightfields: Vec<&Heightfield> = heightfield_q.iter().collect();
+    let colliders: Vec<Aabb> = collider_q
+        .iter()
+        .map(|bounds| bounds.0)
+        .filter(|aabb| aabb.size().max_element() > 0.0)
+        .collect();
+
+    for RecoverRequested(entity) in recover_requests.iter() {
+        if let Ok((_, mut ragdoll, instance, _, _)) = ragdoll_q.get_mut(*entity) {
+            if ragdoll.recovery.is_none() {
+                ragdoll.recovery = Some((0.0, instance.0.position, instance.0.rotation));
+            }
+        }
+    }
+
+    for (entity, mut ragdoll, mut instance, mut animation, state) in ragdoll_q.iter_mut() {
+        let (position, rotation) = match ragdoll.recovery {
+            // Blends back to standing up where the hips came to rest, facing the way it was.
+            Some((progress, fallen_position, fallen_rotation)) => {
+                let progress = (progress + delta / RECOVER_SECONDS).min(1.0);
+                let root = ragdoll.particles[ragdoll.root];
+                let ground = floor(root, &heightfields, &colliders).unwrap_or(root.y);
+                let standing = Vec3::new(root.x, ground, root.z);
+
+                ragdoll.recovery = Some((progress, fallen_position, fallen_rotation));
+
+                if progress >= 1.0 {
+                    if let Some(mut state) = state {
+                        state.0 = PlayerStates::Idle;
+                    }
+
+                    commands.entity(entity).remove::<Ragdoll>();
+                }
+
+                (
+                    fallen_position.lerp(standing, progress),
+                    fallen_rotation.slerp(ragdoll.upright, progress),
+                )
+            }
+            None => {
+                ragdoll.step(delta, &heightfields, &colliders);
+
+                let transform = ragdoll.transform(instance.0.scale);
+
+                if ragdoll.still_for > REST_SECONDS {
+                    ragdoll.recovery = Some((0.0, transform.0, transform.1));
+                }
+
+                // Held until it gets back up.
+                if animation.time != ragdoll.animation_time {
+                    animation.time = ragdoll.animation_time;
+                }
+
+                transform
+            }
+        };
+
+        if instance.0.position != position || instance.0.rotation != rotation {
+            instance.0.position = position;
+            instance.0.rotation = rotation;
+        }
+    }
+}
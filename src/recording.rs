@@ -0,0 +1,208 @@
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::Res,
+};
+
+#[cfg(feature = "wasm")]
+use superconductor::bevy_ecs::prelude::{NonSendMut, ResMut};
+
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::schedule;
+
+// Starts or stops recording a video of the canvas, which is downloaded once it's stopped. Only
+// supported on the web, where the browser's MediaRecorder encodes the canvas stream. Desktop
+// would need the renderer to read frames back, which it can't do yet.
+pub struct RecordingToggled;
+
+#[derive(Default)]
+pub struct Recording {
+    pub active: bool,
+}
+
+pub struct RecordingPlugin;
+
+impl Plugin for RecordingPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Recording::default());
+        app.add_event::<RecordingToggled>();
+
+        #[cfg(feature = "wasm")]
+        app.insert_non_send_resource(web::Recorder::default());
+
+        // With the input systems, so that photo mode can be recorded while the simulation is
+        // paused.
+        app.add_system_set(
+            schedule::input()
+                .with_system(handle_recording_key)
+                .with_system(toggle_recording),
+        );
+    }
+}
+
+fn handle_recording_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut toggles: EventWriter<RecordingToggled>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::Record) {
+            toggles.send(RecordingToggled);
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn toggle_recording(mut toggles: EventReader<RecordingToggled>) {
+    if toggles.iter().count() > 0 {
+        log::warn!("Video capture is only supported on the web so far");
+    }
+}
+
+#[cfg(feature = "wasm")]
+fn toggle_recording(
+    mut toggles: EventReader<RecordingToggled>,
+    mut recording: ResMut<Recording>,
+    mut recorder: NonSendMut<web::Recorder>,
+) {
+    for _ in toggles.iter() {
+        if recording.active {
+            recorder.stop();
+            recording.active = false;
+            continue;
+        }
+
+        match recorder.start() {
+            Ok(()) => recording.active = true,
+            Err(error) => log::warn!("Failed to start recording: {}", error),
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod web {
+    use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+
+    // In order of preference. Browsers that can't record webm, like Safari, record mp4.
+    const MIME_TYPES: [(&str, &str); 3] = [
+        ("video/webm;codecs=vp9", "webm"),
+        ("video/webm", "webm"),
+        ("video/mp4", "mp4"),
+    ];
+    const FRAME_RATE: f64 = 60.0;
+
+    #[derive(Default)]
+    pub struct Recorder {
+        recorder: Option<web_sys::MediaRecorder>,
+    }
+
+    fn js_error(error: JsValue) -> String {
+        format!("{:?}", error)
+    }
+
+    impl Recorder {
+        pub fn start(&mut self) -> Result<(), String> {
+            let document = web_sys::window().unwrap().document().unwrap();
+
+            let canvas: web_sys::HtmlCanvasElement = document
+                .query_selector("canvas:not(#mini-map)")
+                .map_err(js_error)?
+                .ok_or("There's no canvas to record")?
+                .unchecked_into();
+
+            let (mime_type, extension) = MIME_TYPES
+                .iter()
+                .copied()
+                .find(|(mime_type, _)| web_sys::MediaRecorder::is_type_supported(mime_type))
+                .ok_or("The browser can't record webm or mp4 video")?;
+
+            let stream = canvas
+                .capture_stream_with_frame_request_rate(FRAME_RATE)
+                .map_err(js_error)?;
+
+            let mut options = web_sys::MediaRecorderOptions::new();
+            options.mime_type(mime_type);
+
+            let recorder =
+                web_sys::MediaRecorder::new_with_media_stream_and_media_recorder_options(
+                    &stream, &options,
+                )
+                .map_err(js_error)?;
+
+            let chunks = js_sys::Array::new();
+
+            let ondataavailable = {
+                let chunks = chunks.clone();
+
+                Closure::wrap(Box::new(move |event: web_sys::BlobEvent| {
+                    if let Some(data) = event.data() {
+                        chunks.push(&data);
+                    }
+                }) as Box<dyn FnMut(web_sys::BlobEvent)>)
+            };
+
+            let onstop = Closure::wrap(Box::new(move || {
+                if let Err(error) = download(&chunks, mime_type, extension) {
+                    log::warn!("Failed to save the recording: {}", error);
+                }
+            }) as Box<dyn FnMut()>);
+
+            recorder.set_ondataavailable(Some(ondataavailable.as_ref().unchecked_ref()));
+            recorder.set_onstop(Some(onstop.as_ref().unchecked_ref()));
+            ondataavailable.forget();
+            onstop.forget();
+
+            recorder.start().map_err(js_error)?;
+            log::info!("Started recording the canvas as {}", mime_type);
+
+            self.recorder = Some(recorder);
+
+            Ok(())
+        }
+
+        // The video is downloaded once the recorder has handed over the last of it.
+        pub fn stop(&mut self) {
+            if let Some(recorder) = self.recorder.take() {
+                if let Err(error) = recorder.stop() {
+                    log::warn!("Failed to stop recording: {:?}", error);
+                }
+            }
+        }
+    }
+
+    fn download(chunks: &js_sys::Array, mime_type: &str, extension: &str) -> Result<(), String> {
+        let mut options = web_sys::BlobPropertyBag::new();
+        options.type_(mime_type);
+
+        let blob = web_sys::Blob::new_with_blob_sequence_and_options(chunks, &options)
+            .map_err(js_error)?;
+        let url = web_sys::Url::create_object_url_with_blob(&blob).map_err(js_error)?;
+
+        let anchor: web_sys::HtmlAnchorElement = web_sys::window()
+            .unwrap()
+            .document()
+            .unwrap()
+            .create_element("a")
+            .map_err(js_error)?
+            .unchecked_into();
+
+        anchor.set_href(&url);
+        anchor.set_download(&format!(
+            "recording-{}.{}",
+            js_sys::Date::now() as u64,
+            extension
+        ));
+        // The object url is left alive, as revoking it straight after the click cancels the
+        // download in some browsers.
+        anchor.click();
+
+        log::info!("Saved a recording of {} bytes", blob.size());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res, ResMut},
+    resources::{Camera, NewIblTextures, NewIblTexturesInner},
+    url::Url,
+};
+
+use crate::bounds::Aabb;
+use crate::downloads::{DownloadPriority, PrefetchAsset};
+use crate::schedule;
+
+// Local lighting for part of a scene, e.g. the inside of a building. While the camera is
+// inside the volume, the probe's cubemaps replace the global environment map. The renderer
+// only has the one set of image based lighting textures, so probes are switched between
+// rather than blended, and they have to be loaded as they can't be captured.
+#[derive(Component, Clone, Debug)]
+pub struct ReflectionProbe {
+    pub diffuse_cubemap: Url,
+    pub specular_cubemap: Url,
+    pub volume: Aabb,
+    // Where probes overlap, the highest priority one wins, then the smallest.
+    pub priority: i32,
+}
+
+impl ReflectionProbe {
+    fn contains(&self, point: superconductor::Vec3) -> bool {
+        point.cmpge(self.volume.min).all() && point.cmple(self.volume.max).all()
+    }
+
+    fn volume_size(&self) -> f32 {
+        let size = self.volume.size();
+        size.x * size.y * size.z
+    }
+
+    fn textures(&self) -> NewIblTexturesInner {
+        NewIblTexturesInner {
+            diffuse_cubemap: self.diffuse_cubemap.clone(),
+            specular_cubemap: self.specular_cubemap.clone(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct ActiveProbe {
+    entity: Option<Entity>,
+    // The cubemaps that were last set by something other than a probe, to go back to.
+    global: Option<(Url, Url)>,
+}
+
+pub struct ReflectionProbePlugin;
+
+impl Plugin for ReflectionProbePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(ActiveProbe::default());
+
+        app.add_system(prefetch_probe_cubemaps);
+        app.add_system_set(schedule::pre_render().with_system(apply_reflection_probes));
+    }
+}
+
+// Fetched through the download queue like the environment's cubemaps, so that their formats
+// get reported. The renderer fetches them again itself when a probe becomes active.
+fn prefetch_probe_cubemaps(
+    mut commands: Commands,
+    probe_q: Query<&ReflectionProbe, Added<ReflectionProbe>>,
+) {
+    for probe in probe_q.iter() {
+        for url in [&probe.diffuse_cubemap, &probe.specular_cubemap] {
+            commands.spawn().insert(PrefetchAsset {
+                url: url.clone(),
+                priority: DownloadPriority::Environment,
+            });
+        }
+    }
+}
+
+fn apply_reflection_probes(
+    mut active: ResMut<ActiveProbe>,
+    mut ibl: ResMut<NewIblTextures>,
+    camera: Res<Camera>,
+    probe_q: Query<(Entity, &ReflectionProbe)>,
+) {
+    let active_probe = active
+        .entity
+        .and_then(|entity| probe_q.get(entity).ok())
+        .map(|(_, probe)| probe);
+
+    // Anything new that isn't the active probe's cubemaps came from the global environment.
+    let global_changed = match &ibl.0 {
+        Some(textures) => {
+            let from_probe = active_probe.map_or(false, |probe| {
+                probe.diffuse_cubemap == textures.diffuse_cubemap
+                    && probe.specular_cubemap == textures.specular_cubemap
+            });
+
+            if !from_probe {
+                active.global = Some((
+                    textures.diffuse_cubemap.clone(),
+                    textures.specular_cubemap.clone(),
+                ));
+            }
+
+            !from_probe
+        }
+        None => false,
+    };
+
+    let wanted = probe_q
+        .iter()
+        .filter(|(_, probe)| probe.contains(camera.position))
+        .max_by(|(_, a), (_, b)| {
+            a.priority
+                .cmp(&b.priority)
+                .then(b.volume_size().total_cmp(&a.volume_size()))
+        });
+
+    match wanted {
+        Some((entity, probe)) => {
+            if active.entity != Some(entity) || global_changed {
+                ibl.0 = Some(probe.textures());
+                active.entity = Some(entity);
+            }
+        }
+        None => {
+            if active.entity.take().is_some() {
+                if let Some((diffuse_cubemap, specular_cubemap)) = active.global.clone() {
+                    ibl.0 = Some(NewIblTexturesInner {
+                        diffuse_cubemap,
+                        specular_cubemap,
+                    });
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,236 @@
+use std::collections::HashSet;
+
+use serde_json::Value;
+use superconductor::{
+    bevy_app::{App, CoreStage, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Or, Query, ResMut, With, Without},
+    components, renderer_core,
+};
+
+use crate::debug_stats::DebugStats;
+use crate::downloads::ModelInspected;
+use crate::primitives::ProceduralModel;
+use crate::schedule;
+use crate::textures::TranscodeTarget;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct GeometrySize {
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+}
+
+fn accessor_bytes(accessor: &Value) -> u64 {
+    let component_size = match accessor["componentType"].as_u64() {
+        Some(5120 | 5121) => 1,
+        Some(5122 | 5123) => 2,
+        _ => 4,
+    };
+
+    let components = match accessor["type"].as_str() {
+        Some("VEC2") => 2,
+        Some("VEC3") => 3,
+        Some("VEC4" | "MAT2") => 4,
+        Some("MAT3") => 9,
+        Some("MAT4") => 16,
+        _ => 1,
+    };
+
+    accessor["count"].as_u64().unwrap_or_default() * component_size * components
+}
+
+// How much vertex and index data the meshes of a glTF file decode to. Accessors that are
+// shared between primitives are only counted once.
+pub fn gltf_geometry_size(gltf: &Value) -> Option<GeometrySize> {
+    let mut vertex_accessors = HashSet::new();
+    let mut index_accessors = HashSet::new();
+
+    for primitive in gltf["meshes"]
+        .as_array()?
+        .iter()
+        .flat_map(|mesh| mesh["primitives"].as_array().into_iter().flatten())
+    {
+        if let Some(attributes) = primitive["attributes"].as_object() {
+            vertex_accessors.extend(attributes.values().filter_map(|index| index.as_u64()));
+        }
+
+        index_accessors.extend(primitive["indices"].as_u64());
+    }
+
+    let size = |accessors: HashSet<u64>| -> u64 {
+        accessors
+            .into_iter()
+            .map(|index| accessor_bytes(&gltf["accessors"][index as usize]))
+            .sum()
+    };
+
+    Some(GeometrySize {
+        vertex_bytes: size(vertex_accessors),
+        index_bytes: size(index_accessors),
+    })
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+pub struct ModelGeometry(pub GeometrySize);
+
+// Wall clock timings of the last frame. `update_ms` is the time spent running systems, and
+// `render_ms` is everything between the end of one update and the start of the next, which
+// is recording and submitting the frame, presenting it and any frame limiter sleep. When
+// frames are slow and that's most of it, the frame is more likely to be gpu bound.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FrameTimings {
+    pub frame_ms: f32,
+    pub update_ms: f32,
+    pub render_ms: f32,
+}
+
+// Estimates of what the loaded scene occupies on the gpu, worked out from the assets rather
+// than read back from renderer_core, which doesn't expose its allocations. Only KTX2 textures
+// are counted, and bind groups aren't.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    pub texture_bytes: u64,
+    pub vertex_bytes: u64,
+    pub index_bytes: u64,
+    pub loaded_models: usize,
+    pub instances: usize,
+    pub instance_bytes: u64,
+    pub timings: FrameTimings,
+}
+
+// The transcode targets are all block compressed to a byte per pixel, textures that aren't
+// transcoded are counted as uncompressed, and the mip chain adds another third.
+fn texture_bytes(width: u32, height: u32, format: Option<TranscodeTarget>) -> u64 {
+    let bytes_per_pixel = match format {
+        Some(_) => 1,
+        None => 4,
+    };
+
+    width as u64 * height as u64 * bytes_per_pixel * 4 / 3
+}
+
+pub struct RenderStatsPlugin;
+
+impl Plugin for RenderStatsPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RenderStats::default());
+        app.insert_resource(FrameClock::default());
+        app.init_resource::<DebugStats>();
+
+        app.add_system_to_stage(CoreStage::First, start_update_timer);
+        app.add_system_to_stage(CoreStage::Last, finish_update_timer);
+
+        app.add_system(insert_model_geometry);
+        app.add_system_set(schedule::pre_render().with_system(update_render_stats));
+    }
+}
+
+fn insert_model_geometry(
+    mut commands: Commands,
+    mut inspected: EventReader<ModelInspected>,
+    model_q: Query<(), With<components::Instances>>,
+    procedural_q: Query<(Entity, &ProceduralModel), Without<ModelGeometry>>,
+) {
+    for model in inspected.iter() {
+        if model_q.get(model.entity).is_err() {
+            continue;
+        }
+
+        if let Some(size) = gltf_geometry_size(&model.document.json) {
+            commands.entity(model.entity).insert(ModelGeometry(size));
+        }
+    }
+
+    for (entity, model) in procedural_q.iter() {
+        let mesh = model.primitive.mesh();
+
+        commands.entity(entity).insert(ModelGeometry(GeometrySize {
+            // Positions and normals, then uvs.
+            vertex_bytes: mesh.positions.len() as u64 * (12 + 12 + 8),
+            index_bytes: mesh.indices.len() as u64 * 4,
+        }));
+    }
+}
+
+fn update_render_stats(
+    mut stats: ResMut<RenderStats>,
+    mut debug_stats: ResMut<DebugStats>,
+    loaded_q: Query<&ModelGeometry, Or<(With<components::Model>, With<components::AnimatedModel>)>>,
+    instance_q: Query<(), With<components::InstanceOf>>,
+) {
+    let _span = tracing::info_span!("update_render_stats").entered();
+
+    let geometry = loaded_q
+        .iter()
+        .fold(GeometrySize::default(), |total, model| GeometrySize {
+            vertex_bytes: total.vertex_bytes + model.0.vertex_bytes,
+            index_bytes: total.index_bytes + model.0.index_bytes,
+        });
+
+    let instances = instance_q.iter().count();
+
+    *stats = RenderStats {
+        texture_bytes: debug_stats
+            .textures
+            .values()
+            .map(|texture| texture_bytes(texture.info.width, texture.info.height, texture.format))
+            .sum(),
+        vertex_bytes: geometry.vertex_bytes,
+        index_bytes: geometry.index_bytes,
+        loaded_models: loaded_q.iter().count(),
+        instances,
+        instance_bytes: (instances * std::mem::size_of::<renderer_core::Instance>()) as u64,
+        timings: stats.timings,
+    };
+
+    debug_stats.render = *stats;
+}
+
+// In milliseconds.
+#[derive(Default)]
+struct FrameClock {
+    update_start: Option<f64>,
+    update_end: Option<f64>,
+}
+
+pub(crate) fn now_ms() -> f64 {
+    #[cfg(not(feature = "wasm"))]
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs_f64()
+        * 1000.0;
+
+    #[cfg(feature = "wasm")]
+    let now = js_sys::Date::now();
+
+    now
+}
+
+fn start_update_timer(mut clock: ResMut<FrameClock>, mut stats: ResMut<RenderStats>) {
+    let now = now_ms();
+
+    if let Some(update_start) = clock.update_start {
+        stats.timings.frame_ms = (now - update_start) as f32;
+    }
+
+    if let Some(update_end) = clock.update_end {
+        stats.timings.render_ms = (now - update_end).max(0.0) as f32;
+    }
+
+    clock.update_start = Some(now);
+}
+
+fn finish_update_timer(
+    mut clock: ResMut<FrameClock>,
+    mut stats: ResMut<RenderStats>,
+    mut debug_stats: ResMut<DebugStats>,
+) {
+    let now = now_ms();
+
+    if let Some(update_start) = clock.update_start {
+        stats.timings.update_ms = (now - update_start).max(0.0) as f32;
+    }
+
+    clock.update_end = Some(now);
+    debug_stats.render.timings = stats.timings;
+}
@@ -0,0 +1,148 @@
+use std::collections::BTreeMap;
+
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Local, Res, ResMut},
+};
+
+use crate::networking::NetClient;
+use crate::protocol::{ClientMessage, PlayerId, ServerMessage};
+use crate::render_stats::now_ms;
+use crate::schedule;
+
+// The player values that the playground sets itself.
+pub const NAME_KEY: &str = "name";
+pub const PING_KEY: &str = "ping";
+
+const PING_INTERVAL_MS: f64 = 2000.0;
+
+// Key-value state shared by everyone in the room, both for the room as a whole and for each
+// player, kept up to date by the server. Anyone can set the room's values and their own with
+// `set_room_value` and `set_player_value`, for scores, teams and the like. Each player's name
+// (PLAYER_NAME on desktop, ?name= on the web) and ping are set automatically.
+#[derive(Default)]
+pub struct RoomState {
+    pub values: BTreeMap<String, String>,
+    pub players: BTreeMap<PlayerId, BTreeMap<String, String>>,
+    // The last round trip time to the server.
+    pub ping_ms: Option<f64>,
+}
+
+impl RoomState {
+    pub fn player_value(&self, player_id: PlayerId, key: &str) -> Option<&str> {
+        self.players
+            .get(&player_id)
+            .and_then(|values| values.get(key))
+            .map(|value| value.as_str())
+    }
+
+    pub fn player_name(&self, player_id: PlayerId) -> String {
+        self.player_value(player_id, NAME_KEY)
+            .map(|name| name.to_string())
+            .unwrap_or_else(|| format!("Player {}", player_id))
+    }
+}
+
+pub fn set_room_value(client: &NetClient, key: &str, value: Option<String>) {
+    client.send(ClientMessage::SetRoomValue {
+        key: key.to_string(),
+        value,
+    });
+}
+
+pub fn set_player_value(client: &NetClient, key: &str, value: Option<String>) {
+    client.send(ClientMessage::SetPlayerValue {
+        key: key.to_string(),
+        value,
+    });
+}
+
+struct PlayerName(Option<String>);
+
+impl PlayerName {
+    fn from_env() -> Self {
+        #[cfg(not(feature = "wasm"))]
+        let name = std::env::var("PLAYER_NAME").ok();
+
+        #[cfg(feature = "wasm")]
+        let name = crate::query_parameter("name");
+
+        Self(name.filter(|name| !name.trim().is_empty()))
+    }
+}
+
+pub struct RoomStatePlugin;
+
+impl Plugin for RoomStatePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(RoomState::default());
+        app.insert_resource(PlayerName::from_env());
+
+        app.add_system_set(schedule::input().with_system(receive_room_state));
+        app.add_system_set(schedule::pre_render().with_system(send_pings));
+    }
+}
+
+fn receive_room_state(
+    mut server_messages: EventReader<ServerMessage>,
+    mut room_state: ResMut<RoomState>,
+    client: Res<NetClient>,
+    name: Res<PlayerName>,
+) {
+    for message in server_messages.iter() {
+        match message {
+            ServerMessage::Joined { player_id, .. } => {
+                *room_state = RoomState::default();
+                room_state.players.insert(*player_id, BTreeMap::new());
+
+                if let Some(name) = &name.0 {
+                    set_player_value(&client, NAME_KEY, Some(name.clone()));
+                }
+            }
+            ServerMessage::PlayerJoined { player_id } => {
+                room_state.players.entry(*player_id).or_default();
+            }
+            ServerMessage::PlayerLeft { player_id } => {
+                room_state.players.remove(player_id);
+            }
+            ServerMessage::RoomValue { key, value } => match value {
+                Some(value) => {
+                    room_state.values.insert(key.clone(), value.clone());
+                }
+                None => {
+                    room_state.values.remove(key);
+                }
+            },
+            ServerMessage::PlayerValue {
+                player_id,
+                key,
+                value,
+            } => {
+                let values = room_state.players.entry(*player_id).or_default();
+
+                match value {
+                    Some(value) => values.insert(key.clone(), value.clone()),
+                    None => values.remove(key),
+                };
+            }
+            ServerMessage::Pong { time } => {
+                let ping = (now_ms() - time).max(0.0);
+                room_state.ping_ms = Some(ping);
+                set_player_value(&client, PING_KEY, Some(format!("{:.0}", ping)));
+            }
+            _ => {}
+        }
+    }
+}
+
+// `last_ping` is when the last ping was sent.
+fn send_pings(client: Res<NetClient>, mut last_ping: Local<f64>) {
+    let now = now_ms();
+
+    if client.room.is_none() || now - *last_ping < PING_INTERVAL_MS {
+        return;
+    }
+
+    *last_ping = now;
+    client.send(ClientMessage::Ping { time: now });
+}
@@ -0,0 +1,467 @@
+use std::collections::HashMap;
+
+use serde_json::Value;
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Query, Res, With, Without},
+    components,
+    renderer_core::glam::{Mat4, Quat},
+    Vec3,
+};
+
+use crate::bounds::node_transform;
+use crate::downloads::ModelInspected;
+use crate::mesh_compression::GltfDocument;
+use crate::{schedule, PlayerState, PlayerStates, RemotePlayer};
+
+const FLOAT: u64 = 5126;
+const UNSIGNED_BYTE: u64 = 5121;
+const UNSIGNED_SHORT: u64 = 5123;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RootMotionMode {
+    // The clip moves the skeleton away from the entity, as it was authored.
+    InPlace,
+    // The clip's horizontal movement and turning move the entity instead, and are taken back
+    // out of the pose. Vertical movement stays in the pose, so that jumps still leave the
+    // ground.
+    Extract,
+}
+
+// Which player states take their root motion from the clip.
+pub struct RootMotionStates(HashMap<PlayerStates, RootMotionMode>);
+
+impl Default for RootMotionStates {
+    fn default() -> Self {
+        Self(HashMap::from([
+            (PlayerStates::SprinttoRoll, RootMotionMode::Extract),
+            (PlayerStates::RunningJump, RootMotionMode::Extract),
+        ]))
+    }
+}
+
+impl RootMotionStates {
+    pub fn mode(&self, state: PlayerStates) -> RootMotionMode {
+        self.0
+            .get(&state)
+            .copied()
+            .unwrap_or(RootMotionMode::InPlace)
+    }
+
+    pub fn set(&mut self, state: PlayerStates, mode: RootMotionMode) {
+        self.0.insert(state, mode);
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Interpolation {
+    Step,
+    Linear,
+    // The tangents are skipped and the keyframes interpolated linearly.
+    CubicSpline,
+}
+
+// The keyframes of an animation sampler, with any number of values at each time.
+#[derive(Clone, Debug)]
+struct Keyframes {
+    interpolation: Interpolation,
+    times: Vec<f32>,
+    values: Vec<f32>,
+}
+
+impl Keyframes {
+    fn read(gltf: &Value, bin: Option<&[u8]>, sampler: &Value) -> Option<Self> {
+        Some(Self {
+            interpolation: match sampler["interpolation"].as_str() {
+                Some("STEP") => Interpolation::Step,
+                Some("CUBICSPLINE") => Interpolation::CubicSpline,
+                _ => Interpolation::Linear,
+            },
+            times: read_floats(gltf, bin, sampler["input"].as_u64()? as usize)?,
+            values: read_floats(gltf, bin, sampler["output"].as_u64()? as usize)?,
+        })
+    }
+
+    fn duration(&self) -> f32 {
+        self.times.last().copied().unwrap_or_default()
+    }
+
+    fn keyframe(&self, index: usize, count: usize) -> &[f32] {
+        let index = match self.interpolation {
+            Interpolation::CubicSpline => index * 3 + 1,
+            _ => index,
+        };
+
+        self.values
+            .get(index * count..(index + 1) * count)
+            .unwrap_or_default()
+    }
+
+    // Fills `output` with the values at the time, one for each of its elements.
+    fn sample(&self, time: f32, output: &mut [f32]) {
+        let count = output.len();
+
+        let next = self.times.partition_point(|&keyframe| keyframe <= time);
+
+        if next == 0 || next == self.times.len() {
+            let index = next.saturating_sub(1);
+            output.copy_from_slice(&pad(self.keyframe(index, count), count));
+            return;
+        }
+
+        let (from_time, to_time) = (self.times[next - 1], self.times[next]);
+        let from = pad(self.keyframe(next - 1, count), count);
+
+        if self.interpolation == Interpolation::Step {
+            output.copy_from_slice(&from);
+            return;
+        }
+
+        let to = pad(self.keyframe(next, count), count);
+        let t = ((time - from_time) / (to_time - from_time).max(f32::EPSILON)).clamp(0.0, 1.0);
+
+        for ((value, from), to) in output.iter_mut().zip(from).zip(to) {
+            *value = from + (to - from) * t;
+        }
+    }
+}
+
+fn pad(values: &[f32], count: usize) -> Vec<f32> {
+    let mut values = values.to_vec();
+    values.resize(count, 0.0);
+    values
+}
+
+// Reads a float accessor, or a normalized unsigned one. Only
+// accessors in a glb's binary chunk can be read, as other buffers would need fetching first.
+fn read_floats(gltf: &Value, bin: Option<&[u8]>, index: usize) -> Option<Vec<f32>> {
+    let accessor = &gltf["accessors"][index];
+    let view = &gltf["bufferViews"][accessor["bufferView"].as_u64()? as usize];
+
+    if view["buffer"].as_u64().unwrap_or(0) != 0 || !gltf["buffers"][0]["uri"].is_null() {
+        return None;
+    }
+
+    let bin = bin?;
+
+    let components = match accessor["type"].as_str()? {
+        "SCALAR" => 1,
+        "VEC2" => 2,
+        "VEC3" => 3,
+        "VEC4" => 4,
+        _ => return None,
+    };
+
+    let (size, read): (usize, fn(&[u8]) -> f32) = match accessor["componentType"].as_u64()? {
+        FLOAT => (4, |bytes| f32::from_le_bytes(bytes.try_into().unwrap())),
+        UNSIGNED_BYTE => (1, |bytes| bytes[0] as f32 / 255.0),
+        UNSIGNED_SHORT => (2, |bytes| {
+            u16::from_le_bytes(bytes.try_into().unwrap()) as f32 / 65535.0
+        }),
+        _ => return None,
+    };
+
+    let count = accessor["count"].as_u64()? as usize;
+    let offset = view["byteOffset"].as_u64().unwrap_or(0) as usize
+        + accessor["byteOffset"].as_u64().unwrap_or(0) as usize;
+    let stride = view["byteStride"]
+        .as_u64()
+        .map_or(size * components, |stride| stride as usize);
+
+    let mut values = Vec::with_capacity(count * components);
+
+    for element in 0..count {
+        for component in 0..components {
+            let start = offset + element * stride + component * size;
+            values.push(read(bin.get(start..start + size)?));
+        }
+    }
+
+    Some(values)
+}
+
+// The root bone's channels in one of a model's animations.
+#[derive(Clone, Debug)]
+pub struct RootTrack {
+    // From the root bone's parent space to model space.
+    parent: Mat4,
+    translation: Option<Keyframes>,
+    rotation: Option<Keyframes>,
+}
+
+impl RootTrack {
+    fn duration(&self) -> f32 {
+        self.translation
+            .iter()
+            .chain(&self.rotation)
+            .map(|keyframes| keyframes.duration())
+            .fold(0.0, f32::max)
+    }
+
+    // The root's horizontal position and its turn about +Y, in model space.
+    fn sample(&self, time: f32) -> (Vec3, Quat) {
+        let position = self.translation.as_ref().map_or(Vec3::ZERO, |keyframes| {
+            let mut value = [0.0; 3];
+            keyframes.sample(time, &mut value);
+            self.parent.transform_point3(Vec3::from(value))
+        });
+
+        let rotation = self.rotation.as_ref().map_or(Quat::IDENTITY, |keyframes| {
+            let mut value = [0.0, 0.0, 0.0, 1.0];
+            keyframes.sample(time, &mut value);
+            let (_, parent, _) = self.parent.to_scale_rotation_translation();
+            parent * Quat::from_array(value).normalize()
+        });
+
+        let forward = rotation * Vec3::Z;
+
+        (
+            Vec3::new(position.x, 0.0, position.z),
+            Quat::from_rotation_y(forward.x.atan2(forward.z)),
+        )
+    }
+}
+
+// The root bone tracks of a model's animations, in the same order.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ModelRootMotion(pub Vec<Option<RootTrack>>);
+
+impl ModelRootMotion {
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|track| track.is_none())
+    }
+}
+
+// The topmost joint of the first skin, like a Mixamo rig's hips.
+fn root_joint(gltf: &Value) -> Option<usize> {
+    let joints: Vec<usize> = gltf["skins"][0]["joints"]
+        .as_array()?
+        .iter()
+        .filter_map(|joint| joint.as_u64())
+        .map(|joint| joint as usize)
+        .collect();
+
+    joints
+        .iter()
+        .copied()
+        .find(|&joint| parent(gltf, joint).map_or(true, |parent| !joints.contains(&parent)))
+}
+
+fn parent(gltf: &Value, node: usize) -> Option<usize> {
+    gltf["nodes"].as_array()?.iter().position(|other| {
+        other["children"]
+            .as_array()
+            .map_or(false, |children| children.iter().any(|child| child == node))
+    })
+}
+
+// From a node's parent's space to the scene's.
+pub(crate) fn parent_transform(gltf: &Value, node: usize) -> Mat4 {
+    let mut transform = Mat4::IDENTITY;
+    let mut current = parent(gltf, node);
+
+    // Guards against cycles in broken files.
+    for _ in 0..64 {
+        let node = match current {
+            Some(node) => node,
+            None => break,
+        };
+
+        transform = node_transform(&gltf["nodes"][node]) * transform;
+        current = parent(gltf, node);
+    }
+
+    transform
+}
+
+// The root bone's translation and rotation channels in each of a glTF file's animations.
+pub fn gltf_root_motion(document: &GltfDocument) -> ModelRootMotion {
+    let gltf = &document.json;
+
+    let root = match root_joint(gltf) {
+        Some(root) => root,
+        None => return ModelRootMotion::default(),
+    };
+
+    let parent = parent_transform(gltf, root);
+
+    let tracks = gltf["animations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|animation| {
+            let channel = |path: &str| {
+                let channel = animation["channels"].as_array()?.iter().find(|channel| {
+                    channel["target"]["node"] == root && channel["target"]["path"] == path
+                })?;
+
+                let sampler = &animation["samplers"][channel["sampler"].as_u64()? as usize];
+                Keyframes::read(gltf, document.bin.as_deref(), sampler)
+            };
+
+            let track = RootTrack {
+                parent,
+                translation: channel("translation"),
+                rotation: channel("rotation"),
+            };
+
+            if track.translation.is_none() && track.rotation.is_none() {
+                None
+            } else {
+                Some(track)
+            }
+        })
+        .collect();
+
+    ModelRootMotion(tracks)
+}
+
+// The root motion taken out of a player's pose, as offsets from where the player really is.
+// Whatever else moves the player keeps working with the real position, which is recovered
+// from the instance each frame.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct RootMotion {
+    position_offset: Vec3,
+    rotation_offset: Quat,
+    // The animation and clip time of the last frame.
+    previous: Option<(usize, f32)>,
+}
+
+impl Default for RootMotion {
+    fn default() -> Self {
+        Self {
+            position_offset: Vec3::ZERO,
+            rotation_offset: Quat::IDENTITY,
+            previous: None,
+        }
+    }
+}
+
+pub struct RootMotionPlugin;
+
+impl Plugin for RootMotionPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<RootMotionStates>();
+
+        app.add_system(insert_model_root_motion);
+        // Moves the player, so it's part of the simulation.
+        app.add_system_set(schedule::simulation().with_system(apply_root_motion));
+    }
+}
+
+fn insert_model_root_motion(
+    mut commands: Commands,
+    mut inspected: EventReader<ModelInspected>,
+    model_q: Query<(), With<components::Instances>>,
+) {
+    for model in inspected.iter() {
+        if model_q.get(model.entity).is_err() {
+            continue;
+        }
+
+        let root_motion = gltf_root_motion(&model.document);
+
+        if !root_motion.is_empty() {
+            commands.entity(model.entity).insert(root_motion);
+        }
+    }
+}
+
+// Remote players are left alone, as the position they send already has their root motion in
+// it.
+#[allow(clippy::type_complexity)]
+fn apply_root_motion(
+    mut commands: Commands,
+    states: Res<RootMotionStates>,
+    model_q: Query<&ModelRootMotion>,
+    mut player_q: Query<
+        (
+            Entity,
+            &PlayerState,
+            &components::InstanceOf,
+            &components::AnimationState,
+            &mut components::Instance,
+            Option<&mut RootMotion>,
+        ),
+        Without<RemotePlayer>,
+    >,
+) {
+    for (entity, state, instance_of, animation, mut instance, root_motion) in player_q.iter_mut() {
+        let mut current = root_motion.as_deref().copied().unwrap_or_default();
+
+        let mut rotation = instance.0.rotation * current.rotation_offset.inverse();
+        let mut position = instance.0.position - current.position_offset;
+
+        let track = model_q
+            .get(instance_of.0)
+            .ok()
+            .and_then(|model| model.0.get(animation.animation_index)?.as_ref())
+            .filter(|track| track.duration() > 0.0)
+            .filter(|_| states.mode(state.0) == RootMotionMode::Extract);
+
+        match track {
+            Some(track) => {
+                let duration = track.duration();
+                let time = animation.time % duration;
+                let scale = instance.0.scale;
+                let (start, start_yaw) = track.sample(0.0);
+
+                // Moves the player along the clip from one time to a later one.
+                let mut advance = |from: f32, to: f32| {
+                    let (from_position, from_yaw) = track.sample(from);
+                    let (to_position, to_yaw) = track.sample(to);
+                    // Relative to how the clip has turned since it started.
+                    let turned = from_yaw * start_yaw.inverse();
+
+                    position +=
+                        rotation * (turned.inverse() * (to_position - from_position) * scale);
+                    rotation *= (to_yaw * from_yaw.inverse()).normalize();
+                };
+
+                let previous = current
+                    .previous
+                    .filter(|(index, _)| *index == animation.animation_index);
+
+                if let Some((_, previous)) = previous {
+                    if time >= previous {
+                        advance(previous, time);
+                    } else {
+                        // Looped.
+                        advance(previous, duration);
+                        advance(0.0, time);
+                    }
+                }
+
+                let (root, yaw) = track.sample(time);
+                let turned = yaw * start_yaw.inverse();
+
+                current.rotation_offset = turned.inverse();
+                current.position_offset =
+                    -(rotation * current.rotation_offset) * ((root - start) * scale);
+                current.previous = Some((animation.animation_index, time));
+            }
+            None => {
+                current = RootMotion::default();
+            }
+        }
+
+        let new_position = position + current.position_offset;
+        let new_rotation = rotation * current.rotation_offset;
+
+        if instance.0.position != new_position || instance.0.rotation != new_rotation {
+            instance.0.position = new_position;
+            instance.0.rotation = new_rotation;
+        }
+
+        match root_motion {
+            Some(mut root_motion) => {
+                if *root_motion != current {
+                    *root_motion = current;
+                }
+            }
+            None => {
+                commands.entity(entity).insert(current);
+            }
+        }
+    }
+}
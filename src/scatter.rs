@@ -0,0 +1,353 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::{Quat, Vec2},
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::AssetError;
+use crate::determinism::SimRng;
+use crate::downloads::DownloadManager;
+use crate::schedule;
+use crate::terrain::Heightfield;
+use crate::wind::WindField;
+
+// Instances start shrinking away at this fraction of the fade distance.
+const FADE_START: f32 = 0.8;
+// The furthest swaying instances lean over, in radians.
+const MAX_LEAN: f32 = 0.6;
+
+// Scatters instances of a model, such as grass, rocks or trees, over a square area. Each one
+// gets a random position, yaw and scale from the seed, so the same seed always gives the same
+// layout. Instances have no opacity, so they shrink away past the fade distance instead of
+// fading. Changing it after it's been added does nothing.
+#[derive(Component, Clone, Debug)]
+pub struct Scatter {
+    // A model entity, like one from `spawn_primitive`.
+    pub model: Entity,
+    pub center: Vec3,
+    // The width and depth of the area, in meters.
+    pub size: f32,
+    // Instances per square meter, before the density map and the cap.
+    pub density: f32,
+    // A grayscale image over the area, where white is full density and black is none.
+    pub density_map: Option<Url>,
+    pub seed: u64,
+    pub scale: (f32, f32),
+    pub fade_distance: f32,
+    pub max_instances: usize,
+    // An entity with a Heightfield, like a terrain, to put the instances on. They're at the
+    // center's height otherwise.
+    pub surface: Option<Entity>,
+    // How far instances lean over in the wind, in radians per meter per second of it. There's
+    // no vegetation shader to bend them, so they're tilted as a whole about their base. Zero,
+    // the default, keeps rocks and such still.
+    pub sway: f32,
+}
+
+impl Scatter {
+    pub fn new(model: Entity, center: Vec3, size: f32, density: f32) -> Self {
+        Self {
+            model,
+            center,
+            size,
+            density,
+            density_map: None,
+            seed: 0,
+            scale: (0.8, 1.2),
+            fade_distance: 50.0,
+            max_instances: 10_000,
+            surface: None,
+            sway: 0.0,
+        }
+    }
+}
+
+#[derive(Component)]
+struct DensityMap {
+    width: usize,
+    height: usize,
+    values: Vec<f32>,
+}
+
+impl DensityMap {
+    // `uv` is from 0 to 1 across the area.
+    fn sample(&self, uv: Vec2) -> f32 {
+        let x = ((uv.x * self.width as f32) as usize).min(self.width - 1);
+        let y = ((uv.y * self.height as f32) as usize).min(self.height - 1);
+        self.values[y * self.width + x]
+    }
+}
+
+fn decode_density_map(bytes: &[u8]) -> Result<DensityMap, AssetError> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|error| AssetError::Decode(error.to_string()))?
+        .to_luma8();
+
+    Ok(DensityMap {
+        width: image.width() as usize,
+        height: image.height() as usize,
+        values: image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / 255.0)
+            .collect(),
+    })
+}
+
+struct ScatteredInstance {
+    entity: Entity,
+    position: Vec3,
+    scale: f32,
+    rotation: Quat,
+}
+
+struct ScatteredInstances {
+    fade_distance: f32,
+    sway: f32,
+    instances: Vec<ScatteredInstance>,
+}
+
+// The instances of each scatter.
+#[derive(Default)]
+struct Scattered(HashMap<Entity, ScatteredInstances>);
+
+struct DensityMaps {
+    sender: Sender<(Entity, DensityMap)>,
+    receiver: Receiver<(Entity, DensityMap)>,
+}
+
+impl Default for DensityMaps {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self { sender, receiver }
+    }
+}
+
+pub struct ScatterPlugin;
+
+impl Plugin for ScatterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Scattered::default());
+        app.insert_resource(DensityMaps::default());
+
+        app.add_system(load_density_maps);
+        app.add_system(receive_density_maps);
+        app.add_system(scatter_instances);
+        app.add_system(despawn_scattered_instances);
+        app.add_system_set(schedule::simulation().with_system(sway_scattered_instances));
+        app.add_system_set(schedule::pre_render().with_system(fade_scattered_instances));
+    }
+}
+
+fn load_density_maps(
+    density_maps: Res<DensityMaps>,
+    manager: Res<DownloadManager>,
+    scatter_q: Query<(Entity, &Scatter), Added<Scatter>>,
+) {
+    for (entity, scatter) in scatter_q.iter() {
+        let url = match &scatter.density_map {
+            Some(url) => url.clone(),
+            None => continue,
+        };
+
+        let cache = manager.cache();
+        let sender = density_maps.sender.clone();
+
+        manager.spawn(async move {
+            let result = match cache.fetch(&url).await {
+                Ok(bytes) => decode_density_map(&bytes),
+                Err(error) => Err(error),
+            };
+
+            match result {
+                Ok(density_map) => {
+                    let _ = sender.send((entity, density_map));
+                }
+                Err(error) => log::warn!("Failed to load the density map {}: {}", url, error),
+            }
+        });
+    }
+}
+
+fn receive_density_maps(
+    mut commands: Commands,
+    density_maps: Res<DensityMaps>,
+    scatter_q: Query<(), With<Scatter>>,
+) {
+    for (entity, density_map) in density_maps.receiver.try_iter() {
+        // Unless it was removed while the map was loading.
+        if scatter_q.get(entity).is_ok() {
+            commands.entity(entity).insert(density_map);
+        }
+    }
+}
+
+fn scatter_instances(
+    mut commands: Commands,
+    mut scattered: ResMut<Scattered>,
+    scatter_q: Query<(Entity, &Scatter, Option<&DensityMap>)>,
+    heightfield_q: Query<&Heightfield>,
+) {
+    for (entity, scatter, density_map) in scatter_q.iter() {
+        if scattered.0.contains_key(&entity) {
+            continue;
+        }
+
+        // Waits for the density map to be loaded and the surface to be built.
+        if scatter.density_map.is_some() && density_map.is_none() {
+            continue;
+        }
+
+        let heightfield = match scatter.surface {
+            Some(surface) => match heightfield_q.get(surface) {
+                Ok(heightfield) => Some(heightfield),
+                Err(_) => continue,
+            },
+            None => None,
+        };
+
+        let _span = tracing::info_span!("scatter_instances").entered();
+
+        let mut rng = SimRng::new(scatter.seed);
+
+        let candidates =
+            ((scatter.size * scatter.size * scatter.density) as usize).min(scatter.max_instances);
+
+        let mut instances = Vec::new();
+
+        for _ in 0..candidates {
+            let uv = Vec2::new(rng.next_f32(), rng.next_f32());
+            let yaw = rng.range(0.0, TAU);
+            let scale = rng.range(scatter.scale.0, scatter.scale.1);
+            let keep = rng.next_f32();
+
+            if let Some(density_map) = density_map {
+                if keep >= density_map.sample(uv) {
+                    continue;
+                }
+            }
+
+            let offset = (uv - Vec2::splat(0.5)) * scatter.size;
+            let mut position = scatter.center + Vec3::new(offset.x, 0.0, offset.y);
+
+            if let Some(heightfield) = heightfield {
+                match heightfield.height_at(position.x, position.z) {
+                    Some(height) => position.y = height,
+                    None => continue,
+                }
+            }
+
+            let rotation = Quat::from_rotation_y(yaw);
+
+            let instance = commands
+                .spawn()
+                .insert(components::InstanceOf(scatter.model))
+                .insert(components::Instance(renderer_core::Instance::new(
+                    position, scale, rotation,
+                )))
+                .id();
+
+            instances.push(ScatteredInstance {
+                entity: instance,
+                position,
+                scale,
+                rotation,
+            });
+        }
+
+        log::info!("Scattered {} instances", instances.len());
+
+        scattered.0.insert(
+            entity,
+            ScatteredInstances {
+                fade_distance: scatter.fade_distance,
+                sway: scatter.sway,
+                instances,
+            },
+        );
+    }
+}
+
+fn despawn_scattered_instances(
+    mut commands: Commands,
+    mut scattered: ResMut<Scattered>,
+    scatter_q: Query<&Scatter>,
+) {
+    scattered.0.retain(|&scatter, scattered| {
+        let keep = scatter_q.get(scatter).is_ok();
+
+        if !keep {
+            for instance in &scattered.instances {
+                commands.entity(instance.entity).despawn();
+            }
+        }
+
+        keep
+    });
+}
+
+fn fade_scattered_instances(
+    camera: Res<Camera>,
+    scattered: Res<Scattered>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    for scatter in scattered.0.values() {
+        let fade_distance = scatter.fade_distance;
+        let fade_start = fade_distance * FADE_START;
+
+        for scattered in &scatter.instances {
+            let distance = camera.position.distance(scattered.position);
+            let fade = 1.0
+                - ((distance - fade_start) / (fade_distance - fade_start).max(f32::EPSILON))
+                    .clamp(0.0, 1.0);
+            let scale = scattered.scale * fade;
+
+            if let Ok(mut instance) = instance_q.get_mut(scattered.entity) {
+                // Only touched when something changed, so that the renderer isn't sent every
+                // instance again each frame.
+                if instance.0.scale != scale {
+                    instance.0.scale = scale;
+                }
+            }
+        }
+    }
+}
+
+// Leans the instances that sway away from the wind, as far as it's blowing where each one is.
+// Ones that have faded away are left as they are.
+fn sway_scattered_instances(
+    camera: Res<Camera>,
+    wind: Res<WindField>,
+    scattered: Res<Scattered>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    let axis = Vec3::Y.cross(wind.wind.heading());
+
+    for scatter in scattered.0.values() {
+        if scatter.sway <= 0.0 {
+            continue;
+        }
+
+        for scattered in &scatter.instances {
+            if camera.position.distance(scattered.position) > scatter.fade_distance {
+                continue;
+            }
+
+            let lean = (wind.velocity_at(scattered.position).length() * scatter.sway).min(MAX_LEAN);
+            let rotation = Quat::from_axis_angle(axis, lean) * scattered.rotation;
+
+            if let Ok(mut instance) = instance_q.get_mut(scattered.entity) {
+                if instance.0.rotation != rotation {
+                    instance.0.rotation = rotation;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use superconductor::bevy_ecs::{
+    prelude::{Local, Res, ResMut},
+    schedule::{ShouldRun, SystemLabel, SystemSet},
+};
+
+// Known points in the update stage, for other plugins to put their systems before or after.
+// They run in the order listed. Systems that don't care about ordering, like the download
+// and asset systems, aren't labelled.
+#[derive(SystemLabel, Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PlaygroundSystem {
+    // Keyboard input and messages from the server.
+    Input,
+    // Moving the camera, the player and everything else around.
+    Simulation,
+    // Picking the animation to play for the state the simulation left things in.
+    AnimationSync,
+    // Moving the camera to where the simulation left the rig. Unlike the simulation, it keeps
+    // running while paused.
+    Camera,
+    // Reading the final state of the frame: bounds, render stats and network updates.
+    PreRender,
+}
+
+// While paused, the simulation and animation sync are skipped, freezing the scene. Input,
+// camera and pre render systems keep running.
+#[derive(Default)]
+pub struct SimulationPaused(pub bool);
+
+// Runs a paused simulation and animation sync for a single frame. Setting `requested` steps
+// the next frame, as the current one may have already skipped them.
+#[derive(Default)]
+pub struct FrameStep {
+    pub requested: bool,
+    stepping: bool,
+}
+
+impl FrameStep {
+    pub fn is_stepping(&self) -> bool {
+        self.stepping
+    }
+}
+
+pub fn start_frame_step(mut frame_step: ResMut<FrameStep>) {
+    if frame_step.requested || frame_step.stepping {
+        frame_step.stepping = std::mem::take(&mut frame_step.requested);
+    }
+}
+
+// How many times the simulation and animation sync run each frame. The frame limiter raises it
+// while frames are capped below 60 fps, so that the simulation keeps to real time.
+pub struct SimulationSteps(pub u32);
+
+impl Default for SimulationSteps {
+    fn default() -> Self {
+        Self(1)
+    }
+}
+
+// Runs the simulation and animation sync as many times as `SimulationSteps` asks for. Like
+// bevy's fixed timestep, it asks to be checked again after each run until the frame's steps
+// are used up. A frame step while paused only ever takes one.
+fn simulation_running(
+    paused: Option<Res<SimulationPaused>>,
+    frame_step: Option<Res<FrameStep>>,
+    steps_per_frame: Option<Res<SimulationSteps>>,
+    mut remaining_steps: Local<Option<u32>>,
+) -> ShouldRun {
+    let steps = match remaining_steps.take() {
+        Some(steps) => steps,
+        None => {
+            let paused = paused.map_or(false, |paused| paused.0);
+            let stepping = frame_step.map_or(false, |frame_step| frame_step.stepping);
+
+            if paused && !stepping {
+                return ShouldRun::No;
+            }
+
+            match steps_per_frame {
+                Some(steps_per_frame) if !paused => steps_per_frame.0,
+                _ => 1,
+            }
+        }
+    };
+
+    if steps > 1 {
+        *remaining_steps = Some(steps - 1);
+        ShouldRun::YesAndCheckAgain
+    } else {
+        ShouldRun::Yes
+    }
+}
+
+pub fn input() -> SystemSet {
+    SystemSet::new().label(PlaygroundSystem::Input)
+}
+
+pub fn simulation() -> SystemSet {
+    SystemSet::new()
+        .label(PlaygroundSystem::Simulation)
+        .after(PlaygroundSystem::Input)
+        .with_run_criteria(simulation_running)
+}
+
+pub fn animation_sync() -> SystemSet {
+    SystemSet::new()
+        .label(PlaygroundSystem::AnimationSync)
+        .after(PlaygroundSystem::Simulation)
+        .with_run_criteria(simulation_running)
+}
+
+pub fn camera() -> SystemSet {
+    SystemSet::new()
+        .label(PlaygroundSystem::Camera)
+        .after(PlaygroundSystem::AnimationSync)
+}
+
+pub fn pre_render() -> SystemSet {
+    SystemSet::new()
+        .label(PlaygroundSystem::PreRender)
+        .after(PlaygroundSystem::Camera)
+}
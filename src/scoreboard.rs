@@ -0,0 +1,199 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+};
+
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::networking::NetClient;
+use crate::room_state::{RoomState, NAME_KEY, PING_KEY};
+use crate::schedule;
+
+// Everyone in the room with their name, ping and any other player values, one column for each
+// key. The Scoreboard binding (Tab by default) shows or hides it. Only the web draws it, while
+// desktop builds log it when it's shown.
+#[derive(Default)]
+pub struct Scoreboard {
+    pub visible: bool,
+}
+
+pub struct ScoreboardTable {
+    pub columns: Vec<String>,
+    // The local player's row is marked.
+    pub rows: Vec<(bool, Vec<String>)>,
+}
+
+impl ScoreboardTable {
+    pub fn new(room_state: &RoomState, client: &NetClient) -> Self {
+        let mut columns = vec![NAME_KEY.to_string(), PING_KEY.to_string()];
+
+        for values in room_state.players.values() {
+            for key in values.keys() {
+                if !columns.contains(key) {
+                    columns.push(key.clone());
+                }
+            }
+        }
+
+        let rows = room_state
+            .players
+            .iter()
+            .map(|(&player_id, values)| {
+                let cells = columns
+                    .iter()
+                    .map(|column| match column.as_str() {
+                        NAME_KEY => room_state.player_name(player_id),
+                        _ => values.get(column).cloned().unwrap_or_default(),
+                    })
+                    .collect();
+
+                (client.player_id == Some(player_id), cells)
+            })
+            .collect();
+
+        Self { columns, rows }
+    }
+}
+
+pub struct ScoreboardPlugin;
+
+impl Plugin for ScoreboardPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Scoreboard::default());
+        app.add_system_set(schedule::input().with_system(handle_scoreboard_key));
+
+        #[cfg(not(feature = "wasm"))]
+        app.add_system_set(schedule::pre_render().with_system(log_scoreboard));
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(dom::ScoreboardDom::new());
+            app.add_system_set(schedule::pre_render().with_system(dom::update_scoreboard_dom));
+        }
+    }
+}
+
+fn handle_scoreboard_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut scoreboard: ResMut<Scoreboard>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::Scoreboard) {
+            scoreboard.visible = !scoreboard.visible;
+        }
+    }
+}
+
+#[cfg(not(feature = "wasm"))]
+fn log_scoreboard(scoreboard: Res<Scoreboard>, room_state: Res<RoomState>, client: Res<NetClient>) {
+    if !scoreboard.is_changed() || !scoreboard.visible {
+        return;
+    }
+
+    let table = ScoreboardTable::new(&room_state, &client);
+
+    log::info!("{}", table.columns.join("\t"));
+
+    for (local, cells) in &table.rows {
+        log::info!(
+            "{}{}",
+            cells.join("\t"),
+            if *local { "\t(you)" } else { "" }
+        );
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod dom {
+    use superconductor::bevy_ecs::prelude::{NonSend, Res};
+
+    use super::{Scoreboard, ScoreboardTable};
+    use crate::chat::dom::create_element;
+    use crate::networking::NetClient;
+    use crate::photo_mode::PhotoMode;
+    use crate::room_state::RoomState;
+
+    const STYLE: &str = "position: fixed; left: 50%; top: 20%; transform: translateX(-50%); \
+                         min-width: 320px; padding: 8px; border-radius: 8px; color: white; \
+                         background: rgba(0, 0, 0, 0.7); font: 14px sans-serif;";
+    const LOCAL_ROW_STYLE: &str = "font-weight: bold;";
+
+    pub struct ScoreboardDom {
+        root: web_sys::HtmlElement,
+    }
+
+    impl ScoreboardDom {
+        pub fn new() -> Self {
+            let root = create_element("table");
+            root.set_attribute("style", "display: none;").unwrap();
+
+            web_sys::window()
+                .unwrap()
+                .document()
+                .unwrap()
+                .body()
+                .unwrap()
+                .append_child(&root)
+                .unwrap();
+
+            Self { root }
+        }
+
+        // Built from elements rather than html, as the values come from other players.
+        fn rebuild(&self, table: &ScoreboardTable) {
+            self.root.set_inner_text("");
+
+            let header = create_element("tr");
+
+            for column in &table.columns {
+                let cell = create_element("th");
+                cell.set_inner_text(column);
+                header.append_child(&cell).unwrap();
+            }
+
+            self.root.append_child(&header).unwrap();
+
+            for (local, cells) in &table.rows {
+                let row = create_element("tr");
+
+                if *local {
+                    row.set_attribute("style", LOCAL_ROW_STYLE).unwrap();
+                }
+
+                for value in cells {
+                    let cell = create_element("td");
+                    cell.set_inner_text(value);
+                    row.append_child(&cell).unwrap();
+                }
+
+                self.root.append_child(&row).unwrap();
+            }
+        }
+    }
+
+    pub fn update_scoreboard_dom(
+        dom: NonSend<ScoreboardDom>,
+        scoreboard: Res<Scoreboard>,
+        room_state: Res<RoomState>,
+        client: Res<NetClient>,
+        photo_mode: Res<PhotoMode>,
+    ) {
+        if !scoreboard.is_changed() && !room_state.is_changed() && !photo_mode.is_changed() {
+            return;
+        }
+
+        if !scoreboard.visible || photo_mode.hides_ui() {
+            dom.root.set_attribute("style", "display: none;").unwrap();
+            return;
+        }
+
+        dom.rebuild(&ScoreboardTable::new(&room_state, &client));
+        dom.root.set_attribute("style", STYLE).unwrap();
+    }
+}
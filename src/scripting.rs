@@ -0,0 +1,481 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crossbeam_channel::{Receiver, Sender};
+use rhai::{Array, Dynamic, Engine, Scope, AST, FLOAT, INT};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::{EulerRot, Quat},
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::AssetError;
+use crate::assets::{AssetServer, Handle, Model};
+use crate::downloads::DownloadManager;
+use crate::game_events::GameEvent;
+use crate::hot_reload::AssetChanged;
+use crate::time_scale::TimeScale;
+use crate::{schedule, LocalPlayer};
+
+// Comma separated, from SCRIPTS on desktop or ?scripts= on the web.
+pub fn script_urls() -> Vec<Url> {
+    #[cfg(not(feature = "wasm"))]
+    let urls = std::env::var("SCRIPTS").ok();
+
+    #[cfg(feature = "wasm")]
+    let urls = crate::query_parameter("scripts");
+
+    urls.unwrap_or_default()
+        .split(',')
+        .filter(|url| !url.is_empty())
+        .filter_map(|url| match Url::parse(url) {
+            Ok(url) => Some(url),
+            Err(error) => {
+                log::warn!("Invalid script url {:?}: {}", url, error);
+                None
+            }
+        })
+        .collect()
+}
+
+enum Request {
+    SetInstance(INT, renderer_core::Instance),
+    Spawn(INT, Url),
+    Despawn(INT),
+}
+
+// What the script functions can see and do. Entities are passed to scripts as
+// `Entity::to_bits`, apart from the ones scripts spawn themselves, which get negative ids as
+// their entities don't exist yet when `spawn` returns.
+#[derive(Default)]
+struct ScriptContext {
+    // A snapshot of every instance, taken before the scripts run.
+    instances: HashMap<INT, renderer_core::Instance>,
+    local_player: Option<INT>,
+    camera_position: Vec3,
+    requests: Vec<Request>,
+    next_spawn_id: INT,
+}
+
+fn vec3_array(vec: Vec3) -> Array {
+    vec![
+        Dynamic::from(vec.x as FLOAT),
+        Dynamic::from(vec.y as FLOAT),
+        Dynamic::from(vec.z as FLOAT),
+    ]
+}
+
+fn create_engine(context: Arc<Mutex<ScriptContext>>) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.on_print(|text| log::info!("[script] {}", text));
+    engine.on_debug(|text, source, position| {
+        log::debug!(
+            "[script] {} {:?} {}",
+            source.unwrap_or_default(),
+            position,
+            text
+        )
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("local_player", move || -> INT {
+        ctx.lock().unwrap().local_player.unwrap_or(0)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("camera_position", move || -> Array {
+        vec3_array(ctx.lock().unwrap().camera_position)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("exists", move |entity: INT| -> bool {
+        ctx.lock().unwrap().instances.contains_key(&entity)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn("get_position", move |entity: INT| -> Array {
+        ctx.lock()
+            .unwrap()
+            .instances
+            .get(&entity)
+            .map(|instance| vec3_array(instance.position))
+            .unwrap_or_default()
+    });
+
+    // Any change to an instance goes through here, so that scripts read back their own
+    // changes within the same frame.
+    let modify = {
+        let context = context.clone();
+
+        move |entity: INT, function: &dyn Fn(&mut renderer_core::Instance)| {
+            let mut context = context.lock().unwrap();
+
+            if let Some(instance) = context.instances.get_mut(&entity) {
+                function(instance);
+                let instance = *instance;
+                context
+                    .requests
+                    .push(Request::SetInstance(entity, instance));
+            }
+        }
+    };
+
+    let set = modify.clone();
+    engine.register_fn(
+        "set_position",
+        move |entity: INT, x: FLOAT, y: FLOAT, z: FLOAT| {
+            set(entity, &|instance| {
+                instance.position = Vec3::new(x as f32, y as f32, z as f32)
+            })
+        },
+    );
+
+    let set = modify.clone();
+    engine.register_fn(
+        "translate",
+        move |entity: INT, x: FLOAT, y: FLOAT, z: FLOAT| {
+            set(entity, &|instance| {
+                instance.position += Vec3::new(x as f32, y as f32, z as f32)
+            })
+        },
+    );
+
+    // Euler angles in radians, applied in yaw, pitch, roll order.
+    let set = modify.clone();
+    engine.register_fn(
+        "set_rotation",
+        move |entity: INT, yaw: FLOAT, pitch: FLOAT, roll: FLOAT| {
+            set(entity, &|instance| {
+                instance.rotation =
+                    Quat::from_euler(EulerRot::YXZ, yaw as f32, pitch as f32, roll as f32)
+            })
+        },
+    );
+
+    let set = modify;
+    engine.register_fn("set_scale", move |entity: INT, scale: FLOAT| {
+        set(entity, &|instance| instance.scale = scale as f32)
+    });
+
+    let ctx = context.clone();
+    engine.register_fn(
+        "spawn",
+        move |url: &str, x: FLOAT, y: FLOAT, z: FLOAT| -> Result<INT, Box<rhai::EvalAltResult>> {
+            let url =
+                Url::parse(url).map_err(|error| format!("invalid url {:?}: {}", url, error))?;
+            let position = Vec3::new(x as f32, y as f32, z as f32);
+
+            let mut context = ctx.lock().unwrap();
+            context.next_spawn_id -= 1;
+            let id = context.next_spawn_id;
+
+            context.instances.insert(
+                id,
+                renderer_core::Instance::new(position, 1.0, Default::default()),
+            );
+            context.requests.push(Request::Spawn(id, url));
+
+            Ok(id)
+        },
+    );
+
+    let ctx = context;
+    engine.register_fn("despawn", move |entity: INT| {
+        let mut context = ctx.lock().unwrap();
+
+        if context.instances.remove(&entity).is_some() {
+            context.requests.push(Request::Despawn(entity));
+        }
+    });
+
+    engine
+}
+
+struct Script {
+    url: Url,
+    ast: AST,
+    // Bound to `this` in the script's functions, for keeping state between calls.
+    state: Dynamic,
+    has_update: bool,
+    has_on_event: bool,
+}
+
+impl Script {
+    fn call(&mut self, engine: &Engine, name: &str, args: &mut [Dynamic]) {
+        let mut scope = Scope::new();
+
+        if let Err(error) = engine.call_fn_raw(
+            &mut scope,
+            &self.ast,
+            false,
+            false,
+            name,
+            Some(&mut self.state),
+            args,
+        ) {
+            log::warn!("{} in {}: {}", name, self.url, error);
+        }
+    }
+}
+
+// Rhai scripts, fetched at startup and again whenever they change while hot reloading. A script
+// can define `init()`, `update(delta_time)` and `on_event(event)`, where the event is a map
+// with a `type` field like the ones given to the JS callback. `this` is a map that's kept
+// between calls.
+pub struct Scripts {
+    engine: Engine,
+    context: Arc<Mutex<ScriptContext>>,
+    scripts: Vec<Script>,
+    // Entities spawned by scripts, by their negative ids.
+    spawned: HashMap<INT, Entity>,
+    // Keeps the models spawned by scripts loaded.
+    models: Vec<Handle<Model>>,
+    // Game events sent since the scripts last ran, which could be several frames ago while
+    // the simulation is paused.
+    pending_events: Vec<GameEvent>,
+    sender: Sender<(Url, Result<String, AssetError>)>,
+    receiver: Receiver<(Url, Result<String, AssetError>)>,
+}
+
+impl Scripts {
+    pub fn urls(&self) -> impl Iterator<Item = &Url> {
+        self.scripts.iter().map(|script| &script.url)
+    }
+
+    fn fetch(&self, manager: &DownloadManager, url: Url) {
+        let cache = manager.cache();
+        let sender = self.sender.clone();
+
+        manager.spawn(async move {
+            let result = cache.fetch(&url).await.and_then(|bytes| {
+                String::from_utf8(bytes).map_err(|error| AssetError::Decode(error.to_string()))
+            });
+
+            let _ = sender.send((url, result));
+        });
+    }
+
+    fn resolve(&self, id: INT) -> Option<Entity> {
+        if id < 0 {
+            self.spawned.get(&id).copied()
+        } else {
+            Some(Entity::from_bits(id as u64))
+        }
+    }
+}
+
+pub struct ScriptingPlugin;
+
+impl Plugin for ScriptingPlugin {
+    fn build(&self, app: &mut App) {
+        let context = Arc::new(Mutex::new(ScriptContext::default()));
+        let (sender, receiver) = crossbeam_channel::unbounded();
+
+        let scripts = Scripts {
+            engine: create_engine(context.clone()),
+            context,
+            scripts: Vec::new(),
+            spawned: HashMap::new(),
+            models: Vec::new(),
+            pending_events: Vec::new(),
+            sender,
+            receiver,
+        };
+
+        let manager = app.world.resource::<DownloadManager>();
+
+        for url in script_urls() {
+            scripts.fetch(manager, url);
+        }
+
+        app.insert_resource(scripts);
+
+        app.add_system(load_scripts);
+        app.add_system(reload_changed_scripts);
+        app.add_system_set(schedule::input().with_system(queue_script_events));
+        app.add_system_set(schedule::simulation().with_system(run_scripts));
+    }
+}
+
+fn load_scripts(mut scripts: ResMut<Scripts>) {
+    let scripts = &mut *scripts;
+
+    for (url, result) in scripts.receiver.try_iter() {
+        let ast = match result
+            .map_err(|error| error.to_string())
+            .and_then(|source| {
+                scripts
+                    .engine
+                    .compile(&source)
+                    .map_err(|error| error.to_string())
+            }) {
+            Ok(ast) => ast,
+            Err(error) => {
+                log::warn!("Failed to load script {}: {}", url, error);
+                continue;
+            }
+        };
+
+        let has_function = |name: &str| ast.iter_functions().any(|function| function.name == name);
+
+        let mut script = Script {
+            has_update: has_function("update"),
+            has_on_event: has_function("on_event"),
+            url,
+            ast,
+            state: Dynamic::from_map(Default::default()),
+        };
+
+        if script
+            .ast
+            .iter_functions()
+            .any(|function| function.name == "init")
+        {
+            script.call(&scripts.engine, "init", &mut []);
+        }
+
+        log::info!("Loaded script {}", script.url);
+
+        // Reloaded scripts start again from scratch.
+        scripts
+            .scripts
+            .retain(|existing| existing.url != script.url);
+        scripts.scripts.push(script);
+    }
+}
+
+fn reload_changed_scripts(
+    mut changes: EventReader<AssetChanged>,
+    scripts: Res<Scripts>,
+    manager: Res<DownloadManager>,
+) {
+    for AssetChanged(url) in changes.iter() {
+        if scripts.urls().any(|script| script == url) {
+            scripts.fetch(&manager, url.clone());
+        }
+    }
+}
+
+fn queue_script_events(mut events: EventReader<GameEvent>, mut scripts: ResMut<Scripts>) {
+    if scripts.scripts.is_empty() {
+        return;
+    }
+
+    scripts.pending_events.extend(events.iter().cloned());
+}
+
+fn run_scripts(
+    mut commands: Commands,
+    mut scripts: ResMut<Scripts>,
+    mut asset_server: ResMut<AssetServer>,
+    camera: Res<Camera>,
+    time_scale: Res<TimeScale>,
+    mut instance_q: Query<(Entity, &mut components::Instance)>,
+    local_player_q: Query<Entity, With<LocalPlayer>>,
+) {
+    let _span = tracing::info_span!("run_scripts").entered();
+
+    if scripts.scripts.is_empty() {
+        return;
+    }
+
+    let scripts = &mut *scripts;
+
+    // Forget about spawned entities that have been despawned since.
+    scripts
+        .spawned
+        .retain(|_, entity| instance_q.get(*entity).is_ok());
+
+    {
+        let mut context = scripts.context.lock().unwrap();
+
+        context.instances = instance_q
+            .iter()
+            .map(|(entity, instance)| (entity.to_bits() as INT, instance.0))
+            .collect();
+
+        for (&id, entity) in &scripts.spawned {
+            if let Ok((_, instance)) = instance_q.get(*entity) {
+                context.instances.insert(id, instance.0);
+            }
+        }
+
+        context.local_player = local_player_q
+            .get_single()
+            .ok()
+            .map(|entity| entity.to_bits() as INT);
+        context.camera_position = camera.position;
+    }
+
+    let events: Vec<Dynamic> = scripts
+        .pending_events
+        .drain(..)
+        .filter_map(|event| rhai::serde::to_dynamic(&event).ok())
+        .collect();
+
+    for script in &mut scripts.scripts {
+        if script.has_on_event {
+            for event in &events {
+                script.call(&scripts.engine, "on_event", &mut [event.clone()]);
+            }
+        }
+
+        if script.has_update {
+            script.call(
+                &scripts.engine,
+                "update",
+                &mut [Dynamic::from(time_scale.delta() as FLOAT)],
+            );
+        }
+    }
+
+    let mut context = scripts.context.lock().unwrap();
+
+    for request in std::mem::take(&mut context.requests) {
+        match request {
+            Request::SetInstance(id, new) => {
+                if let Some(entity) = scripts.resolve(id) {
+                    if let Ok((_, mut instance)) = instance_q.get_mut(entity) {
+                        instance.0 = new;
+                    }
+                }
+            }
+            // Spawned with any changes made to it after the call to `spawn`, as the entity
+            // doesn't exist for those to be applied to.
+            Request::Spawn(id, url) => {
+                let instance = match context.instances.get(&id) {
+                    Some(instance) => *instance,
+                    None => continue,
+                };
+
+                let model = asset_server.load::<Model>(&mut commands, url);
+
+                let entity = commands
+                    .spawn()
+                    .insert(components::InstanceOf(model.entity()))
+                    .insert(components::Instance(instance))
+                    .id();
+
+                scripts.spawned.insert(id, entity);
+
+                if !scripts.models.contains(&model) {
+                    scripts.models.push(model);
+                }
+            }
+            Request::Despawn(id) => {
+                if let Some(entity) = scripts.resolve(id) {
+                    if instance_q.get(entity).is_ok() {
+                        commands.entity(entity).despawn();
+                    }
+                }
+
+                scripts.spawned.remove(&id);
+            }
+        }
+    }
+}
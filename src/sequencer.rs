@@ -0,0 +1,281 @@
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Entity, Query, Res, ResMut},
+    components,
+    renderer_core::glam::Quat,
+    Vec3,
+};
+
+use crate::schedule::{self, FrameStep, SimulationPaused};
+use crate::time_scale::TimeScale;
+use crate::weather::{Weather, WeatherPreset};
+
+#[derive(Clone, Copy, Debug)]
+pub struct Keyframe<T> {
+    // In seconds from the start of the sequence.
+    pub time: f32,
+    pub value: T,
+}
+
+// A line of cutscene dialogue, shown as a caption from its keyframe for `duration` seconds.
+#[derive(Clone, Debug)]
+pub struct DialogueLine {
+    pub speaker: String,
+    pub text: String,
+    pub duration: f32,
+}
+
+// Keyframes are sorted by time. Values are interpolated linearly, apart from animation indices,
+// weather and dialogue which switch over at each keyframe. Rain and snow from the last weather
+// finish falling as they would otherwise. There's no track for lights, as the scene is only
+// lit by the environment cubemaps.
+#[derive(Clone, Debug)]
+pub enum Track {
+    Position(Entity, Vec<Keyframe<Vec3>>),
+    Rotation(Entity, Vec<Keyframe<Quat>>),
+    Scale(Entity, Vec<Keyframe<f32>>),
+    AnimationIndex(Entity, Vec<Keyframe<usize>>),
+    CameraPosition(Vec<Keyframe<Vec3>>),
+    // Yaw and pitch in degrees.
+    CameraYawPitch(Vec<Keyframe<[f32; 2]>>),
+    Dialogue(Vec<Keyframe<DialogueLine>>),
+    Weather(Vec<Keyframe<WeatherPreset>>),
+}
+
+impl Track {
+    fn duration(&self) -> f32 {
+        fn last<T>(keys: &[Keyframe<T>]) -> f32 {
+            keys.last().map(|key| key.time).unwrap_or(0.0)
+        }
+
+        match self {
+            Self::Position(_, keys) | Self::CameraPosition(keys) => last(keys),
+            Self::Rotation(_, keys) => last(keys),
+            Self::Scale(_, keys) => last(keys),
+            Self::AnimationIndex(_, keys) => last(keys),
+            Self::CameraYawPitch(keys) => last(keys),
+            Self::Weather(keys) => last(keys),
+            Self::Dialogue(keys) => keys
+                .last()
+                .map(|key| key.time + key.value.duration)
+                .unwrap_or(0.0),
+        }
+    }
+}
+
+// The two keyframes around the time and how far between them it is. Clamped to the first and
+// last keyframes.
+fn surrounding<T>(keys: &[Keyframe<T>], time: f32) -> Option<(&T, &T, f32)> {
+    let next = keys.iter().position(|key| key.time > time);
+
+    match next {
+        Some(0) => keys.first().map(|key| (&key.value, &key.value, 0.0)),
+        Some(next) => {
+            let (a, b) = (&keys[next - 1], &keys[next]);
+            Some((&a.value, &b.value, (time - a.time) / (b.time - a.time)))
+        }
+        None => keys.last().map(|key| (&key.value, &key.value, 0.0)),
+    }
+}
+
+fn sample<T: Copy>(keys: &[Keyframe<T>], time: f32, lerp: impl Fn(T, T, f32) -> T) -> Option<T> {
+    surrounding(keys, time).map(|(&a, &b, t)| lerp(a, b, t))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Sequence {
+    pub tracks: Vec<Track>,
+    pub looping: bool,
+}
+
+impl Sequence {
+    pub fn duration(&self) -> f32 {
+        self.tracks.iter().map(Track::duration).fold(0.0, f32::max)
+    }
+}
+
+// Plays one sequence at a time. While a sequence is loaded it overrides whatever its tracks
+// target, even when paused, so that it can be scrubbed through.
+#[derive(Default)]
+pub struct Sequencer {
+    sequence: Option<Sequence>,
+    time: f32,
+    playing: bool,
+}
+
+impl Sequencer {
+    pub fn play(&mut self, sequence: Sequence) {
+        self.sequence = Some(sequence);
+        self.time = 0.0;
+        self.playing = true;
+    }
+
+    pub fn pause(&mut self) {
+        self.playing = false;
+    }
+
+    pub fn resume(&mut self) {
+        self.playing = self.sequence.is_some();
+    }
+
+    pub fn scrub(&mut self, time: f32) {
+        self.time = time.clamp(0.0, self.duration());
+    }
+
+    // Hands control back, leaving everything where the sequence last put it.
+    pub fn stop(&mut self) {
+        self.sequence = None;
+        self.playing = false;
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.sequence
+            .as_ref()
+            .map(Sequence::duration)
+            .unwrap_or(0.0)
+    }
+
+    pub fn is_playing(&self) -> bool {
+        self.playing
+    }
+
+    // The line of dialogue being spoken at the current time, if any.
+    pub fn dialogue(&self) -> Option<&DialogueLine> {
+        let time = self.time;
+
+        self.sequence
+            .iter()
+            .flat_map(|sequence| &sequence.tracks)
+            .filter_map(|track| match track {
+                Track::Dialogue(keys) => keys.iter().rev().find(|key| key.time <= time),
+                _ => None,
+            })
+            .find(|key| time < key.time + key.value.duration)
+            .map(|key| &key.value)
+    }
+}
+
+pub struct SequencerPlugin;
+
+impl Plugin for SequencerPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Sequencer::default());
+
+        // Before the simulation, so that the camera control picks up the camera tracks in the
+        // same frame.
+        app.add_system_set(schedule::input().with_system(apply_sequence));
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn apply_sequence(
+    mut sequencer: ResMut<Sequencer>,
+    paused: Option<Res<SimulationPaused>>,
+    frame_step: Option<Res<FrameStep>>,
+    time_scale: Res<TimeScale>,
+    mut camera_rig: Option<ResMut<dolly::rig::CameraRig>>,
+    mut weather: Option<ResMut<Weather>>,
+    mut instance_q: Query<&mut components::Instance>,
+    mut animation_q: Query<&mut components::AnimationState>,
+) {
+    let _span = tracing::info_span!("apply_sequence").entered();
+
+    let duration = sequencer.duration();
+
+    let sequencer = &mut *sequencer;
+
+    let sequence = match &sequencer.sequence {
+        Some(sequence) => sequence,
+        None => return,
+    };
+
+    // Runs with the input systems, so it checks for a paused simulation itself.
+    let paused = paused.map_or(false, |paused| paused.0)
+        && !frame_step.map_or(false, |frame_step| frame_step.is_stepping());
+
+    if sequencer.playing && !paused {
+        sequencer.time += time_scale.delta();
+
+        if sequencer.time > duration {
+            if sequence.looping && duration > 0.0 {
+                sequencer.time %= duration;
+            } else {
+                sequencer.time = duration;
+                sequencer.playing = false;
+            }
+        }
+    }
+
+    let time = sequencer.time;
+
+    for track in &sequence.tracks {
+        match track {
+            Track::Position(entity, keys) => {
+                if let (Some(position), Ok(mut instance)) =
+                    (sample(keys, time, Vec3::lerp), instance_q.get_mut(*entity))
+                {
+                    instance.0.position = position;
+                }
+            }
+            Track::Rotation(entity, keys) => {
+                if let (Some(rotation), Ok(mut instance)) =
+                    (sample(keys, time, Quat::slerp), instance_q.get_mut(*entity))
+                {
+                    instance.0.rotation = rotation;
+                }
+            }
+            Track::Scale(entity, keys) => {
+                if let (Some(scale), Ok(mut instance)) = (
+                    sample(keys, time, |a, b, t| a + (b - a) * t),
+                    instance_q.get_mut(*entity),
+                ) {
+                    instance.0.scale = scale;
+                }
+            }
+            Track::AnimationIndex(entity, keys) => {
+                if let (Some(index), Ok(mut state)) = (
+                    sample(keys, time, |a, _, _| a),
+                    animation_q.get_mut(*entity),
+                ) {
+                    if state.animation_index != index {
+                        state.animation_index = index;
+                    }
+                }
+            }
+            Track::CameraPosition(keys) => {
+                if let (Some(position), Some(rig)) =
+                    (sample(keys, time, Vec3::lerp), camera_rig.as_mut())
+                {
+                    rig.driver_mut::<dolly::drivers::Position>().position = position;
+                }
+            }
+            Track::CameraYawPitch(keys) => {
+                if let (Some([yaw, pitch]), Some(rig)) = (
+                    sample(keys, time, |a: [f32; 2], b: [f32; 2], t| {
+                        [a[0] + (b[0] - a[0]) * t, a[1] + (b[1] - a[1]) * t]
+                    }),
+                    camera_rig.as_mut(),
+                ) {
+                    let yaw_pitch = rig.driver_mut::<dolly::drivers::YawPitch>();
+                    yaw_pitch.yaw_degrees = yaw;
+                    yaw_pitch.pitch_degrees = pitch;
+                }
+            }
+            Track::Weather(keys) => {
+                if let (Some(preset), Some(weather)) =
+                    (sample(keys, time, |a, _, _| a), weather.as_mut())
+                {
+                    if weather.0 != preset {
+                        weather.0 = preset;
+                    }
+                }
+            }
+            // Shown by the caption plugin, through `Sequencer::dialogue`.
+            Track::Dialogue(_) => {}
+        }
+    }
+}
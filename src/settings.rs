@@ -0,0 +1,123 @@
+use serde::{de::DeserializeOwned, Serialize};
+use superconductor::{
+    bevy_app::App,
+    bevy_ecs::prelude::{Res, ResMut},
+};
+
+use crate::schedule;
+
+// A resource that's kept between sessions, under its own key. Settings live in one json
+// object on desktop (SETTINGS_PATH, by default .cache/settings.json) and in local storage on
+// the web, one item per key. Only settings that are changed while running are saved, so
+// environment variables and query parameters keep working until then.
+pub trait Setting: Serialize + DeserializeOwned + Send + Sync + 'static {
+    const KEY: &'static str;
+}
+
+#[cfg(not(feature = "wasm"))]
+mod storage {
+    use serde_json::{Map, Value};
+
+    fn path() -> String {
+        std::env::var("SETTINGS_PATH").unwrap_or_else(|_| ".cache/settings.json".into())
+    }
+
+    fn read(path: &str) -> Map<String, Value> {
+        std::fs::read_to_string(path)
+            .ok()
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default()
+    }
+
+    pub fn load(key: &str) -> Option<Value> {
+        read(&path()).remove(key)
+    }
+
+    pub fn save(key: &str, value: Value) -> Result<(), String> {
+        let path = path();
+        let mut settings = read(&path);
+        settings.insert(key.into(), value);
+
+        if let Some(parent) = std::path::Path::new(&path).parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+
+        let json = serde_json::to_string_pretty(&settings).map_err(|error| error.to_string())?;
+
+        std::fs::write(&path, json).map_err(|error| format!("{}: {}", path, error))
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod storage {
+    use serde_json::Value;
+
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok().flatten()
+    }
+
+    fn item_key(key: &str) -> String {
+        format!("settings.{}", key)
+    }
+
+    pub fn load(key: &str) -> Option<Value> {
+        let json = local_storage()?.get_item(&item_key(key)).ok().flatten()?;
+
+        serde_json::from_str(&json).ok()
+    }
+
+    pub fn save(key: &str, value: Value) -> Result<(), String> {
+        let storage = local_storage().ok_or("Local storage is unavailable")?;
+
+        storage
+            .set_item(&item_key(key), &value.to_string())
+            .map_err(|error| format!("{:?}", error))
+    }
+}
+
+pub fn load<T: Setting>() -> Option<T> {
+    let value = storage::load(T::KEY)?;
+
+    match serde_json::from_value(value) {
+        Ok(setting) => Some(setting),
+        Err(error) => {
+            log::warn!("Ignoring the saved {} settings: {}", T::KEY, error);
+            None
+        }
+    }
+}
+
+fn save<T: Setting>(setting: &T) {
+    let result = serde_json::to_value(setting)
+        .map_err(|error| error.to_string())
+        .and_then(|value| storage::save(T::KEY, value));
+
+    if let Err(error) = result {
+        log::warn!("Failed to save the {} settings: {}", T::KEY, error);
+    }
+}
+
+// Replaces a resource that's already been inserted with its saved copy, if there is one, and
+// saves it whenever it changes from then on.
+pub fn persist<T: Setting>(app: &mut App) {
+    if let Some(setting) = load::<T>() {
+        app.insert_resource(setting);
+    }
+
+    app.init_resource::<SettingsStore>();
+    app.add_system_set(schedule::pre_render().with_system(save_on_change::<T>));
+}
+
+// Borrowed mutably by every save, so that two settings are never written to the same file at
+// once.
+#[derive(Default)]
+struct SettingsStore;
+
+fn save_on_change<T: Setting>(_store: ResMut<SettingsStore>, setting: Option<Res<T>>) {
+    if let Some(setting) = setting {
+        // Nothing to save when it was only just inserted, from the defaults or the saved copy.
+        if setting.is_changed() && !setting.is_added() {
+            save(&*setting);
+        }
+    }
+}
@@ -0,0 +1,300 @@
+use std::collections::VecDeque;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::{
+        prelude::{Commands, Component, Local, Query, Res, With, Without},
+        system::CommandQueue,
+    },
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    Vec3,
+};
+
+use crate::materials::MaterialDescriptor;
+use crate::networking::NetClient;
+use crate::ownership::{Held, SharedObject};
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::protocol::{ClientMessage, ObjectId, ObjectTransform};
+use crate::render_stats::now_ms;
+use crate::schedule;
+use crate::time_scale::TimeScale;
+
+pub(crate) const GRAVITY: f32 = 9.81;
+pub(crate) const GROUND_HEIGHT: f32 = 0.0;
+const RESTITUTION: f32 = 0.5;
+// The fraction of the horizontal velocity lost per second while rolling along the ground.
+const FRICTION: f32 = 2.0;
+// Bodies on the ground that are slower than this stop, and aren't sent until they're moved.
+const SLEEP_SPEED: f32 = 0.05;
+const SEND_INTERVAL_FRAMES: u32 = 3;
+// How far behind the latest transform other clients show a body, so that there's normally a
+// transform on either side of it to interpolate between. A bit over two send intervals.
+const INTERPOLATION_DELAY_MS: f64 = 120.0;
+const MAX_SNAPSHOTS: usize = 32;
+
+// Object ids after the ownership plugin's own props.
+const BALL_ID: ObjectId = 100;
+const CRATE_ID: ObjectId = 101;
+
+// A shared object that falls and bounces. The room host simulates the ones nobody is holding
+// and sends their transforms to everyone else, who interpolate between them, so everyone sees
+// them in the same place. The server itself only relays and stores the host's transforms.
+// Letting go of a held body throws it with the velocity it was moving at.
+#[derive(Component)]
+pub struct PhysicsBody {
+    pub radius: f32,
+    pub velocity: Vec3,
+    previous_position: Option<Vec3>,
+}
+
+impl PhysicsBody {
+    pub fn new(radius: f32) -> Self {
+        Self {
+            radius,
+            velocity: Vec3::ZERO,
+            previous_position: None,
+        }
+    }
+
+    pub fn is_sleeping(&self) -> bool {
+        self.velocity == Vec3::ZERO
+    }
+}
+
+// The transforms received for a shared object, with the times they arrived at.
+#[derive(Component, Default)]
+pub struct Interpolated {
+    snapshots: VecDeque<(f64, Vec3, Quat)>,
+}
+
+impl Interpolated {
+    pub fn push(&mut self, position: Vec3, rotation: Quat) {
+        self.snapshots.push_back((now_ms(), position, rotation));
+
+        if self.snapshots.len() > MAX_SNAPSHOTS {
+            self.snapshots.pop_front();
+        }
+    }
+
+    // Interpolates between the transforms either side of `time`, holding the last one when
+    // there isn't a later one yet.
+    fn sample(&mut self, time: f64) -> Option<(Vec3, Quat)> {
+        while self.snapshots.len() > 2 && self.snapshots[1].0 <= time {
+            self.snapshots.pop_front();
+        }
+
+        match (self.snapshots.get(0), self.snapshots.get(1)) {
+            (Some(&(a_time, a_position, a_rotation)), Some(&(b_time, b_position, b_rotation)))
+                if time > a_time =>
+            {
+                let t = ((time - a_time) / (b_time - a_time).max(1.0)).min(1.0) as f32;
+                Some((
+                    a_position.lerp(b_position, t),
+                    a_rotation.slerp(b_rotation, t),
+                ))
+            }
+            (Some(&(_, position, rotation)), _) => Some((position, rotation)),
+            _ => None,
+        }
+    }
+
+    fn clear(&mut self) {
+        self.snapshots.clear();
+    }
+}
+
+pub struct SharedPhysicsPlugin;
+
+impl Plugin for SharedPhysicsPlugin {
+    fn build(&self, app: &mut App) {
+        let mut queue = CommandQueue::default();
+        let mut commands = Commands::new(&mut queue, &app.world);
+
+        let ball = spawn_primitive(
+            &mut commands,
+            Primitive::sphere(0.15),
+            MaterialDescriptor::color([0.9, 0.2, 0.1, 1.0]),
+        );
+        let crate_model = spawn_primitive(
+            &mut commands,
+            Primitive::cube(0.4),
+            MaterialDescriptor::color([0.6, 0.45, 0.25, 1.0]),
+        );
+
+        for (id, model, radius, position) in [
+            (BALL_ID, ball, 0.15, Vec3::new(-0.75, 1.5, -3.0)),
+            (CRATE_ID, crate_model, 0.2, Vec3::new(0.75, 1.5, -3.0)),
+        ] {
+            commands
+                .spawn()
+                .insert(components::InstanceOf(model))
+                .insert(components::Instance(renderer_core::Instance::new(
+                    position,
+                    1.0,
+                    Default::default(),
+                )))
+                .insert(SharedObject { id, owner: None })
+                .insert(PhysicsBody::new(radius))
+                .insert(Interpolated::default());
+        }
+
+        queue.apply(&mut app.world);
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(simulate_bodies)
+                .with_system(track_held_bodies)
+                .with_system(interpolate_bodies),
+        );
+        app.add_system_set(schedule::pre_render().with_system(send_body_transforms));
+    }
+}
+
+// Whether this client simulates the bodies nobody is holding. Without a room, the bodies are
+// simulated locally.
+fn is_authority(client: &NetClient) -> bool {
+    client.room.is_none() || client.is_host()
+}
+
+fn simulate_bodies(
+    client: Res<NetClient>,
+    time_scale: Res<TimeScale>,
+    mut body_q: Query<(&SharedObject, &mut PhysicsBody, &mut components::Instance), Without<Held>>,
+) {
+    if !is_authority(&client) {
+        return;
+    }
+
+    let delta = time_scale.delta();
+
+    for (object, mut body, mut instance) in body_q.iter_mut() {
+        if object.owner.is_some() {
+            continue;
+        }
+
+        let floor = GROUND_HEIGHT + body.radius;
+        let resting = instance.0.position.y <= floor && body.is_sleeping();
+
+        if resting {
+            continue;
+        }
+
+        let mut position = instance.0.position;
+        let mut velocity = body.velocity;
+
+        velocity.y -= GRAVITY * delta;
+        position += velocity * delta;
+
+        if position.y < floor {
+            position.y = floor;
+            velocity.y = -velocity.y * RESTITUTION;
+
+            let friction = (1.0 - FRICTION * delta).max(0.0);
+            velocity.x *= friction;
+            velocity.z *= friction;
+
+            if velocity.length() < SLEEP_SPEED {
+                velocity = Vec3::ZERO;
+            }
+        }
+
+        body.velocity = velocity;
+        body.previous_position = Some(position);
+        instance.0.position = position;
+    }
+}
+
+// Keeps the velocity of held bodies up to date, so that they're thrown when let go of.
+fn track_held_bodies(
+    time_scale: Res<TimeScale>,
+    mut held_q: Query<(&mut PhysicsBody, &components::Instance), With<Held>>,
+) {
+    let delta = time_scale.delta();
+
+    for (mut body, instance) in held_q.iter_mut() {
+        let position = instance.0.position;
+
+        if let Some(previous) = body.previous_position {
+            if delta > 0.0 {
+                body.velocity = (position - previous) / delta;
+            }
+        }
+
+        body.previous_position = Some(position);
+    }
+}
+
+// Shows the transforms received from whoever is simulating or holding each body, a little
+// behind time. The velocity is kept up to date too, so that a newly elected host carries on
+// from where the last one left off.
+fn interpolate_bodies(
+    client: Res<NetClient>,
+    time_scale: Res<TimeScale>,
+    mut body_q: Query<
+        (
+            &SharedObject,
+            &mut PhysicsBody,
+            &mut Interpolated,
+            &mut components::Instance,
+        ),
+        Without<Held>,
+    >,
+) {
+    let authority = is_authority(&client);
+    let time = now_ms() - INTERPOLATION_DELAY_MS;
+    let delta = time_scale.delta();
+
+    for (object, mut body, mut interpolated, mut instance) in body_q.iter_mut() {
+        // Only the bodies that someone else is moving.
+        if authority && object.owner.is_none() {
+            interpolated.clear();
+            continue;
+        }
+
+        let (position, rotation) = match interpolated.sample(time) {
+            Some(transform) => transform,
+            None => continue,
+        };
+
+        if let Some(previous) = body.previous_position {
+            if delta > 0.0 {
+                body.velocity = (position - previous) / delta;
+            }
+        }
+
+        body.previous_position = Some(position);
+
+        if instance.0.position != position || instance.0.rotation != rotation {
+            instance.0.position = position;
+            instance.0.rotation = rotation;
+        }
+    }
+}
+
+fn send_body_transforms(
+    client: Res<NetClient>,
+    body_q: Query<(&SharedObject, &PhysicsBody, &components::Instance), Without<Held>>,
+    mut frame: Local<u32>,
+) {
+    *frame += 1;
+
+    if *frame % SEND_INTERVAL_FRAMES != 0 || client.room.is_none() || !client.is_host() {
+        return;
+    }
+
+    // Sleeping bodies are left to the host snapshots.
+    for (object, body, instance) in body_q.iter() {
+        if object.owner.is_some() || body.is_sleeping() {
+            continue;
+        }
+
+        client.send(ClientMessage::ObjectTransform {
+            object_id: object.id,
+            transform: ObjectTransform {
+                position: instance.0.position.to_array(),
+                rotation: instance.0.rotation.to_array(),
+            },
+        });
+    }
+}
@@ -0,0 +1,125 @@
+use serde_json::Value;
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Query, With},
+    components, Vec3,
+};
+
+use crate::bounds::node_transform;
+use crate::downloads::ModelInspected;
+use crate::root_motion::parent_transform;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Joint {
+    pub name: String,
+    // Into the skeleton's joints.
+    pub parent: Option<usize>,
+    // Where the joint is in model space, in the file's rest pose.
+    pub rest: Vec3,
+}
+
+// The joints of a model's first skin, in the skin's order, and how long each of its animations
+// is and what it's called.
+#[derive(Component, Clone, Debug, Default)]
+pub struct ModelSkeleton {
+    pub joints: Vec<Joint>,
+    pub durations: Vec<f32>,
+    pub animation_names: Vec<String>,
+}
+
+impl ModelSkeleton {
+    pub fn is_empty(&self) -> bool {
+        self.joints.is_empty()
+    }
+}
+
+// The joints of the first skin and the animations' lengths, from the accessors' max times.
+// Animations without a name are numbered.
+pub fn gltf_skeleton(gltf: &Value) -> ModelSkeleton {
+    let nodes: Vec<usize> = gltf["skins"][0]["joints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|joint| joint.as_u64())
+        .map(|joint| joint as usize)
+        .collect();
+
+    let joints = nodes
+        .iter()
+        .map(|&node| Joint {
+            name: gltf["nodes"][node]["name"]
+                .as_str()
+                .map_or_else(|| node.to_string(), |name| name.to_string()),
+            parent: nodes.iter().position(|&other| {
+                gltf["nodes"][other]["children"]
+                    .as_array()
+                    .map_or(false, |children| children.iter().any(|child| child == node))
+            }),
+            rest: (parent_transform(gltf, node) * node_transform(&gltf["nodes"][node]))
+                .transform_point3(Vec3::ZERO),
+        })
+        .collect();
+
+    let durations = gltf["animations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|animation| {
+            animation["samplers"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|sampler| {
+                    let input = sampler["input"].as_u64()? as usize;
+                    gltf["accessors"][input]["max"][0].as_f64()
+                })
+                .fold(0.0, f64::max) as f32
+        })
+        .collect();
+
+    let animation_names = gltf["animations"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, animation)| {
+            animation["name"]
+                .as_str()
+                .map_or_else(|| format!("Animation {}", index), |name| name.to_string())
+        })
+        .collect();
+
+    ModelSkeleton {
+        joints,
+        durations,
+        animation_names,
+    }
+}
+
+// Puts a `ModelSkeleton` on each model with a skin once it's downloaded, for ragdolls, cloth
+// anchors and emote lengths.
+pub struct SkeletonPlugin;
+
+impl Plugin for SkeletonPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system(insert_model_skeletons);
+    }
+}
+
+fn insert_model_skeletons(
+    mut commands: Commands,
+    mut inspected: EventReader<ModelInspected>,
+    model_q: Query<(), With<components::Instances>>,
+) {
+    for model in inspected.iter() {
+        if model_q.get(model.entity).is_err() {
+            continue;
+        }
+
+        let skeleton = gltf_skeleton(&model.document.json);
+
+        if !skeleton.is_empty() {
+            commands.entity(model.entity).insert(skeleton);
+        }
+    }
+}
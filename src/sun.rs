@@ -0,0 +1,353 @@
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Entity, Local, Query, Res, With, Without, World},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::bounds::{Bounds, BoundsOutline};
+use crate::console::Console;
+use crate::env_setting;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::settings::{self, Setting};
+use crate::terrain::Heightfield;
+use crate::{schedule, DELTA_TIME};
+
+// How far away the disc is drawn, in meters. Far enough to be behind the scene, and near
+// enough to be in front of the far plane.
+const SUN_DISTANCE: f32 = 500.0;
+// In degrees. About twice the real sun's, so that it reads at headset resolutions.
+const SUN_ANGULAR_RADIUS: f32 = 0.5;
+// The flares are drawn on a plane this far in front of the camera, in meters.
+const FLARE_DISTANCE: f32 = 1.0;
+// The flares fade out as the sun goes further from the middle of the view than this, as the
+// cosine of the angle between them.
+const MIN_FACING: f32 = 0.7;
+// How quickly the flares follow the sun's visibility, per second.
+const FADE_SPEED: f32 = 8.0;
+// Along the line from the middle of the view (0) to the sun (1), with radii in meters at the
+// flare distance and linear rgb colours.
+const FLARES: [(f32, f32, [f32; 3]); 5] = [
+    (0.7, 0.02, [0.5, 0.45, 0.3]),
+    (0.4, 0.008, [0.3, 0.4, 0.5]),
+    (-0.2, 0.015, [0.45, 0.25, 0.45]),
+    (-0.5, 0.03, [0.25, 0.45, 0.3]),
+    (-0.9, 0.05, [0.45, 0.35, 0.2]),
+];
+// Steps through the terrain towards the sun, in meters.
+const TERRAIN_STEP: f32 = 2.0;
+const TERRAIN_STEPS: usize = 100;
+
+// How bright the sun disc and its lens flare are, as multiples of the sun's colour for the
+// disc and of the flares' own colours. Zero turns either off.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SunSettings {
+    pub disc_intensity: f32,
+    pub flare_intensity: f32,
+}
+
+impl Setting for SunSettings {
+    const KEY: &'static str = "sun";
+}
+
+impl Default for SunSettings {
+    fn default() -> Self {
+        Self {
+            disc_intensity: 4.0,
+            flare_intensity: 1.0,
+        }
+    }
+}
+
+impl SunSettings {
+    // SUN_DISC and LENS_FLARE on desktop or ?sun_disc= and ?lens_flare= on the web.
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        let parse = |desktop: &str, web: &str, default: f32| {
+            env_setting(desktop, web)
+                .and_then(|value| value.parse::<f32>().ok())
+                .map_or(default, |value| value.max(0.0))
+        };
+
+        Self {
+            disc_intensity: parse("SUN_DISC", "sun_disc", defaults.disc_intensity),
+            flare_intensity: parse("LENS_FLARE", "lens_flare", defaults.flare_intensity),
+        }
+    }
+}
+
+// Where the sun is in the sky, with the
+// direction its light travels in and a linear rgb colour. It's only drawn as a disc and lens
+// flares, as the renderer only lights the scene with the cubemaps.
+#[derive(Component, Clone, Copy, Debug, PartialEq)]
+pub struct Sun {
+    pub direction: Vec3,
+    pub color: Vec3,
+}
+
+// Marks the sun disc and flare instances, so that they don't hide the sun from themselves.
+#[derive(Component)]
+struct SunSprite;
+
+// The models and instances of the disc and flares, along with the settings and sun colour
+// they were made with, as instances can't be tinted individually.
+struct SunSprites {
+    settings: SunSettings,
+    color: Vec3,
+    models: Vec<Entity>,
+    disc: Option<Entity>,
+    flares: Vec<Entity>,
+}
+
+#[derive(Default)]
+struct SunState {
+    sprites: Option<SunSprites>,
+    // The share of the disc that can be seen from the camera, smoothed.
+    visibility: f32,
+}
+
+// Draws a disc where the first `Sun` is, and lens flares across the view
+// while it can be seen. There's no depth buffer to read back, so the flares are occlusion
+// tested with rays against the terrain and the bounds of everything else, and shrink away
+// rather than fading as instances have no opacity.
+pub struct SunPlugin;
+
+impl Plugin for SunPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(SunSettings::from_env());
+        settings::persist::<SunSettings>(app);
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register("sun", "sun <disc | flare> <intensity>", set_sun);
+
+        app.add_system_set(schedule::pre_render().with_system(update_sun));
+    }
+}
+
+fn set_sun(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let intensity = args
+        .get(1)
+        .ok_or("Missing intensity")?
+        .parse::<f32>()
+        .map_err(|error| error.to_string())?
+        .max(0.0);
+
+    let mut settings = world.resource_mut::<SunSettings>();
+
+    match args.get(0).copied() {
+        Some("disc") => settings.disc_intensity = intensity,
+        Some("flare") => settings.flare_intensity = intensity,
+        _ => return Err("Expected disc or flare".into()),
+    }
+
+    Ok(String::new())
+}
+
+fn spawn_sprite(commands: &mut Commands, model: Entity) -> Entity {
+    commands
+        .spawn()
+        .insert(SunSprite)
+        .insert(components::InstanceOf(model))
+        .insert(components::Instance(renderer_core::Instance::new(
+            Vec3::ZERO,
+            0.0,
+            Quat::IDENTITY,
+        )))
+        .id()
+}
+
+fn spawn_sprites(commands: &mut Commands, settings: SunSettings, color: Vec3) -> SunSprites {
+    let mut models = Vec::new();
+
+    let disc = (settings.disc_intensity > 0.0).then(|| {
+        let model = spawn_primitive(
+            commands,
+            Primitive::sphere(1.0),
+            MaterialDescriptor::emissive((color * settings.disc_intensity).to_array()),
+        );
+        models.push(model);
+        spawn_sprite(commands, model)
+    });
+
+    let flares = if settings.flare_intensity > 0.0 {
+        FLARES
+            .iter()
+            .map(|&(_, _, flare_color)| {
+                let model = spawn_primitive(
+                    commands,
+                    Primitive::sphere(1.0),
+                    MaterialDescriptor::emissive(
+                        (Vec3::from(flare_color) * color * settings.flare_intensity).to_array(),
+                    ),
+                );
+                models.push(model);
+                spawn_sprite(commands, model)
+            })
+            .collect()
+    } else {
+        Vec::new()
+    };
+
+    SunSprites {
+        settings,
+        color,
+        models,
+        disc,
+        flares,
+    }
+}
+
+fn despawn_sprites(commands: &mut Commands, sprites: SunSprites) {
+    for entity in sprites
+        .models
+        .into_iter()
+        .chain(sprites.disc)
+        .chain(sprites.flares)
+    {
+        commands.entity(entity).despawn();
+    }
+}
+
+// Whether a ray towards the sun gets out of the scene. Boxes the camera is inside of are
+// skipped, as they're its own avatar or the environment's.
+fn reaches_sky(
+    origin: Vec3,
+    direction: Vec3,
+    heightfields: &[&Heightfield],
+    boxes: &[&Bounds],
+) -> bool {
+    let blocked_by_box = boxes.iter().any(|bounds| {
+        bounds
+            .0
+            .ray_distance(origin, direction)
+            .map_or(false, |distance| distance > 0.0 && distance < SUN_DISTANCE)
+    });
+
+    let blocked_by_terrain = (1..=TERRAIN_STEPS).any(|step| {
+        let point = origin + direction * (step as f32 * TERRAIN_STEP);
+
+        heightfields
+            .iter()
+            .filter_map(|heightfield| heightfield.height_at(point.x, point.z))
+            .any(|height| height > point.y)
+    });
+
+    !blocked_by_box && !blocked_by_terrain
+}
+
+#[allow(clippy::too_many_arguments, clippy::type_complexity)]
+fn update_sun(
+    mut commands: Commands,
+    mut state: Local<SunState>,
+    settings: Res<SunSettings>,
+    camera: Res<Camera>,
+    sun_q: Query<&Sun>,
+    heightfield_q: Query<&Heightfield>,
+    bounds_q: Query<&Bounds, (Without<SunSprite>, Without<BoundsOutline>)>,
+    mut instance_q: Query<&mut components::Instance, With<SunSprite>>,
+) {
+    let sun = sun_q
+        .iter()
+        .next()
+        .filter(|sun| sun.direction != Vec3::ZERO);
+
+    let sun = match sun {
+        Some(sun) => sun,
+        None => {
+            if let Some(sprites) = state.sprites.take() {
+                despawn_sprites(&mut commands, sprites);
+            }
+
+            return;
+        }
+    };
+
+    let rebuild = state.sprites.as_ref().map_or(true, |sprites| {
+        sprites.settings != *settings || sprites.color != sun.color
+    });
+
+    if rebuild {
+        if let Some(sprites) = state.sprites.take() {
+            despawn_sprites(&mut commands, sprites);
+        }
+
+        state.sprites = Some(spawn_sprites(&mut commands, *settings, sun.color));
+    }
+
+    let to_sun = -sun.direction.normalize();
+    let disc_radius = SUN_DISTANCE * SUN_ANGULAR_RADIUS.to_radians().tan();
+
+    let mut set_instance = |entity: Entity, position: Vec3, scale: f32| {
+        if let Ok(mut instance) = instance_q.get_mut(entity) {
+            if instance.0.position != position || instance.0.scale != scale {
+                instance.0.position = position;
+                instance.0.scale = scale;
+            }
+        }
+    };
+
+    let state = &mut *state;
+    let sprites = match &state.sprites {
+        Some(sprites) => sprites,
+        None => return,
+    };
+
+    if let Some(disc) = sprites.disc {
+        set_instance(disc, camera.position + to_sun * SUN_DISTANCE, disc_radius);
+    }
+
+    if sprites.flares.is_empty() {
+        return;
+    }
+
+    // The middle and edges of the disc.
+    let side = to_sun.cross(Vec3::Y).normalize_or_zero();
+    let up = side.cross(to_sun);
+    let spread = disc_radius / SUN_DISTANCE;
+    let samples = [
+        to_sun,
+        to_sun + side * spread,
+        to_sun - side * spread,
+        to_sun + up * spread,
+        to_sun - up * spread,
+    ];
+
+    let heightfields: Vec<&Heightfield> = heightfield_q.iter().collect();
+    let boxes: Vec<&Bounds> = bounds_q.iter().collect();
+
+    let visible = samples
+        .iter()
+        .filter(|sample| reaches_sky(camera.position, sample.normalize(), &heightfields, &boxes))
+        .count() as f32
+        / samples.len() as f32;
+
+    let visibility =
+        state.visibility + (visible - state.visibility) * (FADE_SPEED * DELTA_TIME).min(1.0);
+    state.visibility = visibility;
+
+    // Towards the sun in view space, where the camera looks down -Z.
+    let view_sun = camera.rotation.inverse() * to_sun;
+    let facing = -view_sun.z;
+    let strength = visibility * ((facing - MIN_FACING) / (1.0 - MIN_FACING)).clamp(0.0, 1.0);
+
+    let center = Vec3::new(0.0, 0.0, -FLARE_DISTANCE);
+    let sun = if facing > 0.0 {
+        view_sun * (FLARE_DISTANCE / facing)
+    } else {
+        center
+    };
+
+    for (&entity, &(along, radius, _)) in sprites.flares.iter().zip(&FLARES) {
+        let point = center + (sun - center) * along;
+        set_instance(
+            entity,
+            camera.position + camera.rotation * point,
+            radius * strength,
+        );
+    }
+}
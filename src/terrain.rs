@@ -0,0 +1,512 @@
+use crossbeam_channel::{Receiver, Sender};
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Query, Res, ResMut},
+    components, renderer_core,
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::{fnv1a, AssetError};
+use crate::downloads::DownloadManager;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::MeshData;
+use crate::schedule;
+
+// Each level of detail skips every other vertex of the one before it.
+const LOD_LEVELS: usize = 3;
+// A chunk drops a level of detail every this many chunk widths away from the camera.
+const LOD_DISTANCE_IN_CHUNKS: f32 = 1.5;
+// How close the camera can get to the ground.
+const MIN_CLEARANCE: f32 = 0.25;
+
+// Blends four colors across the terrain by the red, green, blue and alpha channels of a weight
+// map. Materials only have one base color texture, so the layers are baked into one when the
+// terrain is built, and they're flat colors rather than tiling textures.
+#[derive(Clone, Debug)]
+pub struct SplatMap {
+    pub weights: Url,
+    // Linear rgb, one for each channel of the weights.
+    pub colors: [[f32; 3]; 4],
+}
+
+// A terrain built from a grayscale heightmap image, centered on `position` with black at its
+// height. It's split into `chunks` by `chunks` pieces, each with a few levels of detail that
+// are swapped between by distance. There are no skirts between chunks yet, so small cracks can
+// show where neighbours are at different levels. Changing it after it's been added does nothing.
+#[derive(Component, Clone, Debug)]
+pub struct Terrain {
+    pub heightmap: Url,
+    pub position: Vec3,
+    // The width and depth, in meters.
+    pub size: f32,
+    // How high white is above black, in meters.
+    pub height: f32,
+    pub chunks: u32,
+    pub splat_map: Option<SplatMap>,
+}
+
+impl Terrain {
+    pub fn new(heightmap: Url, position: Vec3, size: f32, height: f32) -> Self {
+        Self {
+            heightmap,
+            position,
+            size,
+            height,
+            chunks: 8,
+            splat_map: None,
+        }
+    }
+
+    fn key(&self) -> Url {
+        Url::parse(&format!(
+            "procedural:terrain-{:016x}",
+            fnv1a(format!("{:?}", self).as_bytes())
+        ))
+        .unwrap()
+    }
+
+    fn min_corner(&self) -> Vec3 {
+        self.position - Vec3::new(self.size, 0.0, self.size) * 0.5
+    }
+}
+
+// The terrain's heights, for keeping things on the ground. There's no character controller
+// yet, so for now it just stops the camera from going underneath the terrain.
+#[derive(Component, Clone, Debug)]
+pub struct Heightfield {
+    // The corner with the lowest x and z.
+    origin: Vec3,
+    // Between samples, in meters.
+    spacing: f32,
+    width: usize,
+    depth: usize,
+    heights: Vec<f32>,
+}
+
+impl Heightfield {
+    fn sample(&self, x: usize, z: usize) -> f32 {
+        self.heights[z.min(self.depth - 1) * self.width + x.min(self.width - 1)]
+    }
+
+    // The height of the ground in world space, or None outside of the terrain.
+    pub fn height_at(&self, x: f32, z: f32) -> Option<f32> {
+        let x = (x - self.origin.x) / self.spacing;
+        let z = (z - self.origin.z) / self.spacing;
+
+        if x < 0.0 || z < 0.0 || x > (self.width - 1) as f32 || z > (self.depth - 1) as f32 {
+            return None;
+        }
+
+        let (x0, z0) = (x.floor() as usize, z.floor() as usize);
+        let (tx, tz) = (x.fract(), z.fract());
+
+        let near = self.sample(x0, z0) * (1.0 - tx) + self.sample(x0 + 1, z0) * tx;
+        let far = self.sample(x0, z0 + 1) * (1.0 - tx) + self.sample(x0 + 1, z0 + 1) * tx;
+
+        Some(self.origin.y + near * (1.0 - tz) + far * tz)
+    }
+
+    fn normal(&self, x: usize, z: usize) -> Vec3 {
+        let dx = self.sample(x + 1, z) - self.sample(x.saturating_sub(1), z);
+        let dz = self.sample(x, z + 1) - self.sample(x, z.saturating_sub(1));
+
+        Vec3::new(-dx, 2.0 * self.spacing, -dz).normalize()
+    }
+
+    // A grid over the samples from `start` to `end` (inclusive), taking every `stride`th one.
+    // Positions are relative to the origin, and the uvs go across the whole terrain.
+    fn mesh(&self, start: (usize, usize), end: (usize, usize), stride: usize) -> MeshData {
+        let steps = |from: usize, to: usize| {
+            let mut steps: Vec<usize> = (from..to).step_by(stride).collect();
+            steps.push(to);
+            steps
+        };
+
+        let xs = steps(start.0, end.0);
+        let zs = steps(start.1, end.1);
+
+        let mut mesh = MeshData::default();
+
+        for &z in &zs {
+            for &x in &xs {
+                mesh.positions.push(Vec3::new(
+                    x as f32 * self.spacing,
+                    self.sample(x, z),
+                    z as f32 * self.spacing,
+                ));
+                mesh.normals.push(self.normal(x, z));
+                mesh.uvs.push([
+                    x as f32 / (self.width - 1) as f32,
+                    z as f32 / (self.depth - 1) as f32,
+                ]);
+            }
+        }
+
+        let row = xs.len() as u32;
+
+        for z in 0..zs.len() as u32 - 1 {
+            for x in 0..row - 1 {
+                let corner = z * row + x;
+
+                mesh.indices.extend_from_slice(&[
+                    corner,
+                    corner + row,
+                    corner + 1,
+                    corner + 1,
+                    corner + row,
+                    corner + row + 1,
+                ]);
+            }
+        }
+
+        mesh
+    }
+}
+
+struct BuiltChunk {
+    center: Vec3,
+    // From the most detailed down.
+    lods: Vec<Url>,
+}
+
+struct BuiltTerrain {
+    terrain: Entity,
+    heightfield: Heightfield,
+    chunk_size: f32,
+    chunks: Vec<BuiltChunk>,
+}
+
+// The model of each level of detail of a chunk, and the instance that shows one of them.
+#[derive(Component)]
+pub struct TerrainChunk {
+    center: Vec3,
+    size: f32,
+    lods: Vec<Entity>,
+    current: usize,
+}
+
+fn to_srgb(linear: f32) -> u8 {
+    (linear.clamp(0.0, 1.0).powf(1.0 / 2.2) * 255.0).round() as u8
+}
+
+fn bake_splat_map(bytes: &[u8], colors: &[[f32; 3]; 4]) -> Result<Vec<u8>, String> {
+    let weights = image::load_from_memory(bytes)
+        .map_err(|error| error.to_string())?
+        .to_rgba8();
+
+    let baked = image::RgbImage::from_fn(weights.width(), weights.height(), |x, y| {
+        let weights = weights.get_pixel(x, y).0.map(|weight| weight as f32);
+        let total: f32 = weights.iter().sum();
+
+        if total == 0.0 {
+            return image::Rgb(colors[0].map(to_srgb));
+        }
+
+        let color = weights
+            .iter()
+            .zip(colors)
+            .fold([0.0; 3], |mut color, (weight, layer)| {
+                for (channel, layer) in color.iter_mut().zip(layer) {
+                    *channel += layer * weight / total;
+                }
+                color
+            });
+
+        image::Rgb(color.map(to_srgb))
+    });
+
+    let mut png = Vec::new();
+
+    baked
+        .write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        )
+        .map_err(|error| error.to_string())?;
+
+    Ok(png)
+}
+
+fn build_heightfield(terrain: &Terrain, bytes: &[u8]) -> Result<Heightfield, String> {
+    let image = image::load_from_memory(bytes)
+        .map_err(|error| error.to_string())?
+        .to_luma16();
+
+    let (width, depth) = (image.width() as usize, image.height() as usize);
+
+    if width < 2 || depth < 2 {
+        return Err(format!("{}x{} is too small for a heightmap", width, depth));
+    }
+
+    Ok(Heightfield {
+        origin: terrain.min_corner(),
+        spacing: terrain.size / (width.max(depth) - 1) as f32,
+        width,
+        depth,
+        heights: image
+            .pixels()
+            .map(|pixel| pixel.0[0] as f32 / u16::MAX as f32 * terrain.height)
+            .collect(),
+    })
+}
+
+// Returns the chunks' meshes as glb files, from the most detailed level down.
+fn build_chunks(
+    terrain: &Terrain,
+    heightfield: &Heightfield,
+    material: &MaterialDescriptor,
+) -> Vec<(Vec3, Vec<Vec<u8>>)> {
+    let _span = tracing::info_span!("build_terrain_chunks").entered();
+
+    let chunks = terrain.chunks.max(1) as usize;
+    let bounds = |samples: usize, chunk: usize| (samples - 1) * chunk / chunks;
+
+    let mut built = Vec::new();
+
+    for chunk_z in 0..chunks {
+        for chunk_x in 0..chunks {
+            let start = (
+                bounds(heightfield.width, chunk_x),
+                bounds(heightfield.depth, chunk_z),
+            );
+            let end = (
+                bounds(heightfield.width, chunk_x + 1),
+                bounds(heightfield.depth, chunk_z + 1),
+            );
+
+            if start.0 == end.0 || start.1 == end.1 {
+                continue;
+            }
+
+            let center = heightfield.origin
+                + Vec3::new(
+                    (start.0 + end.0) as f32 * 0.5 * heightfield.spacing,
+                    0.0,
+                    (start.1 + end.1) as f32 * 0.5 * heightfield.spacing,
+                );
+
+            let lods = (0..LOD_LEVELS)
+                .map(|lod| heightfield.mesh(start, end, 1 << lod).to_glb(material))
+                .collect();
+
+            built.push((center, lods));
+        }
+    }
+
+    built
+}
+
+pub(crate) struct TerrainBuilds {
+    sender: Sender<BuiltTerrain>,
+    receiver: Receiver<BuiltTerrain>,
+}
+
+impl Default for TerrainBuilds {
+    fn default() -> Self {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        Self { sender, receiver }
+    }
+}
+
+pub struct TerrainPlugin;
+
+impl Plugin for TerrainPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TerrainBuilds::default());
+
+        app.add_system(build_terrains);
+        app.add_system(spawn_terrain_chunks);
+        app.add_system_set(schedule::simulation().with_system(stay_above_terrain));
+        app.add_system_set(schedule::pre_render().with_system(select_chunk_lods));
+    }
+}
+
+fn build_terrains(
+    builds: Res<TerrainBuilds>,
+    manager: Res<DownloadManager>,
+    terrain_q: Query<(Entity, &Terrain), Added<Terrain>>,
+) {
+    for (entity, terrain) in terrain_q.iter() {
+        let terrain = terrain.clone();
+        let cache = manager.cache();
+        let sender = builds.sender.clone();
+
+        log::info!("Building a terrain from {}", terrain.heightmap);
+
+        manager.spawn(async move {
+            let result: Result<_, AssetError> = async {
+                let heightmap = cache.fetch(&terrain.heightmap).await?;
+
+                let key = terrain.key();
+
+                let base_color_texture = match &terrain.splat_map {
+                    Some(splat_map) => {
+                        let weights = cache.fetch(&splat_map.weights).await?;
+                        let baked = bake_splat_map(&weights, &splat_map.colors)
+                            .map_err(AssetError::Decode)?;
+
+                        Some(cache.store_derived(&key, "splat.png", &baked)?)
+                    }
+                    None => None,
+                };
+
+                let material = MaterialDescriptor {
+                    base_color: [1.0; 4],
+                    roughness: 0.9,
+                    base_color_texture,
+                    ..Default::default()
+                };
+
+                let build = move || {
+                    let heightfield = build_heightfield(&terrain, &heightmap)?;
+                    let chunks = build_chunks(&terrain, &heightfield, &material);
+                    let chunk_size = terrain.size / terrain.chunks.max(1) as f32;
+                    Ok::<_, String>((heightfield, chunks, chunk_size))
+                };
+
+                #[cfg(not(feature = "wasm"))]
+                let built = tokio::task::spawn_blocking(build)
+                    .await
+                    .map_err(|error| AssetError::Decode(error.to_string()))?;
+
+                #[cfg(feature = "wasm")]
+                let built = build();
+
+                let (heightfield, chunks, chunk_size) = built.map_err(AssetError::Decode)?;
+
+                let chunks = chunks
+                    .into_iter()
+                    .enumerate()
+                    .map(|(index, (center, lods))| {
+                        let lods = lods
+                            .iter()
+                            .enumerate()
+                            .map(|(lod, glb)| {
+                                cache.store_derived(&key, &format!("{}.{}.glb", index, lod), glb)
+                            })
+                            .collect::<Result<_, _>>()?;
+
+                        Ok(BuiltChunk { center, lods })
+                    })
+                    .collect::<Result<_, AssetError>>()?;
+
+                Ok(BuiltTerrain {
+                    terrain: entity,
+                    heightfield,
+                    chunk_size,
+                    chunks,
+                })
+            }
+            .await;
+
+            match result {
+                Ok(built) => {
+                    let _ = sender.send(built);
+                }
+                Err(error) => log::warn!("Failed to build a terrain: {}", error),
+            }
+        });
+    }
+}
+
+fn spawn_terrain_chunks(
+    mut commands: Commands,
+    builds: Res<TerrainBuilds>,
+    terrain_q: Query<&Terrain>,
+) {
+    for built in builds.receiver.try_iter() {
+        // Removed while it was being built.
+        let terrain = match terrain_q.get(built.terrain) {
+            Ok(terrain) => terrain,
+            Err(_) => continue,
+        };
+
+        let origin = terrain.min_corner();
+
+        for chunk in built.chunks {
+            let lods: Vec<Entity> = chunk
+                .lods
+                .into_iter()
+                .map(|url| {
+                    commands
+                        .spawn()
+                        .insert(components::ModelUrl(url))
+                        .insert(components::Instances(Default::default()))
+                        .insert(components::InstanceRange(Default::default()))
+                        .id()
+                })
+                .collect();
+
+            commands
+                .spawn()
+                .insert(components::InstanceOf(lods[0]))
+                .insert(components::Instance(renderer_core::Instance::new(
+                    origin,
+                    1.0,
+                    Default::default(),
+                )))
+                .insert(TerrainChunk {
+                    center: chunk.center,
+                    size: built.chunk_size,
+                    lods,
+                    current: 0,
+                });
+        }
+
+        commands.entity(built.terrain).insert(built.heightfield);
+    }
+}
+
+fn select_chunk_lods(
+    mut commands: Commands,
+    camera: Res<Camera>,
+    mut chunk_q: Query<(Entity, &mut TerrainChunk)>,
+) {
+    for (entity, mut chunk) in chunk_q.iter_mut() {
+        let distance = Vec3::new(
+            camera.position.x - chunk.center.x,
+            0.0,
+            camera.position.z - chunk.center.z,
+        )
+        .length();
+
+        let lod =
+            ((distance / (chunk.size * LOD_DISTANCE_IN_CHUNKS)) as usize).min(chunk.lods.len() - 1);
+
+        if lod != chunk.current {
+            chunk.current = lod;
+            commands
+                .entity(entity)
+                .insert(components::InstanceOf(chunk.lods[lod]));
+        }
+    }
+}
+
+// Goes by the camera rig, which is what moves the player.
+fn stay_above_terrain(
+    camera_rig: Option<ResMut<dolly::rig::CameraRig>>,
+    heightfield_q: Query<&Heightfield>,
+) {
+    let mut camera_rig = match camera_rig {
+        Some(camera_rig) => camera_rig,
+        None => return,
+    };
+
+    let position = camera_rig.driver::<dolly::drivers::Position>().position;
+
+    let ground = heightfield_q
+        .iter()
+        .filter_map(|heightfield| heightfield.height_at(position.x, position.z))
+        .reduce(f32::max);
+
+    if let Some(ground) = ground {
+        if position.y < ground + MIN_CLEARANCE {
+            camera_rig
+                .driver_mut::<dolly::drivers::Position>()
+                .position
+                .y = ground + MIN_CLEARANCE;
+        }
+    }
+}
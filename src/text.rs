@@ -0,0 +1,415 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Changed, Commands, Component, Entity, Query, Res, ResMut, With, Without},
+    components, renderer_core,
+    renderer_core::glam::{Mat3, Quat},
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::fnv1a;
+use crate::downloads::DownloadManager;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+
+const GLYPH_WIDTH: u32 = 5;
+const GLYPH_HEIGHT: u32 = 7;
+// Glyph cells, with a column and two rows between them.
+const CELL_WIDTH: u32 = GLYPH_WIDTH + 1;
+const CELL_HEIGHT: u32 = GLYPH_HEIGHT + 2;
+// Around the text, in font pixels.
+const PADDING: u32 = 2;
+// Texels per font pixel, so that the glyphs stay sharp with linear filtering.
+const TEXEL_SCALE: u32 = 4;
+const TEXTURE_EXTENSION: &str = "png";
+
+// The classic 5x7 font for printable ascii (' ' to '~'), as columns from left to right with
+// the top row in the lowest bit. Anything else is drawn as '?'.
+const FONT: [[u8; 5]; 95] = [
+    [0x00, 0x00, 0x00, 0x00, 0x00],
+    [0x00, 0x00, 0x5f, 0x00, 0x00],
+    [0x00, 0x07, 0x00, 0x07, 0x00],
+    [0x14, 0x7f, 0x14, 0x7f, 0x14],
+    [0x24, 0x2a, 0x7f, 0x2a, 0x12],
+    [0x23, 0x13, 0x08, 0x64, 0x62],
+    [0x36, 0x49, 0x55, 0x22, 0x50],
+    [0x00, 0x05, 0x03, 0x00, 0x00],
+    [0x00, 0x1c, 0x22, 0x41, 0x00],
+    [0x00, 0x41, 0x22, 0x1c, 0x00],
+    [0x14, 0x08, 0x3e, 0x08, 0x14],
+    [0x08, 0x08, 0x3e, 0x08, 0x08],
+    [0x00, 0x50, 0x30, 0x00, 0x00],
+    [0x08, 0x08, 0x08, 0x08, 0x08],
+    [0x00, 0x60, 0x60, 0x00, 0x00],
+    [0x20, 0x10, 0x08, 0x04, 0x02],
+    [0x3e, 0x51, 0x49, 0x45, 0x3e],
+    [0x00, 0x42, 0x7f, 0x40, 0x00],
+    [0x42, 0x61, 0x51, 0x49, 0x46],
+    [0x21, 0x41, 0x45, 0x4b, 0x31],
+    [0x18, 0x14, 0x12, 0x7f, 0x10],
+    [0x27, 0x45, 0x45, 0x45, 0x39],
+    [0x3c, 0x4a, 0x49, 0x49, 0x30],
+    [0x01, 0x71, 0x09, 0x05, 0x03],
+    [0x36, 0x49, 0x49, 0x49, 0x36],
+    [0x06, 0x49, 0x49, 0x29, 0x1e],
+    [0x00, 0x36, 0x36, 0x00, 0x00],
+    [0x00, 0x56, 0x36, 0x00, 0x00],
+    [0x08, 0x14, 0x22, 0x41, 0x00],
+    [0x14, 0x14, 0x14, 0x14, 0x14],
+    [0x00, 0x41, 0x22, 0x14, 0x08],
+    [0x02, 0x01, 0x51, 0x09, 0x06],
+    [0x32, 0x49, 0x79, 0x41, 0x3e],
+    [0x7e, 0x11, 0x11, 0x11, 0x7e],
+    [0x7f, 0x49, 0x49, 0x49, 0x36],
+    [0x3e, 0x41, 0x41, 0x41, 0x22],
+    [0x7f, 0x41, 0x41, 0x22, 0x1c],
+    [0x7f, 0x49, 0x49, 0x49, 0x41],
+    [0x7f, 0x09, 0x09, 0x09, 0x01],
+    [0x3e, 0x41, 0x49, 0x49, 0x7a],
+    [0x7f, 0x08, 0x08, 0x08, 0x7f],
+    [0x00, 0x41, 0x7f, 0x41, 0x00],
+    [0x20, 0x40, 0x41, 0x3f, 0x01],
+    [0x7f, 0x08, 0x14, 0x22, 0x41],
+    [0x7f, 0x40, 0x40, 0x40, 0x40],
+    [0x7f, 0x02, 0x0c, 0x02, 0x7f],
+    [0x7f, 0x04, 0x08, 0x10, 0x7f],
+    [0x3e, 0x41, 0x41, 0x41, 0x3e],
+    [0x7f, 0x09, 0x09, 0x09, 0x06],
+    [0x3e, 0x41, 0x51, 0x21, 0x5e],
+    [0x7f, 0x09, 0x19, 0x29, 0x46],
+    [0x46, 0x49, 0x49, 0x49, 0x31],
+    [0x01, 0x01, 0x7f, 0x01, 0x01],
+    [0x3f, 0x40, 0x40, 0x40, 0x3f],
+    [0x1f, 0x20, 0x40, 0x20, 0x1f],
+    [0x3f, 0x40, 0x38, 0x40, 0x3f],
+    [0x63, 0x14, 0x08, 0x14, 0x63],
+    [0x07, 0x08, 0x70, 0x08, 0x07],
+    [0x61, 0x51, 0x49, 0x45, 0x43],
+    [0x00, 0x7f, 0x41, 0x41, 0x00],
+    [0x02, 0x04, 0x08, 0x10, 0x20],
+    [0x00, 0x41, 0x41, 0x7f, 0x00],
+    [0x04, 0x02, 0x01, 0x02, 0x04],
+    [0x40, 0x40, 0x40, 0x40, 0x40],
+    [0x00, 0x01, 0x02, 0x04, 0x00],
+    [0x20, 0x54, 0x54, 0x54, 0x78],
+    [0x7f, 0x48, 0x44, 0x44, 0x38],
+    [0x38, 0x44, 0x44, 0x44, 0x20],
+    [0x38, 0x44, 0x44, 0x48, 0x7f],
+    [0x38, 0x54, 0x54, 0x54, 0x18],
+    [0x08, 0x7e, 0x09, 0x01, 0x02],
+    [0x0c, 0x52, 0x52, 0x52, 0x3e],
+    [0x7f, 0x08, 0x04, 0x04, 0x78],
+    [0x00, 0x44, 0x7d, 0x40, 0x00],
+    [0x20, 0x40, 0x44, 0x3d, 0x00],
+    [0x7f, 0x10, 0x28, 0x44, 0x00],
+    [0x00, 0x41, 0x7f, 0x40, 0x00],
+    [0x7c, 0x04, 0x18, 0x04, 0x78],
+    [0x7c, 0x08, 0x04, 0x04, 0x78],
+    [0x38, 0x44, 0x44, 0x44, 0x38],
+    [0x7c, 0x14, 0x14, 0x14, 0x08],
+    [0x08, 0x14, 0x14, 0x18, 0x7c],
+    [0x7c, 0x08, 0x04, 0x04, 0x08],
+    [0x48, 0x54, 0x54, 0x54, 0x20],
+    [0x04, 0x3f, 0x44, 0x40, 0x20],
+    [0x3c, 0x40, 0x40, 0x20, 0x7c],
+    [0x1c, 0x20, 0x40, 0x20, 0x1c],
+    [0x3c, 0x40, 0x30, 0x40, 0x3c],
+    [0x44, 0x28, 0x10, 0x28, 0x44],
+    [0x0c, 0x50, 0x50, 0x50, 0x3c],
+    [0x44, 0x64, 0x54, 0x4c, 0x44],
+    [0x00, 0x08, 0x36, 0x41, 0x00],
+    [0x00, 0x00, 0x7f, 0x00, 0x00],
+    [0x00, 0x41, 0x36, 0x08, 0x00],
+    [0x08, 0x04, 0x08, 0x10, 0x08],
+];
+
+fn glyph(character: char) -> &'static [u8; 5] {
+    match character {
+        ' '..='~' => &FONT[character as usize - ' ' as usize],
+        _ => &FONT['?' as usize - ' ' as usize],
+    }
+}
+
+// Breaks the text into lines of at most `columns` characters, between words where it can.
+pub fn wrap(text: &str, columns: usize) -> Vec<String> {
+    let columns = columns.max(1);
+    let mut lines = Vec::new();
+
+    for paragraph in text.lines() {
+        let mut line = String::new();
+
+        for word in paragraph.split_whitespace() {
+            let mut word: Vec<char> = word.chars().collect();
+
+            // Words that don't fit on a line of their own are split.
+            while word.len() > columns {
+                if !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+
+                lines.push(word.drain(..columns).collect());
+            }
+
+            let length = line.chars().count();
+
+            if length > 0 && length + 1 + word.len() > columns {
+                lines.push(std::mem::take(&mut line));
+            }
+
+            if !line.is_empty() {
+                line.push(' ');
+            }
+
+            line.extend(word);
+        }
+
+        lines.push(line);
+    }
+
+    lines
+}
+
+// Draws the lines onto an image, in sRGB colours.
+pub fn rasterize(lines: &[String], color: [u8; 4], background: [u8; 4]) -> image::RgbaImage {
+    let columns = lines
+        .iter()
+        .map(|line| line.chars().count())
+        .max()
+        .unwrap_or(0) as u32;
+    let rows = lines.len().max(1) as u32;
+
+    let width = (columns * CELL_WIDTH + PADDING * 2) * TEXEL_SCALE;
+    let height = (rows * CELL_HEIGHT + PADDING * 2) * TEXEL_SCALE;
+
+    let mut image = image::RgbaImage::from_pixel(width, height, image::Rgba(background));
+
+    for (row, line) in lines.iter().enumerate() {
+        for (column, character) in line.chars().enumerate() {
+            let left = PADDING + column as u32 * CELL_WIDTH;
+            let top = PADDING + row as u32 * CELL_HEIGHT + 1;
+
+            for (x, bits) in glyph(character).iter().enumerate() {
+                for y in 0..GLYPH_HEIGHT {
+                    if bits & (1 << y) == 0 {
+                        continue;
+                    }
+
+                    for texel_y in 0..TEXEL_SCALE {
+                        for texel_x in 0..TEXEL_SCALE {
+                            image.put_pixel(
+                                (left + x as u32) * TEXEL_SCALE + texel_x,
+                                (top + y) * TEXEL_SCALE + texel_y,
+                                image::Rgba(color),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    image
+}
+
+// Text drawn in the world on a quad that turns around the vertical axis to face the camera,
+// like speech bubbles and measurement labels. The text is rasterised with a built in bitmap
+// font onto a texture, so changing it loads a new model and is best kept to when it changes.
+#[derive(Component, Clone, Debug, PartialEq)]
+pub struct TextLabel {
+    pub text: String,
+    // The middle of the label's bottom edge.
+    pub position: Vec3,
+    // Of each line of text, in meters.
+    pub line_height: f32,
+    // Lines are wrapped to this many characters.
+    pub columns: usize,
+    // sRGB, with alpha.
+    pub color: [u8; 4],
+    pub background: [u8; 4],
+}
+
+impl TextLabel {
+    pub fn new(text: impl Into<String>, position: Vec3) -> Self {
+        Self {
+            text: text.into(),
+            position,
+            line_height: 0.08,
+            columns: 24,
+            color: [0, 0, 0, 255],
+            background: [255, 255, 255, 230],
+        }
+    }
+
+    // Everything that goes into the texture, for telling when it needs to be drawn again.
+    fn texture_key(&self) -> u64 {
+        fnv1a(
+            format!(
+                "{:?} {} {:?} {:?}",
+                self.text, self.columns, self.color, self.background
+            )
+            .as_bytes(),
+        )
+    }
+}
+
+// The quad model of each label, along with the texture it was drawn with.
+#[derive(Default)]
+struct TextModels(HashMap<Entity, (Entity, u64)>);
+
+// Named after the label as well as the text, so that two labels with the same text don't share
+// a texture that one of them might remove.
+fn texture_url(label: Entity, key: u64) -> Url {
+    Url::parse(&format!("text:{:x}/{:016x}", label.to_bits(), key)).unwrap()
+}
+
+pub struct TextPlugin;
+
+impl Plugin for TextPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TextModels::default());
+
+        app.add_system(spawn_text_models);
+        app.add_system(despawn_text_models);
+        app.add_system_set(schedule::pre_render().with_system(face_text_labels));
+    }
+}
+
+fn spawn_text_models(
+    mut commands: Commands,
+    mut models: ResMut<TextModels>,
+    manager: Res<DownloadManager>,
+    label_q: Query<(Entity, &TextLabel), Changed<TextLabel>>,
+) {
+    for (entity, label) in label_q.iter() {
+        let key = label.texture_key();
+
+        if models.0.get(&entity).map(|&(_, drawn)| drawn) == Some(key) {
+            continue;
+        }
+
+        let lines = wrap(&label.text, label.columns);
+        let image = rasterize(&lines, label.color, label.background);
+
+        let mut png = Vec::new();
+
+        if let Err(error) = image.write_to(
+            &mut std::io::Cursor::new(&mut png),
+            image::ImageOutputFormat::Png,
+        ) {
+            log::warn!("Failed to encode the text {:?}: {}", label.text, error);
+            continue;
+        }
+
+        let texture_url = texture_url(entity, key);
+        let texture = match manager
+            .cache()
+            .store_derived(&texture_url, TEXTURE_EXTENSION, &png)
+        {
+            Ok(texture) => texture,
+            Err(error) => {
+                log::warn!("Failed to store the text {:?}: {}", label.text, error);
+                continue;
+            }
+        };
+
+        let depth = label.line_height * image.height() as f32 / (CELL_HEIGHT * TEXEL_SCALE) as f32;
+        let width = depth * image.width() as f32 / image.height() as f32;
+
+        let model = spawn_primitive(
+            &mut commands,
+            Primitive::Plane { width, depth },
+            MaterialDescriptor {
+                base_color: [0.0, 0.0, 0.0, 1.0],
+                base_color_texture: Some(texture.clone()),
+                emissive: [1.0; 3],
+                emissive_texture: Some(texture),
+                blend: true,
+                ..Default::default()
+            },
+        );
+
+        // Placed by `face_text_labels`.
+        commands
+            .entity(entity)
+            .insert(components::InstanceOf(model))
+            .insert(components::Instance(renderer_core::Instance::new(
+                label.position,
+                1.0,
+                Quat::IDENTITY,
+            )))
+            .insert(TextSize(depth));
+
+        if let Some((previous, previous_key)) = models.0.insert(entity, (model, key)) {
+            commands.entity(previous).despawn();
+            manager
+                .cache()
+                .remove_derived(&texture_url(entity, previous_key), TEXTURE_EXTENSION);
+        }
+    }
+}
+
+// The height of a label's quad, in meters.
+#[derive(Component)]
+struct TextSize(f32);
+
+fn despawn_text_models(
+    mut commands: Commands,
+    mut models: ResMut<TextModels>,
+    manager: Res<DownloadManager>,
+    label_q: Query<(), With<TextLabel>>,
+    removed_q: Query<Entity, (With<TextSize>, Without<TextLabel>)>,
+) {
+    models.0.retain(|&label, &mut (model, key)| {
+        let keep = label_q.get(label).is_ok();
+
+        if !keep {
+            commands.entity(model).despawn();
+            manager
+                .cache()
+                .remove_derived(&texture_url(label, key), TEXTURE_EXTENSION);
+        }
+
+        keep
+    });
+
+    // Labels taken off entities that are still around.
+    for entity in removed_q.iter() {
+        commands
+            .entity(entity)
+            .remove::<components::InstanceOf>()
+            .remove::<components::Instance>()
+            .remove::<TextSize>();
+    }
+}
+
+fn face_text_labels(
+    camera: Res<Camera>,
+    mut label_q: Query<(&TextLabel, &TextSize, &mut components::Instance)>,
+) {
+    for (label, size, mut instance) in label_q.iter_mut() {
+        let center = label.position + Vec3::new(0.0, size.0 * 0.5, 0.0);
+
+        let mut toward_camera = camera.position - center;
+        toward_camera.y = 0.0;
+        let toward_camera = toward_camera.try_normalize().unwrap_or(Vec3::Z);
+
+        // The plane faces up with the top of its texture towards -Z, so this stands it up
+        // facing the camera with the text the right way up.
+        let rotation = Quat::from_mat3(&Mat3::from_cols(
+            Vec3::Y.cross(toward_camera),
+            toward_camera,
+            -Vec3::Y,
+        ));
+
+        // Only touched when something changed, so that the renderer isn't sent every label
+        // again each frame.
+        if instance.0.position != center || instance.0.rotation != rotation {
+            instance.0.position = center;
+            instance.0.rotation = rotation;
+        }
+    }
+}
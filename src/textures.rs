@@ -0,0 +1,199 @@
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Res, ResMut},
+};
+
+use crate::debug_stats::DebugStats;
+use crate::downloads::TextureInspected;
+
+const KTX2_IDENTIFIER: &[u8; 12] = b"\xABKTX 20\xBB\r\n\x1A\n";
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+
+// The gpu format that Basis Universal textures are expected to be transcoded into. The
+// renderer picks the real one itself from what the adapter supports, without saying which, so
+// this is a guess by platform and only goes into the stats.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TranscodeTarget {
+    Astc,
+    Bc7,
+    Etc2,
+}
+
+impl TranscodeTarget {
+    // ASTC is what mobile gpus (including the Quest) are best at, desktop gpus all support BC7,
+    // and ETC2 is guaranteed to exist on anything running GLES 3 / WebGL 2.
+    pub fn for_platform() -> Self {
+        #[cfg(not(feature = "wasm"))]
+        {
+            if cfg!(any(target_os = "android", target_os = "ios")) {
+                Self::Astc
+            } else {
+                Self::Bc7
+            }
+        }
+
+        #[cfg(feature = "wasm")]
+        {
+            let user_agent = web_sys::window()
+                .and_then(|window| window.navigator().user_agent().ok())
+                .unwrap_or_default();
+
+            if ["OculusBrowser", "Quest", "iPhone", "iPad"]
+                .iter()
+                .any(|device| user_agent.contains(device))
+            {
+                Self::Astc
+            } else if user_agent.contains("Android") || user_agent.contains("Mobile") {
+                Self::Etc2
+            } else {
+                Self::Bc7
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ktx2Source {
+    Etc1s,
+    Uastc,
+    // Already stored in a gpu format, so no transcoding happens.
+    Native(u32),
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct Ktx2Info {
+    pub source: Ktx2Source,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Ktx2Info {
+    pub fn parse(bytes: &[u8]) -> Option<Self> {
+        if !bytes.starts_with(KTX2_IDENTIFIER) {
+            return None;
+        }
+
+        let read_u32 = |offset: usize| {
+            Some(u32::from_le_bytes(
+                bytes.get(offset..offset + 4)?.try_into().ok()?,
+            ))
+        };
+
+        let vk_format = read_u32(12)?;
+        let supercompression = read_u32(44)?;
+
+        let source = if vk_format != 0 {
+            Ktx2Source::Native(vk_format)
+        } else if supercompression == SUPERCOMPRESSION_BASIS_LZ {
+            Ktx2Source::Etc1s
+        } else {
+            Ktx2Source::Uastc
+        };
+
+        Some(Self {
+            source,
+            width: read_u32(20)?,
+            height: read_u32(24)?,
+        })
+    }
+}
+
+pub struct TexturePlugin;
+
+impl Plugin for TexturePlugin {
+    fn build(&self, app: &mut App) {
+        let target = TranscodeTarget::for_platform();
+
+        log::info!("Expecting KTX2 textures to be transcoded to {:?}", target);
+
+        app.insert_resource(target);
+        app.init_resource::<DebugStats>();
+
+        app.add_system(record_texture_formats);
+    }
+}
+
+fn record_texture_formats(
+    mut inspected: EventReader<TextureInspected>,
+    target: Res<TranscodeTarget>,
+    mut stats: ResMut<DebugStats>,
+) {
+    stats.transcode_target = Some(*target);
+
+    for TextureInspected { url, info } in inspected.iter() {
+        let format = match info.source {
+            Ktx2Source::Native(_) => None,
+            _ => Some(*target),
+        };
+
+        log::info!(
+            "{}: {}x{} {:?} -> {:?}",
+            url,
+            info.width,
+            info.height,
+            info.source,
+            format
+        );
+
+        stats.textures.insert(
+            url.clone(),
+            TextureStats {
+                info: *info,
+                format,
+            },
+        );
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TextureStats {
+    pub info: Ktx2Info,
+    // The expected transcode target, or None for textures that aren't transcoded.
+    pub format: Option<TranscodeTarget>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(vk_format: u32, width: u32, height: u32, supercompression: u32) -> Vec<u8> {
+        let mut bytes = vec![0; 80];
+        bytes[..12].copy_from_slice(KTX2_IDENTIFIER);
+
+        for (offset, value) in [
+            (12, vk_format),
+            (20, width),
+            (24, height),
+            (44, supercompression),
+        ] {
+            bytes[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+
+        bytes
+    }
+
+    #[test]
+    fn basis_textures() {
+        let info = Ktx2Info::parse(&header(0, 512, 256, SUPERCOMPRESSION_BASIS_LZ)).unwrap();
+
+        assert_eq!(info.source, Ktx2Source::Etc1s);
+        assert_eq!((info.width, info.height), (512, 256));
+
+        let info = Ktx2Info::parse(&header(0, 64, 64, 0)).unwrap();
+        assert_eq!(info.source, Ktx2Source::Uastc);
+    }
+
+    #[test]
+    fn native_textures() {
+        let info = Ktx2Info::parse(&header(97, 128, 128, 0)).unwrap();
+
+        assert_eq!(info.source, Ktx2Source::Native(97));
+        assert_eq!((info.width, info.height), (128, 128));
+    }
+
+    #[test]
+    fn not_ktx2() {
+        assert!(Ktx2Info::parse(b"\x89PNG\r\n\x1a\n").is_none());
+        assert!(Ktx2Info::parse(&header(0, 1, 1, 0)[..40]).is_none());
+    }
+}
@@ -0,0 +1,163 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, CoreStage, EventReader, Plugin},
+    bevy_ecs::prelude::{Entity, Local, Query, Res, ResMut, World},
+    components,
+};
+
+use crate::console::Console;
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::photo_mode::PhotoMode;
+use crate::schedule::{self, FrameStep, SimulationPaused};
+use crate::DELTA_TIME;
+
+const MIN_SCALE: f32 = 1.0 / 16.0;
+const MAX_SCALE: f32 = 4.0;
+
+// Slows down (or speeds up) the simulation and animations, for looking at animation blending
+// and particles in slow motion. Camera input isn't scaled, so the scene can still be walked
+// around at the usual speed. The SlowDown and SpeedUp bindings ([ and ] by default) halve and
+// double it, Pause (\) pauses the simulation and FrameStep (.) steps it a frame at a time.
+pub struct TimeScale {
+    pub scale: f32,
+}
+
+impl TimeScale {
+    // How far the simulation moves each frame.
+    pub fn delta(&self) -> f32 {
+        DELTA_TIME * self.scale
+    }
+}
+
+impl Default for TimeScale {
+    fn default() -> Self {
+        Self { scale: 1.0 }
+    }
+}
+
+pub struct TimeScalePlugin;
+
+impl Plugin for TimeScalePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(TimeScale::default());
+        app.init_resource::<SimulationPaused>();
+        app.init_resource::<FrameStep>();
+
+        let mut console = app.world.get_resource_or_insert_with(Console::default);
+        console.register("set_time_scale", "set_time_scale <scale>", set_time_scale);
+        console.register("pause", "pause", pause);
+        console.register("step", "step", step);
+
+        app.add_system_to_stage(CoreStage::First, schedule::start_frame_step);
+        app.add_system_set(schedule::input().with_system(handle_time_scale_keys));
+        app.add_system_set(schedule::pre_render().with_system(scale_animations));
+    }
+}
+
+fn set_scale(time_scale: &mut TimeScale, scale: f32) {
+    time_scale.scale = scale.clamp(MIN_SCALE, MAX_SCALE);
+    log::info!("Time scale {}", time_scale.scale);
+}
+
+fn handle_time_scale_keys(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut time_scale: ResMut<TimeScale>,
+    mut paused: ResMut<SimulationPaused>,
+    mut frame_step: ResMut<FrameStep>,
+    photo_mode: Res<PhotoMode>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        match bindings.action(*key) {
+            Some(KeyAction::SlowDown) => {
+                let scale = time_scale.scale / 2.0;
+                set_scale(&mut time_scale, scale);
+            }
+            Some(KeyAction::SpeedUp) => {
+                let scale = time_scale.scale * 2.0;
+                set_scale(&mut time_scale, scale);
+            }
+            // Photo mode pauses the simulation itself, until it's left.
+            Some(KeyAction::Pause) if !photo_mode.is_active() => paused.0 = !paused.0,
+            Some(KeyAction::FrameStep) if paused.0 => frame_step.requested = true,
+            _ => {}
+        }
+    }
+}
+
+fn set_time_scale(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let arg = args.get(0).ok_or("Missing scale")?;
+    let scale: f32 = arg
+        .parse()
+        .map_err(|_| format!("Invalid scale {:?}", arg))?;
+
+    set_scale(&mut world.resource_mut::<TimeScale>(), scale);
+
+    Ok(String::new())
+}
+
+fn pause(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    if world.resource::<PhotoMode>().is_active() {
+        return Err("Photo mode already pauses the simulation".to_string());
+    }
+
+    let mut paused = world.resource_mut::<SimulationPaused>();
+    paused.0 = !paused.0;
+
+    Ok(if paused.0 { "Paused" } else { "Resumed" }.to_string())
+}
+
+fn step(world: &mut World, _args: &[&str]) -> Result<String, String> {
+    if !world.resource::<SimulationPaused>().0 {
+        return Err("The simulation has to be paused to step it".to_string());
+    }
+
+    world.resource_mut::<FrameStep>().requested = true;
+
+    Ok(String::new())
+}
+
+// The renderer advances animations by the real frame time itself, so how far each one moved
+// since the last frame is scaled back here. `last_times` has the animation index and time that
+// each one was left at.
+fn scale_animations(
+    time_scale: Res<TimeScale>,
+    paused: Res<SimulationPaused>,
+    frame_step: Res<FrameStep>,
+    photo_mode: Res<PhotoMode>,
+    mut last_times: Local<HashMap<Entity, (usize, f32)>>,
+    mut animation_q: Query<(Entity, &mut components::AnimationState)>,
+) {
+    let scale = if paused.0 && !frame_step.is_stepping() {
+        0.0
+    } else {
+        time_scale.scale
+    };
+
+    // Photo mode holds animations itself.
+    let scaling = scale != 1.0 && !photo_mode.is_active();
+
+    last_times.retain(|entity, _| animation_q.get(*entity).is_ok());
+
+    for (entity, mut state) in animation_q.iter_mut() {
+        if scaling {
+            if let Some(&(index, time)) = last_times.get(&entity) {
+                let advanced = state.time - time;
+
+                // Anything else is a new animation or one that has looped.
+                if index == state.animation_index && advanced > 0.0 {
+                    state.time = time + advanced * scale;
+                }
+            }
+        }
+
+        last_times.insert(entity, (state.animation_index, state.time));
+    }
+}
@@ -0,0 +1,157 @@
+use std::f32::consts::PI;
+
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Query, Res, ResMut, With},
+    renderer_core::glam::Quat,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::bounds::Bounds;
+use crate::camera_focus::{framing_distance, Selected};
+use crate::input::KeyPressed;
+use crate::key_bindings::{KeyAction, KeyBindings};
+use crate::recording::{Recording, RecordingToggled};
+use crate::schedule;
+
+// Three seconds at the fixed timestep.
+const DEFAULT_FRAMES: u32 = 180;
+// How far above the selection the camera looks down from.
+const ELEVATION_DEGREES: f32 = 20.0;
+
+// Orbits the camera once around the selection over `frames` frames and records it, for
+// sharing previews of models. The recording is a webm (or mp4) made by the recording plugin,
+// so it's only saved on the web, at the canvas' resolution. Animated gifs would need the
+// renderer to read frames back, which it can't do yet.
+pub struct TurntableRequested {
+    pub frames: u32,
+}
+
+struct Orbit {
+    center: Vec3,
+    distance: f32,
+    frame: u32,
+    frames: u32,
+    // Whether the turntable started the recording, and so should stop it.
+    recording: bool,
+}
+
+#[derive(Default)]
+pub struct Turntable {
+    orbit: Option<Orbit>,
+}
+
+impl Turntable {
+    pub fn is_active(&self) -> bool {
+        self.orbit.is_some()
+    }
+}
+
+pub struct TurntablePlugin;
+
+impl Plugin for TurntablePlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Turntable::default());
+        app.add_event::<TurntableRequested>();
+
+        app.add_system_set(schedule::input().with_system(handle_turntable_key));
+        // After the simulation, so that the orbit overrides the camera control.
+        app.add_system_set(
+            schedule::pre_render()
+                .with_system(start_turntable)
+                .with_system(orbit_camera),
+        );
+    }
+}
+
+fn handle_turntable_key(
+    mut key_presses: EventReader<KeyPressed>,
+    bindings: Option<Res<KeyBindings>>,
+    mut requests: EventWriter<TurntableRequested>,
+) {
+    let bindings = match bindings {
+        Some(bindings) => bindings,
+        None => return,
+    };
+
+    for KeyPressed(key) in key_presses.iter() {
+        if bindings.action(*key) == Some(KeyAction::Turntable) {
+            requests.send(TurntableRequested {
+                frames: DEFAULT_FRAMES,
+            });
+        }
+    }
+}
+
+fn start_turntable(
+    mut requests: EventReader<TurntableRequested>,
+    mut turntable: ResMut<Turntable>,
+    mut recording_toggles: EventWriter<RecordingToggled>,
+    recording: Res<Recording>,
+    selected_q: Query<&Bounds, With<Selected>>,
+) {
+    let frames = match requests.iter().last() {
+        Some(request) => request.frames.max(1),
+        None => return,
+    };
+
+    if turntable.is_active() {
+        return;
+    }
+
+    let aabb = match selected_q
+        .iter()
+        .map(|bounds| bounds.0)
+        .reduce(|a, b| a.union(&b))
+    {
+        Some(aabb) => aabb,
+        None => {
+            log::info!("Select a model to make a turntable of");
+            return;
+        }
+    };
+
+    let start_recording = !recording.active;
+
+    if start_recording {
+        recording_toggles.send(RecordingToggled);
+    }
+
+    turntable.orbit = Some(Orbit {
+        center: aabb.center(),
+        distance: framing_distance(&aabb),
+        frame: 0,
+        frames,
+        recording: start_recording,
+    });
+}
+
+fn orbit_camera(
+    mut turntable: ResMut<Turntable>,
+    mut recording_toggles: EventWriter<RecordingToggled>,
+    mut camera: ResMut<Camera>,
+) {
+    let orbit = match turntable.orbit.as_mut() {
+        Some(orbit) => orbit,
+        None => return,
+    };
+
+    let angle = orbit.frame as f32 / orbit.frames as f32 * PI * 2.0;
+    let rotation =
+        Quat::from_rotation_y(angle) * Quat::from_rotation_x(-ELEVATION_DEGREES.to_radians());
+
+    camera.position = orbit.center + rotation * Vec3::Z * orbit.distance;
+    camera.rotation = rotation;
+
+    orbit.frame += 1;
+
+    if orbit.frame > orbit.frames {
+        if orbit.recording {
+            recording_toggles.send(RecordingToggled);
+        }
+
+        // The camera control puts the camera back where it was on the next frame.
+        turntable.orbit = None;
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::HashMap;
+use std::f32::consts::PI;
+
+use superconductor::{
+    bevy_app::{App, EventWriter, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Mut, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::{Quat, Vec2},
+    url::Url,
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::materials::MaterialDescriptor;
+use crate::photo_mode::PhotoMode;
+use crate::pointers::PointerRays;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+
+// How far each layer (background, widgets, slider knobs) sits in front of the one behind it.
+const LAYER_OFFSET: f32 = 0.002;
+
+struct PanelColors {
+    background: [f32; 4],
+    widget: [f32; 4],
+    hover: [f32; 4],
+    knob: [f32; 4],
+}
+
+const DEFAULT_COLORS: PanelColors = PanelColors {
+    background: [0.08, 0.08, 0.1, 1.0],
+    widget: [0.3, 0.3, 0.35, 1.0],
+    hover: [0.4, 0.5, 0.8, 1.0],
+    knob: [0.9, 0.9, 0.9, 1.0],
+};
+
+// Black and white, with yellow for whatever is hovered over.
+const HIGH_CONTRAST_COLORS: PanelColors = PanelColors {
+    background: [0.0, 0.0, 0.0, 1.0],
+    widget: [1.0, 1.0, 1.0, 1.0],
+    hover: [1.0, 0.85, 0.0, 1.0],
+    knob: [0.0, 0.0, 0.0, 1.0],
+};
+// Added to icons while they're hovered over.
+const HOVER_GLOW: [f32; 3] = [0.2, 0.2, 0.2];
+
+#[derive(Clone, Debug)]
+pub enum WidgetKind {
+    // Drawn with the icon texture if there is one, as there's no text rendering.
+    Button { icon: Option<Url> },
+    // From 0 to 1, set by clicking along the track.
+    Slider { value: f32 },
+}
+
+#[derive(Clone, Debug)]
+pub struct Widget {
+    pub kind: WidgetKind,
+    // In meters from the center of the panel, with y up.
+    pub position: Vec2,
+    pub size: Vec2,
+    // Replaces the default colour of a button or slider track, to tell them apart.
+    pub color: Option<[f32; 4]>,
+}
+
+// A flat panel of buttons and sliders placed in the world, that the mouse (and any other
+// pointer) can click on. Clicks come out as `ButtonClicked` and `SliderChanged` events.
+// Panels can be moved, but their size and widgets can't be changed after they've been added,
+// apart from slider values.
+#[derive(Component, Clone, Debug)]
+pub struct UiPanel {
+    pub position: Vec3,
+    // Panels face along +z before being rotated.
+    pub rotation: Quat,
+    pub size: Vec2,
+    pub widgets: Vec<Widget>,
+    // Hidden panels are shrunk to nothing and can't be pointed at.
+    pub visible: bool,
+}
+
+impl UiPanel {
+    pub fn new(position: Vec3, rotation: Quat, size: Vec2) -> Self {
+        Self {
+            position,
+            rotation,
+            size,
+            widgets: Vec::new(),
+            visible: true,
+        }
+    }
+
+    pub fn with_button(mut self, position: Vec2, size: Vec2, icon: Option<Url>) -> Self {
+        self.widgets.push(Widget {
+            kind: WidgetKind::Button { icon },
+            position,
+            size,
+            color: None,
+        });
+        self
+    }
+
+    pub fn with_slider(mut self, position: Vec2, size: Vec2, value: f32) -> Self {
+        self.widgets.push(Widget {
+            kind: WidgetKind::Slider { value },
+            position,
+            size,
+            color: None,
+        });
+        self
+    }
+
+    // Colours the last widget that was added.
+    pub fn with_color(mut self, color: [f32; 4]) -> Self {
+        if let Some(widget) = self.widgets.last_mut() {
+            widget.color = Some(color);
+        }
+        self
+    }
+
+    fn scale(&self) -> f32 {
+        if self.visible {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn transform(&self, local: Vec2, layer: u32) -> (Vec3, Quat) {
+        let offset = Vec3::new(local.x, local.y, LAYER_OFFSET * layer as f32);
+
+        // The plane primitive faces up, so it's stood up to face along +z first.
+        (
+            self.position + self.rotation * offset,
+            self.rotation * Quat::from_rotation_x(PI / 2.0),
+        )
+    }
+
+    // The distance along the ray and where it hits the front of the panel, in panel space.
+    pub(crate) fn ray_hit(&self, origin: Vec3, direction: Vec3) -> Option<(f32, Vec2)> {
+        if !self.visible {
+            return None;
+        }
+
+        let inverse = self.rotation.inverse();
+        let origin = inverse * (origin - self.position);
+        let direction = inverse * direction;
+
+        if direction.z >= -f32::EPSILON || origin.z <= 0.0 {
+            return None;
+        }
+
+        let distance = -origin.z / direction.z;
+        let hit = origin + direction * distance;
+        let hit = Vec2::new(hit.x, hit.y);
+
+        if hit.abs().cmple(self.size * 0.5).all() {
+            Some((distance, hit))
+        } else {
+            None
+        }
+    }
+}
+
+impl Widget {
+    fn contains(&self, point: Vec2) -> bool {
+        (point - self.position).abs().cmple(self.size * 0.5).all()
+    }
+
+    fn knob_position(&self, value: f32) -> Vec2 {
+        Vec2::new(
+            self.position.x + (value.clamp(0.0, 1.0) - 0.5) * self.size.x,
+            self.position.y,
+        )
+    }
+}
+
+pub struct ButtonClicked {
+    pub panel: Entity,
+    pub widget: usize,
+}
+
+pub struct SliderChanged {
+    pub panel: Entity,
+    pub widget: usize,
+    pub value: f32,
+}
+
+struct WidgetModels {
+    normal: Entity,
+    hover: Entity,
+    instance: Entity,
+    // The model and instance of a slider's knob.
+    knob: Option<(Entity, Entity)>,
+}
+
+struct PanelModels {
+    background: Entity,
+    widgets: Vec<WidgetModels>,
+    hovered: Option<usize>,
+}
+
+#[derive(Default)]
+struct UiPanels(HashMap<Entity, PanelModels>);
+
+pub struct UiPanelPlugin;
+
+impl Plugin for UiPanelPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_event::<ButtonClicked>();
+        app.add_event::<SliderChanged>();
+        app.insert_resource(UiPanels::default());
+
+        app.add_system(spawn_panel_models);
+        app.add_system(despawn_panel_models);
+        app.add_system_set(schedule::simulation().with_system(handle_panel_pointers));
+        app.add_system_set(schedule::pre_render().with_system(update_panel_instances));
+    }
+}
+
+fn spawn_instance(
+    commands: &mut Commands,
+    model: Entity,
+    (position, rotation): (Vec3, Quat),
+    scale: f32,
+) -> Entity {
+    commands
+        .spawn()
+        .insert(components::InstanceOf(model))
+        .insert(components::Instance(renderer_core::Instance::new(
+            position, scale, rotation,
+        )))
+        .id()
+}
+
+fn plane(size: Vec2) -> Primitive {
+    Primitive::Plane {
+        width: size.x,
+        depth: size.y,
+    }
+}
+
+// Part of the way towards white.
+fn highlight(color: [f32; 4]) -> [f32; 4] {
+    let [r, g, b, a] = color;
+    let lighten = |channel: f32| channel + (1.0 - channel) * 0.4;
+
+    [lighten(r), lighten(g), lighten(b), a]
+}
+
+fn spawn_widget_models(
+    commands: &mut Commands,
+    colors: &PanelColors,
+    panel: &UiPanel,
+    widget: &Widget,
+) -> WidgetModels {
+    let (normal, hover) = match &widget.kind {
+        WidgetKind::Button { icon: Some(icon) } => (
+            MaterialDescriptor {
+                base_color_texture: Some(icon.clone()),
+                blend: true,
+                ..Default::default()
+            },
+            MaterialDescriptor {
+                base_color_texture: Some(icon.clone()),
+                emissive: HOVER_GLOW,
+                blend: true,
+                ..Default::default()
+            },
+        ),
+        _ => match widget.color {
+            Some(color) => (
+                MaterialDescriptor::color(color),
+                MaterialDescriptor::color(highlight(color)),
+            ),
+            None => (
+                MaterialDescriptor::color(colors.widget),
+                MaterialDescriptor::color(colors.hover),
+            ),
+        },
+    };
+
+    let normal = spawn_primitive(commands, plane(widget.size), normal);
+    let hover = spawn_primitive(commands, plane(widget.size), hover);
+    let instance = spawn_instance(
+        commands,
+        normal,
+        panel.transform(widget.position, 1),
+        panel.scale(),
+    );
+
+    let knob = match widget.kind {
+        WidgetKind::Slider { value } => {
+            let model = spawn_primitive(
+                commands,
+                plane(Vec2::splat(widget.size.y)),
+                MaterialDescriptor::color(colors.knob),
+            );
+
+            let instance = spawn_instance(
+                commands,
+                model,
+                panel.transform(widget.knob_position(value), 2),
+                panel.scale(),
+            );
+
+            Some((model, instance))
+        }
+        WidgetKind::Button { .. } => None,
+    };
+
+    WidgetModels {
+        normal,
+        hover,
+        instance,
+        knob,
+    }
+}
+
+fn spawn_panel_models(
+    mut commands: Commands,
+    mut panels: ResMut<UiPanels>,
+    accessibility: Res<AccessibilitySettings>,
+    panel_q: Query<(Entity, &UiPanel)>,
+    added_q: Query<(), Added<UiPanel>>,
+) {
+    let colors = if accessibility.high_contrast {
+        &HIGH_CONTRAST_COLORS
+    } else {
+        &DEFAULT_COLORS
+    };
+
+    for (entity, panel) in panel_q.iter() {
+        // Every panel is rebuilt in the new colours when the settings change.
+        if added_q.get(entity).is_err() && !accessibility.is_changed() {
+            continue;
+        }
+
+        let background = spawn_primitive(
+            &mut commands,
+            plane(panel.size),
+            MaterialDescriptor::color(colors.background),
+        );
+
+        let (position, rotation) = panel.transform(Vec2::ZERO, 0);
+
+        commands
+            .entity(entity)
+            .insert(components::InstanceOf(background))
+            .insert(components::Instance(renderer_core::Instance::new(
+                position,
+                panel.scale(),
+                rotation,
+            )));
+
+        let widgets = panel
+            .widgets
+            .iter()
+            .map(|widget| spawn_widget_models(&mut commands, colors, panel, widget))
+            .collect();
+
+        let models = PanelModels {
+            background,
+            widgets,
+            hovered: None,
+        };
+
+        if let Some(previous) = panels.0.insert(entity, models) {
+            despawn_models(&mut commands, previous);
+        }
+    }
+}
+
+fn despawn_models(commands: &mut Commands, models: PanelModels) {
+    commands.entity(models.background).despawn();
+
+    for widget in models.widgets {
+        commands.entity(widget.normal).despawn();
+        commands.entity(widget.hover).despawn();
+        commands.entity(widget.instance).despawn();
+
+        if let Some((model, instance)) = widget.knob {
+            commands.entity(model).despawn();
+            commands.entity(instance).despawn();
+        }
+    }
+}
+
+fn despawn_panel_models(
+    mut commands: Commands,
+    mut panels: ResMut<UiPanels>,
+    panel_q: Query<(), With<UiPanel>>,
+) {
+    let removed: Vec<Entity> = panels
+        .0
+        .keys()
+        .copied()
+        .filter(|&panel| panel_q.get(panel).is_err())
+        .collect();
+
+    for panel in removed {
+        if let Some(models) = panels.0.remove(&panel) {
+            despawn_models(&mut commands, models);
+        }
+    }
+}
+
+fn handle_panel_pointers(
+    mut commands: Commands,
+    mut panels: ResMut<UiPanels>,
+    mut button_clicks: EventWriter<ButtonClicked>,
+    mut slider_changes: EventWriter<SliderChanged>,
+    pointers: Res<PointerRays>,
+    mut panel_q: Query<(Entity, &mut UiPanel)>,
+) {
+    let _span = tracing::info_span!("handle_panel_pointers").entered();
+
+    let mut hovered: HashMap<Entity, usize> = HashMap::new();
+
+    for pointer in &pointers.0 {
+        // Only the closest panel along the ray gets the pointer.
+        let hit = panel_q
+            .iter()
+            .filter_map(|(entity, panel)| {
+                panel
+                    .ray_hit(pointer.origin, pointer.direction)
+                    .map(|(distance, point)| (entity, distance, point))
+            })
+            .min_by(|(_, a, _), (_, b, _)| a.total_cmp(b));
+
+        let (entity, point) = match hit {
+            Some((entity, _, point)) => (entity, point),
+            None => continue,
+        };
+
+        let mut panel = match panel_q.get_mut(entity) {
+            Ok((_, panel)) => panel,
+            Err(_) => continue,
+        };
+
+        let index = match panel
+            .widgets
+            .iter()
+            .position(|widget| widget.contains(point))
+        {
+            Some(index) => index,
+            None => continue,
+        };
+
+        hovered.insert(entity, index);
+
+        if !pointer.clicked {
+            continue;
+        }
+
+        let widget = &mut panel.widgets[index];
+
+        match &mut widget.kind {
+            WidgetKind::Button { .. } => button_clicks.send(ButtonClicked {
+                panel: entity,
+                widget: index,
+            }),
+            WidgetKind::Slider { value } => {
+                let new_value =
+                    ((point.x - widget.position.x) / widget.size.x + 0.5).clamp(0.0, 1.0);
+                *value = new_value;
+
+                slider_changes.send(SliderChanged {
+                    panel: entity,
+                    widget: index,
+                    value: new_value,
+                });
+            }
+        }
+    }
+
+    for (entity, models) in panels.0.iter_mut() {
+        let now_hovered = hovered.get(entity).copied();
+
+        if models.hovered == now_hovered {
+            continue;
+        }
+
+        if let Some(widget) = models.hovered.and_then(|index| models.widgets.get(index)) {
+            commands
+                .entity(widget.instance)
+                .insert(components::InstanceOf(widget.normal));
+        }
+
+        if let Some(widget) = now_hovered.and_then(|index| models.widgets.get(index)) {
+            commands
+                .entity(widget.instance)
+                .insert(components::InstanceOf(widget.hover));
+        }
+
+        models.hovered = now_hovered;
+    }
+}
+
+fn set_transform(
+    mut instance: Mut<components::Instance>,
+    (position, rotation): (Vec3, Quat),
+    scale: f32,
+) {
+    // Only touched when something changed, so that the renderer isn't sent every panel again
+    // each frame.
+    if instance.0.position != position
+        || instance.0.rotation != rotation
+        || instance.0.scale != scale
+    {
+        instance.0.position = position;
+        instance.0.rotation = rotation;
+        instance.0.scale = scale;
+    }
+}
+
+fn update_panel_instances(
+    panels: Res<UiPanels>,
+    photo_mode: Res<PhotoMode>,
+    panel_q: Query<(Entity, &UiPanel)>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    for (entity, panel) in panel_q.iter() {
+        let models = match panels.0.get(&entity) {
+            Some(models) => models,
+            None => continue,
+        };
+
+        let scale = if photo_mode.hides_ui() {
+            0.0
+        } else {
+            panel.scale()
+        };
+
+        if let Ok(instance) = instance_q.get_mut(entity) {
+            set_transform(instance, panel.transform(Vec2::ZERO, 0), scale);
+        }
+
+        for (widget, widget_models) in panel.widgets.iter().zip(&models.widgets) {
+            if let Ok(instance) = instance_q.get_mut(widget_models.instance) {
+                set_transform(instance, panel.transform(widget.position, 1), scale);
+            }
+
+            if let (WidgetKind::Slider { value }, Some((_, knob))) =
+                (&widget.kind, widget_models.knob)
+            {
+                if let Ok(instance) = instance_q.get_mut(knob) {
+                    set_transform(
+                        instance,
+                        panel.transform(widget.knob_position(*value), 2),
+                        scale,
+                    );
+                }
+            }
+        }
+    }
+}
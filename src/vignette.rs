@@ -0,0 +1,153 @@
+use std::f32::consts::PI;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Local, Query, Res},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    Vec3,
+};
+
+use crate::camera_control::ControlSettings;
+use crate::materials::MaterialDescriptor;
+use crate::photo_mode::PhotoMode;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::{schedule, DELTA_TIME};
+
+// The vignette is an opening in a ring of black quads just in front of the camera, as there's
+// no post processing pass to darken the edges of the screen with.
+const SEGMENTS: usize = 12;
+const DISTANCE: f32 = 0.3;
+// The radius of the opening when the vignette is fully open, far enough out to be off screen.
+const OPEN_RADIUS: f32 = DISTANCE * 1.5;
+const QUAD_SIZE: f32 = DISTANCE * 4.0;
+// Full strength is reached this many times the onset speed past the onset.
+const FULL_STRENGTH_SPEED: f32 = 2.0;
+// How quickly the strength follows the speed, per second.
+const RESPONSE: f32 = 6.0;
+// Anything faster is a teleport or recenter, which the vignette ignores.
+const MAX_SMOOTH_SPEED: f32 = 20.0;
+
+#[derive(Default)]
+struct VignetteState {
+    quads: Option<(Entity, Vec<Entity>)>,
+    previous: Option<(Vec3, Quat)>,
+    strength: f32,
+}
+
+pub struct ComfortVignettePlugin;
+
+impl Plugin for ComfortVignettePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_system_set(schedule::pre_render().with_system(update_comfort_vignette));
+    }
+}
+
+fn spawn_quads(commands: &mut Commands) -> (Entity, Vec<Entity>) {
+    let model = spawn_primitive(
+        commands,
+        Primitive::plane(QUAD_SIZE),
+        MaterialDescriptor {
+            double_sided: true,
+            ..MaterialDescriptor::emissive([0.0; 3])
+        },
+    );
+
+    let instances = (0..SEGMENTS)
+        .map(|_| {
+            commands
+                .spawn()
+                .insert(components::InstanceOf(model))
+                .insert(components::Instance(renderer_core::Instance::new(
+                    Vec3::ZERO,
+                    0.0,
+                    Quat::IDENTITY,
+                )))
+                .id()
+        })
+        .collect();
+
+    (model, instances)
+}
+
+fn update_comfort_vignette(
+    mut commands: Commands,
+    mut state: Local<VignetteState>,
+    settings: Res<ControlSettings>,
+    photo_mode: Res<PhotoMode>,
+    camera: Res<Camera>,
+    mut instance_q: Query<&mut components::Instance>,
+) {
+    // Photo mode's free camera shouldn't have the vignette in its shots.
+    let vignette = match settings.comfort_vignette.filter(|_| !photo_mode.hides_ui()) {
+        Some(vignette) => vignette,
+        None => {
+            if let Some((model, instances)) = state.quads.take() {
+                commands.entity(model).despawn();
+
+                for instance in instances {
+                    commands.entity(instance).despawn();
+                }
+            }
+
+            return;
+        }
+    };
+
+    let speed = match state.previous {
+        Some((position, rotation)) => {
+            let moved = camera.position.distance(position);
+            let turned = 2.0 * rotation.dot(camera.rotation).abs().min(1.0).acos();
+            (moved + turned) / DELTA_TIME
+        }
+        None => 0.0,
+    };
+
+    state.previous = Some((camera.position, camera.rotation));
+
+    let ramp = (vignette.onset * FULL_STRENGTH_SPEED).max(0.1);
+
+    let target = if speed > MAX_SMOOTH_SPEED {
+        0.0
+    } else {
+        ((speed - vignette.onset) / ramp).clamp(0.0, 1.0)
+    };
+
+    state.strength += (target - state.strength) * (RESPONSE * DELTA_TIME).min(1.0);
+
+    let strength = state.strength;
+
+    let (_, instances) = state
+        .quads
+        .get_or_insert_with(|| spawn_quads(&mut commands));
+
+    let radius = OPEN_RADIUS * (1.0 - vignette.intensity * strength);
+    let visible = strength > 0.01;
+
+    for (index, &entity) in instances.iter().enumerate() {
+        let mut instance = match instance_q.get_mut(entity) {
+            Ok(instance) => instance,
+            Err(_) => continue,
+        };
+
+        if !visible {
+            if instance.0.scale != 0.0 {
+                instance.0.scale = 0.0;
+            }
+
+            continue;
+        }
+
+        let angle = index as f32 / SEGMENTS as f32 * PI * 2.0;
+        let outwards = Vec3::new(angle.cos(), angle.sin(), 0.0);
+
+        // Each quad's inner edge touches the opening, and the plane primitive is stood up to
+        // face the camera.
+        instance.0.position = camera.position
+            + camera.rotation * (outwards * (radius + QUAD_SIZE * 0.5) - Vec3::Z * DISTANCE);
+        instance.0.rotation =
+            camera.rotation * Quat::from_rotation_z(angle) * Quat::from_rotation_x(PI / 2.0);
+        instance.0.scale = 1.0;
+    }
+}
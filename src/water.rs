@@ -0,0 +1,142 @@
+use std::collections::HashMap;
+use std::f32::consts::TAU;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Added, Commands, Component, Entity, Local, Query, Res, ResMut, With},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    Vec3,
+};
+
+use crate::accessibility::AccessibilitySettings;
+use crate::materials::MaterialDescriptor;
+use crate::primitives::{spawn_primitive, Primitive};
+use crate::schedule;
+use crate::time_scale::TimeScale;
+
+// A flat, stylized water surface. It's a smooth, see-through plane, so the renderer's fresnel
+// picks up more of the environment map at grazing angles, and it rises and falls with a gentle
+// swell. Scrolling normal maps need the renderer to offset texture coordinates over time, and
+// planar reflection and refraction need a second pass, neither of which it can do yet.
+#[derive(Component, Clone, Copy, Debug)]
+pub struct Water {
+    // The middle of the surface at rest.
+    pub position: Vec3,
+    // The extent and color can't be changed after the water has been added.
+    pub width: f32,
+    pub depth: f32,
+    // Linear rgba. The alpha is how much of what's underneath shows through.
+    pub color: [f32; 4],
+    // In meters, above and below the rest position.
+    pub swell_height: f32,
+    // Swells per second.
+    pub swell_frequency: f32,
+}
+
+impl Water {
+    pub fn new(position: Vec3, width: f32, depth: f32) -> Self {
+        Self {
+            position,
+            width,
+            depth,
+            color: [0.02, 0.12, 0.2, 0.75],
+            swell_height: 0.03,
+            swell_frequency: 0.25,
+        }
+    }
+}
+
+// The plane model of each water surface.
+#[derive(Default)]
+struct WaterModels(HashMap<Entity, Entity>);
+
+pub struct WaterPlugin;
+
+impl Plugin for WaterPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(WaterModels::default());
+
+        app.add_system(spawn_water_models);
+        app.add_system(despawn_water_models);
+        app.add_system_set(schedule::simulation().with_system(swell_water));
+    }
+}
+
+fn spawn_water_models(
+    mut commands: Commands,
+    mut models: ResMut<WaterModels>,
+    water_q: Query<(Entity, &Water), Added<Water>>,
+) {
+    for (entity, water) in water_q.iter() {
+        let model = spawn_primitive(
+            &mut commands,
+            Primitive::Plane {
+                width: water.width,
+                depth: water.depth,
+            },
+            MaterialDescriptor {
+                base_color: water.color,
+                metallic: 0.0,
+                roughness: 0.05,
+                double_sided: true,
+                blend: true,
+                ..Default::default()
+            },
+        );
+
+        commands
+            .entity(entity)
+            .insert(components::InstanceOf(model))
+            .insert(components::Instance(renderer_core::Instance::new(
+                water.position,
+                1.0,
+                Quat::IDENTITY,
+            )));
+
+        if let Some(previous) = models.0.insert(entity, model) {
+            commands.entity(previous).despawn();
+        }
+    }
+}
+
+fn despawn_water_models(
+    mut commands: Commands,
+    mut models: ResMut<WaterModels>,
+    water_q: Query<(), With<Water>>,
+) {
+    models.0.retain(|&water, &mut model| {
+        let keep = water_q.get(water).is_ok();
+
+        if !keep {
+            commands.entity(model).despawn();
+        }
+
+        keep
+    });
+}
+
+fn swell_water(
+    mut time: Local<f32>,
+    accessibility: Res<AccessibilitySettings>,
+    time_scale: Res<TimeScale>,
+    mut water_q: Query<(&Water, &mut components::Instance)>,
+) {
+    *time += time_scale.delta();
+
+    for (water, mut instance) in water_q.iter_mut() {
+        let offset = if accessibility.reduced_motion {
+            0.0
+        } else {
+            water.swell_height * (*time * water.swell_frequency * TAU).sin()
+        };
+
+        let position = water.position + Vec3::Y * offset;
+
+        // Only touched when something changed, so that still water isn't sent to the renderer
+        // again each frame.
+        if instance.0.position != position {
+            instance.0.position = position;
+        }
+    }
+}
@@ -0,0 +1,223 @@
+use std::collections::HashMap;
+
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Component, Query, Res, World},
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::console::Console;
+use crate::env_setting;
+use crate::particles::ParticleEmitter;
+use crate::schedule;
+
+// Rain and snow fall over a square this far out from the camera in each direction, from this
+// high above it.
+const PRECIPITATION_HALF_SIZE: f32 = 15.0;
+const PRECIPITATION_HEIGHT: f32 = 10.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum WeatherPreset {
+    Clear,
+    Rain,
+    Snow,
+}
+
+impl WeatherPreset {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "clear" => Some(Self::Clear),
+            "rain" => Some(Self::Rain),
+            "snow" => Some(Self::Snow),
+            _ => None,
+        }
+    }
+
+    fn particles(&self, camera: Vec3) -> Option<ParticleEmitter> {
+        match self {
+            Self::Rain => Some(ParticleEmitter::rain(
+                camera,
+                PRECIPITATION_HALF_SIZE,
+                PRECIPITATION_HEIGHT,
+            )),
+            Self::Snow => Some(ParticleEmitter::snow(
+                camera,
+                PRECIPITATION_HALF_SIZE,
+                PRECIPITATION_HEIGHT,
+            )),
+            Self::Clear => None,
+        }
+    }
+}
+
+// The current weather. Changing it lets the old precipitation finish falling and starts the
+// new one. Set with WEATHER on desktop or ?weather= on the web, the weather console command or
+// a sequencer track.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Weather(pub WeatherPreset);
+
+impl Weather {
+    pub fn from_env() -> Self {
+        let preset = env_setting("WEATHER", "weather").and_then(|name| {
+            let preset = WeatherPreset::from_name(&name);
+
+            if preset.is_none() {
+                log::warn!("Unknown weather {:?}", name);
+            }
+
+            preset
+        });
+
+        Self(preset.unwrap_or(WeatherPreset::Clear))
+    }
+}
+
+// The ambient loop for each preset, like the patter of rain. None come with the playground, so
+// apps add their own. They're played on the web only, as there's no audio output on desktop.
+pub struct WeatherSounds {
+    pub loops: HashMap<WeatherPreset, Url>,
+    // From 0 to 1.
+    pub volume: f32,
+}
+
+impl Default for WeatherSounds {
+    fn default() -> Self {
+        Self {
+            loops: HashMap::new(),
+            volume: 0.5,
+        }
+    }
+}
+
+// Marks the rain or snow that a preset started.
+#[derive(Component)]
+struct WeatherParticles(WeatherPreset);
+
+pub struct WeatherPlugin;
+
+impl Plugin for WeatherPlugin {
+    fn build(&self, app: &mut App) {
+        let weather = Weather::from_env();
+
+        app.insert_resource(weather);
+        app.init_resource::<WeatherSounds>();
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register("weather", "weather <clear | rain | snow>", set_weather);
+
+        app.add_system_set(schedule::simulation().with_system(update_weather));
+
+        #[cfg(feature = "wasm")]
+        {
+            app.insert_non_send_resource(audio::AmbientLoop::default());
+            app.add_system_set(schedule::pre_render().with_system(audio::play_ambient_loop));
+        }
+    }
+}
+
+fn set_weather(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let preset = args
+        .get(0)
+        .and_then(|name| WeatherPreset::from_name(name))
+        .ok_or("Expected clear, rain or snow")?;
+
+    world.insert_resource(Weather(preset));
+
+    Ok(String::new())
+}
+
+fn update_weather(
+    mut commands: Commands,
+    weather: Res<Weather>,
+    camera: Res<Camera>,
+    mut emitter_q: Query<(&mut ParticleEmitter, &WeatherParticles)>,
+) {
+    let preset = weather.0;
+
+    let position = camera.position + Vec3::new(0.0, PRECIPITATION_HEIGHT, 0.0);
+    let mut falling = false;
+
+    for (mut emitter, particles) in emitter_q.iter_mut() {
+        // Winding down.
+        if emitter.despawn_when_done {
+            continue;
+        }
+
+        if particles.0 == preset {
+            falling = true;
+
+            if emitter.position != position {
+                emitter.position = position;
+            }
+        } else {
+            // Stops spawning, and goes once the last of it has landed.
+            emitter.spawn_rate = 0.0;
+            emitter.despawn_when_done = true;
+        }
+    }
+
+    if !falling {
+        if let Some(emitter) = preset.particles(camera.position) {
+            commands
+                .spawn()
+                .insert(emitter)
+                .insert(WeatherParticles(preset));
+        }
+    }
+}
+
+#[cfg(feature = "wasm")]
+mod audio {
+    use superconductor::bevy_ecs::prelude::{NonSendMut, Res};
+
+    use super::{Weather, WeatherSounds};
+
+    // The loop that's playing, along with its url.
+    #[derive(Default)]
+    pub struct AmbientLoop(Option<(String, web_sys::HtmlAudioElement)>);
+
+    pub fn play_ambient_loop(
+        mut ambient: NonSendMut<AmbientLoop>,
+        weather: Res<Weather>,
+        sounds: Res<WeatherSounds>,
+    ) {
+        if !weather.is_changed() && !sounds.is_changed() {
+            return;
+        }
+
+        let url = sounds.loops.get(&weather.0);
+        let playing = ambient.0.as_ref().map(|(playing, _)| playing.as_str());
+
+        if let Some((_, element)) = ambient.0.as_ref() {
+            element.set_volume(sounds.volume.clamp(0.0, 1.0) as f64);
+        }
+
+        if playing == url.map(|url| url.as_str()) {
+            return;
+        }
+
+        if let Some((_, element)) = ambient.0.take() {
+            let _ = element.pause();
+        }
+
+        let url = match url {
+            Some(url) => url,
+            None => return,
+        };
+
+        match web_sys::HtmlAudioElement::new_with_src(url.as_str()) {
+            Ok(element) => {
+                element.set_loop(true);
+                element.set_volume(sounds.volume.clamp(0.0, 1.0) as f64);
+                // Browsers hold this back until the page has been interacted with.
+                let _ = element.play();
+
+                ambient.0 = Some((url.to_string(), element));
+            }
+            Err(error) => log::warn!("Couldn't play {}: {:?}", url, error),
+        }
+    }
+}
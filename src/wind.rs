@@ -0,0 +1,223 @@
+use std::f32::consts::TAU;
+
+use serde::{Deserialize, Serialize};
+use superconductor::{
+    bevy_app::{App, EventReader, Plugin},
+    bevy_ecs::prelude::{Component, Query, Res, ResMut, With, World},
+    renderer_core::glam::{Quat, Vec2},
+    Vec3,
+};
+
+use crate::console::Console;
+use crate::env_setting;
+use crate::schedule;
+use crate::settings::{self, Setting};
+use crate::time_scale::TimeScale;
+use crate::ui_panels::{SliderChanged, UiPanel, WidgetKind};
+
+// The strength at the right end of the panel's slider, in meters per second.
+const MAX_STRENGTH: f32 = 20.0;
+// How far apart gusts are as they travel along with the wind, in meters.
+const GUST_LENGTH: f32 = 12.0;
+
+const SLIDER_SIZE: Vec2 = Vec2::new(0.5, 0.06);
+const SLIDER_SPACING: f32 = 0.1;
+// Direction, strength and gustiness, top to bottom.
+const SLIDER_COLORS: [[f32; 4]; 3] = [
+    [0.2, 0.5, 0.9, 1.0],
+    [0.3, 0.8, 0.4, 1.0],
+    [0.9, 0.8, 0.2, 1.0],
+];
+
+// The wind everywhere in the world. It blows horizontally, and gusts roll through it along the
+// way it's blowing, so that nearby things sway together and far apart ones don't.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Wind {
+    // Which way it blows towards, in degrees from +Z towards +X.
+    pub direction: f32,
+    // The average speed, in meters per second.
+    pub strength: f32,
+    // How much gusts add to and take from the strength, from 0 to 1.
+    pub gustiness: f32,
+}
+
+impl Setting for Wind {
+    const KEY: &'static str = "wind";
+}
+
+impl Default for Wind {
+    fn default() -> Self {
+        Self {
+            direction: 0.0,
+            strength: 2.0,
+            gustiness: 0.5,
+        }
+    }
+}
+
+impl Wind {
+    // WIND=<direction>,<strength>,<gustiness> on desktop or ?wind=... on the web, where any
+    // left off keep their defaults.
+    pub fn from_env() -> Self {
+        let mut wind = Self::default();
+
+        if let Some(value) = env_setting("WIND", "wind") {
+            let mut values = value.split(',').map(|value| value.trim().parse::<f32>());
+
+            if let Some(Ok(direction)) = values.next() {
+                wind.direction = direction;
+            }
+
+            if let Some(Ok(strength)) = values.next() {
+                wind.strength = strength.max(0.0);
+            }
+
+            if let Some(Ok(gustiness)) = values.next() {
+                wind.gustiness = gustiness.clamp(0.0, 1.0);
+            }
+        }
+
+        wind
+    }
+
+    pub fn heading(&self) -> Vec3 {
+        let radians = self.direction.to_radians();
+        Vec3::new(radians.sin(), 0.0, radians.cos())
+    }
+}
+
+// The wind as it's sampled: the settings, and how far the gusts have rolled along. Kept apart
+// from `Wind` so that the settings aren't saved again every frame.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindField {
+    pub wind: Wind,
+    // In seconds, scaled and paused with the simulation.
+    pub time: f32,
+}
+
+impl WindField {
+    // The air's velocity at a point, in meters per second.
+    pub fn velocity_at(&self, position: Vec3) -> Vec3 {
+        let heading = self.wind.heading();
+        // Gusts move along with the wind, at its average speed.
+        let phase = (position.dot(heading) - self.time * self.wind.strength) / GUST_LENGTH;
+        // A few waves of different lengths, so that it doesn't repeat noticeably.
+        let gust = 0.5 * (phase * TAU).sin()
+            + 0.3 * (phase * TAU * 2.3 + 1.7).sin()
+            + 0.2 * (phase * TAU * 4.1 + 4.2).sin();
+
+        heading * (self.wind.strength * (1.0 + self.wind.gustiness * gust)).max(0.0)
+    }
+}
+
+#[derive(Component)]
+struct WindPanel;
+
+// Spawns a panel with a slider for each of the wind's settings, in front of where the player
+// starts.
+pub struct WindPlugin;
+
+impl Plugin for WindPlugin {
+    fn build(&self, app: &mut App) {
+        app.insert_resource(Wind::from_env());
+        settings::persist::<Wind>(app);
+        app.init_resource::<WindField>();
+
+        app.world
+            .get_resource_or_insert_with(Console::default)
+            .register("wind", "wind <direction> <strength> [gustiness]", set_wind);
+
+        let mut panel = UiPanel::new(
+            Vec3::new(-1.5, 1.3, -2.5),
+            Quat::IDENTITY,
+            Vec2::new(
+                SLIDER_SIZE.x + SLIDER_SPACING,
+                SLIDER_SPACING * SLIDER_COLORS.len() as f32,
+            ),
+        );
+
+        for (index, color) in SLIDER_COLORS.iter().enumerate() {
+            let y = (1.0 - index as f32) * SLIDER_SPACING;
+            panel = panel
+                .with_slider(Vec2::new(0.0, y), SLIDER_SIZE, 0.0)
+                .with_color(*color);
+        }
+
+        app.world.spawn().insert(panel).insert(WindPanel);
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(advance_wind)
+                .with_system(handle_wind_sliders),
+        );
+        app.add_system_set(schedule::pre_render().with_system(update_wind_sliders));
+    }
+}
+
+fn set_wind(world: &mut World, args: &[&str]) -> Result<String, String> {
+    let parse = |index: usize| -> Result<Option<f32>, String> {
+        args.get(index)
+            .map(|value| value.parse::<f32>().map_err(|error| error.to_string()))
+            .transpose()
+    };
+
+    let (direction, strength) = match (parse(0)?, parse(1)?) {
+        (Some(direction), Some(strength)) => (direction, strength),
+        _ => return Err("Expected a direction in degrees and a strength".into()),
+    };
+
+    let mut wind = world.resource_mut::<Wind>();
+    wind.direction = direction.rem_euclid(360.0);
+    wind.strength = strength.max(0.0);
+
+    if let Some(gustiness) = parse(2)? {
+        wind.gustiness = gustiness.clamp(0.0, 1.0);
+    }
+
+    Ok(String::new())
+}
+
+fn advance_wind(wind: Res<Wind>, time_scale: Res<TimeScale>, mut field: ResMut<WindField>) {
+    field.wind = *wind;
+    field.time += time_scale.delta();
+}
+
+fn handle_wind_sliders(
+    mut changes: EventReader<SliderChanged>,
+    mut wind: ResMut<Wind>,
+    panel_q: Query<(), With<WindPanel>>,
+) {
+    for change in changes.iter() {
+        if panel_q.get(change.panel).is_err() {
+            continue;
+        }
+
+        match change.widget {
+            0 => wind.direction = change.value * 360.0,
+            1 => wind.strength = change.value * MAX_STRENGTH,
+            2 => wind.gustiness = change.value,
+            _ => {}
+        }
+    }
+}
+
+// Keeps the sliders where the settings are, for when they're changed from the console.
+fn update_wind_sliders(wind: Res<Wind>, mut panel_q: Query<&mut UiPanel, With<WindPanel>>) {
+    if !wind.is_changed() {
+        return;
+    }
+
+    let values = [
+        wind.direction / 360.0,
+        wind.strength / MAX_STRENGTH,
+        wind.gustiness,
+    ];
+
+    for mut panel in panel_q.iter_mut() {
+        for (widget, new_value) in panel.widgets.iter_mut().zip(values) {
+            if let WidgetKind::Slider { value } = &mut widget.kind {
+                *value = new_value.clamp(0.0, 1.0);
+            }
+        }
+    }
+}
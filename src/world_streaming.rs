@@ -0,0 +1,319 @@
+use crossbeam_channel::Receiver;
+use serde::Deserialize;
+use superconductor::{
+    bevy_app::{App, Plugin},
+    bevy_ecs::prelude::{Commands, Entity, Res, ResMut},
+    components, renderer_core,
+    renderer_core::glam::Quat,
+    resources::Camera,
+    url::Url,
+    Vec3,
+};
+
+use crate::asset_cache::AssetError;
+use crate::assets::{AssetServer, Handle, Model};
+use crate::bounds::Aabb;
+use crate::downloads::DownloadManager;
+use crate::occlusion::{CellPortal, OcclusionCell};
+
+fn default_load_distance() -> f32 {
+    100.0
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+#[derive(Deserialize)]
+pub struct WorldModel {
+    // Relative to the manifest.
+    pub url: String,
+    pub position: [f32; 3],
+    #[serde(default)]
+    pub yaw_degrees: f32,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+#[derive(Deserialize)]
+pub struct WorldChunk {
+    pub center: [f32; 3],
+    // How far the chunk's models reach from its center.
+    #[serde(default)]
+    pub radius: f32,
+    pub models: Vec<WorldModel>,
+}
+
+// An occlusion cell, such as a room.
+#[derive(Deserialize)]
+pub struct WorldCell {
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+// A doorway between two cells, by their index in the list of cells.
+#[derive(Deserialize)]
+pub struct WorldPortal {
+    pub cells: [usize; 2],
+    pub min: [f32; 3],
+    pub max: [f32; 3],
+}
+
+// Distances are from the camera to the edge of a chunk. Chunks unload a little further out
+// than they load, so that one on the boundary doesn't keep loading and unloading.
+#[derive(Deserialize)]
+pub struct WorldManifest {
+    #[serde(default = "default_load_distance")]
+    pub load_distance: f32,
+    #[serde(default)]
+    pub unload_distance: Option<f32>,
+    pub chunks: Vec<WorldChunk>,
+    #[serde(default)]
+    pub cells: Vec<WorldCell>,
+    #[serde(default)]
+    pub portals: Vec<WorldPortal>,
+}
+
+pub fn world_manifest_url() -> Option<Url> {
+    #[cfg(not(feature = "wasm"))]
+    let url = std::env::var("WORLD_MANIFEST").ok();
+
+    #[cfg(feature = "wasm")]
+    let url = crate::query_parameter("world");
+
+    let url = url?;
+
+    match Url::parse(&url) {
+        Ok(url) => Some(url),
+        Err(error) => {
+            log::warn!("Invalid world manifest url {:?}: {}", url, error);
+            None
+        }
+    }
+}
+
+struct ChunkModel {
+    url: Url,
+    position: Vec3,
+    rotation: Quat,
+    scale: f32,
+}
+
+struct Chunk {
+    center: Vec3,
+    radius: f32,
+    models: Vec<ChunkModel>,
+    // The handles keep the models loaded while the chunk is.
+    loaded: Option<(Vec<Handle<Model>>, Vec<Entity>)>,
+}
+
+// The chunks of a big world, loaded as the camera gets near them and unloaded once it's far
+// away again. Unloading despawns the chunk's instances and drops its handles, so the asset
+// server unloads any model that no other chunk is using.
+pub struct WorldStreaming {
+    load_distance: f32,
+    unload_distance: f32,
+    chunks: Vec<Chunk>,
+    manifest: Option<Receiver<Result<(Url, WorldManifest), AssetError>>>,
+}
+
+impl WorldStreaming {
+    pub fn loaded_chunks(&self) -> usize {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.loaded.is_some())
+            .count()
+    }
+
+    pub fn total_chunks(&self) -> usize {
+        self.chunks.len()
+    }
+
+    fn set_manifest(&mut self, manifest_url: &Url, manifest: WorldManifest) {
+        self.load_distance = manifest.load_distance;
+        self.unload_distance = manifest
+            .unload_distance
+            .unwrap_or(manifest.load_distance * 1.25)
+            .max(manifest.load_distance);
+
+        self.chunks = manifest
+            .chunks
+            .into_iter()
+            .map(|chunk| Chunk {
+                center: Vec3::from(chunk.center),
+                radius: chunk.radius,
+                models: chunk
+                    .models
+                    .into_iter()
+                    .filter_map(|model| match manifest_url.join(&model.url) {
+                        Ok(url) => Some(ChunkModel {
+                            url,
+                            position: Vec3::from(model.position),
+                            rotation: Quat::from_rotation_y(model.yaw_degrees.to_radians()),
+                            scale: model.scale,
+                        }),
+                        Err(error) => {
+                            log::warn!("Invalid world model url {:?}: {}", model.url, error);
+                            None
+                        }
+                    })
+                    .collect(),
+                loaded: None,
+            })
+            .collect();
+
+        log::info!(
+            "Streaming {} chunks from {}",
+            self.chunks.len(),
+            manifest_url
+        );
+    }
+}
+
+// Streams the chunks of the world manifest at WORLD_MANIFEST on desktop or ?world= on the web.
+#[derive(Default)]
+pub struct WorldStreamingPlugin {
+    pub manifest_url: Option<Url>,
+}
+
+impl Plugin for WorldStreamingPlugin {
+    fn build(&self, app: &mut App) {
+        let manifest = self
+            .manifest_url
+            .clone()
+            .or_else(world_manifest_url)
+            .map(|manifest_url| {
+                let (sender, receiver) = crossbeam_channel::bounded(1);
+                let manager = app.world.resource::<DownloadManager>();
+                let cache = manager.cache();
+
+                manager.spawn(async move {
+                    let result = cache.fetch(&manifest_url).await.and_then(|bytes| {
+                        serde_json::from_slice(&bytes)
+                            .map(|manifest| (manifest_url, manifest))
+                            .map_err(|error| AssetError::Decode(error.to_string()))
+                    });
+
+                    let _ = sender.send(result);
+                });
+
+                receiver
+            });
+
+        app.insert_resource(WorldStreaming {
+            load_distance: default_load_distance(),
+            unload_distance: default_load_distance(),
+            chunks: Vec::new(),
+            manifest,
+        });
+
+        app.add_system(receive_world_manifest);
+        app.add_system(stream_chunks);
+    }
+}
+
+fn spawn_cells(commands: &mut Commands, manifest: &WorldManifest) {
+    let aabb = |min: [f32; 3], max: [f32; 3]| Aabb {
+        min: Vec3::from(min),
+        max: Vec3::from(max),
+    };
+
+    let cells: Vec<Entity> = manifest
+        .cells
+        .iter()
+        .map(|cell| {
+            commands
+                .spawn()
+                .insert(OcclusionCell(aabb(cell.min, cell.max)))
+                .id()
+        })
+        .collect();
+
+    for portal in &manifest.portals {
+        match (cells.get(portal.cells[0]), cells.get(portal.cells[1])) {
+            (Some(&a), Some(&b)) => {
+                commands.spawn().insert(CellPortal {
+                    cells: (a, b),
+                    aabb: aabb(portal.min, portal.max),
+                });
+            }
+            _ => log::warn!(
+                "A portal refers to cells {:?}, which don't exist",
+                portal.cells
+            ),
+        }
+    }
+}
+
+fn receive_world_manifest(mut commands: Commands, mut streaming: ResMut<WorldStreaming>) {
+    let result = match streaming
+        .manifest
+        .as_ref()
+        .and_then(|receiver| receiver.try_recv().ok())
+    {
+        Some(result) => result,
+        None => return,
+    };
+
+    streaming.manifest = None;
+
+    match result {
+        Ok((manifest_url, manifest)) => {
+            spawn_cells(&mut commands, &manifest);
+            streaming.set_manifest(&manifest_url, manifest);
+        }
+        Err(error) => log::error!("Failed to load the world manifest: {}", error),
+    }
+}
+
+fn stream_chunks(
+    mut commands: Commands,
+    mut streaming: ResMut<WorldStreaming>,
+    mut server: ResMut<AssetServer>,
+    camera: Res<Camera>,
+) {
+    let streaming = &mut *streaming;
+
+    for chunk in &mut streaming.chunks {
+        let distance = (camera.position.distance(chunk.center) - chunk.radius).max(0.0);
+
+        match &chunk.loaded {
+            None if distance <= streaming.load_distance => {
+                let _span = tracing::info_span!("load_chunk").entered();
+
+                let mut handles = Vec::new();
+                let mut instances = Vec::new();
+
+                for model in &chunk.models {
+                    let handle: Handle<Model> = server.load(&mut commands, model.url.clone());
+
+                    instances.push(
+                        commands
+                            .spawn()
+                            .insert(components::InstanceOf(handle.entity()))
+                            .insert(components::Instance(renderer_core::Instance::new(
+                                model.position,
+                                model.scale,
+                                model.rotation,
+                            )))
+                            .id(),
+                    );
+
+                    handles.push(handle);
+                }
+
+                log::info!("Loading the chunk at {}", chunk.center);
+                chunk.loaded = Some((handles, instances));
+            }
+            Some((_, instances)) if distance > streaming.unload_distance => {
+                for &instance in instances {
+                    commands.entity(instance).despawn();
+                }
+
+                log::info!("Unloading the chunk at {}", chunk.center);
+                chunk.loaded = None;
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,107 @@
+use std::f32::consts::PI;
+
+use superconductor::{
+    bevy_app::{App, EventReader, EventWriter, Plugin},
+    bevy_ecs::prelude::{Component, Query, Res, With},
+    renderer_core::glam::{Quat, Vec2},
+    Vec3,
+};
+
+use crate::laser_pointers::{Controllers, LEFT_CONTROLLER};
+use crate::schedule;
+use crate::ui_panels::{ButtonClicked, UiPanel};
+
+const BUTTON_SIZE: f32 = 0.04;
+const BUTTON_SPACING: f32 = 0.05;
+// From the controller, along its up axis and back towards the wrist.
+const WRIST_OFFSET: Vec3 = Vec3::new(0.0, 0.03, 0.08);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WristMenuAction {
+    Recenter,
+    Screenshot,
+}
+
+// In button order, left to right, each with its own colour as there's no text rendering.
+const ACTIONS: [(WristMenuAction, [f32; 4]); 2] = [
+    (WristMenuAction::Recenter, [0.2, 0.5, 0.9, 1.0]),
+    (WristMenuAction::Screenshot, [0.9, 0.8, 0.2, 1.0]),
+];
+
+// Sent when a button on the wrist menu is pressed. The menu only sends these, so that each
+// action can be handled by whatever plugin owns it.
+pub struct WristMenuActivated(pub WristMenuAction);
+
+#[derive(Component)]
+struct WristMenu;
+
+// A row of quick action buttons that sits on the back of the left controller, and is hidden
+// while that controller isn't tracked. Pressed with the right controller's laser.
+pub struct WristMenuPlugin;
+
+impl Plugin for WristMenuPlugin {
+    fn build(&self, app: &mut App) {
+        let width = BUTTON_SPACING * ACTIONS.len() as f32;
+
+        let mut panel = UiPanel::new(Vec3::ZERO, Quat::IDENTITY, Vec2::new(width, BUTTON_SPACING));
+
+        for (index, (_, color)) in ACTIONS.iter().enumerate() {
+            let x = (index as f32 + 0.5) * BUTTON_SPACING - width * 0.5;
+
+            panel = panel
+                .with_button(Vec2::new(x, 0.0), Vec2::splat(BUTTON_SIZE), None)
+                .with_color(*color);
+        }
+
+        panel.visible = false;
+
+        app.world.spawn().insert(panel).insert(WristMenu);
+
+        app.add_event::<WristMenuActivated>();
+
+        app.add_system_set(
+            schedule::simulation()
+                .with_system(follow_left_controller)
+                .with_system(handle_wrist_menu_clicks),
+        );
+    }
+}
+
+fn follow_left_controller(
+    controllers: Res<Controllers>,
+    mut menu_q: Query<&mut UiPanel, With<WristMenu>>,
+) {
+    let pose = controllers.0.get(LEFT_CONTROLLER).copied().flatten();
+
+    for mut panel in menu_q.iter_mut() {
+        match pose {
+            Some(pose) => {
+                panel.visible = true;
+                panel.position = pose.position + pose.rotation * WRIST_OFFSET;
+                // Panels face along +z, so it's turned to face along the controller's up axis.
+                panel.rotation = pose.rotation * Quat::from_rotation_x(-PI / 2.0);
+            }
+            None => {
+                if panel.visible {
+                    panel.visible = false;
+                }
+            }
+        }
+    }
+}
+
+fn handle_wrist_menu_clicks(
+    mut clicks: EventReader<ButtonClicked>,
+    mut actions: EventWriter<WristMenuActivated>,
+    menu_q: Query<(), With<WristMenu>>,
+) {
+    for click in clicks.iter() {
+        if menu_q.get(click.panel).is_err() {
+            continue;
+        }
+
+        if let Some((action, _)) = ACTIONS.get(click.widget) {
+            actions.send(WristMenuActivated(*action));
+        }
+    }
+}
@@ -0,0 +1,16 @@
+// Converts environment map panoramas into cubemaps off the main thread, with its own instance
+// of the module. See `environment_maps::worker`.
+import init, { convert_environment_map } from "./pkg/times_square_superconductor.js";
+
+const ready = init();
+
+onmessage = async (event) => {
+    await ready;
+
+    try {
+        const [diffuse, specular] = convert_environment_map(event.data);
+        postMessage({ diffuse, specular }, [diffuse.buffer, specular.buffer]);
+    } catch (error) {
+        postMessage({ error: String(error) });
+    }
+};